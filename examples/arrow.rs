@@ -0,0 +1,69 @@
+use arrow2::io::parquet::write::{
+    transverse, CompressionOptions, Encoding, FileWriter, RowGroupIterator, Version, WriteOptions,
+};
+use binlog::{entries_to_record_batch, record_batch_schema, Entry, MemoryStore, Range, RangeableStore, Store};
+use std::borrow::Cow;
+use std::error::Error;
+use std::fs::File;
+use std::io::ErrorKind;
+use std::process::Command;
+use string_cache::DefaultAtom as Atom;
+
+/// Summarizes the exported entries by name. DuckDB reads the parquet file's arrow columns directly.
+const QUERY: &str = "select name, count(*), min(ts), max(ts) from 'example.parquet' group by name order by name";
+
+/// Demonstrates exporting entries to arrow, with results in `example.parquet`, and then querying
+/// them with SQL through the `duckdb` CLI, if it's installed.
+fn main() -> Result<(), Box<dyn Error>> {
+    let store = MemoryStore::default();
+
+    // Add 100 entries across two names.
+    for i in 1..101u8 {
+        let name = if i % 2 == 0 { "even" } else { "odd" };
+        let entry = Entry::new_with_timestamp(i as i64, Atom::from(name), vec![i]);
+        store.push(Cow::Owned(entry))?;
+    }
+
+    // Convert every entry into a columnar record batch.
+    let entries = store
+        .range(.., Option::<String>::None)?
+        .iter()?
+        .collect::<Result<Vec<Entry>, binlog::Error>>()?;
+    let batch = entries_to_record_batch(&entries);
+
+    // Write the record batch out as parquet.
+    let schema = record_batch_schema();
+    let options = WriteOptions {
+        write_statistics: true,
+        compression: CompressionOptions::Uncompressed,
+        version: Version::V2,
+        data_pagesize_limit: None,
+    };
+    let encodings = schema
+        .fields
+        .iter()
+        .map(|f| transverse(&f.data_type, |_| Encoding::Plain))
+        .collect();
+    let row_groups = RowGroupIterator::try_new(vec![Ok(batch)].into_iter(), &schema, options, encodings)?;
+    let mut writer = FileWriter::try_new(File::create("example.parquet")?, schema, options)?;
+    for group in row_groups {
+        writer.write(group?)?;
+    }
+    writer.end(None)?;
+
+    println!("wrote {} entries to example.parquet", entries.len());
+
+    // Query the output with DuckDB.
+    match Command::new("duckdb").arg("-c").arg(QUERY).status() {
+        Ok(status) if status.success() => {}
+        Ok(status) => return Err(format!("duckdb failed: {}", status).into()),
+        Err(err) if err.kind() == ErrorKind::NotFound => {
+            println!(
+                "duckdb isn't installed; to query the entries, run:\n  duckdb -c \"{}\"",
+                QUERY
+            );
+        }
+        Err(err) => return Err(err.into()),
+    }
+    Ok(())
+}
@@ -0,0 +1,109 @@
+#![no_main]
+use std::borrow::Cow;
+use std::ops::{self, RangeBounds};
+
+use arbitrary::Arbitrary;
+use binlog::{Entry, Error, MemoryStore, Range, RangeableStore, SqliteStore, Store};
+use libfuzzer_sys::fuzz_target;
+use tempfile::NamedTempFile;
+
+#[derive(Arbitrary, Clone, Copy, Debug, PartialEq)]
+enum ArbitraryMicrosBound {
+    Included(i64),
+    Excluded(i64),
+    Unbounded,
+}
+
+impl ArbitraryMicrosBound {
+    fn to_bound(self) -> ops::Bound<i64> {
+        match self {
+            ArbitraryMicrosBound::Included(micros) => ops::Bound::Included(micros),
+            ArbitraryMicrosBound::Excluded(micros) => ops::Bound::Excluded(micros),
+            ArbitraryMicrosBound::Unbounded => ops::Bound::Unbounded,
+        }
+    }
+
+    fn micros(self) -> Option<i64> {
+        match self {
+            ArbitraryMicrosBound::Included(micros) | ArbitraryMicrosBound::Excluded(micros) => Some(micros),
+            ArbitraryMicrosBound::Unbounded => None,
+        }
+    }
+
+    /// Swaps `Included` for `Excluded`, and vice versa.
+    fn toggled(self) -> Self {
+        match self {
+            ArbitraryMicrosBound::Included(micros) => ArbitraryMicrosBound::Excluded(micros),
+            ArbitraryMicrosBound::Excluded(micros) => ArbitraryMicrosBound::Included(micros),
+            ArbitraryMicrosBound::Unbounded => ArbitraryMicrosBound::Unbounded,
+        }
+    }
+}
+
+#[derive(Arbitrary, Clone, Debug, PartialEq)]
+struct Input {
+    start_bound: ArbitraryMicrosBound,
+    end_bound: ArbitraryMicrosBound,
+    timestamps: Vec<i64>,
+}
+
+/// Whether a range should be accepted: it's rejected if it's reversed, or
+/// if its bounds are equal but either is exclusive.
+fn is_valid(start_bound: ArbitraryMicrosBound, end_bound: ArbitraryMicrosBound) -> bool {
+    match (start_bound.micros(), end_bound.micros()) {
+        (Some(start), Some(end)) if start == end => {
+            matches!(start_bound, ArbitraryMicrosBound::Included(_))
+                && matches!(end_bound, ArbitraryMicrosBound::Included(_))
+        }
+        (Some(start), Some(end)) => start < end,
+        _ => true,
+    }
+}
+
+fuzz_target!(|input: Input| {
+    let file = NamedTempFile::new().unwrap().into_temp_path();
+    let memory_log = MemoryStore::default();
+    let sqlite_log = SqliteStore::new(file, None).unwrap();
+    for timestamp in input.timestamps.iter() {
+        let entry = Entry::new_with_timestamp(*timestamp, "fuzz_check_bounds", vec![]);
+        memory_log.push(Cow::Borrowed(&entry)).unwrap();
+        sqlite_log.push(Cow::Owned(entry)).unwrap();
+    }
+
+    // every combination of inclusive and exclusive bounds with the same
+    // values should be accepted or rejected consistently
+    for (start_bound, end_bound) in [
+        (input.start_bound, input.end_bound),
+        (input.start_bound.toggled(), input.end_bound),
+        (input.start_bound, input.end_bound.toggled()),
+        (input.start_bound.toggled(), input.end_bound.toggled()),
+    ] {
+        let bounds = (start_bound.to_bound(), end_bound.to_bound());
+        match (
+            memory_log.range(bounds, None::<String>),
+            sqlite_log.range(bounds, None::<String>),
+        ) {
+            (Ok(memory_range), Ok(sqlite_range)) => {
+                assert!(is_valid(start_bound, end_bound), "accepted {:?}", bounds);
+                let expected = input
+                    .timestamps
+                    .iter()
+                    .filter(|timestamp| bounds.contains(*timestamp))
+                    .count();
+                assert_eq!(memory_range.count().unwrap(), expected as u64);
+                assert_eq!(sqlite_range.count().unwrap(), expected as u64);
+            }
+            (Err(Error::BadRange), Err(Error::BadRange)) => {
+                assert!(!is_valid(start_bound, end_bound), "rejected {:?}", bounds);
+            }
+            (memory_range, sqlite_range) => {
+                panic!(
+                    "memory and sqlite disagree on {:?}: {:?} vs {:?}",
+                    bounds,
+                    memory_range.map(|_| ()),
+                    sqlite_range.map(|_| ())
+                );
+            }
+        }
+    }
+});
@@ -6,6 +6,8 @@ use arbitrary::Arbitrary;
 use binlog::{Entry, MemoryStore, RedisStreamStore, Store, SubscribeableStore, Subscription};
 use libfuzzer_sys::fuzz_target;
 
+const AFTER_TIMEOUT: Duration = Duration::from_millis(10);
+
 macro_rules! cmp {
     ($memory_value:expr, $redis_value:expr) => {
         match ($memory_value, $redis_value) {
@@ -74,10 +76,15 @@ fuzz_target!(|ops: Vec<Op>| {
                         cmp!(memory_sub_value, redis_sub_value);
                     }
                     SubscribeAt::After => {
+                        // Subscriptions only see entries pushed after they're
+                        // created, so both should time out rather than replay
+                        // the push
+                        let timeout = Some(timeout.unwrap_or(AFTER_TIMEOUT).min(AFTER_TIMEOUT));
                         let mut memory_sub = memory_log.subscribe(name.clone()).unwrap();
                         let mut redis_sub = redis_log.subscribe(name).unwrap();
                         let memory_sub_value = memory_sub.next(timeout);
                         let redis_sub_value = redis_sub.next(timeout);
+                        assert!(matches!(memory_sub_value, Ok(None)));
                         cmp!(memory_sub_value, redis_sub_value);
                     }
                     _ => {}
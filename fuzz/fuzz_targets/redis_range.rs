@@ -0,0 +1,152 @@
+#![no_main]
+use std::borrow::Cow;
+use std::ops;
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use arbitrary::Arbitrary;
+use binlog::{Entry, Range, RangeableStore, RedisStreamStore, SqliteStore, Store};
+use libfuzzer_sys::fuzz_target;
+use redis::Commands;
+use tempfile::NamedTempFile;
+
+macro_rules! cmp_result {
+    ($sqlite_value:expr, $redis_value:expr) => {
+        match ($sqlite_value, $redis_value) {
+            (Err(sqlite_err), Err(redis_err)) => {
+                assert_eq!(format!("{:?}", sqlite_err), format!("{:?}", redis_err));
+                None
+            }
+            (Err(err), Ok(_)) => {
+                panic!("redis result ok, but sqlite result errored: {}", err)
+            }
+            (Ok(_), Err(err)) => {
+                panic!("sqlite result ok, but redis result errored: {}", err)
+            }
+            (Ok(sqlite_value), Ok(redis_value)) => Some((sqlite_value, redis_value)),
+        }
+    };
+}
+
+macro_rules! cmp {
+    ($sqlite_value:expr, $redis_value:expr) => {
+        match ($sqlite_value, $redis_value) {
+            (Ok(sqlite_value), Ok(redis_value)) => {
+                assert_eq!(sqlite_value, redis_value);
+            }
+            (sqlite_value, redis_value) => {
+                assert_eq!(format!("{:?}", sqlite_value), format!("{:?}", redis_value));
+            }
+        }
+    };
+}
+
+#[derive(Arbitrary, Clone, Debug, PartialEq)]
+enum Op {
+    Push(i64, String, Vec<u8>),
+    Len(ArbitraryMicrosRange, Option<String>),
+    Remove(ArbitraryMicrosRange, Option<String>),
+    Iter(ArbitraryMicrosRange, Option<String>),
+}
+
+#[derive(Arbitrary, Clone, Debug, PartialEq)]
+enum ArbitraryMicrosBound {
+    Included(i64),
+    Excluded(i64),
+    Unbounded,
+}
+
+impl ArbitraryMicrosBound {
+    fn to_bound(&self) -> ops::Bound<i64> {
+        match self {
+            ArbitraryMicrosBound::Included(micros) => ops::Bound::Included(*micros),
+            ArbitraryMicrosBound::Excluded(micros) => ops::Bound::Excluded(*micros),
+            ArbitraryMicrosBound::Unbounded => ops::Bound::Unbounded,
+        }
+    }
+}
+
+#[derive(Arbitrary, Clone, Debug, PartialEq)]
+struct ArbitraryMicrosRange {
+    start_bound: ArbitraryMicrosBound,
+    end_bound: ArbitraryMicrosBound,
+}
+
+impl ArbitraryMicrosRange {
+    fn to_bounds(&self) -> (ops::Bound<i64>, ops::Bound<i64>) {
+        (self.start_bound.to_bound(), self.end_bound.to_bound())
+    }
+}
+
+static RUNS: AtomicU64 = AtomicU64::new(0);
+
+fn sorted(entries: Vec<Entry>) -> Vec<Entry> {
+    // sqlite only orders by timestamp, so normalize the order of entries
+    // that share a timestamp
+    let mut entries = entries;
+    entries.sort_by(|a, b| (a.timestamp, &*a.name).cmp(&(b.timestamp, &*b.name)));
+    entries
+}
+
+fuzz_target!(|ops: Vec<Op>| {
+    let file = NamedTempFile::new().unwrap().into_temp_path();
+
+    // Stream IDs are never reused, so each run gets streams of its own,
+    // under a prefix no other run (or anything else in redis) uses
+    let key_prefix = format!(
+        "binlog:fuzz:redis_range:{}:{}:",
+        process::id(),
+        RUNS.fetch_add(1, Ordering::SeqCst)
+    );
+    let sqlite_log = SqliteStore::new(file, None).unwrap();
+    let redis_log = RedisStreamStore::new_with_prefix("redis://localhost:6379", &key_prefix)
+        .unwrap()
+        .with_max_len(None)
+        .with_timestamp_as_stream_id(true);
+
+    let get_ranges = |range: ArbitraryMicrosRange, name: Option<String>| {
+        let range = range.to_bounds();
+        let sqlite_range = sqlite_log.range(range, name.clone());
+        let redis_range = redis_log.range(range, name);
+        cmp_result!(sqlite_range, redis_range)
+    };
+
+    for op in ops {
+        match op {
+            Op::Push(timestamp, name, value) => {
+                // Redis rejects entries whose timestamps aren't increasing,
+                // so only mirror the entries it accepts.
+                let entry = Entry::new_with_timestamp(timestamp, name, value);
+                if redis_log.push(Cow::Borrowed(&entry)).is_ok() {
+                    sqlite_log.push(Cow::Owned(entry)).unwrap();
+                }
+            }
+            Op::Len(range, name) => {
+                if let Some((sqlite_range, redis_range)) = get_ranges(range, name) {
+                    cmp!(sqlite_range.count(), redis_range.count());
+                }
+            }
+            Op::Remove(range, name) => {
+                if let Some((sqlite_range, redis_range)) = get_ranges(range, name) {
+                    cmp!(sqlite_range.remove(), redis_range.remove());
+                }
+            }
+            Op::Iter(range, name) => {
+                if let Some((sqlite_range, redis_range)) = get_ranges(range, name) {
+                    if let Some((sqlite_iter, redis_iter)) = cmp_result!(sqlite_range.iter(), redis_range.iter()) {
+                        let sqlite_entries = sqlite_iter.collect::<Result<Vec<Entry>, _>>().map(sorted);
+                        let redis_entries = redis_iter.collect::<Result<Vec<Entry>, _>>().map(sorted);
+                        cmp!(sqlite_entries, redis_entries);
+                    }
+                }
+            }
+        }
+    }
+
+    let client = redis::Client::open("redis://localhost:6379").unwrap();
+    let mut conn = client.get_connection().unwrap();
+    let keys: Vec<String> = conn.scan_match(format!("{}*", key_prefix)).unwrap().collect();
+    if !keys.is_empty() {
+        conn.del::<_, ()>(keys).unwrap();
+    }
+});
@@ -76,6 +76,27 @@ impl ArbitraryMicrosRange {
     }
 }
 
+/// Both stores iterate by ascending timestamp, then ascending name, then
+/// insertion order. Entries can share a timestamp and name, or be pushed
+/// more than once, so only sequence numbers are strictly ascending.
+fn assert_ordered(previous: &Entry, entry: &Entry) {
+    let previous_key = (previous.timestamp, previous.name.as_ref());
+    let key = (entry.timestamp, entry.name.as_ref());
+    assert!(previous_key <= key, "out of order: {:?}, {:?}", previous, entry);
+    if previous_key == key {
+        if let (Some(previous_sequence_number), Some(sequence_number)) =
+            (previous.sequence_number, entry.sequence_number)
+        {
+            assert!(
+                previous_sequence_number < sequence_number,
+                "out of insertion order: {:?}, {:?}",
+                previous,
+                entry
+            );
+        }
+    }
+}
+
 fuzz_target!(|ops: Vec<Op>| {
     let file = NamedTempFile::new().unwrap().into_temp_path();
 
@@ -112,9 +133,16 @@ fuzz_target!(|ops: Vec<Op>| {
                     if let Some((mut memory_iter, mut sqlite_iter)) =
                         cmp_result!(memory_range.iter(), sqlite_range.iter())
                     {
+                        let mut previous: Option<Entry> = None;
                         loop {
                             match (memory_iter.next(), sqlite_iter.next()) {
                                 (Some(memory_value), Some(sqlite_value)) => {
+                                    if let Ok(entry) = &memory_value {
+                                        if let Some(previous) = &previous {
+                                            assert_ordered(previous, entry);
+                                        }
+                                        previous = Some(entry.clone());
+                                    }
                                     cmp!(memory_value, sqlite_value)
                                 }
                                 (Some(value), None) => {
@@ -0,0 +1,17 @@
+import pytest
+from binlog import binlog
+
+
+def test_new():
+    entry = binlog.Entry(1, "pytest_entry", [1, 2, 3])
+    assert entry.timestamp == 1
+    assert entry.name == "pytest_entry"
+    assert entry.value == [1, 2, 3]
+
+def test_as_tuple():
+    entry = binlog.Entry(1, "pytest_entry", [1, 2, 3])
+    assert entry.as_tuple() == (1, "pytest_entry", b"\x01\x02\x03")
+
+def test_name_too_long():
+    with pytest.raises(ValueError, match="invalid entry: name is longer than 1024 bytes"):
+        binlog.Entry(1, "a" * 1025, [1, 2, 3])
@@ -1,5 +1,7 @@
 import tempfile
+import threading
 import time
+import pytest
 from binlog import binlog
 
 
@@ -14,3 +16,36 @@ def test_pubsub():
     assert entry.value == sub_entry.value
     sub_entry = sub.next(0.01)
     assert sub_entry == None
+
+def test_max_value_len():
+    store = binlog.RedisStreamStore("redis://localhost:6379", max_len=1, max_value_len=2)
+    store.push(binlog.Entry(1, "pytest_redis_max_value_len", [1, 2]))
+    try:
+        store.push(binlog.Entry(2, "pytest_redis_max_value_len", [1, 2, 3]))
+        assert False, "expected an oversized value to be rejected"
+    except ValueError:
+        pass
+
+def test_iter():
+    store = binlog.RedisStreamStore("redis://localhost:6379")
+    sub = store.subscribe("pytest_redis_iter")
+    assert not sub.closed
+    store.push(binlog.Entry(1, "pytest_redis_iter", [1]))
+    assert next(iter(sub)).timestamp == 1
+
+    # closing from another thread ends iteration
+    timer = threading.Timer(0.2, sub.close)
+    timer.start()
+    assert list(sub) == []
+    timer.join()
+    assert sub.closed
+    with pytest.raises(StopIteration):
+        next(sub)
+    with pytest.raises(ValueError):
+        sub.next(0.01)
+
+def test_bad_timeout():
+    store = binlog.RedisStreamStore("redis://localhost:6379")
+    sub = store.subscribe("pytest_redis_bad_timeout")
+    with pytest.raises(ValueError):
+        sub.next(-1.0)
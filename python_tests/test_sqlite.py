@@ -1,4 +1,6 @@
+import sqlite3
 import tempfile
+import pytest
 from binlog import binlog
 
 
@@ -12,10 +14,12 @@ def test_remove():
         store = binlog.SqliteStore(f.name)
         insert_sample_data(store)
         assert store.range(None, None, None).count() == 10
+        assert store.count() == 10
         store.range(2, None, None).remove()
         assert store.range(None, None, None).count() == 1
         store.range(None, None, "pytest_sqlite").remove()
         assert store.range(None, None, None).count() == 0
+        assert store.range(None, None, None).is_empty()
 
 def test_iter():
     with tempfile.NamedTemporaryFile(suffix="binlog.db") as f:
@@ -29,6 +33,68 @@ def test_iter():
             assert result.name == "pytest_sqlite"
             assert result.value == [i]
 
+def test_empty_ranges():
+    with tempfile.NamedTemporaryFile(suffix="binlog.db") as f:
+        store = binlog.SqliteStore(f.name)
+        insert_sample_data(store)
+        for r in [store.range(None, None, "pytest_sqlite_missing"), store.range(11, None, None)]:
+            assert r.count() == 0
+            assert r.is_empty()
+            assert not r.consumed
+            assert list(r.iter()) == []
+            assert r.consumed
+            with pytest.raises(ValueError):
+                r.iter()
+        r = store.range(11, None, None)
+        r.remove()
+        assert r.consumed
+        assert store.count() == 10
+
+def test_protocols():
+    with tempfile.NamedTemporaryFile(suffix="binlog.db") as f:
+        store = binlog.SqliteStore(f.name)
+        assert len(store.range(None, None, None)) == 0
+        assert not store.range(None, None, None)
+        insert_sample_data(store)
+        r = store.range(2, 5, None)
+        assert len(r) == 3
+        assert r
+        assert [e.timestamp for e in r] == [2, 3, 4]
+        assert r.consumed
+        with pytest.raises(ValueError):
+            len(r)
+        with pytest.raises(ValueError):
+            bool(r)
+        with pytest.raises(ValueError):
+            iter(r)
+
+def test_iter_raises_errors():
+    with tempfile.NamedTemporaryFile(suffix="binlog.db") as f:
+        store = binlog.SqliteStore(f.name)
+        insert_sample_data(store)
+        conn = sqlite3.connect(f.name)
+        conn.execute("update log set codec = 'pytest_unknown' where ts = 3")
+        conn.commit()
+        conn.close()
+        # entries are read a page at a time, so the whole page fails
+        it = iter(store.range(None, None, None))
+        with pytest.raises(IOError):
+            next(it)
+
+def test_properties():
+    with tempfile.NamedTemporaryFile(suffix="binlog.db") as f:
+        store = binlog.SqliteStore(f.name)
+        assert store.get_property("owner") is None
+        store.set_property("owner", "a")
+        store.set_property("owner", "b")
+        store = binlog.SqliteStore(f.name)
+        assert store.get_property("owner") == "b"
+        info = store.info()
+        assert info.created_at > 0
+        assert info.created_by
+        assert info.schema_version > 0
+        assert info.properties == {"owner": "b"}
+
 def insert_sample_data(store):
     for i in range(1, 11):
         store.push(binlog.Entry(i, "pytest_sqlite", [i]))
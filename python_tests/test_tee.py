@@ -0,0 +1,45 @@
+import tempfile
+import pytest
+from binlog import binlog
+
+
+def test_push():
+    with tempfile.NamedTemporaryFile(suffix="binlog.db") as f:
+        redis_store = binlog.RedisStreamStore("redis://localhost:6379")
+        sqlite_store = binlog.SqliteStore(f.name)
+        store = binlog.TeeStore(redis_store, sqlite_store, "durable", 100, 0.1)
+        sub = store.subscribe("pytest_tee_push")
+        entry = binlog.Entry(1, "pytest_tee_push", [1, 2, 3])
+        store.push(entry)
+        assert store.queued() == 0
+        sub_entry = sub.next(None)
+        assert entry.timestamp == sub_entry.timestamp
+        assert entry.value == sub_entry.value
+        assert sqlite_store.range(None, None, "pytest_tee_push").count() == 1
+
+def test_bad_policy():
+    with tempfile.NamedTemporaryFile(suffix="binlog.db") as f:
+        redis_store = binlog.RedisStreamStore("redis://localhost:6379")
+        sqlite_store = binlog.SqliteStore(f.name)
+        try:
+            binlog.TeeStore(redis_store, sqlite_store, "sometimes")
+            assert False
+        except ValueError:
+            pass
+
+def test_latest():
+    with tempfile.NamedTemporaryFile(suffix="binlog.db") as f:
+        redis_store = binlog.RedisStreamStore("redis://localhost:6379")
+        sqlite_store = binlog.SqliteStore(f.name)
+        store = binlog.TeeStore(redis_store, sqlite_store, "primary")
+        entry = binlog.Entry(1, "pytest_tee_latest", [1, 2, 3])
+        store.push(entry)
+        latest = store.latest("pytest_tee_latest")
+        assert latest.as_tuple() == entry.as_tuple()
+
+def test_bad_retry_interval():
+    with tempfile.NamedTemporaryFile(suffix="binlog.db") as f:
+        redis_store = binlog.RedisStreamStore("redis://localhost:6379")
+        sqlite_store = binlog.SqliteStore(f.name)
+        with pytest.raises(ValueError):
+            binlog.TeeStore(redis_store, sqlite_store, "durable", 100, -1.0)
@@ -5,7 +5,9 @@ set -ex
 rust_variant=$1
 os=$2
 
-cargo test --features=redis-store,sqlite-store
+cargo test --features=redis-store,redis-sentinel,sqlite-store,serde,time,diff,testkit,shmem,postcard
+cargo test --no-default-features --features=sqlite-store
+cargo test --features=sqlite-cipher
 
 if [ "$os" == "ubuntu-latest" ]; then
     if [ "$rust_variant" == "stable" ]; then
@@ -0,0 +1,85 @@
+use std::error::Error as StdError;
+use std::io::{Error as IoError, ErrorKind as IoErrorKind};
+
+use crate::{Entry, Error};
+
+use arrow2::array::{Array, BinaryArray, Int64Array, Utf8Array};
+use arrow2::chunk::Chunk;
+use arrow2::datatypes::{DataType, Field, Schema};
+
+fn invalid_data_err<E: Into<Box<dyn StdError + Send + Sync>>>(msg: E) -> Error {
+    IoError::new(IoErrorKind::InvalidData, msg).into()
+}
+
+fn column<'a, A: Array + 'static>(batch: &'a Chunk<Box<dyn Array>>, idx: usize, name: &str) -> Result<&'a A, Error> {
+    let array = batch
+        .arrays()
+        .get(idx)
+        .ok_or_else(|| invalid_data_err(format!("record batch is missing the `{}` column", name)))?;
+    if array.null_count() > 0 {
+        return Err(invalid_data_err(format!("`{}` column contains nulls", name)));
+    }
+    array
+        .as_any()
+        .downcast_ref::<A>()
+        .ok_or_else(|| invalid_data_err(format!("`{}` column has an unexpected type", name)))
+}
+
+/// The schema of record batches produced by `entries_to_record_batch`.
+pub fn record_batch_schema() -> Schema {
+    Schema::from(vec![
+        Field::new("ts", DataType::Int64, false),
+        Field::new("name", DataType::Utf8, false),
+        Field::new("value", DataType::Binary, false),
+    ])
+}
+
+/// Converts entries into a columnar record batch, with the columns `ts`,
+/// `name` and `value`.
+pub fn entries_to_record_batch(entries: &[Entry]) -> Chunk<Box<dyn Array>> {
+    let ts = Int64Array::from_vec(entries.iter().map(|e| e.timestamp).collect());
+    let name = Utf8Array::<i32>::from_iter_values(entries.iter().map(|e| e.name.as_ref()));
+    let value = BinaryArray::<i32>::from_iter_values(entries.iter().map(|e| e.value.as_slice()));
+    Chunk::new(vec![ts.boxed(), name.boxed(), value.boxed()])
+}
+
+/// Converts a record batch produced by `entries_to_record_batch` (or one
+/// with the same schema) back into entries.
+pub fn record_batch_to_entries(batch: &Chunk<Box<dyn Array>>) -> Result<Vec<Entry>, Error> {
+    let ts = column::<Int64Array>(batch, 0, "ts")?;
+    let name = column::<Utf8Array<i32>>(batch, 1, "name")?;
+    let value = column::<BinaryArray<i32>>(batch, 2, "value")?;
+
+    let entries = ts
+        .values_iter()
+        .zip(name.values_iter())
+        .zip(value.values_iter())
+        .map(|((ts, name), value)| Entry::new_with_timestamp(*ts, name, value.to_vec()))
+        .collect();
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{entries_to_record_batch, record_batch_to_entries};
+    use crate::Entry;
+    use arrow2::array::{Array, Int64Array};
+    use arrow2::chunk::Chunk;
+
+    #[test]
+    fn round_trip() {
+        let entries: Vec<Entry> = (0..100u8)
+            .map(|i| Entry::new_with_timestamp(i.into(), format!("arrow_{}", i % 3), vec![i; i as usize]))
+            .collect();
+        let batch = entries_to_record_batch(&entries);
+        assert_eq!(batch.len(), 100);
+        assert_eq!(batch.arrays().len(), 3);
+        assert_eq!(record_batch_to_entries(&batch).unwrap(), entries);
+    }
+
+    #[test]
+    fn bad_batch() {
+        let batch: Chunk<Box<dyn Array>> = Chunk::new(vec![Int64Array::from_vec(vec![1]).boxed()]);
+        assert!(record_batch_to_entries(&batch).is_err());
+    }
+}
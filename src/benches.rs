@@ -1,4 +1,6 @@
+use std::alloc::{GlobalAlloc, Layout, System};
 use std::borrow::Cow;
+use std::cell::Cell;
 use std::thread;
 
 use crate::{Entry, Range, RangeableStore, Store};
@@ -6,14 +8,51 @@ use crate::{Entry, Range, RangeableStore, Store};
 use string_cache::DefaultAtom as Atom;
 use test::Bencher;
 
-/// Defines a benchmark function.
+/// Wraps the system allocator to count each thread's allocations, so that
+/// benches can check how many allocations an operation makes.
+pub struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATIONS: Cell<u64> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // The counter may already be gone while a thread shuts down
+        let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[cfg(test)]
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// The number of allocations made by the current thread so far, if
+/// `CountingAllocator` is the global allocator.
+pub fn allocations() -> u64 {
+    ALLOCATIONS.with(Cell::get)
+}
+
+/// Defines a benchmark function. The store constructor is called with a
+/// `TempFiles` that outlives the store.
 #[doc(hidden)]
 #[macro_export]
 macro_rules! define_bench {
     ($name:ident, $store_constructor:expr) => {
         #[bench]
         fn $name(b: &mut test::Bencher) {
-            let store = $store_constructor;
+            let files = $crate::testkit::TempFiles::new();
+            let store = ($store_constructor)(&files);
             $crate::benches::$name(b, &store);
         }
     };
@@ -24,6 +63,7 @@ macro_rules! define_bench {
 macro_rules! bench_store_impl {
     ($code:expr) => {
         define_bench!(push, $code);
+        define_bench!(push_parts, $code);
         define_bench!(push_parallel, $code);
         define_bench!(latest, $code);
     };
@@ -44,6 +84,39 @@ pub fn push<S: Store>(b: &mut Bencher, store: &S) {
     });
 }
 
+pub fn push_parts<S: Store>(b: &mut Bencher, store: &S) {
+    let name = Atom::from("bench_push_parts");
+    let mut value = Vec::with_capacity(3);
+    let mut timestamp = 0;
+    b.iter(|| {
+        value.clear();
+        value.extend_from_slice(&[1, 2, 3]);
+        timestamp += 1;
+        store.push_parts(timestamp, &name, &value).unwrap();
+    });
+}
+
+/// Counts the allocations each `push_parts` call makes on average, reusing
+/// a buffer for values.
+pub fn push_parts_allocations<S: Store>(store: &S) -> f64 {
+    let name = Atom::from("bench_push_parts_allocations");
+    let mut value = Vec::with_capacity(3);
+    let mut push = |timestamp: i64| {
+        value.clear();
+        value.extend_from_slice(&[1, 2, 3]);
+        store.push_parts(timestamp, &name, &value).unwrap();
+    };
+    // warm up caches, e.g. of prepared statements
+    for timestamp in 0..100 {
+        push(timestamp);
+    }
+    let start = allocations();
+    for timestamp in 100..1100 {
+        push(timestamp);
+    }
+    (allocations() - start) as f64 / 1000.0
+}
+
 pub fn push_parallel<S: Store + Clone + 'static>(b: &mut Bencher, store: &S) {
     b.iter(|| {
         let mut threads = Vec::default();
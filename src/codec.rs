@@ -0,0 +1,207 @@
+use std::borrow::Cow;
+
+use crate::Error;
+
+/// Values shorter than this are stored uncompressed, since compressing them
+/// rarely saves space.
+pub static MIN_SIZE_TO_COMPRESS: usize = 32;
+
+/// Compresses and decompresses entry values. Stores record the name of the
+/// codec that produced each value, so values remain readable after switching
+/// codecs.
+pub trait Codec: Send + Sync {
+    /// A unique name for the codec. This is persisted alongside values, so it
+    /// must not change.
+    fn name(&self) -> &str;
+    fn compress(&self, value: &[u8]) -> Result<Vec<u8>, Error>;
+    /// Decompresses a blob, given the length of the original value.
+    fn decompress(&self, blob: &[u8], size: usize) -> Result<Vec<u8>, Error>;
+}
+
+/// A codec that stores values as-is.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoopCodec;
+
+impl NoopCodec {
+    pub const NAME: &'static str = "none";
+}
+
+impl Codec for NoopCodec {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn compress(&self, value: &[u8]) -> Result<Vec<u8>, Error> {
+        Ok(value.to_vec())
+    }
+
+    fn decompress(&self, blob: &[u8], _size: usize) -> Result<Vec<u8>, Error> {
+        Ok(blob.to_vec())
+    }
+}
+
+/// A codec that compresses values with zstd.
+#[cfg(feature = "compression")]
+#[derive(Clone, Copy, Debug)]
+pub struct ZstdCodec {
+    level: i32,
+}
+
+#[cfg(feature = "compression")]
+impl ZstdCodec {
+    pub const NAME: &'static str = "zstd";
+    pub const DEFAULT_LEVEL: i32 = 1;
+
+    pub fn new(level: i32) -> Self {
+        Self { level }
+    }
+}
+
+#[cfg(feature = "compression")]
+impl Default for ZstdCodec {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_LEVEL)
+    }
+}
+
+#[cfg(feature = "compression")]
+impl Codec for ZstdCodec {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn compress(&self, value: &[u8]) -> Result<Vec<u8>, Error> {
+        Ok(zstd::bulk::compress(value, self.level)?)
+    }
+
+    fn decompress(&self, blob: &[u8], size: usize) -> Result<Vec<u8>, Error> {
+        Ok(zstd::bulk::decompress(blob, size)?)
+    }
+}
+
+/// Encodes a value the way `SqliteStore` stores it, so that other stores can
+/// produce compatible blobs. Returns the blob, the value's size if it was
+/// compressed (or 0 if it's stored as-is), and the name of the codec to
+/// record alongside the blob. Values that would grow when compressed, e.g.
+/// because they're already compressed or random, are stored as-is.
+pub fn encode_value<'a, 'c>(codec: &'c dyn Codec, value: &'a [u8]) -> Result<(Cow<'a, [u8]>, usize, &'c str), Error> {
+    if value.len() >= MIN_SIZE_TO_COMPRESS && codec.name() != NoopCodec::NAME {
+        let blob = codec.compress(value)?;
+        if blob.len() <= value.len() {
+            return Ok((Cow::Owned(blob), value.len(), codec.name()));
+        }
+    }
+    Ok((Cow::Borrowed(value), 0, NoopCodec::NAME))
+}
+
+/// Decodes a blob produced by `encode_value`, given the codec name and size
+/// recorded alongside it. Blobs are decoded with `codec` if it has that name,
+/// or otherwise one of the built-in codecs.
+pub fn decode_value(codec: &dyn Codec, codec_name: &str, size: usize, blob: Vec<u8>) -> Result<Vec<u8>, Error> {
+    if codec_name == NoopCodec::NAME {
+        return Ok(blob);
+    }
+    decompress_value(codec, codec_name, size, &blob)
+}
+
+/// Like `decode_value`, but borrows the blob, e.g. so it's kept if decoding
+/// fails. Blobs stored as-is are copied.
+pub(crate) fn decompress_value(
+    codec: &dyn Codec,
+    codec_name: &str,
+    size: usize,
+    blob: &[u8],
+) -> Result<Vec<u8>, Error> {
+    if codec_name == NoopCodec::NAME {
+        return Ok(blob.to_vec());
+    } else if codec_name == codec.name() {
+        return codec.decompress(blob, size);
+    }
+    #[cfg(feature = "compression")]
+    if codec_name == ZstdCodec::NAME {
+        return ZstdCodec::default().decompress(blob, size);
+    }
+    Err(Error::UnsupportedCodec(codec_name.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_value, encode_value, Codec, NoopCodec, MIN_SIZE_TO_COMPRESS};
+    use crate::Error;
+
+    fn round_trip<C: Codec>(codec: C) {
+        let value: Vec<u8> = (0..1000u32).map(|i| (i % 7) as u8).collect();
+        let blob = codec.compress(&value).unwrap();
+        assert_eq!(codec.decompress(&blob, value.len()).unwrap(), value);
+    }
+
+    #[test]
+    fn noop() {
+        round_trip(NoopCodec);
+    }
+
+    fn encode_round_trip<C: Codec>(codec: C) {
+        for len in [0, MIN_SIZE_TO_COMPRESS - 1, MIN_SIZE_TO_COMPRESS, 1000] {
+            let value: Vec<u8> = (0..len).map(|i| (i % 7) as u8).collect();
+            let (blob, size, codec_name) = encode_value(&codec, &value).unwrap();
+            if len < MIN_SIZE_TO_COMPRESS || codec.name() == NoopCodec::NAME {
+                assert_eq!((&*blob, size, codec_name), (&value[..], 0, NoopCodec::NAME));
+            } else if codec_name == NoopCodec::NAME {
+                // values that would grow are stored as-is
+                assert_eq!((&*blob, size), (&value[..], 0));
+            } else {
+                assert_eq!((size, codec_name), (len, codec.name()));
+                assert!(blob.len() <= len);
+            }
+            // blobs are readable regardless of the reader's codec
+            let blob = blob.into_owned();
+            assert_eq!(decode_value(&codec, codec_name, size, blob.clone()).unwrap(), value);
+            assert_eq!(decode_value(&NoopCodec, codec_name, size, blob).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn encode_value_noop() {
+        encode_round_trip(NoopCodec);
+        assert!(matches!(
+            decode_value(&NoopCodec, "unknown", 3, vec![1, 2, 3]),
+            Err(Error::UnsupportedCodec(name)) if name == "unknown"
+        ));
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn zstd() {
+        round_trip(super::ZstdCodec::default());
+        assert!(super::ZstdCodec::default().decompress(&[1, 2, 3], 3).is_err());
+        encode_round_trip(super::ZstdCodec::default());
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn encode_value_incompressible() {
+        let codec = super::ZstdCodec::default();
+        let value: Vec<u8> = (0..1000u32).map(|i| (i % 7) as u8).collect();
+        let (blob, size, codec_name) = encode_value(&codec, &value).unwrap();
+        assert_eq!((size, codec_name), (1000, super::ZstdCodec::NAME));
+        assert!(blob.len() < 1000);
+
+        // a xorshift sequence, which zstd's framing makes bigger
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let value: Vec<u8> = (0..1000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+        assert!(codec.compress(&value).unwrap().len() > value.len());
+        let (blob, size, codec_name) = encode_value(&codec, &value).unwrap();
+        assert_eq!((&*blob, size, codec_name), (&value[..], 0, NoopCodec::NAME));
+        assert_eq!(
+            decode_value(&codec, codec_name, size, blob.into_owned()).unwrap(),
+            value
+        );
+    }
+}
@@ -0,0 +1,114 @@
+use crate::{Entry, Error};
+
+/// The difference between two entries, e.g. consecutive entries with the
+/// same name.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EntryDiff {
+    /// How much later the new entry is than the old one, in microseconds.
+    pub timestamp_delta: i64,
+    /// Whether the values differ.
+    pub value_changed: bool,
+    /// A bsdiff patch that turns the old value into the new one. This is only
+    /// produced with the `diff` feature enabled, and only when the value
+    /// changed.
+    pub patch: Option<Vec<u8>>,
+}
+
+/// Compares two entries.
+pub fn diff(old: &Entry, new: &Entry) -> EntryDiff {
+    let value_changed = old.value != new.value;
+    EntryDiff {
+        timestamp_delta: new.timestamp.wrapping_sub(old.timestamp),
+        value_changed,
+        patch: if value_changed {
+            patch(&old.value, &new.value)
+        } else {
+            None
+        },
+    }
+}
+
+#[cfg(feature = "diff")]
+fn patch(old: &[u8], new: &[u8]) -> Option<Vec<u8>> {
+    let mut patch = Vec::default();
+    bsdiff::diff(old, new, &mut patch).expect("writing to a vec cannot fail");
+    Some(patch)
+}
+
+#[cfg(not(feature = "diff"))]
+fn patch(_old: &[u8], _new: &[u8]) -> Option<Vec<u8>> {
+    None
+}
+
+impl Entry {
+    /// Recreates the new entry of a diff from the old one. Fails with
+    /// `Error::InvalidEntry` if the value changed but the diff has no patch,
+    /// or the patch can't be applied.
+    pub fn apply_patch(base: &Entry, diff: &EntryDiff) -> Result<Entry, Error> {
+        let timestamp = base.timestamp.wrapping_add(diff.timestamp_delta);
+        let value = match (diff.value_changed, &diff.patch) {
+            (false, _) => base.value.clone(),
+            (true, Some(patch)) => apply(&base.value, patch)?,
+            (true, None) => return Err(Error::InvalidEntry("diff has no patch".to_string())),
+        };
+        Ok(Entry::new_with_timestamp(timestamp, base.name.clone(), value))
+    }
+}
+
+#[cfg(feature = "diff")]
+fn apply(base: &[u8], mut patch: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut value = Vec::default();
+    bsdiff::patch(base, &mut patch, &mut value)
+        .map_err(|err| Error::InvalidEntry(format!("could not apply patch: {}", err)))?;
+    Ok(value)
+}
+
+#[cfg(not(feature = "diff"))]
+fn apply(_base: &[u8], _patch: &[u8]) -> Result<Vec<u8>, Error> {
+    Err(Error::InvalidEntry("patches require the diff feature".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{diff, EntryDiff};
+    use crate::{Entry, Error};
+
+    #[test]
+    fn unchanged() {
+        let old = Entry::new_with_timestamp(10, "test_diff", vec![1, 2, 3]);
+        let new = Entry::new_with_timestamp(15, "test_diff", vec![1, 2, 3]);
+        let entry_diff = diff(&old, &new);
+        assert_eq!(
+            entry_diff,
+            EntryDiff {
+                timestamp_delta: 5,
+                value_changed: false,
+                patch: None,
+            }
+        );
+        assert_eq!(Entry::apply_patch(&old, &entry_diff).unwrap(), new);
+        assert_eq!(diff(&new, &old).timestamp_delta, -5);
+    }
+
+    #[test]
+    fn changed() {
+        let old = Entry::new_with_timestamp(i64::MIN, "test_diff", b"the quick brown fox".to_vec());
+        let new = Entry::new_with_timestamp(i64::MAX, "test_diff", b"the quick red fox jumps".to_vec());
+        let entry_diff = diff(&old, &new);
+        assert!(entry_diff.value_changed);
+
+        #[cfg(feature = "diff")]
+        assert_eq!(Entry::apply_patch(&old, &entry_diff).unwrap(), new);
+        #[cfg(not(feature = "diff"))]
+        assert!(entry_diff.patch.is_none());
+
+        let entry_diff = EntryDiff {
+            patch: None,
+            ..entry_diff
+        };
+        assert!(matches!(
+            Entry::apply_patch(&old, &entry_diff),
+            Err(Error::InvalidEntry(_))
+        ));
+    }
+}
@@ -1,23 +1,129 @@
+use std::borrow::Cow;
+use std::cell::{OnceCell, RefCell};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+#[cfg(feature = "compression")]
+use std::io::{Error as IoError, ErrorKind as IoErrorKind};
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use crate::codec::{decode_value, decompress_value};
+#[cfg(feature = "compression")]
+use crate::ZstdCodec;
+use crate::{Clock, Codec, Error, NoopCodec};
+
+#[cfg(feature = "serde")]
+use serde::{de::DeserializeOwned, Serialize};
 use string_cache::DefaultAtom as Atom;
+#[cfg(feature = "time")]
+use time::OffsetDateTime;
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+/// The maximum length of an entry name, in bytes.
+pub const MAX_NAME_LEN: usize = 1024;
+/// The name of unnamed entries, e.g. for stores that hold a single stream.
+/// Unnamed entries are otherwise like any other: ranges over every name
+/// include them, and ranges, `latest` and subscriptions for `UNNAMED` only
+/// see them.
+pub const UNNAMED: &str = "";
+/// The maximum size of an entry value, in bytes. This is the largest value
+/// all of the built-in stores can hold (redis caps strings at 512mb.)
+pub const MAX_VALUE_LEN: usize = 512 * 1024 * 1024;
+/// The metadata key `EntryBuilder::ttl` records the time to live under, in
+/// microseconds.
+pub const TTL_METADATA_KEY: &str = "ttl";
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub(crate) fn now_micros() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("great scott!!")
+        .as_micros()
+        .try_into()
+        .expect("great scott!!")
+}
+
+/// `SystemTime::now` panics in browsers, so ask javascript instead. It only
+/// has millisecond precision, so entries pushed within the same millisecond
+/// share a timestamp.
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+pub(crate) fn now_micros() -> i64 {
+    (js_sys::Date::now() * 1000.0) as i64
+}
+
+/// Entries are non-exhaustive, since stores attach fields to the entries
+/// they return; create them via `Entry::new` and friends.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
 pub struct Entry {
     pub timestamp: i64,
     pub name: Atom,
     pub value: Vec<u8>,
+    /// The order in which the entry was pushed, relative to other entries in
+    /// the same store. This is populated by stores that track insertion
+    /// order, and is otherwise `None`.
+    pub sequence_number: Option<u64>,
+    /// Whether the entry's timestamp was outside the bounds allowed by the
+    /// store's `StoreOptions` when it was pushed, under
+    /// `SkewPolicy::Flag`. Like sequence numbers, this is not considered for
+    /// equality.
+    pub skewed: bool,
+}
+
+impl PartialEq for Entry {
+    /// Sequence numbers and skew flags are assigned by stores, so they are
+    /// not considered for equality.
+    fn eq(&self, other: &Self) -> bool {
+        self.timestamp == other.timestamp && self.name == other.name && self.value == other.value
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    /// Orders entries by timestamp, then name, then sequence number, then
+    /// value, so that entries with the same timestamp and name are ordered
+    /// by insertion. Entries without a sequence number sort before those
+    /// with one, which keeps the order total. Unlike equality, this
+    /// considers sequence numbers, so the same entry pushed twice is equal
+    /// to itself but ordered by when each copy was pushed.
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.timestamp
+            .cmp(&other.timestamp)
+            .then_with(|| self.name.as_ref().cmp(other.name.as_ref()))
+            .then_with(|| self.sequence_number.cmp(&other.sequence_number))
+            .then_with(|| self.value.cmp(&other.value))
+    }
 }
 
 impl Entry {
+    /// Creates an entry timestamped with the system clock.
     pub fn new<A: Into<Atom>>(name: A, value: Vec<u8>) -> Entry {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("great scott!!")
-            .as_micros()
-            .try_into()
-            .expect("great scott!!");
-        Self::new_with_timestamp(now, name.into(), value)
+        Self::new_with_timestamp(now_micros(), name.into(), value)
+    }
+
+    /// Creates an entry timestamped with `clock`, e.g. a `ManualClock` in
+    /// tests.
+    pub fn new_with_clock<A: Into<Atom>>(clock: &dyn Clock, name: A, value: Vec<u8>) -> Entry {
+        Self::new_with_timestamp(clock.now_micros(), name.into(), value)
+    }
+
+    /// Creates an unnamed entry timestamped with the system clock.
+    pub fn new_unnamed(value: Vec<u8>) -> Entry {
+        Self::new(UNNAMED, value)
+    }
+
+    /// Creates an unnamed entry with the given timestamp.
+    pub fn new_unnamed_with_timestamp(timestamp: i64, value: Vec<u8>) -> Entry {
+        Self::new_with_timestamp(timestamp, UNNAMED, value)
     }
 
     pub fn new_with_timestamp<A: Into<Atom>>(timestamp: i64, name: A, value: Vec<u8>) -> Entry {
@@ -25,6 +131,686 @@ impl Entry {
             timestamp,
             name: name.into(),
             value,
+            sequence_number: None,
+            skewed: false,
+        }
+    }
+
+    /// Creates an entry from its timestamp, name and value, i.e. the
+    /// inverse of `into_parts`.
+    pub fn from_parts<A: Into<Atom>>(timestamp: i64, name: A, value: Vec<u8>) -> Entry {
+        Self::new_with_timestamp(timestamp, name, value)
+    }
+
+    /// Splits the entry into its timestamp, name and value, e.g. for
+    /// `let (timestamp, name, value) = entry.into_parts();`.
+    pub fn into_parts(self) -> (i64, Atom, Vec<u8>) {
+        (self.timestamp, self.name, self.value)
+    }
+
+    /// Like `into_parts`, but borrows the name and value.
+    pub fn as_parts(&self) -> (i64, &Atom, &[u8]) {
+        (self.timestamp, &self.name, &self.value)
+    }
+
+    /// Returns the entry's name, without depending on how names are
+    /// interned.
+    pub fn name(&self) -> &str {
+        self.name.as_ref()
+    }
+
+    /// Whether the entry's name is `UNNAMED`.
+    pub fn is_unnamed(&self) -> bool {
+        self.name.is_empty()
+    }
+
+    /// Creates a builder, which validates the entry when it's built.
+    pub fn builder() -> EntryBuilder {
+        EntryBuilder::default()
+    }
+
+    /// Checks that the entry's name and value are within the limits that
+    /// all stores support. Timestamps are not checked; negative values are
+    /// allowed. Stores don't validate entries when they're pushed, so
+    /// entries that aren't built via `Entry::builder` should be checked with
+    /// this first.
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.name.len() > MAX_NAME_LEN {
+            return Err(Error::InvalidEntry(format!(
+                "name is longer than {} bytes",
+                MAX_NAME_LEN
+            )));
+        }
+        if self.value.len() > MAX_VALUE_LEN {
+            return Err(Error::InvalidEntry(format!(
+                "value is larger than {} bytes",
+                MAX_VALUE_LEN
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "time")]
+impl Entry {
+    /// Creates an entry timestamped at `datetime`, rounded down to the
+    /// microsecond.
+    pub fn new_with_offset_datetime<A: Into<Atom>>(datetime: OffsetDateTime, name: A, value: Vec<u8>) -> Entry {
+        Self::new_with_timestamp(crate::utils::offset_datetime_micros(&datetime), name, value)
+    }
+
+    /// Returns the entry's timestamp as a UTC datetime. Fails with
+    /// `Error::TimeTooLarge` if the timestamp is outside the range
+    /// `OffsetDateTime` supports.
+    pub fn offset_datetime(&self) -> Result<OffsetDateTime, Error> {
+        OffsetDateTime::from_unix_timestamp_nanos(i128::from(self.timestamp) * 1000).map_err(|_| Error::TimeTooLarge)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Entry {
+    /// Creates an entry whose value is `value` serialized as JSON.
+    pub fn from_value<A: Into<Atom>, T: Serialize + ?Sized>(
+        timestamp: i64,
+        name: A,
+        value: &T,
+    ) -> Result<Entry, serde_json::Error> {
+        Ok(Self::new_with_timestamp(timestamp, name, serde_json::to_vec(value)?))
+    }
+
+    /// Deserializes the entry's value from JSON.
+    pub fn parse_value_as<T: DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
+        serde_json::from_slice(&self.value)
+    }
+}
+
+/// Key-value pairs attached to an entry when it's pushed, e.g. routing or
+/// source information, without embedding them in the value. See
+/// `Store::push_with_metadata`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EntryMetadata(pub HashMap<String, String>);
+
+/// An entry read back from a store, alongside metadata about how it was
+/// stored. Returned by `iter_with_metadata` on ranges of stores that track
+/// it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StoredEntry {
+    pub entry: Entry,
+    /// When the entry was written, in microseconds since the unix epoch,
+    /// according to the store's clock. This is `None` for entries written
+    /// before the store tracked it.
+    pub ingested_at: Option<i64>,
+    /// The store's ID for the entry, which matches its sequence number.
+    pub id: u64,
+    /// The metadata the entry was pushed with, if any.
+    pub metadata: Option<EntryMetadata>,
+}
+
+impl StoredEntry {
+    /// Returns how long after its timestamp the entry was written, in
+    /// microseconds. This is negative for entries dated in the future.
+    pub fn ingestion_lag(&self) -> Option<i64> {
+        self.ingested_at
+            .map(|ingested_at| ingested_at.saturating_sub(self.entry.timestamp))
+    }
+}
+
+/// Percentiles of how long after their timestamps entries were written, in
+/// microseconds, e.g. to monitor ingestion lag. Entries without an ingestion
+/// time aren't counted. Percentiles are by nearest rank, and are all zero if
+/// no entries are counted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct IngestionLagStats {
+    /// The number of entries counted.
+    pub count: u64,
+    pub p50: i64,
+    pub p90: i64,
+    pub p99: i64,
+    pub max: i64,
+}
+
+impl IngestionLagStats {
+    /// Computes the ingestion lag percentiles of stored entries.
+    pub fn from_entries<'a, I: IntoIterator<Item = &'a StoredEntry>>(entries: I) -> Self {
+        Self::from_lags(entries.into_iter().filter_map(StoredEntry::ingestion_lag).collect())
+    }
+
+    /// Like `from_entries`, but for ranges, which yield entries one at a
+    /// time so only their lags need to be kept.
+    pub(crate) fn from_range<I: Iterator<Item = Result<StoredEntry, Error>>>(entries: I) -> Result<Self, Error> {
+        let mut lags = Vec::default();
+        for entry in entries {
+            lags.extend(entry?.ingestion_lag());
+        }
+        Ok(Self::from_lags(lags))
+    }
+
+    fn from_lags(mut lags: Vec<i64>) -> Self {
+        lags.sort_unstable();
+        let percentile = |p: usize| match lags.len() {
+            0 => 0,
+            len => lags[(len * p).div_ceil(100).max(1) - 1],
+        };
+        Self {
+            count: lags.len() as u64,
+            p50: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99),
+            max: lags.last().copied().unwrap_or(0),
+        }
+    }
+}
+
+/// An entry with its value compressed with zstd, e.g. to hold more entries
+/// in memory. See `CompressedMemoryStore`.
+#[cfg(feature = "compression")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompressedEntry {
+    /// The entry, with its value replaced by the compressed value.
+    entry: Entry,
+    decompressed_size: usize,
+}
+
+#[cfg(feature = "compression")]
+impl CompressedEntry {
+    /// Compresses an entry's value at a zstd compression level.
+    pub fn new(mut entry: Entry, level: i32) -> Result<Self, Error> {
+        let decompressed_size = entry.value.len();
+        entry.value = ZstdCodec::new(level).compress(&entry.value)?;
+        Ok(Self {
+            entry,
+            decompressed_size,
+        })
+    }
+
+    /// Wraps an entry whose value was compressed by `new`, reading the
+    /// value's original size from the zstd frame.
+    pub(crate) fn from_compressed(entry: Entry) -> Result<Self, Error> {
+        let decompressed_size = zstd::zstd_safe::get_frame_content_size(&entry.value);
+        if decompressed_size >= zstd::zstd_safe::CONTENTSIZE_ERROR {
+            return Err(IoError::new(IoErrorKind::InvalidData, "value is not a sized zstd frame").into());
+        }
+        Ok(Self {
+            entry,
+            decompressed_size: decompressed_size as usize,
+        })
+    }
+
+    /// The entry as compressed.
+    pub(crate) fn as_entry(&self) -> &Entry {
+        &self.entry
+    }
+
+    /// Returns the entry with its value decompressed.
+    pub fn decompress(&self) -> Result<Entry, Error> {
+        let value = ZstdCodec::default().decompress(&self.entry.value, self.decompressed_size)?;
+        Ok(Entry {
+            value,
+            name: self.entry.name.clone(),
+            ..self.entry
+        })
+    }
+
+    pub fn compressed_size(&self) -> usize {
+        self.entry.value.len()
+    }
+
+    pub fn decompressed_size(&self) -> usize {
+        self.decompressed_size
+    }
+}
+
+/// An entry whose value is only decoded when it's first read, yielded by
+/// `Range::iter_lazy`.
+#[derive(Debug)]
+pub struct LazyEntry {
+    pub timestamp: i64,
+    pub name: Atom,
+    pub value: LazyValue,
+    /// As in `Entry`.
+    pub sequence_number: Option<u64>,
+    /// As in `Entry`.
+    pub skewed: bool,
+}
+
+impl LazyEntry {
+    /// Decodes the value, returning the entry as `iter` would have.
+    pub fn into_entry(self) -> Result<Entry, Error> {
+        Ok(Entry {
+            timestamp: self.timestamp,
+            name: self.name,
+            value: self.value.into_vec()?,
+            sequence_number: self.sequence_number,
+            skewed: self.skewed,
+        })
+    }
+}
+
+/// Wraps an already decoded entry.
+impl From<Entry> for LazyEntry {
+    fn from(entry: Entry) -> Self {
+        Self {
+            timestamp: entry.timestamp,
+            name: entry.name,
+            value: LazyValue::from(entry.value),
+            sequence_number: entry.sequence_number,
+            skewed: entry.skewed,
+        }
+    }
+}
+
+/// A value as stored, which is decoded (e.g. decompressed) the first time
+/// it's read via `get`, and kept decoded afterwards. Decoding errors are
+/// returned by `get` rather than when the entry is read, and decoding is
+/// retried on every call until it succeeds. The encoded value is dropped
+/// once it's been decoded.
+pub struct LazyValue {
+    decoded: OnceCell<Vec<u8>>,
+    encoded: RefCell<Option<EncodedValue>>,
+}
+
+/// A value as encoded by `codec::encode_value`.
+struct EncodedValue {
+    codec: Arc<dyn Codec>,
+    codec_name: String,
+    size: usize,
+    blob: Vec<u8>,
+}
+
+impl LazyValue {
+    /// Wraps a value encoded by `codec::encode_value`, alongside the codec
+    /// name and size it returned, to be decoded via `codec::decode_value`
+    /// on first access, e.g. for other stores' `iter_lazy`.
+    pub fn encoded(codec: Arc<dyn Codec>, codec_name: String, size: usize, blob: Vec<u8>) -> Self {
+        if codec_name == NoopCodec::NAME {
+            return Self::from(blob);
+        }
+        Self {
+            decoded: OnceCell::new(),
+            encoded: RefCell::new(Some(EncodedValue {
+                codec,
+                codec_name,
+                size,
+                blob,
+            })),
+        }
+    }
+
+    /// The size of the decoded value, in bytes, without decoding it.
+    pub fn len(&self) -> usize {
+        match (self.decoded.get(), &*self.encoded.borrow()) {
+            (Some(decoded), _) => decoded.len(),
+            (None, Some(encoded)) => encoded.size,
+            (None, None) => 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns whether the value has been decoded, either because it was
+    /// stored as-is or because it's already been read.
+    pub fn is_decoded(&self) -> bool {
+        self.decoded.get().is_some()
+    }
+
+    /// Returns the value, decoding it if this is the first access.
+    pub fn get(&self) -> Result<Cow<'_, [u8]>, Error> {
+        if let Some(decoded) = self.decoded.get() {
+            return Ok(Cow::Borrowed(decoded));
+        }
+        let mut encoded = self.encoded.borrow_mut();
+        let decoded = {
+            let encoded = encoded
+                .as_ref()
+                .expect("lazy value has neither a decoded nor encoded value");
+            decompress_value(encoded.codec.as_ref(), &encoded.codec_name, encoded.size, &encoded.blob)?
+        };
+        *encoded = None;
+        Ok(Cow::Borrowed(self.decoded.get_or_init(|| decoded)))
+    }
+
+    /// Returns the value, decoding it if it hasn't been yet, without copying
+    /// it.
+    pub fn into_vec(self) -> Result<Vec<u8>, Error> {
+        match (self.decoded.into_inner(), self.encoded.into_inner()) {
+            (Some(decoded), _) => Ok(decoded),
+            (None, Some(encoded)) => {
+                decode_value(encoded.codec.as_ref(), &encoded.codec_name, encoded.size, encoded.blob)
+            }
+            (None, None) => unreachable!("lazy value has neither a decoded nor encoded value"),
+        }
+    }
+}
+
+/// Wraps an already decoded value.
+impl From<Vec<u8>> for LazyValue {
+    fn from(value: Vec<u8>) -> Self {
+        Self {
+            decoded: OnceCell::from(value),
+            encoded: RefCell::new(None),
+        }
+    }
+}
+
+impl fmt::Debug for LazyValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.decoded.get() {
+            Some(decoded) => f.debug_tuple("LazyValue").field(decoded).finish(),
+            None => f
+                .debug_struct("LazyValue")
+                .field("len", &self.len())
+                .field(
+                    "codec",
+                    &self.encoded.borrow().as_ref().map(|encoded| encoded.codec_name.clone()),
+                )
+                .finish(),
         }
     }
 }
+
+/// Builds a validated `Entry`. Tags and the time to live are metadata, so
+/// entries with either are built via `build_with_metadata` and pushed via
+/// `Store::push_with_metadata`.
+#[derive(Clone, Debug, Default)]
+pub struct EntryBuilder {
+    timestamp: Option<i64>,
+    name: Option<Atom>,
+    value: Vec<u8>,
+    metadata: EntryMetadata,
+}
+
+impl EntryBuilder {
+    pub fn name<A: Into<Atom>>(mut self, name: A) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Names the entry `UNNAMED`.
+    pub fn unnamed(self) -> Self {
+        self.name(UNNAMED)
+    }
+
+    pub fn value(mut self, value: Vec<u8>) -> Self {
+        self.value = value;
+        self
+    }
+
+    pub fn timestamp(mut self, timestamp: i64) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    /// Sets the timestamp to the current time.
+    pub fn timestamp_now(self) -> Self {
+        self.timestamp(now_micros())
+    }
+
+    /// Tags the entry with a key-value pair, replacing any earlier tag with
+    /// the same key.
+    pub fn tag<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.metadata.0.insert(key.into(), value.into());
+        self
+    }
+
+    /// Sets how long the entry should be kept for, recorded in its metadata
+    /// under `TTL_METADATA_KEY`. Stores don't remove expired entries
+    /// themselves; it's up to readers to honor it.
+    pub fn ttl(self, ttl: Duration) -> Self {
+        self.tag(TTL_METADATA_KEY, ttl.as_micros().to_string())
+    }
+
+    /// Builds the entry. A name (possibly via `unnamed`) and timestamp must
+    /// have been set, and the result must pass `Entry::validate`. Fails if
+    /// tags or a time to live were set, since they'd be lost; use
+    /// `build_with_metadata` for those.
+    pub fn build(self) -> Result<Entry, Error> {
+        let (entry, metadata) = self.build_with_metadata()?;
+        if !metadata.0.is_empty() {
+            return Err(Error::InvalidEntry(
+                "tags and ttls require build_with_metadata".to_string(),
+            ));
+        }
+        Ok(entry)
+    }
+
+    /// Like `build`, but also returns the entry's tags and time to live, to
+    /// pass to `Store::push_with_metadata`. Tag keys can't be empty.
+    pub fn build_with_metadata(self) -> Result<(Entry, EntryMetadata), Error> {
+        let timestamp = self
+            .timestamp
+            .ok_or_else(|| Error::InvalidEntry("missing timestamp".to_string()))?;
+        let name = self
+            .name
+            .ok_or_else(|| Error::InvalidEntry("missing name".to_string()))?;
+        if self.metadata.0.contains_key("") {
+            return Err(Error::InvalidEntry("empty tag key".to_string()));
+        }
+        let entry = Entry::new_with_timestamp(timestamp, name, self.value);
+        entry.validate()?;
+        Ok((entry, self.metadata))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+    #[cfg(feature = "compression")]
+    use std::sync::Arc;
+
+    #[cfg(feature = "compression")]
+    use super::{Codec, LazyValue, ZstdCodec};
+    use super::{Duration, Entry, IngestionLagStats, StoredEntry, MAX_NAME_LEN, TTL_METADATA_KEY};
+
+    fn with_sequence_number(mut entry: Entry, sequence_number: u64) -> Entry {
+        entry.sequence_number = Some(sequence_number);
+        entry
+    }
+
+    #[test]
+    fn ordering() {
+        let a = Entry::new_with_timestamp(0, "a", vec![2]);
+        let b = Entry::new_with_timestamp(0, "a", vec![1]);
+        assert_eq!(a, with_sequence_number(a.clone(), 1));
+        assert!(b < a);
+        // sequence numbers break ties between the same timestamp and name
+        assert!(with_sequence_number(a.clone(), 1) < with_sequence_number(b.clone(), 2));
+        assert_eq!(
+            with_sequence_number(a.clone(), 1).cmp(&with_sequence_number(a.clone(), 2)),
+            Ordering::Less
+        );
+        assert!(b < with_sequence_number(a.clone(), 1));
+        assert!(a < with_sequence_number(b.clone(), 1));
+        assert!(a < Entry::new_with_timestamp(0, "b", vec![0]));
+        assert!(with_sequence_number(a.clone(), 2) < Entry::new_with_timestamp(1, "a", vec![0]));
+    }
+
+    #[test]
+    fn name() {
+        let entry = Entry::new_with_timestamp(0, "test_name", vec![]);
+        assert_eq!(entry.name(), "test_name");
+    }
+
+    #[test]
+    fn parts() {
+        let entry = with_sequence_number(Entry::new_with_timestamp(-5, "test_parts", vec![1, 2]), 3);
+        let (timestamp, name, value) = entry.as_parts();
+        assert_eq!((timestamp, name.as_ref(), value), (-5, "test_parts", &[1u8, 2][..]));
+        let (timestamp, name, value) = entry.clone().into_parts();
+        let round_tripped = Entry::from_parts(timestamp, name, value);
+        assert_eq!(round_tripped, entry);
+        assert_eq!(round_tripped.sequence_number, None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_values() {
+        use std::borrow::Cow;
+
+        use crate::{MemoryStore, Store};
+        use serde::{Deserialize, Serialize};
+
+        #[derive(Debug, PartialEq, Serialize, Deserialize)]
+        struct SensorReading {
+            celsius: f64,
+        }
+
+        let store = MemoryStore::default();
+        let entry = Entry::from_value(1, "temperature", &SensorReading { celsius: 25.5 }).unwrap();
+        store.push(Cow::Owned(entry)).unwrap();
+        let entry = store.latest("temperature").unwrap().unwrap();
+        assert_eq!(entry.timestamp, 1);
+        assert_eq!(
+            entry.parse_value_as::<SensorReading>().unwrap(),
+            SensorReading { celsius: 25.5 }
+        );
+        assert!(entry.parse_value_as::<Vec<u8>>().is_err());
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn offset_datetimes() {
+        use std::borrow::Cow;
+
+        use crate::{Error, MemoryStore, Range, RangeableStore, Store};
+        use time::{Duration, OffsetDateTime};
+
+        let datetime = OffsetDateTime::UNIX_EPOCH + Duration::nanoseconds(1_500_999);
+        let entry = Entry::new_with_offset_datetime(datetime, "test_offset_datetimes", vec![]);
+        assert_eq!(entry.timestamp, 1_500);
+        assert_eq!(
+            entry.offset_datetime().unwrap(),
+            OffsetDateTime::UNIX_EPOCH + Duration::microseconds(1_500)
+        );
+        let entry = Entry::new_with_offset_datetime(
+            OffsetDateTime::UNIX_EPOCH - Duration::nanoseconds(1),
+            "test_offset_datetimes",
+            vec![],
+        );
+        assert_eq!(entry.timestamp, -1);
+        assert!(matches!(
+            Entry::new_with_timestamp(i64::MAX, "test_offset_datetimes", vec![]).offset_datetime(),
+            Err(Error::TimeTooLarge)
+        ));
+
+        let store = MemoryStore::default();
+        for seconds in 0..3 {
+            let datetime = OffsetDateTime::UNIX_EPOCH + Duration::seconds(seconds);
+            let entry = Entry::new_with_offset_datetime(datetime, "test_offset_datetimes", vec![]);
+            store.push(Cow::Owned(entry)).unwrap();
+        }
+        let start = OffsetDateTime::UNIX_EPOCH + Duration::seconds(1);
+        let range = store
+            .range_offset_datetime(start.., Some("test_offset_datetimes"))
+            .unwrap();
+        assert_eq!(range.count().unwrap(), 2);
+    }
+
+    #[test]
+    fn builder() {
+        let entry = Entry::builder()
+            .name("test_builder")
+            .value(vec![1])
+            .timestamp(-1)
+            .build()
+            .unwrap();
+        assert_eq!(entry, Entry::new_with_timestamp(-1, "test_builder", vec![1]));
+        assert!(Entry::builder().name("test_builder").timestamp_now().build().is_ok());
+        let entry = Entry::builder().unnamed().timestamp(1).build().unwrap();
+        assert!(entry.is_unnamed());
+        assert_eq!(entry, Entry::new_unnamed_with_timestamp(1, vec![]));
+    }
+
+    #[test]
+    fn builder_validation() {
+        let err = Entry::builder().name("test_builder").build().unwrap_err();
+        assert_eq!(err.to_string(), "invalid entry: missing timestamp");
+        let err = Entry::builder().timestamp(1).build().unwrap_err();
+        assert_eq!(err.to_string(), "invalid entry: missing name");
+        let err = Entry::builder()
+            .name("a".repeat(MAX_NAME_LEN + 1))
+            .timestamp(1)
+            .build()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "invalid entry: name is longer than 1024 bytes");
+        assert!(Entry::builder()
+            .name("a".repeat(MAX_NAME_LEN))
+            .timestamp(1)
+            .build()
+            .is_ok());
+    }
+
+    #[test]
+    fn builder_metadata() {
+        let builder = Entry::builder()
+            .name("test_builder_metadata")
+            .timestamp(1)
+            .tag("source", "sensor-1")
+            .ttl(Duration::from_secs(60));
+        let (entry, metadata) = builder.clone().build_with_metadata().unwrap();
+        assert_eq!(entry, Entry::new_with_timestamp(1, "test_builder_metadata", vec![]));
+        assert_eq!(metadata.0.get("source").map(String::as_str), Some("sensor-1"));
+        assert_eq!(metadata.0.get(TTL_METADATA_KEY).map(String::as_str), Some("60000000"));
+
+        let err = builder.build().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid entry: tags and ttls require build_with_metadata"
+        );
+        let err = Entry::builder()
+            .name("test_builder_metadata")
+            .timestamp(1)
+            .tag("", "")
+            .build_with_metadata()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "invalid entry: empty tag key");
+    }
+
+    #[test]
+    fn ingestion_lag_stats() {
+        assert_eq!(IngestionLagStats::from_entries(&[]), IngestionLagStats::default());
+        // lags of 1 through 100, plus an entry without an ingestion time
+        let entries: Vec<StoredEntry> = (0..=100)
+            .map(|i| StoredEntry {
+                entry: Entry::new_with_timestamp(0, "test_ingestion_lag_stats", vec![]),
+                ingested_at: if i == 0 { None } else { Some(i) },
+                id: i as u64,
+                metadata: None,
+            })
+            .rev()
+            .collect();
+        assert_eq!(
+            IngestionLagStats::from_entries(&entries),
+            IngestionLagStats {
+                count: 100,
+                p50: 50,
+                p90: 90,
+                p99: 99,
+                max: 100,
+            }
+        );
+        assert_eq!(IngestionLagStats::from_entries(&entries[..1]).p50, 100);
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn lazy_value_drops_encoded_blob() {
+        let codec: Arc<dyn Codec> = Arc::new(ZstdCodec::default());
+        let value = vec![7; 4096];
+        let blob = codec.compress(&value).unwrap();
+        let lazy = LazyValue::encoded(codec.clone(), ZstdCodec::NAME.to_string(), value.len(), blob);
+        assert!(!lazy.is_decoded());
+        assert_eq!(lazy.get().unwrap().as_ref(), value.as_slice());
+        assert!(lazy.encoded.borrow().is_none());
+        assert_eq!(lazy.len(), 4096);
+        assert_eq!(lazy.into_vec().unwrap(), value);
+
+        // failed attempts keep the blob, to retry with
+        let corrupt = LazyValue::encoded(codec, ZstdCodec::NAME.to_string(), 4096, vec![0]);
+        assert!(corrupt.get().is_err());
+        assert!(corrupt.get().is_err());
+        assert!(corrupt.encoded.borrow().is_some());
+        assert_eq!(corrupt.len(), 4096);
+    }
+}
@@ -8,13 +8,24 @@ pub enum Error {
     Database(Box<dyn StdError + Send + Sync>),
     Io(IoError),
     BadRange,
+    BadRangeExpr(String),
+    InvalidEntry(String),
+    InvalidConfig(String),
+    UnsupportedCodec(String),
+    TimestampOutOfBounds(i64),
+    TimeTooLarge,
+    CompressionRoundTrip(String),
+    Unsupported(String),
+    NameInUse(String),
+    Lagged,
+    QueueFull,
 }
 
 impl StdError for Error {
     fn source(&self) -> Option<&(dyn StdError + 'static)> {
         match *self {
             Error::Database(ref err) => Some(&**err),
-            Error::Io(ref err) => Some(&*err),
+            Error::Io(ref err) => Some(err),
             _ => None,
         }
     }
@@ -29,10 +40,35 @@ impl fmt::Display for Error {
                 f,
                 "ranges cannot be reversed, or have exclusive bounds with equal durations"
             ),
+            Error::BadRangeExpr(ref expr) => write!(f, "invalid range expression: {:?}", expr),
+            Error::InvalidEntry(ref msg) => write!(f, "invalid entry: {}", msg),
+            Error::InvalidConfig(ref msg) => write!(f, "invalid config: {}", msg),
+            Error::UnsupportedCodec(ref name) => write!(f, "unsupported codec: {}", name),
+            Error::TimestampOutOfBounds(timestamp) => {
+                write!(f, "timestamp {} is too far from the store's clock", timestamp)
+            }
+            Error::TimeTooLarge => write!(f, "time cannot be represented as microseconds in an i64"),
+            Error::CompressionRoundTrip(ref name) => {
+                write!(f, "value did not survive a round trip through codec {}", name)
+            }
+            Error::Unsupported(ref msg) => write!(f, "unsupported: {}", msg),
+            Error::NameInUse(ref name) => write!(f, "name {} already has entries", name),
+            Error::Lagged => write!(
+                f,
+                "subscription fell behind, and entries it hadn't read were overwritten"
+            ),
+            Error::QueueFull => write!(f, "retry queue is full"),
         }
     }
 }
 
+#[cfg(any(feature = "sqlite-store", feature = "redis-store"))]
+impl From<r2d2::Error> for Error {
+    fn from(err: r2d2::Error) -> Self {
+        Error::Database(Box::new(err))
+    }
+}
+
 impl From<IoError> for Error {
     fn from(err: IoError) -> Self {
         Error::Io(err)
@@ -1,26 +1,69 @@
 #![cfg_attr(feature = "benches", feature(test))]
 #[cfg(feature = "benches")]
 extern crate test;
+#[macro_use]
+extern crate bitflags;
 
+pub mod codec;
+mod diff;
 mod entry;
 mod errors;
+mod options;
+mod select;
 mod stores;
+mod time_range;
 mod utils;
+#[cfg(any(test, feature = "testkit"))]
 #[macro_use]
-pub mod tests;
+pub mod testkit;
 
+#[cfg(feature = "arrow")]
+mod arrow;
 #[cfg(feature = "python")]
 mod python;
 #[cfg(feature = "benches")]
 #[macro_use]
 pub mod benches;
 
-pub use self::entry::Entry;
+#[cfg(feature = "compression")]
+pub use self::codec::ZstdCodec;
+pub use self::codec::{Codec, NoopCodec};
+pub use self::diff::{diff, EntryDiff};
+#[cfg(feature = "compression")]
+pub use self::entry::CompressedEntry;
+pub use self::entry::{
+    Entry, EntryBuilder, EntryMetadata, IngestionLagStats, LazyEntry, LazyValue, StoredEntry, MAX_NAME_LEN,
+    MAX_VALUE_LEN, TTL_METADATA_KEY, UNNAMED,
+};
 pub use self::errors::Error;
-pub use self::stores::memory::{MemoryRange, MemoryStore, MemoryStreamSubscription};
-pub use self::stores::traits::{Range, RangeableStore, Store, SubscribeableStore, Subscription};
+pub use self::options::{
+    Clock, ManualClock, SkewPolicy, SlowOp, SlowOpCallback, SlowOpConfig, SlowOpKind, StoreOptions, SystemClock,
+};
+pub use self::select::{select, Waker};
+#[cfg(feature = "compression")]
+pub use self::stores::compressed::{CompressedMemoryRange, CompressedMemoryRangeIterator, CompressedMemoryStore};
+pub use self::stores::memory::{FilteredSubscription, MemoryRange, MemoryStore, MemoryStreamSubscription};
+pub use self::stores::tee::{TeeErrorCallback, TeePolicy, TeeStore};
+pub use self::stores::traits::{
+    Capabilities, CompactionReport, OrderBy, PushCallback, Range, RangeableStore, Store, SubscribeableStore,
+    Subscription, SubscriptionDyn,
+};
+pub use self::time_range::TimeRange;
 
+#[cfg(feature = "arrow")]
+pub use self::arrow::{entries_to_record_batch, record_batch_schema, record_batch_to_entries};
 #[cfg(feature = "redis-store")]
-pub use self::stores::redis::{RedisStreamStore, RedisStreamSubscription};
+pub use self::stores::redis::{RedisFieldLayout, RedisStreamRange, RedisStreamStore, RedisStreamSubscription};
+#[cfg(all(feature = "shmem", unix))]
+pub use self::stores::shm::{ShmStore, ShmSubscription};
+#[cfg(feature = "sqlite-store")]
+pub use self::stores::sqlite::{
+    Aggregate, AggregateReport, CompressionVerificationReport, HealthCheckHandle, NameCompressionStats, PoolMonitor,
+    PoolStats, SqliteRange, SqliteRangeIterator, SqliteRangeMetadataIterator, SqliteSnapshot, SqliteSnapshotRange,
+    SqliteSnapshotRangeIterator, SqliteStore, SqliteStoreConfig, SqliteStoreInfo, SqliteSubscription, ThreadMode,
+    ValueType,
+};
 #[cfg(feature = "sqlite-store")]
-pub use self::stores::sqlite::{SqliteRange, SqliteRangeIterator, SqliteStore};
+pub use self::stores::sqlite_partitioned::{
+    Partition, PartitionedSqliteRange, PartitionedSqliteRangeIterator, PartitionedSqliteStore,
+};
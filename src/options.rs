@@ -0,0 +1,348 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::entry::now_micros;
+use crate::{Entry, Error};
+
+use string_cache::DefaultAtom as Atom;
+
+/// A source of the current time, in microseconds since the unix epoch.
+pub trait Clock: Send + Sync {
+    fn now_micros(&self) -> i64;
+}
+
+/// The system's wall clock.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_micros(&self) -> i64 {
+        now_micros()
+    }
+}
+
+/// A clock that only moves when told to, for deterministic tests.
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    now: AtomicI64,
+}
+
+impl ManualClock {
+    pub fn new(now_micros: i64) -> Self {
+        Self {
+            now: AtomicI64::new(now_micros),
+        }
+    }
+
+    pub fn set(&self, now_micros: i64) {
+        self.now.store(now_micros, Ordering::SeqCst);
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.now.fetch_add(duration_micros(duration), Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_micros(&self) -> i64 {
+        self.now.load(Ordering::SeqCst)
+    }
+}
+
+/// What to do with an entry whose timestamp is outside the bounds allowed by
+/// `StoreOptions`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SkewPolicy {
+    /// Fail the push with `Error::TimestampOutOfBounds`.
+    Reject,
+    /// Replace the entry's timestamp with the current time.
+    Clamp,
+    /// Store the entry as-is, but with `Entry::skewed` set. If
+    /// `ignore_in_latest` is set, `latest` skips over flagged entries, so
+    /// that a single entry dated far in the future can't shadow the rest.
+    Flag { ignore_in_latest: bool },
+}
+
+/// The kind of operation reported to a `SlowOpConfig`'s callback.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SlowOpKind {
+    /// Pushing one or more entries.
+    Push,
+    /// Reading entries, e.g. `latest`, or a page of a range.
+    Query,
+}
+
+/// An operation that took longer than its threshold.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SlowOp {
+    pub kind: SlowOpKind,
+    /// The name of the entries involved, if the operation was limited to
+    /// one name.
+    pub name: Option<Atom>,
+    pub duration: Duration,
+    /// The number of entries pushed or read.
+    pub rows: u64,
+    /// For sqlite stores, the statement that ran.
+    pub sql: Option<String>,
+}
+
+/// A callback invoked with every slow operation.
+pub type SlowOpCallback = Arc<dyn Fn(&SlowOp) + Send + Sync>;
+
+/// Reports operations that take longer than a threshold. Operations are
+/// only timed when this is configured.
+#[derive(Clone)]
+pub struct SlowOpConfig {
+    pub push_threshold: Duration,
+    pub query_threshold: Duration,
+    pub callback: SlowOpCallback,
+}
+
+impl SlowOpConfig {
+    /// A callback that logs slow operations as warnings via the `log` crate.
+    pub fn log(op: &SlowOp) {
+        log::warn!(
+            "slow binlog {:?}: {:?} for {} rows (name: {:?}, sql: {:?})",
+            op.kind,
+            op.duration,
+            op.rows,
+            op.name,
+            op.sql
+        );
+    }
+
+    pub(crate) fn start(&self) -> OpTimer<'_> {
+        OpTimer {
+            config: self,
+            start: Instant::now(),
+        }
+    }
+}
+
+/// Reports pushes slower than 10ms and queries slower than 100ms via
+/// `SlowOpConfig::log`.
+impl Default for SlowOpConfig {
+    fn default() -> Self {
+        Self {
+            push_threshold: Duration::from_millis(10),
+            query_threshold: Duration::from_millis(100),
+            callback: Arc::new(Self::log),
+        }
+    }
+}
+
+impl fmt::Debug for SlowOpConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SlowOpConfig")
+            .field("push_threshold", &self.push_threshold)
+            .field("query_threshold", &self.query_threshold)
+            .finish()
+    }
+}
+
+/// Times an operation, started via `SlowOpConfig::start`.
+pub(crate) struct OpTimer<'a> {
+    config: &'a SlowOpConfig,
+    start: Instant,
+}
+
+impl OpTimer<'_> {
+    /// Reports the operation if it exceeded its threshold. `sql` is only
+    /// called for slow operations.
+    pub(crate) fn finish<F: FnOnce() -> Option<String>>(
+        self,
+        kind: SlowOpKind,
+        name: Option<&Atom>,
+        rows: u64,
+        sql: F,
+    ) {
+        let duration = self.start.elapsed();
+        let threshold = match kind {
+            SlowOpKind::Push => self.config.push_threshold,
+            SlowOpKind::Query => self.config.query_threshold,
+        };
+        if duration > threshold {
+            (self.config.callback)(&SlowOp {
+                kind,
+                name: name.cloned(),
+                duration,
+                rows,
+                sql: sql(),
+            });
+        }
+    }
+}
+
+/// Options that apply to every store.
+#[derive(Clone)]
+pub struct StoreOptions {
+    /// How far ahead of the store's clock a pushed entry's timestamp may be.
+    pub max_future_skew: Option<Duration>,
+    /// How far behind the store's clock a pushed entry's timestamp may be.
+    pub max_past_skew: Option<Duration>,
+    /// What to do with entries that are out of bounds.
+    pub skew_policy: SkewPolicy,
+    /// The clock timestamps are checked against.
+    pub clock: Arc<dyn Clock>,
+    /// If set, operations slower than a threshold are reported.
+    pub slow_ops: Option<SlowOpConfig>,
+}
+
+impl Default for StoreOptions {
+    fn default() -> Self {
+        Self {
+            max_future_skew: None,
+            max_past_skew: None,
+            skew_policy: SkewPolicy::Reject,
+            clock: Arc::new(SystemClock),
+            slow_ops: None,
+        }
+    }
+}
+
+impl fmt::Debug for StoreOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StoreOptions")
+            .field("max_future_skew", &self.max_future_skew)
+            .field("max_past_skew", &self.max_past_skew)
+            .field("skew_policy", &self.skew_policy)
+            .field("slow_ops", &self.slow_ops)
+            .finish()
+    }
+}
+
+fn duration_micros(duration: Duration) -> i64 {
+    duration.as_micros().try_into().unwrap_or(i64::MAX)
+}
+
+impl StoreOptions {
+    /// Starts timing an operation, if slow operations are reported.
+    pub(crate) fn start_op(&self) -> Option<OpTimer<'_>> {
+        self.slow_ops.as_ref().map(SlowOpConfig::start)
+    }
+
+    /// Whether `latest` should skip over flagged entries.
+    pub(crate) fn ignores_skewed_in_latest(&self) -> bool {
+        matches!(self.skew_policy, SkewPolicy::Flag { ignore_in_latest: true })
+    }
+
+    /// Applies the skew policy to an entry that is about to be pushed.
+    pub(crate) fn check_skew<'a>(&self, entry: Cow<'a, Entry>) -> Result<Cow<'a, Entry>, Error> {
+        match self.check_skew_timestamp(entry.timestamp)? {
+            None => Ok(entry),
+            Some((timestamp, skewed)) => {
+                let mut entry = entry.into_owned();
+                entry.timestamp = timestamp;
+                entry.skewed |= skewed;
+                Ok(Cow::Owned(entry))
+            }
+        }
+    }
+
+    /// Applies the skew policy to the timestamp of an entry that is about to
+    /// be pushed. If the timestamp is out of bounds, returns the timestamp to
+    /// push the entry with instead, and whether to flag it.
+    pub(crate) fn check_skew_timestamp(&self, timestamp: i64) -> Result<Option<(i64, bool)>, Error> {
+        if self.max_future_skew.is_none() && self.max_past_skew.is_none() {
+            return Ok(None);
+        }
+
+        let now = self.clock.now_micros();
+        let too_new = self
+            .max_future_skew
+            .is_some_and(|skew| timestamp > now.saturating_add(duration_micros(skew)));
+        let too_old = self
+            .max_past_skew
+            .is_some_and(|skew| timestamp < now.saturating_sub(duration_micros(skew)));
+        if !too_new && !too_old {
+            return Ok(None);
+        }
+
+        match self.skew_policy {
+            SkewPolicy::Reject => Err(Error::TimestampOutOfBounds(timestamp)),
+            SkewPolicy::Clamp => Ok(Some((now, false))),
+            SkewPolicy::Flag { .. } => Ok(Some((timestamp, true))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::{Clock, ManualClock, SkewPolicy, StoreOptions};
+    use crate::{Entry, Error};
+
+    fn options(skew_policy: SkewPolicy) -> StoreOptions {
+        StoreOptions {
+            max_future_skew: Some(Duration::from_secs(1)),
+            max_past_skew: Some(Duration::from_secs(2)),
+            skew_policy,
+            clock: Arc::new(ManualClock::new(10_000_000)),
+            slow_ops: None,
+        }
+    }
+
+    fn check(options: &StoreOptions, timestamp: i64) -> Result<Entry, Error> {
+        let entry = Entry::new_with_timestamp(timestamp, "test_skew", vec![1]);
+        options.check_skew(Cow::Owned(entry)).map(Cow::into_owned)
+    }
+
+    #[test]
+    fn manual_clock() {
+        let clock = ManualClock::new(5);
+        assert_eq!(clock.now_micros(), 5);
+        clock.advance(Duration::from_millis(1));
+        assert_eq!(clock.now_micros(), 1005);
+        clock.set(-1);
+        assert_eq!(clock.now_micros(), -1);
+    }
+
+    #[test]
+    fn unbounded() {
+        let options = StoreOptions::default();
+        assert!(!check(&options, i64::MAX).unwrap().skewed);
+        assert!(!check(&options, i64::MIN).unwrap().skewed);
+    }
+
+    #[test]
+    fn reject() {
+        let options = options(SkewPolicy::Reject);
+        assert_eq!(check(&options, 11_000_000).unwrap().timestamp, 11_000_000);
+        assert_eq!(check(&options, 8_000_000).unwrap().timestamp, 8_000_000);
+        assert!(matches!(
+            check(&options, 11_000_001),
+            Err(Error::TimestampOutOfBounds(11_000_001))
+        ));
+        assert!(matches!(
+            check(&options, 7_999_999),
+            Err(Error::TimestampOutOfBounds(7_999_999))
+        ));
+    }
+
+    #[test]
+    fn clamp() {
+        let options = options(SkewPolicy::Clamp);
+        assert_eq!(check(&options, 9_000_000).unwrap().timestamp, 9_000_000);
+        assert_eq!(check(&options, i64::MAX).unwrap().timestamp, 10_000_000);
+        assert_eq!(check(&options, 0).unwrap().timestamp, 10_000_000);
+    }
+
+    #[test]
+    fn flag() {
+        let options = options(SkewPolicy::Flag {
+            ignore_in_latest: false,
+        });
+        let entry = check(&options, 9_000_000).unwrap();
+        assert!(!entry.skewed);
+        let entry = check(&options, i64::MAX).unwrap();
+        assert!(entry.skewed);
+        assert_eq!(entry.timestamp, i64::MAX);
+        assert!(check(&options, 0).unwrap().skewed);
+    }
+}
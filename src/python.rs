@@ -1,20 +1,47 @@
 use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::ops::Bound;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
 use std::time::Duration;
 
-use crate::{Error, Range, RangeableStore, Store, SubscribeableStore, Subscription};
+use crate::{Error, Range, RangeableStore, Store, SubscribeableStore, Subscription, TeePolicy};
 
 use pyo3::exceptions::{PyIOError, PyRuntimeError, PyValueError};
 use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+
+/// How long iterating over a subscription waits for an entry before
+/// checking whether it was closed, in seconds.
+static SUBSCRIPTION_POLL_INTERVAL: f32 = 0.1;
 
 fn map_result<T>(res: Result<T, Error>) -> PyResult<T> {
     res.map_err(|err| match err {
         Error::Database(err) => PyRuntimeError::new_err(format!("{}", err)),
         Error::Io(err) => PyIOError::new_err(err),
         Error::BadRange => PyValueError::new_err("bad range"),
+        Error::BadRangeExpr(expr) => PyValueError::new_err(format!("invalid range expression: {:?}", expr)),
+        Error::InvalidEntry(msg) => PyValueError::new_err(format!("invalid entry: {}", msg)),
+        Error::InvalidConfig(msg) => PyValueError::new_err(format!("invalid config: {}", msg)),
+        Error::UnsupportedCodec(name) => PyIOError::new_err(format!("unsupported codec: {}", name)),
+        Error::TimestampOutOfBounds(timestamp) => {
+            PyValueError::new_err(format!("timestamp {} is too far from the store's clock", timestamp))
+        }
+        Error::TimeTooLarge => PyValueError::new_err("time too large"),
+        Error::Unsupported(msg) => PyValueError::new_err(format!("unsupported: {}", msg)),
+        Error::CompressionRoundTrip(name) => PyIOError::new_err(format!("compression round trip failed: {}", name)),
+        Error::NameInUse(name) => PyValueError::new_err(format!("name {} already has entries", name)),
+        Error::Lagged => PyRuntimeError::new_err("subscription fell behind"),
+        Error::QueueFull => PyRuntimeError::new_err("retry queue is full"),
     })
 }
 
+/// Converts a number of seconds from python, which may be negative, NaN or
+/// too large to be a duration.
+fn duration_from_secs(secs: f32) -> PyResult<Duration> {
+    Duration::try_from_secs_f32(secs).map_err(|_| PyValueError::new_err(format!("invalid duration: {} seconds", secs)))
+}
+
 #[pyclass]
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Entry {
@@ -29,8 +56,18 @@ pub struct Entry {
 #[pymethods]
 impl Entry {
     #[new]
-    pub fn new(timestamp: i64, name: String, value: Vec<u8>) -> Self {
-        Entry { timestamp, name, value }
+    pub fn new(timestamp: i64, name: String, value: Vec<u8>) -> PyResult<Self> {
+        let entry = crate::Entry::builder()
+            .timestamp(timestamp)
+            .name(name)
+            .value(value)
+            .build();
+        Ok(map_result(entry)?.into())
+    }
+
+    /// Returns the entry as a `(timestamp, name, value)` tuple.
+    pub fn as_tuple<'py>(&self, py: Python<'py>) -> (i64, String, &'py PyBytes) {
+        (self.timestamp, self.name.clone(), PyBytes::new(py, &self.value))
     }
 }
 
@@ -42,7 +79,11 @@ impl From<Entry> for crate::Entry {
 
 impl From<crate::Entry> for Entry {
     fn from(entry: crate::Entry) -> Entry {
-        Entry::new(entry.timestamp, entry.name.to_string(), entry.value)
+        Entry {
+            timestamp: entry.timestamp,
+            name: entry.name.to_string(),
+            value: entry.value,
+        }
     }
 }
 
@@ -65,6 +106,10 @@ impl SqliteStore {
         py.allow_threads(move || map_result(self.store.push(entry)))
     }
 
+    pub fn count(&self, py: Python) -> PyResult<u64> {
+        py.allow_threads(move || map_result(self.store.count()))
+    }
+
     pub fn range(
         &self,
         start_bound: Option<i64>,
@@ -82,8 +127,43 @@ impl SqliteStore {
         let range = map_result(self.store.range((start_bound, end_bound), name))?;
         Ok(SqliteRange { range: Some(range) })
     }
+
+    pub fn set_property(&self, py: Python, key: String, value: String) -> PyResult<()> {
+        py.allow_threads(move || map_result(self.store.set_property(key, value)))
+    }
+
+    pub fn get_property(&self, py: Python, key: String) -> PyResult<Option<String>> {
+        py.allow_threads(move || map_result(self.store.get_property(&key)))
+    }
+
+    pub fn info(&self, py: Python) -> PyResult<SqliteStoreInfo> {
+        let info = py.allow_threads(move || map_result(self.store.info()))?;
+        Ok(SqliteStoreInfo {
+            created_at: info.created_at,
+            created_by: info.created_by,
+            schema_version: info.schema_version,
+            properties: info.properties,
+        })
+    }
+}
+
+#[pyclass]
+pub struct SqliteStoreInfo {
+    #[pyo3(get)]
+    pub created_at: i64,
+    #[pyo3(get)]
+    pub created_by: String,
+    #[pyo3(get)]
+    pub schema_version: u32,
+    #[pyo3(get)]
+    pub properties: BTreeMap<String, String>,
 }
 
+/// A range of entries. `remove` and `iter` consume the range, after which
+/// every method fails with `ValueError`; check `consumed` first to avoid
+/// that. Ranges without entries are not consumed, and behave like any other.
+/// `len()` and `bool()` count the range and check it for entries, and
+/// iterating over the range directly consumes it like `iter`.
 #[pyclass]
 pub struct SqliteRange {
     range: Option<crate::SqliteRange>,
@@ -91,6 +171,12 @@ pub struct SqliteRange {
 
 #[pymethods]
 impl SqliteRange {
+    /// Whether `remove` or `iter` has been called on the range.
+    #[getter]
+    pub fn consumed(&self) -> bool {
+        self.range.is_none()
+    }
+
     pub fn count(&self, py: Python) -> PyResult<u64> {
         if let Some(range) = &self.range {
             py.allow_threads(move || map_result(range.count()))
@@ -99,6 +185,14 @@ impl SqliteRange {
         }
     }
 
+    pub fn is_empty(&self, py: Python) -> PyResult<bool> {
+        if let Some(range) = &self.range {
+            py.allow_threads(move || map_result(range.is_empty()))
+        } else {
+            Err(PyValueError::new_err("range already consumed"))
+        }
+    }
+
     pub fn remove(&mut self, py: Python) -> PyResult<()> {
         if let Some(range) = self.range.take() {
             py.allow_threads(move || map_result(range.remove()))
@@ -117,8 +211,22 @@ impl SqliteRange {
             Err(PyValueError::new_err("range already consumed"))
         }
     }
+
+    fn __len__(&self, py: Python) -> PyResult<usize> {
+        Ok(self.count(py)? as usize)
+    }
+
+    fn __bool__(&self, py: Python) -> PyResult<bool> {
+        Ok(!self.is_empty(py)?)
+    }
+
+    fn __iter__(&mut self, py: Python) -> PyResult<SqliteRangeIterator> {
+        self.iter(py)
+    }
 }
 
+/// Iterates over a range's entries. Errors reading entries are raised as
+/// exceptions.
 #[pyclass]
 pub struct SqliteRangeIterator {
     iter: crate::SqliteRangeIterator,
@@ -130,12 +238,10 @@ impl SqliteRangeIterator {
         slf
     }
 
-    fn __next__(mut slf: PyRefMut<'_, Self>, py: Python) -> Option<PyObject> {
-        match slf.iter.next() {
-            Some(Ok(entry)) => Some(Entry::from(entry).into_py(py)),
-            Some(Err(err)) => Some(map_result::<()>(Err(err)).unwrap_err().into_py(py)),
-            None => None,
-        }
+    fn __next__(mut slf: PyRefMut<'_, Self>, py: Python) -> PyResult<Option<Entry>> {
+        let iter = &mut slf.iter;
+        py.allow_threads(move || map_result(iter.next().transpose()))
+            .map(|entry| entry.map(|entry| entry.into()))
     }
 }
 
@@ -146,10 +252,14 @@ pub struct RedisStreamStore {
 
 #[pymethods]
 impl RedisStreamStore {
+    /// Creates a store. `max_len` is the number of entries retained per
+    /// stream, and `max_value_len` the largest value that can be pushed, in
+    /// bytes; both are unlimited by default.
     #[new]
-    pub fn new(connection_url: String) -> PyResult<Self> {
+    pub fn new(connection_url: String, max_len: Option<usize>, max_value_len: Option<usize>) -> PyResult<Self> {
+        let store = map_result(crate::RedisStreamStore::new(connection_url))?;
         Ok(Self {
-            store: map_result(crate::RedisStreamStore::new(connection_url))?,
+            store: store.with_max_len(max_len).with_max_value_len(max_value_len),
         })
     }
 
@@ -160,33 +270,147 @@ impl RedisStreamStore {
 
     pub fn subscribe(&self, name: String) -> PyResult<RedisStreamSubscription> {
         let subscription = map_result(self.store.subscribe(name))?;
-        Ok(RedisStreamSubscription { subscription })
+        Ok(RedisStreamSubscription::new(subscription))
     }
 }
 
+/// A subscription to a name. Iterating over it blocks until each entry is
+/// pushed, and stops once `close` is called, e.g. from another thread.
+/// After that, `next` fails with `ValueError`.
 #[pyclass]
 pub struct RedisStreamSubscription {
-    subscription: crate::RedisStreamSubscription,
+    subscription: Mutex<Option<crate::RedisStreamSubscription>>,
+    // Set by `close` without taking the lock, which `next` holds while it
+    // waits for an entry
+    closed: AtomicBool,
+}
+
+impl RedisStreamSubscription {
+    fn new(subscription: crate::RedisStreamSubscription) -> Self {
+        Self {
+            subscription: Mutex::new(Some(subscription)),
+            closed: AtomicBool::new(false),
+        }
+    }
 }
 
 #[pymethods]
 impl RedisStreamSubscription {
-    pub fn next(&mut self, py: Python, duration: Option<f32>) -> PyResult<Option<Entry>> {
-        let duration = duration.map(Duration::from_secs_f32);
+    pub fn next(&self, py: Python, duration: Option<f32>) -> PyResult<Option<Entry>> {
+        let duration = duration.map(duration_from_secs).transpose()?;
         py.allow_threads(move || {
-            let entry = map_result(self.subscription.next(duration))?;
-            Ok(entry.map(|e| e.into()))
+            let mut subscription = self.subscription.lock().unwrap();
+            if self.closed.load(Ordering::SeqCst) {
+                subscription.take();
+            }
+            match subscription.as_mut() {
+                Some(subscription) => {
+                    let entry = map_result(subscription.next(duration))?;
+                    Ok(entry.map(|e| e.into()))
+                }
+                None => Err(PyValueError::new_err("subscription closed")),
+            }
         })
     }
+
+    /// Closes the subscription, ending any iteration over it. This returns
+    /// right away, even while another thread waits in `next`; the
+    /// subscription is then dropped once that wait ends.
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        if let Ok(mut subscription) = self.subscription.try_lock() {
+            subscription.take();
+        }
+    }
+
+    #[getter]
+    pub fn closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    /// Polls for the next entry in short intervals, so that `close` and
+    /// signals such as `KeyboardInterrupt` are noticed promptly.
+    fn __next__(&self, py: Python) -> PyResult<Option<Entry>> {
+        loop {
+            if self.closed() {
+                return Ok(None);
+            }
+            match self.next(py, Some(SUBSCRIPTION_POLL_INTERVAL)) {
+                Ok(Some(entry)) => return Ok(Some(entry)),
+                Ok(None) => py.check_signals()?,
+                // closed while polling
+                Err(_) if self.closed() => return Ok(None),
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+#[pyclass]
+pub struct TeeStore {
+    store: crate::TeeStore<crate::RedisStreamStore, crate::SqliteStore>,
+}
+
+#[pymethods]
+impl TeeStore {
+    /// Pushes entries to a redis store for live subscribers, and then to a
+    /// sqlite store for history. `policy` is `"fail_fast"`, `"primary"` or
+    /// `"durable"`; `max_queued` and `retry_interval` (in seconds) only
+    /// apply to the latter.
+    #[new]
+    pub fn new(
+        primary: &RedisStreamStore,
+        secondary: &SqliteStore,
+        policy: &str,
+        max_queued: Option<usize>,
+        retry_interval: Option<f32>,
+    ) -> PyResult<Self> {
+        let policy = match policy {
+            "fail_fast" => TeePolicy::FailFast,
+            "primary" => TeePolicy::Primary,
+            "durable" => TeePolicy::Durable {
+                max_queued: max_queued.unwrap_or(10_000),
+                retry_interval: duration_from_secs(retry_interval.unwrap_or(1.0))?,
+            },
+            _ => return Err(PyValueError::new_err(format!("unknown tee policy: {}", policy))),
+        };
+        Ok(Self {
+            store: crate::TeeStore::new(primary.store.clone(), secondary.store.clone(), policy),
+        })
+    }
+
+    pub fn push(&self, py: Python, entry: Entry) -> PyResult<()> {
+        let entry = Cow::Owned(entry.into());
+        py.allow_threads(move || map_result(self.store.push(entry)))
+    }
+
+    pub fn queued(&self) -> usize {
+        self.store.queued()
+    }
+
+    pub fn latest(&self, py: Python, name: String) -> PyResult<Option<Entry>> {
+        py.allow_threads(move || Ok(map_result(self.store.latest(name))?.map(Entry::from)))
+    }
+
+    pub fn subscribe(&self, name: String) -> PyResult<RedisStreamSubscription> {
+        let subscription = map_result(self.store.primary().subscribe(name))?;
+        Ok(RedisStreamSubscription::new(subscription))
+    }
 }
 
 #[pymodule]
 fn binlog(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_class::<Entry>()?;
     m.add_class::<SqliteStore>()?;
+    m.add_class::<SqliteStoreInfo>()?;
     m.add_class::<SqliteRange>()?;
     m.add_class::<SqliteRangeIterator>()?;
     m.add_class::<RedisStreamStore>()?;
     m.add_class::<RedisStreamSubscription>()?;
+    m.add_class::<TeeStore>()?;
     Ok(())
 }
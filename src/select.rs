@@ -0,0 +1,72 @@
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+
+use crate::{Entry, Error, SubscriptionDyn};
+
+use crossbeam_channel::{bounded, Receiver, Select, Sender, TrySendError};
+
+/// Wakes a pending `select` call when a subscription may have a new entry.
+#[derive(Clone, Debug)]
+pub struct Waker {
+    sender: Sender<()>,
+    guard: Weak<()>,
+}
+
+impl Waker {
+    /// Wakes the `select` call. Returns false if the call has already
+    /// finished, in which case the waker can be discarded.
+    pub fn wake(&self) -> bool {
+        !matches!(self.sender.try_send(()), Err(TrySendError::Disconnected(_)))
+    }
+
+    /// Returns whether the `select` call is still pending.
+    pub fn is_alive(&self) -> bool {
+        self.guard.strong_count() > 0
+    }
+}
+
+/// Waits for the next entry across several subscriptions, returning the
+/// index of the subscription it came from alongside the entry. If several
+/// subscriptions have entries available, one is picked at random, so that
+/// no subscription is starved. Returns `None` if the timeout elapses, or
+/// immediately if there are no subscriptions.
+pub fn select(
+    subscriptions: &mut [&mut dyn SubscriptionDyn],
+    timeout: Option<Duration>,
+) -> Result<Option<(usize, Entry)>, Error> {
+    if subscriptions.is_empty() {
+        return Ok(None);
+    }
+
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let guard = Arc::new(());
+    let mut receivers = Vec::<Receiver<()>>::with_capacity(subscriptions.len());
+    for subscription in subscriptions.iter_mut() {
+        let (sender, receiver) = bounded(1);
+        subscription.register_waker(Waker {
+            sender,
+            guard: Arc::downgrade(&guard),
+        })?;
+        receivers.push(receiver);
+    }
+
+    let mut select = Select::new();
+    for receiver in &receivers {
+        select.recv(receiver);
+    }
+
+    loop {
+        let index = match deadline {
+            Some(deadline) => match select.ready_deadline(deadline) {
+                Ok(index) => index,
+                Err(_) => return Ok(None),
+            },
+            None => select.ready(),
+        };
+        if receivers[index].try_recv().is_ok() {
+            if let Some(entry) = subscriptions[index].try_next()? {
+                return Ok(Some((index, entry)));
+            }
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use std::borrow::Cow;
+use std::ops::RangeBounds;
+use std::vec::IntoIter as VecIter;
+
+use crate::{
+    Capabilities, CompressedEntry, Entry, Error, MemoryRange, MemoryStore, Range, RangeableStore, Store, ZstdCodec,
+};
+
+use string_cache::DefaultAtom as Atom;
+
+/// A `MemoryStore` that holds values compressed with zstd, trading CPU for
+/// memory. Values are compressed when pushed, and decompressed when read.
+#[derive(Clone)]
+pub struct CompressedMemoryStore {
+    store: MemoryStore,
+    level: i32,
+}
+
+impl CompressedMemoryStore {
+    /// Creates a store that compresses values at a zstd compression level.
+    pub fn new(level: i32) -> Self {
+        Self {
+            store: MemoryStore::default(),
+            level,
+        }
+    }
+
+    /// Pushes an entry that's already compressed, without recompressing it.
+    pub fn push_compressed(&self, entry: &CompressedEntry) -> Result<(), Error> {
+        self.store.push(Cow::Borrowed(entry.as_entry()))
+    }
+
+    /// Like `latest`, but leaves the entry compressed.
+    pub fn latest_compressed<A: Into<Atom>>(&self, name: A) -> Result<Option<CompressedEntry>, Error> {
+        self.store
+            .latest(name)?
+            .map(CompressedEntry::from_compressed)
+            .transpose()
+    }
+}
+
+impl Default for CompressedMemoryStore {
+    fn default() -> Self {
+        Self::new(ZstdCodec::DEFAULT_LEVEL)
+    }
+}
+
+impl Store for CompressedMemoryStore {
+    fn push(&self, entry: Cow<Entry>) -> Result<(), Error> {
+        self.push_compressed(&CompressedEntry::new(entry.into_owned(), self.level)?)
+    }
+
+    fn latest<A: Into<Atom>>(&self, name: A) -> Result<Option<Entry>, Error> {
+        self.latest_compressed(name)?
+            .map(|entry| entry.decompress())
+            .transpose()
+    }
+
+    fn latest_n<A: Into<Atom>>(&self, name: A, n: usize) -> Result<Vec<Entry>, Error> {
+        self.store
+            .latest_n(name, n)?
+            .into_iter()
+            .map(|entry| CompressedEntry::from_compressed(entry)?.decompress())
+            .collect()
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::RANGE | Capabilities::REMOVE | Capabilities::COMPRESSED
+    }
+}
+
+impl RangeableStore for CompressedMemoryStore {
+    type Range = CompressedMemoryRange;
+
+    fn range<A: Into<Atom>, R: RangeBounds<i64>>(&self, range: R, name: Option<A>) -> Result<Self::Range, Error> {
+        Ok(CompressedMemoryRange {
+            range: self.store.range(range, name)?,
+        })
+    }
+
+    /// Entries are taken out of the underlying store in one go, and then
+    /// decompressed.
+    fn drain_all(&self) -> Result<Vec<Entry>, Error> {
+        self.store
+            .drain_all()?
+            .into_iter()
+            .map(|entry| CompressedEntry::from_compressed(entry)?.decompress())
+            .collect()
+    }
+
+    fn rename<A: Into<Atom>, B: Into<Atom>>(&self, old: A, new: B) -> Result<u64, Error> {
+        self.store.rename(old, new)
+    }
+}
+
+pub struct CompressedMemoryRange {
+    range: MemoryRange,
+}
+
+/// Iterates like `iter`, e.g. in `for` loops.
+impl IntoIterator for CompressedMemoryRange {
+    type Item = Result<Entry, Error>;
+    type IntoIter = CompressedMemoryRangeIterator;
+
+    fn into_iter(self) -> Self::IntoIter {
+        CompressedMemoryRangeIterator {
+            inner: self.range.into_iter(),
+        }
+    }
+}
+
+impl Range for CompressedMemoryRange {
+    type Iter = CompressedMemoryRangeIterator;
+
+    fn count(&self) -> Result<u64, Error> {
+        self.range.count()
+    }
+
+    fn is_empty(&self) -> Result<bool, Error> {
+        self.range.is_empty()
+    }
+
+    fn remove(self) -> Result<(), Error> {
+        self.range.remove()
+    }
+
+    fn iter(self) -> Result<Self::Iter, Error> {
+        Ok(CompressedMemoryRangeIterator {
+            inner: self.range.iter()?,
+        })
+    }
+
+    fn iter_timestamps(self) -> Result<impl Iterator<Item = Result<i64, Error>>, Error> {
+        self.range.iter_timestamps()
+    }
+
+    fn iter_names_and_timestamps(self) -> Result<impl Iterator<Item = Result<(Atom, i64), Error>>, Error> {
+        self.range.iter_names_and_timestamps()
+    }
+}
+
+/// Iterates over a range, decompressing each value as it's reached. If
+/// decompressing a value fails, its error is yielded in place of the entry.
+pub struct CompressedMemoryRangeIterator {
+    inner: VecIter<Result<Entry, Error>>,
+}
+
+impl Iterator for CompressedMemoryRangeIterator {
+    type Item = Result<Entry, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner
+            .next()
+            .map(|entry| CompressedEntry::from_compressed(entry?)?.decompress())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use crate::{
+        test_rangeable_store_impl, test_store_impl, CompressedEntry, CompressedMemoryStore, Entry, Range,
+        RangeableStore, Store,
+    };
+
+    test_store_impl!(|_| CompressedMemoryStore::default());
+    test_rangeable_store_impl!(|_| CompressedMemoryStore::default());
+
+    fn text(len: usize) -> Vec<u8> {
+        let words = ["binlog ", "entry ", "value ", "sensor ", "reading ", "temperature "];
+        words.iter().cycle().flat_map(|word| word.bytes()).take(len).collect()
+    }
+
+    #[test]
+    fn compressed_entry() {
+        let entry = Entry::new_with_timestamp(1, "test_compressed_entry", text(10 * 1024));
+        let compressed = CompressedEntry::new(entry.clone(), 3).unwrap();
+        assert!(compressed.compressed_size() < 1024);
+        assert_eq!(compressed.decompressed_size(), 10 * 1024);
+        assert_eq!(compressed.decompress().unwrap(), entry);
+
+        let empty = Entry::new_with_timestamp(1, "test_compressed_entry", vec![]);
+        assert_eq!(
+            CompressedEntry::new(empty.clone(), 3).unwrap().decompress().unwrap(),
+            empty
+        );
+    }
+
+    #[test]
+    fn push_compressed() {
+        let store = CompressedMemoryStore::new(3);
+        let entry = Entry::new_with_timestamp(1, "test_push_compressed", text(1024));
+        store
+            .push_compressed(&CompressedEntry::new(entry.clone(), 19).unwrap())
+            .unwrap();
+        let latest = store.latest_compressed("test_push_compressed").unwrap().unwrap();
+        assert_eq!(latest.decompressed_size(), 1024);
+        assert_eq!(latest.decompress().unwrap(), entry);
+
+        store.push(Cow::Owned(entry.clone())).unwrap();
+        assert_eq!(store.latest("test_push_compressed").unwrap(), Some(entry.clone()));
+        for result in store.range(.., Some("test_push_compressed")).unwrap() {
+            assert_eq!(result.unwrap(), entry);
+        }
+        assert_eq!(store.latest("test_push_compressed_missing").unwrap(), None);
+    }
+
+    #[test]
+    fn iter_decompresses_lazily() {
+        let store = CompressedMemoryStore::new(3);
+        let entry = Entry::new_with_timestamp(1, "test_iter_decompresses_lazily", text(1024));
+        store.push(Cow::Owned(entry.clone())).unwrap();
+        // not a zstd frame, so only this entry fails to decompress
+        store
+            .store
+            .push(Cow::Owned(Entry::new_with_timestamp(
+                2,
+                "test_iter_decompresses_lazily",
+                vec![0; 16],
+            )))
+            .unwrap();
+        let later = Entry::new_with_timestamp(3, "test_iter_decompresses_lazily", text(512));
+        store.push(Cow::Owned(later.clone())).unwrap();
+
+        let mut iter = store
+            .range(.., Some("test_iter_decompresses_lazily"))
+            .unwrap()
+            .iter()
+            .unwrap();
+        assert_eq!(iter.size_hint(), (3, Some(3)));
+        assert_eq!(iter.next().unwrap().unwrap(), entry);
+        assert!(iter.next().unwrap().is_err());
+        assert_eq!(iter.next().unwrap().unwrap(), later);
+        assert!(iter.next().is_none());
+    }
+}
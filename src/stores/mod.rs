@@ -1,6 +1,17 @@
+#[cfg(feature = "compression")]
+pub mod compressed;
 pub mod memory;
 #[cfg(feature = "redis-store")]
 pub mod redis;
+#[cfg(feature = "redis-sentinel")]
+mod redis_sentinel;
+#[cfg(all(feature = "shmem", unix))]
+pub mod shm;
 #[cfg(feature = "sqlite-store")]
 pub mod sqlite;
+#[cfg(feature = "sqlite-store")]
+pub mod sqlite_partitioned;
+#[cfg(feature = "sea-query")]
+mod sqlite_schema;
+pub mod tee;
 pub mod traits;
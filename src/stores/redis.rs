@@ -1,18 +1,69 @@
 use std::borrow::Cow;
 use std::error::Error as StdError;
 use std::io::{Error as IoError, ErrorKind as IoErrorKind};
+use std::ops::{Deref, DerefMut, RangeBounds};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender, TryRecvError};
 use std::sync::{Arc, Mutex};
+use std::thread;
 use std::time::Duration;
+use std::vec::IntoIter as VecIter;
 
-use crate::{Entry, Error, Store, SubscribeableStore, Subscription};
+use crate::{
+    utils, Capabilities, Entry, Error, PushCallback, Range, RangeableStore, SlowOpKind, Store, StoreOptions,
+    SubscribeableStore, Subscription, SubscriptionDyn, Waker,
+};
+
+#[cfg(feature = "redis-sentinel")]
+use super::redis_sentinel::SentinelConnector;
 
 use byteorder::{ByteOrder, LittleEndian};
+use r2d2::{Pool, PooledConnection};
 use redis::streams::{StreamId, StreamMaxlen, StreamRangeReply, StreamReadOptions, StreamReadReply};
-use redis::{Client, Cmd, Commands, Connection, ConnectionLike, IntoConnectionInfo, RedisError, Value};
+use redis::{Client, Cmd, Commands, Connection, FromRedisValue, IntoConnectionInfo, RedisError, Script, Value};
 use string_cache::DefaultAtom as Atom;
 
 static STREAM_READ_BLOCK_MS: usize = 1000;
 static CONN_POOL_MAX_COUNT: usize = 4;
+static DEFAULT_KEY_PREFIX: &str = "binlog:stream:v0:";
+// Key prefixes end with this, and names can't contain it, so that a store's
+// keys can't be mistaken for those of a store with a longer prefix
+static KEY_DELIMITER: char = ':';
+// The field set on entries flagged as skewed. It's omitted otherwise.
+static SKEWED_FIELD: &str = "skewed";
+// The field set on entries pushed by `push_or_replace`, which may replace them
+static REPLACEABLE_FIELD: &str = "replaceable";
+// Finds the entry `push_or_replace` replaces, i.e. the one with the timestamp
+// field `ARGV[1]` set to `ARGV[2]` and the field `ARGV[3]` set, then adds the
+// new entry with the remaining arguments to `XADD`, and deletes the old one.
+// It's added first, so that nothing is deleted if adding fails.
+static PUSH_OR_REPLACE_SCRIPT: &str = r#"
+local replaced = nil
+for _, entry in ipairs(redis.call('XRANGE', KEYS[1], '-', '+')) do
+    local fields = entry[2]
+    local timestamp_matches, replaceable = false, false
+    for i = 1, #fields, 2 do
+        if fields[i] == ARGV[1] and fields[i + 1] == ARGV[2] then
+            timestamp_matches = true
+        elseif fields[i] == ARGV[3] then
+            replaceable = true
+        end
+    end
+    if timestamp_matches and replaceable then
+        replaced = entry[1]
+    end
+end
+redis.call('XADD', KEYS[1], unpack(ARGV, 4))
+if replaced then
+    redis.call('XDEL', KEYS[1], replaced)
+    return 1
+end
+return 0
+"#;
+// How many stream entries `latest` reads at a time when skipping over
+// skewed entries
+static LATEST_PAGE_SIZE: usize = 100;
+// How many stream entries ranges read at a time
+static RANGE_PAGE_SIZE: usize = 1000;
 
 impl From<RedisError> for Error {
     fn from(err: RedisError) -> Self {
@@ -20,8 +71,26 @@ impl From<RedisError> for Error {
     }
 }
 
-fn redis_channel(name: &Atom) -> String {
-    format!("binlog:stream:v0:{}", name)
+/// Converts a timestamp into a stream ID, with the milliseconds as the first
+/// part of the ID and the remaining microseconds as the sequence number.
+fn timestamp_stream_id(timestamp: i64) -> Result<String, Error> {
+    // redis does not allow the stream ID `0-0`
+    if timestamp <= 0 {
+        return Err(Error::InvalidEntry(
+            "only positive timestamps can be used as stream ids".to_string(),
+        ));
+    }
+    Ok(format!("{}-{}", timestamp / 1000, timestamp % 1000))
+}
+
+fn check_name(name: &str) -> Result<(), Error> {
+    if name.contains(KEY_DELIMITER) {
+        return Err(Error::InvalidEntry(format!(
+            "redis store names cannot contain `{}`: {:?}",
+            KEY_DELIMITER, name
+        )));
+    }
+    Ok(())
 }
 
 fn invalid_data_err<E: Into<Box<dyn StdError + Send + Sync>>>(msg: E) -> Error {
@@ -32,36 +101,365 @@ fn unexpected_data_format() -> Error {
     invalid_data_err("unexpected data format received from redis")
 }
 
-fn entry_from_stream_id(stream_id: &StreamId, name: Atom) -> Result<Entry, Error> {
-    let (timestamp, value) = match (stream_id.map.get("timestamp"), stream_id.map.get("value")) {
-        (Some(Value::Data(timestamp_bytes)), Some(Value::Data(value_bytes))) => {
-            (LittleEndian::read_i64(timestamp_bytes), value_bytes)
+/// Converts a stream ID back into a timestamp; the inverse of
+/// `timestamp_stream_id`.
+fn stream_id_timestamp(id: &str) -> Result<i64, Error> {
+    let (ms, seq) = id.split_once('-').ok_or_else(unexpected_data_format)?;
+    let ms: i64 = ms.parse().map_err(|_| unexpected_data_format())?;
+    let seq: i64 = seq.parse().map_err(|_| unexpected_data_format())?;
+    if seq >= 1000 {
+        return Err(invalid_data_err(format!(
+            "stream id {} cannot be converted into a timestamp",
+            id
+        )));
+    }
+    ms.checked_mul(1000)
+        .and_then(|micros| micros.checked_add(seq))
+        .ok_or_else(unexpected_data_format)
+}
+
+/// The fields of redis stream entries that entries are stored in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RedisFieldLayout {
+    /// The field holding the timestamp, as a little-endian i64. If `None`,
+    /// the timestamp is instead derived from the stream ID: the ID `ms-seq`
+    /// has the timestamp `ms * 1000 + seq`, so stream IDs must have sequence
+    /// numbers under 1000. Pushes then always use stream IDs derived from
+    /// timestamps; see `RedisStreamStore::with_timestamp_as_stream_id`.
+    pub timestamp_field: Option<String>,
+    /// The field holding the value.
+    pub value_field: String,
+}
+
+impl Default for RedisFieldLayout {
+    fn default() -> Self {
+        Self {
+            timestamp_field: Some("timestamp".to_string()),
+            value_field: "value".to_string(),
         }
-        _ => {
-            return Err(unexpected_data_format());
+    }
+}
+
+impl RedisFieldLayout {
+    fn entry_from_stream_id(&self, stream_id: &StreamId, name: Atom) -> Result<Entry, Error> {
+        let timestamp = match &self.timestamp_field {
+            Some(timestamp_field) => match stream_id.map.get(timestamp_field) {
+                Some(Value::Data(timestamp_bytes)) if timestamp_bytes.len() == 8 => {
+                    LittleEndian::read_i64(timestamp_bytes)
+                }
+                _ => return Err(unexpected_data_format()),
+            },
+            None => stream_id_timestamp(&stream_id.id)?,
+        };
+        let value = match stream_id.map.get(&self.value_field) {
+            Some(Value::Data(value_bytes)) => value_bytes,
+            _ => return Err(unexpected_data_format()),
+        };
+        let mut entry = Entry::new_with_timestamp(timestamp, name, value.clone());
+        entry.skewed = stream_id.map.contains_key(SKEWED_FIELD);
+        Ok(entry)
+    }
+}
+
+/// A connection that is either owned, or borrowed from an external pool and
+/// returned to it when dropped.
+enum RedisConnection {
+    Owned(Connection),
+    Pooled(PooledConnection<Client>),
+}
+
+impl Deref for RedisConnection {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        match self {
+            RedisConnection::Owned(conn) => conn,
+            RedisConnection::Pooled(conn) => conn,
         }
-    };
-    Ok(Entry::new_with_timestamp(timestamp, name, value.clone()))
+    }
+}
+
+impl DerefMut for RedisConnection {
+    fn deref_mut(&mut self) -> &mut Connection {
+        match self {
+            RedisConnection::Owned(conn) => conn,
+            RedisConnection::Pooled(conn) => conn,
+        }
+    }
 }
 
+/// Creates connections, either to a fixed server, from an external pool, or
+/// to whichever server the sentinels report as the current master.
+#[derive(Clone)]
+enum RedisConnector {
+    Client(Client),
+    Pool(Pool<Client>),
+    #[cfg(feature = "redis-sentinel")]
+    Sentinel(Arc<SentinelConnector>),
+}
+
+impl RedisConnector {
+    fn get_connection(&self) -> Result<RedisConnection, Error> {
+        match self {
+            RedisConnector::Client(client) => Ok(RedisConnection::Owned(client.get_connection()?)),
+            RedisConnector::Pool(pool) => Ok(RedisConnection::Pooled(pool.get()?)),
+            #[cfg(feature = "redis-sentinel")]
+            RedisConnector::Sentinel(sentinel) => Ok(RedisConnection::Owned(sentinel.get_connection()?)),
+        }
+    }
+
+    /// Returns whether an error indicates that connections should be
+    /// re-established because the master may have failed over.
+    #[cfg_attr(not(feature = "redis-sentinel"), allow(unused_variables))]
+    fn is_failover_error(&self, err: &Error) -> bool {
+        match self {
+            RedisConnector::Client(_) | RedisConnector::Pool(_) => false,
+            #[cfg(feature = "redis-sentinel")]
+            RedisConnector::Sentinel(_) => SentinelConnector::is_failover_error(err),
+        }
+    }
+}
+
+/// A store backed by one redis stream per name. Streams retain every entry
+/// unless capped via `with_max_len`; they used to be trimmed to the latest
+/// entry by default, so stores that relied on that must now set
+/// `with_max_len(Some(1))`.
 #[derive(Clone)]
 pub struct RedisStreamStore {
-    client: Client,
+    connector: RedisConnector,
     conn_pool: Arc<Mutex<Vec<Connection>>>,
+    on_push: Option<PushCallback>,
+    max_len: Option<usize>,
+    max_value_len: Option<usize>,
+    use_timestamp_as_stream_id: bool,
+    key_prefix: String,
+    field_layout: Arc<RedisFieldLayout>,
+    options: StoreOptions,
 }
 
 impl RedisStreamStore {
     pub fn new_with_client(client: Client) -> Self {
+        Self::new_with_connector(RedisConnector::Client(client))
+    }
+
+    fn new_with_connector(connector: RedisConnector) -> Self {
         Self {
-            client,
+            connector,
             conn_pool: Arc::new(Mutex::new(Vec::default())),
+            on_push: None,
+            max_len: None,
+            max_value_len: None,
+            use_timestamp_as_stream_id: false,
+            key_prefix: DEFAULT_KEY_PREFIX.to_string(),
+            field_layout: Arc::new(RedisFieldLayout::default()),
+            options: StoreOptions::default(),
         }
     }
 
+    /// Creates a store that borrows connections from an existing pool, rather
+    /// than maintaining its own. Note that subscriptions hold onto a
+    /// connection from the pool for as long as they're alive, and another
+    /// one while they're used with `select`.
+    pub fn new_with_pool(pool: Pool<Client>) -> Self {
+        Self::new_with_connector(RedisConnector::Pool(pool))
+    }
+
     pub fn new<T: IntoConnectionInfo>(params: T) -> Result<Self, Error> {
         Ok(Self::new_with_client(Client::open(params)?))
     }
 
+    /// Creates a store that connects to the master of `service_name`, as
+    /// reported by the given sentinels, which are tried in order. `password`
+    /// is used to authenticate with the master, not the sentinels.
+    ///
+    /// After a failover, operations that fail because the old master went
+    /// away or was demoted to a read-only replica return the error, and
+    /// subsequent operations connect to the new master. Subscriptions
+    /// reconnect to the new master transparently.
+    #[cfg(feature = "redis-sentinel")]
+    pub fn new_sentinel(sentinels: Vec<String>, service_name: String, password: Option<String>) -> Result<Self, Error> {
+        let connector = SentinelConnector::new(sentinels, service_name, password)?;
+        Ok(Self::new_with_connector(RedisConnector::Sentinel(Arc::new(connector))))
+    }
+
+    /// Creates a store whose stream keys start with `key_prefix`, rather than
+    /// the default of `binlog:stream:v0:`. Stores with different prefixes
+    /// are isolated from each other, even when sharing a redis instance, and
+    /// even if one prefix starts with the other. The prefix must end with
+    /// `:`, and cannot contain glob characters. Names used with redis stores
+    /// cannot contain `:`.
+    pub fn new_with_prefix<T: IntoConnectionInfo>(params: T, key_prefix: &str) -> Result<Self, Error> {
+        Self::new_with_client_and_prefix(Client::open(params)?, key_prefix)
+    }
+
+    pub fn new_with_client_and_prefix(client: Client, key_prefix: &str) -> Result<Self, Error> {
+        if !key_prefix.ends_with(KEY_DELIMITER) {
+            return Err(Error::InvalidConfig(format!(
+                "redis key prefix must end with `{}`: {}",
+                KEY_DELIMITER, key_prefix
+            )));
+        }
+        if key_prefix.contains(['*', '?', '[', ']', '\\']) {
+            return Err(Error::InvalidConfig(format!(
+                "redis key prefix cannot contain glob characters: {}",
+                key_prefix
+            )));
+        }
+        let mut store = Self::new_with_client(client);
+        store.key_prefix = key_prefix.to_string();
+        Ok(store)
+    }
+
+    /// Gets the redis key of the stream that holds entries with the given
+    /// name.
+    pub fn channel_for<A: Into<Atom>>(&self, name: A) -> String {
+        format!("{}{}", self.key_prefix, name.into())
+    }
+
+    /// Like `channel_for`, but fails for names that could reach into the
+    /// keys of a store with a longer prefix.
+    fn checked_channel_for(&self, name: &Atom) -> Result<String, Error> {
+        check_name(name)?;
+        Ok(self.channel_for(name.clone()))
+    }
+
+    /// Like `subscribe`, but also replays the entries still retained in the
+    /// stream, oldest first.
+    pub fn subscribe_from_beginning<A: Into<Atom>>(&self, name: A) -> Result<RedisStreamSubscription, Error> {
+        self.subscribe_from_id(name, "0")
+    }
+
+    /// Like `subscribe`, but also replays the entries still retained in the
+    /// stream after the given stream ID, e.g. one returned by `push_with_id`
+    /// or `RedisStreamSubscription::next_with_id`.
+    pub fn subscribe_from_id<A: Into<Atom>>(&self, name: A, id: &str) -> Result<RedisStreamSubscription, Error> {
+        let conn = self.connector.get_connection()?;
+        let name = name.into();
+        let channel = self.checked_channel_for(&name)?;
+        Ok(RedisStreamSubscription::new(
+            self.connector.clone(),
+            conn,
+            name,
+            channel,
+            self.field_layout.clone(),
+            id.to_string(),
+        ))
+    }
+
+    /// Sets a callback that is invoked after every successful push,
+    /// regardless of the entry's name.
+    pub fn with_on_push(mut self, callback: PushCallback) -> Self {
+        self.on_push = Some(callback);
+        self
+    }
+
+    /// Sets the maximum number of entries retained per stream, or `None` to
+    /// retain every entry. Streams are trimmed to this length on every push,
+    /// discarding their oldest entries, e.g. `Some(1)` retains only the
+    /// latest entry. Defaults to `None`, so streams grow until trimmed or
+    /// removed.
+    pub fn with_max_len(mut self, max_len: Option<usize>) -> Self {
+        self.max_len = max_len;
+        self
+    }
+
+    /// Sets the maximum size of a value in bytes, or `None` for no limit
+    /// beyond redis' own. Pushing a larger value fails with
+    /// `Error::InvalidEntry`, and nothing is written to redis.
+    pub fn with_max_value_len(mut self, max_value_len: Option<usize>) -> Self {
+        self.max_value_len = max_value_len;
+        self
+    }
+
+    /// Sets whether stream IDs are derived from entry timestamps, rather than
+    /// assigned by redis. This allows ranges to be served natively by
+    /// `XRANGE`, which is otherwise only possible by scanning whole streams.
+    /// The ID's first part is the timestamp's milliseconds, and its sequence
+    /// number is the remaining microseconds, so entries within the same
+    /// millisecond don't collide.
+    ///
+    /// The tradeoff is that redis requires stream IDs to be increasing. An
+    /// entry is rejected if its timestamp is not greater than that of every
+    /// entry previously pushed with the same name, which also means two
+    /// entries with the same name and timestamp cannot both be pushed. Only
+    /// positive timestamps are allowed.
+    pub fn with_timestamp_as_stream_id(mut self, enabled: bool) -> Self {
+        self.use_timestamp_as_stream_id = enabled;
+        self
+    }
+
+    /// Sets the fields that entries are stored in, e.g. to interoperate with
+    /// streams produced by other systems.
+    pub fn with_field_layout(mut self, field_layout: RedisFieldLayout) -> Self {
+        self.field_layout = Arc::new(field_layout);
+        self
+    }
+
+    /// Sets options, e.g. to guard against entries pushed with skewed
+    /// clocks. Note that with a `max_len` of 1, a flagged entry still
+    /// replaces the entry before it, so ignoring flagged entries in `latest`
+    /// requires retaining more entries.
+    pub fn with_options(mut self, options: StoreOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    fn uses_timestamp_stream_ids(&self) -> bool {
+        self.use_timestamp_as_stream_id || self.field_layout.timestamp_field.is_none()
+    }
+
+    fn xadd_cmd(&self, entry: &Entry) -> Result<Cmd, Error> {
+        self.xadd_parts_cmd(entry.timestamp, &entry.name, &entry.value, entry.skewed)
+    }
+
+    fn check_value_len(&self, value: &[u8]) -> Result<(), Error> {
+        if let Some(max_value_len) = self.max_value_len {
+            if value.len() > max_value_len {
+                return Err(Error::InvalidEntry(format!(
+                    "value is {} bytes, over the store's limit of {} bytes",
+                    value.len(),
+                    max_value_len
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// The fields an entry is written with, given its encoded timestamp.
+    fn stream_fields<'a>(
+        &'a self,
+        timestamp_bytes: &'a [u8],
+        value: &'a [u8],
+        skewed: bool,
+    ) -> Vec<(&'a str, &'a [u8])> {
+        let mut items = Vec::with_capacity(2);
+        if let Some(timestamp_field) = &self.field_layout.timestamp_field {
+            items.push((timestamp_field.as_str(), timestamp_bytes));
+        }
+        items.push((self.field_layout.value_field.as_str(), value));
+        if skewed {
+            items.push((SKEWED_FIELD, b"1".as_slice()));
+        }
+        items
+    }
+
+    fn xadd_parts_cmd(&self, timestamp: i64, name: &Atom, value: &[u8], skewed: bool) -> Result<Cmd, Error> {
+        self.check_value_len(value)?;
+        let channel = self.checked_channel_for(name)?;
+        let id = if self.uses_timestamp_stream_ids() {
+            timestamp_stream_id(timestamp)?
+        } else {
+            "*".to_string()
+        };
+        let mut timestamp_bytes = [0; 8];
+        LittleEndian::write_i64(&mut timestamp_bytes, timestamp);
+        let items = self.stream_fields(&timestamp_bytes, value, skewed);
+        Ok(match self.max_len {
+            Some(max_len) => Cmd::xadd_maxlen(channel, StreamMaxlen::Equals(max_len), id, &items),
+            None => Cmd::xadd(channel, id, &items),
+        })
+    }
+
     fn with_connection<T, F>(&self, f: F) -> Result<T, Error>
     where
         F: FnOnce(&mut Connection) -> Result<T, Error>,
@@ -69,106 +467,637 @@ impl RedisStreamStore {
         let mut conn = {
             let mut conn_pool = self.conn_pool.lock().unwrap();
             if let Some(conn) = conn_pool.pop() {
-                conn
+                RedisConnection::Owned(conn)
             } else {
-                self.client.get_connection()?
+                self.connector.get_connection()?
             }
         };
 
         // It's possible that the connection is in a bad state, so don't return
-        // it to the pool if an error occurred.
-        let result = f(&mut conn)?;
+        // it to the pool if an error occurred. If the master failed over, the
+        // pooled connections are stale too.
+        let result = match f(&mut conn) {
+            Ok(result) => result,
+            Err(err) => {
+                if self.connector.is_failover_error(&err) {
+                    self.conn_pool.lock().unwrap().clear();
+                }
+                return Err(err);
+            }
+        };
+
+        // Connections from an external pool are returned to it when dropped
+        if let RedisConnection::Owned(conn) = conn {
+            let mut conn_pool = self.conn_pool.lock().unwrap();
+            if conn_pool.len() < CONN_POOL_MAX_COUNT {
+                conn_pool.push(conn);
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Pushes an entry, returning the ID redis assigned to it in the stream.
+    pub fn push_with_id(&self, entry: Cow<Entry>) -> Result<String, Error> {
+        let timer = self.options.start_op();
+        let entry = self.options.check_skew(entry)?;
+        let cmd = self.xadd_cmd(&entry)?;
+        let id: String = self.with_connection(|conn| Ok(cmd.query(conn)?))?;
+
+        if let Some(on_push) = &self.on_push {
+            on_push(&entry);
+        }
+        if let Some(timer) = timer {
+            timer.finish(SlowOpKind::Push, Some(&entry.name), 1, || None);
+        }
+        Ok(id)
+    }
+
+    /// Gets the newest entry with a name, paging past flagged entries if
+    /// they're ignored.
+    fn find_latest(&self, name: Atom) -> Result<Option<Entry>, Error> {
+        let channel = self.checked_channel_for(&name)?;
+        let ignore_skewed = self.options.ignores_skewed_in_latest();
+        let page_size = if ignore_skewed { LATEST_PAGE_SIZE } else { 1 };
+
+        // Pages are read newest first. Each page after the first starts at
+        // the last ID of the previous one, since `XREVRANGE` is inclusive.
+        let mut end = "+".to_string();
+        loop {
+            let reply: StreamRangeReply = self.with_connection(|conn| {
+                let value = conn.xrevrange_count(&channel, &end, "-", page_size)?;
+                Ok(value)
+            })?;
+            let page_len = reply.ids.len();
+            for stream_id in reply.ids {
+                if stream_id.id == end {
+                    continue;
+                }
+                let entry = self.field_layout.entry_from_stream_id(&stream_id, name.clone())?;
+                if !(ignore_skewed && entry.skewed) {
+                    return Ok(Some(entry));
+                }
+                end = stream_id.id;
+            }
+            if page_len < page_size {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Gets up to the `n` newest entries with a name, oldest first, paging
+    /// past flagged entries if they're ignored.
+    fn find_latest_n(&self, name: Atom, n: usize) -> Result<Vec<Entry>, Error> {
+        let channel = self.checked_channel_for(&name)?;
+        let ignore_skewed = self.options.ignores_skewed_in_latest();
+        let mut entries = Vec::new();
 
-        let mut conn_pool = self.conn_pool.lock().unwrap();
-        if conn_pool.len() < CONN_POOL_MAX_COUNT {
-            conn_pool.push(conn);
+        // Pages are read newest first, as in `find_latest`.
+        let mut end = "+".to_string();
+        while entries.len() < n {
+            let mut page_size = n - entries.len();
+            if ignore_skewed {
+                page_size = page_size.max(LATEST_PAGE_SIZE);
+            }
+            if end != "+" {
+                page_size = page_size.saturating_add(1);
+            }
+            let reply: StreamRangeReply = self.with_connection(|conn| {
+                let value = conn.xrevrange_count(&channel, &end, "-", page_size)?;
+                Ok(value)
+            })?;
+            let page_len = reply.ids.len();
+            for stream_id in reply.ids {
+                if stream_id.id == end {
+                    continue;
+                }
+                let entry = self.field_layout.entry_from_stream_id(&stream_id, name.clone())?;
+                if !(ignore_skewed && entry.skewed) {
+                    entries.push(entry);
+                    if entries.len() == n {
+                        break;
+                    }
+                }
+                end = stream_id.id;
+            }
+            if page_len < page_size {
+                break;
+            }
         }
+        entries.reverse();
+        Ok(entries)
+    }
+
+    /// Pushes several entries in a single pipelined round-trip, returning the
+    /// stream ID assigned to each entry, in order.
+    pub fn push_batch_with_ids<'a, I: IntoIterator<Item = Cow<'a, Entry>>>(
+        &self,
+        entries: I,
+    ) -> Result<Vec<String>, Error> {
+        self.push_pipelined(entries, false)
+    }
 
+    /// Like `push_batch_with_ids`, but redis' replies are discarded rather
+    /// than parsed into IDs.
+    pub fn push_many_pipelined<'a, I: IntoIterator<Item = Cow<'a, Entry>>>(&self, entries: I) -> Result<(), Error> {
+        self.push_pipelined(entries, true)
+    }
+
+    fn push_pipelined<'a, I, T>(&self, entries: I, ignore_ids: bool) -> Result<T, Error>
+    where
+        I: IntoIterator<Item = Cow<'a, Entry>>,
+        T: FromRedisValue + Default,
+    {
+        let timer = self.options.start_op();
+        let entries = entries
+            .into_iter()
+            .map(|entry| self.options.check_skew(entry))
+            .collect::<Result<Vec<Cow<Entry>>, Error>>()?;
+        if entries.is_empty() {
+            return Ok(T::default());
+        }
+
+        let mut pipe = redis::pipe();
+        for entry in entries.iter() {
+            pipe.add_command(self.xadd_cmd(entry)?);
+            if ignore_ids {
+                pipe.ignore();
+            }
+        }
+        let result: T = self.with_connection(|conn| Ok(pipe.query(conn)?))?;
+
+        if let Some(on_push) = &self.on_push {
+            for entry in entries.iter() {
+                on_push(entry);
+            }
+        }
+        if let Some(timer) = timer {
+            timer.finish(SlowOpKind::Push, None, entries.len() as u64, || None);
+        }
         Ok(result)
     }
 }
 
 impl Store for RedisStreamStore {
     fn push(&self, entry: Cow<Entry>) -> Result<(), Error> {
-        let channel = redis_channel(&entry.name);
+        self.push_with_id(entry)?;
+        Ok(())
+    }
+
+    /// The value is written to the command without first copying it into
+    /// an `Entry`, which is only built if there's an `on_push` callback.
+    fn push_parts(&self, timestamp: i64, name: &Atom, value: &[u8]) -> Result<(), Error> {
+        let timer = self.options.start_op();
+        let (timestamp, skewed) = self
+            .options
+            .check_skew_timestamp(timestamp)?
+            .unwrap_or((timestamp, false));
+        let cmd = self.xadd_parts_cmd(timestamp, name, value, skewed)?;
+        let _: String = self.with_connection(|conn| Ok(cmd.query(conn)?))?;
+
+        if let Some(on_push) = &self.on_push {
+            let mut entry = Entry::new_with_timestamp(timestamp, name.clone(), value.to_vec());
+            entry.skewed = skewed;
+            on_push(&entry);
+        }
+        if let Some(timer) = timer {
+            timer.finish(SlowOpKind::Push, Some(name), 1, || None);
+        }
+        Ok(())
+    }
+
+    /// Streams can't be edited, so the new entry is added to the end of the
+    /// stream and the one it replaces is deleted, atomically via a script.
+    /// Finding the replaced entry scans the stream, so this is meant for
+    /// streams bounded by `with_max_len`. Entries can't be added before the
+    /// end of a stream with stream IDs derived from timestamps, so stores
+    /// using them fail with `Error::Unsupported`.
+    fn push_or_replace(&self, entry: Cow<Entry>) -> Result<bool, Error> {
+        let timestamp_field = match &self.field_layout.timestamp_field {
+            Some(timestamp_field) if !self.use_timestamp_as_stream_id => timestamp_field,
+            _ => {
+                return Err(Error::Unsupported(
+                    "replacing values with timestamps as stream ids".to_string(),
+                ))
+            }
+        };
+        let timer = self.options.start_op();
+        let entry = self.options.check_skew(entry)?;
+        self.check_value_len(&entry.value)?;
+        let channel = self.checked_channel_for(&entry.name)?;
         let mut timestamp_bytes = [0; 8];
         LittleEndian::write_i64(&mut timestamp_bytes, entry.timestamp);
-        let cmd = Cmd::xadd_maxlen(
-            channel,
-            StreamMaxlen::Equals(1),
-            "*",
-            &[
-                ("timestamp", timestamp_bytes.as_slice()),
-                ("value", entry.value.as_slice()),
-            ],
-        );
 
-        self.with_connection(|conn| {
-            conn.req_command(&cmd)?;
-            Ok(())
-        })
+        let script = Script::new(PUSH_OR_REPLACE_SCRIPT);
+        let mut invocation = script.key(channel);
+        invocation
+            .arg(timestamp_field.as_str())
+            .arg(timestamp_bytes.as_slice())
+            .arg(REPLACEABLE_FIELD);
+        if let Some(max_len) = self.max_len {
+            invocation.arg("MAXLEN").arg("=").arg(max_len);
+        }
+        invocation.arg("*");
+        for (field, value) in self.stream_fields(&timestamp_bytes, &entry.value, entry.skewed) {
+            invocation.arg(field).arg(value);
+        }
+        invocation.arg(REPLACEABLE_FIELD).arg("1");
+        let replaced: bool = self.with_connection(|conn| Ok(invocation.invoke(conn)?))?;
+
+        if let Some(on_push) = &self.on_push {
+            on_push(&entry);
+        }
+        if let Some(timer) = timer {
+            timer.finish(SlowOpKind::Push, Some(&entry.name), 1, || None);
+        }
+        Ok(replaced)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::RANGE | Capabilities::SUBSCRIBE | Capabilities::REMOVE | Capabilities::DURABLE
     }
 
     fn latest<A: Into<Atom>>(&self, name: A) -> Result<Option<Entry>, Error> {
+        let timer = self.options.start_op();
+        let name = name.into();
+        let latest = self.find_latest(name.clone())?;
+        if let Some(timer) = timer {
+            timer.finish(SlowOpKind::Query, Some(&name), latest.is_some() as u64, || None);
+        }
+        Ok(latest)
+    }
+
+    fn latest_n<A: Into<Atom>>(&self, name: A, n: usize) -> Result<Vec<Entry>, Error> {
+        let timer = self.options.start_op();
         let name = name.into();
-        let channel = redis_channel(&name);
-        let reply: StreamRangeReply = self.with_connection(move |conn| {
-            let value = conn.xrevrange_count(channel, "+", "-", 1i8)?;
-            Ok(value)
+        let latest = self.find_latest_n(name.clone(), n)?;
+        if let Some(timer) = timer {
+            timer.finish(SlowOpKind::Query, Some(&name), latest.len() as u64, || None);
+        }
+        Ok(latest)
+    }
+}
+
+impl RangeableStore for RedisStreamStore {
+    type Range = RedisStreamRange;
+
+    fn range<A: Into<Atom>, R: RangeBounds<i64>>(&self, range: R, name: Option<A>) -> Result<Self::Range, Error> {
+        utils::check_bounds(range.start_bound(), range.end_bound())?;
+        let name = name.map(|n| n.into());
+        if let Some(name) = &name {
+            check_name(name)?;
+        }
+        Ok(RedisStreamRange {
+            store: self.clone(),
+            bounds: utils::inclusive_bounds(range.start_bound(), range.end_bound()),
+            name,
+        })
+    }
+
+    /// Each stream is read, and then the entries read are deleted by their
+    /// stream IDs, so entries pushed in between are left in the store
+    /// rather than removed without being returned.
+    fn drain_all(&self) -> Result<Vec<Entry>, Error> {
+        let range = self.range(.., Option::<Atom>::None)?;
+        let mut entries = self.with_connection(|conn| {
+            let mut entries = Vec::default();
+            for name in range.names(conn)? {
+                let (ids, drained): (Vec<String>, Vec<Entry>) = range
+                    .stream_ids(conn, &name)?
+                    .into_iter()
+                    .map(|(stream_id, entry)| (stream_id.id, entry))
+                    .unzip();
+                if !ids.is_empty() {
+                    conn.xdel::<_, _, ()>(self.channel_for(name), &ids)?;
+                }
+                entries.extend(drained);
+            }
+            Ok(entries)
         })?;
+        // As in `RedisStreamRange::iter`, a stable sort of streams visited in
+        // name order yields entries ordered by timestamp, then name, then
+        // insertion.
+        entries.sort_by_key(|entry| entry.timestamp);
+        Ok(entries)
+    }
+
+    /// Renames the stream via `RENAMENX`, so `new` is considered in use if
+    /// its stream exists, even if every entry in it was removed.
+    fn rename<A: Into<Atom>, B: Into<Atom>>(&self, old: A, new: B) -> Result<u64, Error> {
+        let (old, new) = (old.into(), new.into());
+        let (old_channel, new_channel) = (self.checked_channel_for(&old)?, self.checked_channel_for(&new)?);
+        self.with_connection(|conn| {
+            // RENAMENX fails if the stream being renamed doesn't exist
+            if !conn.exists::<_, bool>(&old_channel)? {
+                return if conn.exists::<_, bool>(&new_channel)? {
+                    Err(Error::NameInUse(new.to_string()))
+                } else {
+                    Ok(0)
+                };
+            }
+            let (count, renamed): (u64, bool) = redis::pipe()
+                .atomic()
+                .xlen(&old_channel)
+                .rename_nx(&old_channel, &new_channel)
+                .query(conn)?;
+            if renamed {
+                Ok(count)
+            } else {
+                Err(Error::NameInUse(new.to_string()))
+            }
+        })
+    }
+}
+
+/// A range of entries in redis streams. When the store derives stream IDs
+/// from timestamps, queries are served by `XRANGE` between the bounds' IDs;
+/// otherwise each stream is scanned in full. Either way, streams are read a
+/// page at a time, rather than in a single reply.
+pub struct RedisStreamRange {
+    store: RedisStreamStore,
+    bounds: Option<(i64, i64)>,
+    name: Option<Atom>,
+}
 
-        debug_assert!(reply.ids.len() <= 1);
+impl RedisStreamRange {
+    fn names(&self, conn: &mut Connection) -> Result<Vec<Atom>, Error> {
+        if let Some(name) = &self.name {
+            return Ok(vec![name.clone()]);
+        }
+        let key_prefix = &self.store.key_prefix;
+        let channels: Vec<String> = conn.scan_match(format!("{}*", key_prefix))?.collect();
+        // Keys with a delimiter past the prefix belong to stores with longer
+        // prefixes
+        let mut names: Vec<Atom> = channels
+            .iter()
+            .filter_map(|channel| channel.strip_prefix(key_prefix.as_str()))
+            .filter(|name| !name.contains(KEY_DELIMITER))
+            .map(Atom::from)
+            .collect();
+        names.sort();
+        names.dedup();
+        Ok(names)
+    }
 
-        if reply.ids.is_empty() {
-            Ok(None)
+    fn stream_ids(&self, conn: &mut Connection, name: &Atom) -> Result<Vec<(StreamId, Entry)>, Error> {
+        let (start, end) = match self.bounds {
+            Some(bounds) => bounds,
+            None => return Ok(Vec::default()),
+        };
+        let (start_id, end_id) = if self.store.uses_timestamp_stream_ids() {
+            if end <= 0 {
+                return Ok(Vec::default());
+            }
+            let start_id = if start <= 0 {
+                "-".to_string()
+            } else {
+                timestamp_stream_id(start)?
+            };
+            (start_id, timestamp_stream_id(end)?)
         } else {
-            match entry_from_stream_id(&reply.ids[0], name) {
-                Ok(entry) => Ok(Some(entry)),
-                Err(err) => Err(err),
+            ("-".to_string(), "+".to_string())
+        };
+
+        // Pages are read oldest first. Each page after the first starts at
+        // the last ID of the previous one, since `XRANGE` is inclusive.
+        let channel = self.store.channel_for(name.clone());
+        let mut results = Vec::default();
+        let mut start_id = start_id;
+        let mut first_page = true;
+        loop {
+            let reply: StreamRangeReply = conn.xrange_count(&channel, &start_id, &end_id, RANGE_PAGE_SIZE)?;
+            let page_len = reply.ids.len();
+            for stream_id in reply.ids {
+                if !first_page && stream_id.id == start_id {
+                    continue;
+                }
+                let entry = self.store.field_layout.entry_from_stream_id(&stream_id, name.clone())?;
+                start_id = stream_id.id.clone();
+                if entry.timestamp >= start && entry.timestamp <= end {
+                    results.push((stream_id, entry));
+                }
+            }
+            if page_len < RANGE_PAGE_SIZE {
+                return Ok(results);
             }
+            first_page = false;
         }
     }
 }
 
+impl Range for RedisStreamRange {
+    type Iter = VecIter<Result<Entry, Error>>;
+
+    fn count(&self) -> Result<u64, Error> {
+        if self.bounds.is_none() {
+            return Ok(0);
+        }
+        self.store.with_connection(|conn| {
+            let mut count = 0;
+            for name in self.names(conn)? {
+                count += self.stream_ids(conn, &name)?.len() as u64;
+            }
+            Ok(count)
+        })
+    }
+
+    /// Stops at the first stream with entries in the range.
+    fn is_empty(&self) -> Result<bool, Error> {
+        if self.bounds.is_none() {
+            return Ok(true);
+        }
+        self.store.with_connection(|conn| {
+            for name in self.names(conn)? {
+                if !self.stream_ids(conn, &name)?.is_empty() {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        })
+    }
+
+    fn remove(self) -> Result<(), Error> {
+        if self.bounds.is_none() {
+            return Ok(());
+        }
+        self.store.with_connection(|conn| {
+            for name in self.names(conn)? {
+                let ids: Vec<String> = self
+                    .stream_ids(conn, &name)?
+                    .into_iter()
+                    .map(|(stream_id, _)| stream_id.id)
+                    .collect();
+                if !ids.is_empty() {
+                    conn.xdel::<_, _, ()>(self.store.channel_for(name), &ids)?;
+                }
+            }
+            Ok(())
+        })
+    }
+
+    fn iter(self) -> Result<Self::Iter, Error> {
+        if self.bounds.is_none() {
+            return Ok(Vec::default().into_iter());
+        }
+        let mut entries = self.store.with_connection(|conn| {
+            let mut entries = Vec::default();
+            for name in self.names(conn)? {
+                entries.extend(self.stream_ids(conn, &name)?.into_iter().map(|(_, entry)| entry));
+            }
+            Ok(entries)
+        })?;
+        // Streams are visited in name order and are each in insertion order,
+        // so a stable sort yields entries ordered by timestamp, then name,
+        // then insertion.
+        entries.sort_by_key(|entry| entry.timestamp);
+        let entries: Vec<Result<Entry, Error>> = entries.into_iter().map(Ok).collect();
+        Ok(entries.into_iter())
+    }
+}
+
 impl SubscribeableStore for RedisStreamStore {
     type Subscription = RedisStreamSubscription;
+
+    /// Subscribes to entries pushed after this call, like
+    /// `MemoryStore::subscribe`. Use `subscribe_from_beginning` or
+    /// `subscribe_from_id` to replay retained entries.
     fn subscribe<A: Into<Atom>>(&self, name: A) -> Result<Self::Subscription, Error> {
-        let conn = self.client.get_connection()?;
-        Ok(RedisStreamSubscription::new(conn, name.into()))
+        let name = name.into();
+        let channel = self.checked_channel_for(&name)?;
+        // Start from the stream's newest ID, rather than `$`, so that entries
+        // pushed between now and the first read aren't missed
+        let reply: StreamRangeReply = self.with_connection(|conn| Ok(conn.xrevrange_count(&channel, "+", "-", 1)?))?;
+        let last_id = match reply.ids.into_iter().next() {
+            Some(stream_id) => stream_id.id,
+            None => "0".to_string(),
+        };
+        self.subscribe_from_id(name, &last_id)
+    }
+}
+
+/// Watches a stream on a dedicated connection and thread, waking registered
+/// wakers whenever new entries arrive. Dropping the watcher doesn't wait for
+/// the thread, which exits within `STREAM_READ_BLOCK_MS` of the drop, once
+/// its current read returns.
+struct RedisStreamWatcher {
+    wakers: Arc<Mutex<Vec<Waker>>>,
+    // Dropped along with the watcher, which disconnects the thread's
+    // receiver to tell it to stop
+    _stop: Sender<()>,
+}
+
+impl RedisStreamWatcher {
+    fn spawn(connector: RedisConnector, mut conn: RedisConnection, channel: String, mut last_id: String) -> Self {
+        let wakers = Arc::new(Mutex::new(Vec::<Waker>::default()));
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread_wakers = wakers.clone();
+
+        thread::spawn(move || {
+            let opts = StreamReadOptions::default().block(STREAM_READ_BLOCK_MS);
+            while let Err(TryRecvError::Empty) = stopped.try_recv() {
+                match conn.xread_options::<_, _, StreamReadReply>(&[&channel], &[&last_id], &opts) {
+                    Ok(reply) => {
+                        if let Some(stream_id) = reply.keys.into_iter().flat_map(|key| key.ids).last() {
+                            last_id = stream_id.id;
+                            thread_wakers.lock().unwrap().retain(|waker| waker.wake());
+                        }
+                    }
+                    Err(err) => {
+                        // Wake up selects so that the error surfaces when they
+                        // read from the subscription, then back off
+                        thread_wakers.lock().unwrap().retain(|waker| waker.wake());
+                        // The back off ends early if the watcher is dropped
+                        let backoff = Duration::from_millis(STREAM_READ_BLOCK_MS as u64);
+                        if let Err(RecvTimeoutError::Disconnected) = stopped.recv_timeout(backoff) {
+                            break;
+                        }
+                        if connector.is_failover_error(&err.into()) {
+                            if let Ok(new_conn) = connector.get_connection() {
+                                conn = new_conn;
+                            }
+                        }
+                    }
+                }
+            }
+        });
+
+        Self { wakers, _stop: stop }
+    }
+
+    fn register(&self, waker: Waker) {
+        let mut wakers = self.wakers.lock().unwrap();
+        wakers.retain(|waker| waker.is_alive());
+        wakers.push(waker);
     }
 }
 
 pub struct RedisStreamSubscription {
-    conn: Connection,
+    connector: RedisConnector,
+    conn: RedisConnection,
     name: Atom,
+    channel: String,
+    field_layout: Arc<RedisFieldLayout>,
     last_id: String,
+    watcher: Option<RedisStreamWatcher>,
 }
 
 impl RedisStreamSubscription {
-    fn new(conn: Connection, name: Atom) -> Self {
+    fn new(
+        connector: RedisConnector,
+        conn: RedisConnection,
+        name: Atom,
+        channel: String,
+        field_layout: Arc<RedisFieldLayout>,
+        last_id: String,
+    ) -> Self {
         RedisStreamSubscription {
+            connector,
             conn,
             name,
-            last_id: "0".to_string(),
+            channel,
+            field_layout,
+            last_id,
+            watcher: None,
         }
     }
+
+    /// Reads the first stream entry after the last one returned, without
+    /// marking it as returned.
+    fn xread(&mut self, opts: &StreamReadOptions) -> Result<Option<StreamId>, Error> {
+        let reply: StreamReadReply = match self.conn.xread_options(&[&self.channel], &[&self.last_id], opts) {
+            Ok(reply) => reply,
+            Err(err) => {
+                // Reconnect to the new master after a failover, and retry
+                let err = Error::from(err);
+                if !self.connector.is_failover_error(&err) {
+                    return Err(err);
+                }
+                self.conn = self.connector.get_connection()?;
+                self.conn.xread_options(&[&self.channel], &[&self.last_id], opts)?
+            }
+        };
+        Ok(reply.keys.into_iter().flat_map(|key| key.ids).next())
+    }
+
+    fn take(&mut self, stream_id: StreamId) -> Result<(String, Entry), Error> {
+        let value = self.field_layout.entry_from_stream_id(&stream_id, self.name.clone())?;
+        self.last_id = stream_id.id.clone();
+        Ok((stream_id.id, value))
+    }
 }
 
-impl Subscription for RedisStreamSubscription {
-    fn next(&mut self, timeout: Option<Duration>) -> Result<Option<Entry>, Error> {
-        let channels = vec![redis_channel(&self.name)];
+impl RedisStreamSubscription {
+    /// Gets the next entry along with its stream ID, which matches the ID
+    /// returned when it was pushed via `push_with_id`. Consumers can use the
+    /// ID to deduplicate entries.
+    pub fn next_with_id(&mut self, timeout: Option<Duration>) -> Result<Option<(String, Entry)>, Error> {
         let opts = StreamReadOptions::default().block(match timeout {
             Some(timeout) => timeout.as_millis().try_into().unwrap(),
             None => STREAM_READ_BLOCK_MS,
         });
         loop {
-            let reply: StreamReadReply = self.conn.xread_options(&channels, &[&self.last_id], &opts)?;
-            if let Some(stream_key) = reply.keys.into_iter().next() {
-                if let Some(stream_id) = stream_key.ids.into_iter().next() {
-                    let value = entry_from_stream_id(&stream_id, self.name.clone())?;
-                    self.last_id = stream_id.id;
-                    return Ok(Some(value));
-                }
+            if let Some(stream_id) = self.xread(&opts)? {
+                return Ok(Some(self.take(stream_id)?));
             }
             if timeout.is_some() {
                 return Ok(None);
@@ -177,16 +1106,667 @@ impl Subscription for RedisStreamSubscription {
     }
 }
 
+impl Subscription for RedisStreamSubscription {
+    fn next(&mut self, timeout: Option<Duration>) -> Result<Option<Entry>, Error> {
+        Ok(self.next_with_id(timeout)?.map(|(_, entry)| entry))
+    }
+}
+
+impl SubscriptionDyn for RedisStreamSubscription {
+    fn register_waker(&mut self, waker: Waker) -> Result<(), Error> {
+        if self.watcher.is_none() {
+            let conn = self.connector.get_connection()?;
+            self.watcher = Some(RedisStreamWatcher::spawn(
+                self.connector.clone(),
+                conn,
+                self.channel.clone(),
+                self.last_id.clone(),
+            ));
+        }
+        if let Some(watcher) = &self.watcher {
+            watcher.register(waker.clone());
+        }
+        // The waker is registered before checking for an unread entry, so
+        // that entries pushed in between aren't missed
+        if self.xread(&StreamReadOptions::default().count(1))?.is_some() {
+            waker.wake();
+        }
+        Ok(())
+    }
+
+    fn try_next(&mut self) -> Result<Option<Entry>, Error> {
+        match self.xread(&StreamReadOptions::default().count(1))? {
+            Some(stream_id) => Ok(Some(self.take(stream_id)?.1)),
+            None => Ok(None),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::{define_test, test_store_impl, test_subscribeable_store_impl, RedisStreamStore};
-    test_store_impl!(RedisStreamStore::new("redis://localhost:6379").unwrap());
-    test_subscribeable_store_impl!(RedisStreamStore::new("redis://localhost:6379").unwrap());
+    use std::borrow::Cow;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use redis::Commands;
+
+    use crate::{
+        test_store_impl, test_subscribeable_store_impl, Capabilities, Entry, EntryMetadata, Error, Range,
+        RangeableStore, RedisFieldLayout, RedisStreamStore, SkewPolicy, Store, StoreOptions, SubscribeableStore,
+        Subscription, SubscriptionDyn,
+    };
+
+    use super::STREAM_READ_BLOCK_MS;
+
+    test_store_impl!(|_| RedisStreamStore::new("redis://localhost:6379").unwrap());
+    // subscriptions only see the latest entry if it's the only one retained
+    test_subscribeable_store_impl!(|_| RedisStreamStore::new("redis://localhost:6379")
+        .unwrap()
+        .with_max_len(Some(1)));
+
+    fn parse_id(id: &str) -> (u64, u64) {
+        let (ms, seq) = id.split_once('-').unwrap();
+        (ms.parse().unwrap(), seq.parse().unwrap())
+    }
+
+    #[test]
+    fn push_with_ids() {
+        let store = RedisStreamStore::new("redis://localhost:6379")
+            .unwrap()
+            .with_max_len(Some(1));
+        let mut subscriber = store.subscribe("test_push_with_ids").unwrap();
+
+        let entry = Entry::new_with_timestamp(1, "test_push_with_ids", vec![1]);
+        let id = store.push_with_id(Cow::Borrowed(&entry)).unwrap();
+        assert_eq!(subscriber.next_with_id(None).unwrap(), Some((id.clone(), entry)));
+
+        let entries: Vec<Entry> = (2..5u8)
+            .map(|i| Entry::new_with_timestamp(i.into(), "test_push_with_ids", vec![i]))
+            .collect();
+        let ids = store.push_batch_with_ids(entries.iter().map(Cow::Borrowed)).unwrap();
+        assert_eq!(ids.len(), 3);
+        let mut last_id = parse_id(&id);
+        for id in ids.iter() {
+            assert!(parse_id(id) > last_id);
+            last_id = parse_id(id);
+        }
+
+        // only the last entry is retained in the stream
+        assert_eq!(
+            subscriber.next_with_id(None).unwrap(),
+            Some((ids[2].clone(), entries[2].clone()))
+        );
+    }
+
+    #[test]
+    fn max_lens() {
+        let name = "test_redis_max_lens";
+        let store = RedisStreamStore::new("redis://localhost:6379").unwrap();
+        let _: () = store
+            .with_connection(|conn| Ok(conn.del(store.channel_for(name))?))
+            .unwrap();
+        let stream_len = || -> usize {
+            store
+                .with_connection(|conn| Ok(conn.xlen(store.channel_for(name))?))
+                .unwrap()
+        };
+
+        // every entry is retained by default
+        for i in 0..3u8 {
+            store
+                .push(Cow::Owned(Entry::new_with_timestamp(i.into(), name, vec![i])))
+                .unwrap();
+        }
+        assert_eq!(stream_len(), 3);
+        let trimming_store = store.clone().with_max_len(Some(2));
+        trimming_store
+            .push(Cow::Owned(Entry::new_with_timestamp(3, name, vec![3])))
+            .unwrap();
+        assert_eq!(stream_len(), 2);
+
+        // oversized values are rejected without touching the stream
+        let capped_store = store.clone().with_max_value_len(Some(4));
+        capped_store
+            .push(Cow::Owned(Entry::new_with_timestamp(4, name, vec![4; 4])))
+            .unwrap();
+        let oversized = Entry::new_with_timestamp(5, name, vec![5; 5]);
+        assert!(matches!(
+            capped_store.push(Cow::Borrowed(&oversized)),
+            Err(Error::InvalidEntry(msg)) if msg == "value is 5 bytes, over the store's limit of 4 bytes"
+        ));
+        assert!(matches!(
+            capped_store.push_parts(5, &oversized.name, &oversized.value),
+            Err(Error::InvalidEntry(_))
+        ));
+        assert!(matches!(
+            capped_store.push_many_pipelined([Cow::Borrowed(&oversized)]),
+            Err(Error::InvalidEntry(_))
+        ));
+        assert_eq!(stream_len(), 3);
+        assert_eq!(capped_store.latest(name).unwrap().unwrap().value, vec![4; 4]);
+    }
+
+    #[test]
+    fn push_many_pipelined() {
+        let store = RedisStreamStore::new("redis://localhost:6379")
+            .unwrap()
+            .with_max_len(None);
+        let name = "test_push_many_pipelined";
+        let _: () = store
+            .with_connection(|conn| Ok(conn.del(store.channel_for(name))?))
+            .unwrap();
+
+        let entries: Vec<Entry> = (1..=100u8)
+            .map(|i| Entry::new_with_timestamp(i.into(), name, vec![i]))
+            .collect();
+        store.push_many_pipelined(entries.iter().map(Cow::Borrowed)).unwrap();
+        store.push_many_pipelined(Vec::<Cow<Entry>>::default()).unwrap();
+        let pushed: Vec<Entry> = store
+            .range(.., Some(name))
+            .unwrap()
+            .iter()
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(pushed, entries);
+    }
+
+    #[test]
+    fn subscribe_positions() {
+        let store = RedisStreamStore::new("redis://localhost:6379")
+            .unwrap()
+            .with_max_len(None);
+        let name = "test_subscribe_positions";
+        let _: () = store
+            .with_connection(|conn| Ok(conn.del(store.channel_for(name))?))
+            .unwrap();
+        let timeout = Some(Duration::from_millis(10));
+
+        let entries: Vec<Entry> = (1..4u8)
+            .map(|i| Entry::new_with_timestamp(i.into(), name, vec![i]))
+            .collect();
+        let ids = store.push_batch_with_ids(entries.iter().map(Cow::Borrowed)).unwrap();
+
+        // live-only by default
+        let mut live = store.subscribe(name).unwrap();
+        assert_eq!(live.next(timeout).unwrap(), None);
+        let mut from_beginning = store.subscribe_from_beginning(name).unwrap();
+        for entry in entries.iter() {
+            assert_eq!(from_beginning.next(timeout).unwrap().as_ref(), Some(entry));
+        }
+        assert_eq!(from_beginning.next(timeout).unwrap(), None);
+        let mut from_id = store.subscribe_from_id(name, &ids[0]).unwrap();
+        assert_eq!(from_id.next(timeout).unwrap().as_ref(), Some(&entries[1]));
+
+        let entry = Entry::new_with_timestamp(4, name, vec![4]);
+        store.push(Cow::Borrowed(&entry)).unwrap();
+        assert_eq!(live.next(timeout).unwrap(), Some(entry));
+        assert_eq!(live.next(timeout).unwrap(), None);
+    }
+
+    #[test]
+    fn slow_ops() {
+        let make_store = |options| {
+            RedisStreamStore::new_with_prefix("redis://localhost:6379", "test_slow_ops:")
+                .unwrap()
+                .with_options(options)
+        };
+        crate::testkit::slow_ops(make_store);
+    }
+
+    #[test]
+    fn push_or_replace() {
+        let store = RedisStreamStore::new("redis://localhost:6379").unwrap();
+        store.range(.., Some("test_push_or_replace")).unwrap().remove().unwrap();
+        crate::testkit::push_or_replace(store.clone());
+
+        let store = store.with_timestamp_as_stream_id(true);
+        let entry = Entry::new_with_timestamp(1, "test_push_or_replace", vec![1]);
+        assert!(matches!(
+            store.push_or_replace(Cow::Owned(entry)),
+            Err(Error::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn metadata_unsupported() {
+        let store = RedisStreamStore::new("redis://localhost:6379").unwrap();
+        assert!(!store.capabilities().contains(Capabilities::METADATA));
+        let entry = Entry::new_with_timestamp(1, "test_metadata_unsupported", vec![1]);
+        assert!(matches!(
+            store.push_with_metadata(Cow::Owned(entry), EntryMetadata::default()),
+            Err(Error::Unsupported(_))
+        ));
+        assert_eq!(store.latest("test_metadata_unsupported").unwrap(), None);
+    }
+
+    #[test]
+    fn skew_policies() {
+        let prefix = "test_skew_policies:";
+        let client = redis::Client::open("redis://localhost:6379").unwrap();
+        let mut conn = client.get_connection().unwrap();
+        let keys: Vec<String> = conn.keys(format!("{}*", prefix)).unwrap();
+        for key in keys {
+            let _: () = conn.del(key).unwrap();
+        }
+        let make_store = |options| {
+            RedisStreamStore::new_with_prefix("redis://localhost:6379", prefix)
+                .unwrap()
+                .with_max_len(None)
+                .with_options(options)
+        };
+        crate::testkit::skew_policies(make_store);
+
+        // `latest` pages past runs of flagged entries
+        let options = StoreOptions {
+            max_future_skew: Some(Duration::from_secs(60)),
+            skew_policy: SkewPolicy::Flag { ignore_in_latest: true },
+            ..StoreOptions::default()
+        };
+        let store = make_store(options);
+        let entry = Entry::new("test_skew_paging", vec![0]);
+        store.push(Cow::Borrowed(&entry)).unwrap();
+        let skewed: Vec<Cow<Entry>> = (0..250)
+            .map(|i| Cow::Owned(Entry::new_with_timestamp(i64::MAX - i, "test_skew_paging", vec![1])))
+            .collect();
+        store.push_batch_with_ids(skewed).unwrap();
+        let latest = store.latest("test_skew_paging").unwrap().unwrap();
+        assert_eq!(latest, entry);
+        assert!(!latest.skewed);
+    }
+
+    #[test]
+    fn key_prefix() {
+        assert!(RedisStreamStore::new_with_prefix("redis://localhost:6379", "").is_err());
+        assert!(RedisStreamStore::new_with_prefix("redis://localhost:6379", "app:*:").is_err());
+        assert!(RedisStreamStore::new_with_prefix("redis://localhost:6379", "app").is_err());
+
+        let a = RedisStreamStore::new_with_prefix("redis://localhost:6379", "test_key_prefix:a:").unwrap();
+        let b = RedisStreamStore::new_with_prefix("redis://localhost:6379", "test_key_prefix:b:").unwrap();
+        assert_eq!(a.channel_for("x"), "test_key_prefix:a:x");
+        assert_eq!(
+            RedisStreamStore::new("redis://localhost:6379")
+                .unwrap()
+                .channel_for("x"),
+            "binlog:stream:v0:x"
+        );
+
+        let mut a_subscriber = a.subscribe("x").unwrap();
+        let mut b_subscriber = b.subscribe("x").unwrap();
+        let entry = Entry::new_with_timestamp(1, "x", vec![1]);
+        a.push(Cow::Borrowed(&entry)).unwrap();
+
+        assert_eq!(a.latest("x").unwrap(), Some(entry.clone()));
+        assert_eq!(b.latest("x").unwrap(), None);
+        assert_eq!(a_subscriber.next(None).unwrap(), Some(entry));
+        assert_eq!(b_subscriber.next(Some(Duration::from_millis(10))).unwrap(), None);
+    }
+
+    #[test]
+    fn nested_key_prefixes() {
+        let outer = RedisStreamStore::new_with_prefix("redis://localhost:6379", "test_nested_key_prefixes:")
+            .unwrap()
+            .with_max_len(None);
+        let inner = RedisStreamStore::new_with_prefix("redis://localhost:6379", "test_nested_key_prefixes:inner:")
+            .unwrap()
+            .with_max_len(None);
+        let _: () = outer
+            .with_connection(|conn| {
+                let keys: Vec<String> = conn.keys("test_nested_key_prefixes:*")?;
+                if !keys.is_empty() {
+                    conn.del::<_, ()>(keys)?;
+                }
+                Ok(())
+            })
+            .unwrap();
+
+        let entry = Entry::new_with_timestamp(1, "x", vec![1]);
+        outer.push(Cow::Borrowed(&entry)).unwrap();
+        inner.push(Cow::Borrowed(&entry)).unwrap();
+        inner
+            .push(Cow::Owned(Entry::new_with_timestamp(2, "y", vec![2])))
+            .unwrap();
+
+        // the outer store neither lists nor ranges over the inner store's
+        // streams, and can't reach them by name
+        let entries: Vec<Entry> = outer
+            .range(.., None::<String>)
+            .unwrap()
+            .iter()
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(entries, vec![entry.clone()]);
+        assert_eq!(outer.range(.., None::<String>).unwrap().count().unwrap(), 1);
+        assert!(outer.latest("inner:y").is_err());
+        assert!(outer.range(.., Some("inner:y")).is_err());
+        assert!(outer
+            .push(Cow::Owned(Entry::new_with_timestamp(3, "inner:y", vec![3])))
+            .is_err());
+        assert_eq!(inner.range(.., None::<String>).unwrap().count().unwrap(), 2);
+    }
+
+    #[test]
+    fn field_layout() {
+        let layout = RedisFieldLayout {
+            timestamp_field: Some("ts".to_string()),
+            value_field: "payload".to_string(),
+        };
+        let store = RedisStreamStore::new("redis://localhost:6379")
+            .unwrap()
+            .with_field_layout(layout);
+        let _: () = store
+            .with_connection(|conn| {
+                let channels = [
+                    store.channel_for("test_field_layout"),
+                    store.channel_for("test_field_layout_ids"),
+                ];
+                Ok(conn.del(&channels)?)
+            })
+            .unwrap();
+        let entry = Entry::new_with_timestamp(1, "test_field_layout", vec![1]);
+        store.push(Cow::Borrowed(&entry)).unwrap();
+        assert_eq!(store.latest("test_field_layout").unwrap(), Some(entry));
+        // the default layout can't read the entry
+        let default_store = RedisStreamStore::new("redis://localhost:6379").unwrap();
+        assert!(default_store.latest("test_field_layout").is_err());
+
+        // entries written by other systems, with timestamps in the stream ids
+        let layout = RedisFieldLayout {
+            timestamp_field: None,
+            value_field: "payload".to_string(),
+        };
+        let store = RedisStreamStore::new("redis://localhost:6379")
+            .unwrap()
+            .with_field_layout(layout)
+            .with_max_len(None);
+        let client = redis::Client::open("redis://localhost:6379").unwrap();
+        let mut conn = client.get_connection().unwrap();
+        let channel = store.channel_for("test_field_layout_ids");
+        let _: String = conn.xadd(&channel, "5-0", &[("payload", vec![5u8])]).unwrap();
+        let _: String = conn.xadd(&channel, "6-2", &[("payload", vec![6u8])]).unwrap();
+        let entry = Entry::new_with_timestamp(7001, "test_field_layout_ids", vec![7]);
+        assert_eq!(store.push_with_id(Cow::Borrowed(&entry)).unwrap(), "7-1");
+        assert_eq!(store.latest("test_field_layout_ids").unwrap(), Some(entry));
+        let timestamps: Vec<i64> = store
+            .range(..7000, Some("test_field_layout_ids"))
+            .unwrap()
+            .iter()
+            .unwrap()
+            .map(|entry| entry.unwrap().timestamp)
+            .collect();
+        assert_eq!(timestamps, vec![5000, 6002]);
+    }
+
+    /// Starts a fake sentinel that reports the master at the address in
+    /// `master`, returning its URL.
+    #[cfg(feature = "redis-sentinel")]
+    fn fake_sentinel(master: std::sync::Arc<std::sync::Mutex<Option<u16>>>) -> String {
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("redis://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let master = master.clone();
+                std::thread::spawn(move || {
+                    let mut buf = [0; 1024];
+                    while let Ok(len) = stream.read(&mut buf) {
+                        if len == 0 {
+                            break;
+                        }
+                        let reply = match *master.lock().unwrap() {
+                            Some(port) => {
+                                let port = port.to_string();
+                                format!("*2\r\n$9\r\n127.0.0.1\r\n${}\r\n{}\r\n", port.len(), port)
+                            }
+                            None => "*-1\r\n".to_string(),
+                        };
+                        if stream.write_all(reply.as_bytes()).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+        url
+    }
+
+    #[cfg(feature = "redis-sentinel")]
+    #[test]
+    fn sentinel() {
+        use crate::Error;
+        use std::net::TcpListener;
+        use std::sync::{Arc, Mutex};
+
+        assert!(matches!(
+            RedisStreamStore::new_sentinel(vec![], "mymaster".to_string(), None),
+            Err(Error::InvalidConfig(_))
+        ));
+
+        // a port that nothing is listening on
+        let dead_port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let master = Arc::new(Mutex::new(None));
+        let sentinels = vec![
+            format!("redis://127.0.0.1:{}", dead_port),
+            fake_sentinel(master.clone()),
+        ];
+        let store = RedisStreamStore::new_sentinel(sentinels, "mymaster".to_string(), None).unwrap();
+        let entry = Entry::new_with_timestamp(1, "test_sentinel", vec![1]);
+
+        // the sentinels don't know of the master
+        assert!(store.push(Cow::Borrowed(&entry)).is_err());
+
+        // the master is down
+        *master.lock().unwrap() = Some(dead_port);
+        assert!(store.push(Cow::Borrowed(&entry)).is_err());
+
+        // after failing over, the new master is used
+        *master.lock().unwrap() = Some(6379);
+        store.push(Cow::Borrowed(&entry)).unwrap();
+        assert_eq!(store.latest("test_sentinel").unwrap(), Some(entry));
+        let mut subscriber = store.subscribe_from_beginning("test_sentinel").unwrap();
+        assert!(subscriber.next(Some(Duration::from_millis(100))).unwrap().is_some());
+    }
+
+    #[test]
+    fn external_pool() {
+        let client = redis::Client::open("redis://localhost:6379").unwrap();
+        let pool = r2d2::Pool::builder().max_size(2).build(client).unwrap();
+        let store = RedisStreamStore::new_with_pool(pool.clone());
+        let entry = Entry::new_with_timestamp(1, "test_external_pool", vec![1]);
+        for _ in 0..10 {
+            store.push(Cow::Borrowed(&entry)).unwrap();
+        }
+        assert_eq!(store.latest("test_external_pool").unwrap(), Some(entry.clone()));
+        // connections are returned to the external pool
+        assert_eq!(pool.state().idle_connections, pool.state().connections);
+
+        {
+            let mut subscriber = store.subscribe_from_beginning("test_external_pool").unwrap();
+            assert_eq!(pool.state().idle_connections + 1, pool.state().connections);
+            assert_eq!(subscriber.next(None).unwrap(), Some(entry));
+        }
+        assert_eq!(pool.state().idle_connections, pool.state().connections);
+    }
+
+    #[test]
+    fn drop_watcher() {
+        let client = redis::Client::open("redis://localhost:6379").unwrap();
+        let pool = r2d2::Pool::builder().max_size(2).build(client).unwrap();
+        let store = RedisStreamStore::new_with_pool(pool.clone());
+        let mut subscriber = store.subscribe("test_drop_watcher").unwrap();
+        // selecting registers a waker, which starts the watcher
+        let mut subscribers: Vec<&mut dyn SubscriptionDyn> = vec![&mut subscriber];
+        assert!(crate::select(&mut subscribers, Some(Duration::from_millis(10)))
+            .unwrap()
+            .is_none());
+        assert_eq!(pool.state().idle_connections, pool.state().connections - 2);
+
+        // dropping doesn't wait for the watcher's blocking read, but the
+        // watcher gives its connection back once the read returns
+        let start = Instant::now();
+        drop(subscriber);
+        assert!(start.elapsed() < Duration::from_millis(100));
+        while pool.state().idle_connections < pool.state().connections {
+            assert!(start.elapsed() < Duration::from_millis(STREAM_READ_BLOCK_MS as u64 + 500));
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn rename() {
+        let store = RedisStreamStore::new("redis://localhost:6379")
+            .unwrap()
+            .with_max_len(None);
+        let client = redis::Client::open("redis://localhost:6379").unwrap();
+        let mut conn = client.get_connection().unwrap();
+        for name in [
+            "test_redis_rename_old",
+            "test_redis_rename_new",
+            "test_redis_rename_other",
+        ] {
+            let _: () = conn.del(store.channel_for(name)).unwrap();
+        }
+        for (i, name) in [
+            (1, "test_redis_rename_old"),
+            (2, "test_redis_rename_old"),
+            (3, "test_redis_rename_other"),
+        ] {
+            store
+                .push(Cow::Owned(Entry::new_with_timestamp(i, name, vec![1])))
+                .unwrap();
+        }
+        let mut subscription = store.subscribe("test_redis_rename_old").unwrap();
+
+        assert_eq!(
+            store.rename("test_redis_rename_old", "test_redis_rename_new").unwrap(),
+            2
+        );
+        assert!(store.latest("test_redis_rename_old").unwrap().is_none());
+        assert_eq!(store.latest("test_redis_rename_new").unwrap().unwrap().timestamp, 2);
+        assert!(matches!(
+            store.rename("test_redis_rename_new", "test_redis_rename_other"),
+            Err(Error::NameInUse(_))
+        ));
+        assert!(matches!(
+            store.rename("test_redis_rename_missing", "test_redis_rename_other"),
+            Err(Error::NameInUse(_))
+        ));
+        assert_eq!(
+            store
+                .rename("test_redis_rename_missing", "test_redis_rename_old")
+                .unwrap(),
+            0
+        );
+
+        // the subscription doesn't follow the rename
+        store
+            .push(Cow::Owned(Entry::new_with_timestamp(
+                4,
+                "test_redis_rename_new",
+                vec![1],
+            )))
+            .unwrap();
+        assert!(subscription.next(Some(Duration::from_millis(10))).unwrap().is_none());
+    }
+
+    #[test]
+    fn range() {
+        let store = RedisStreamStore::new("redis://localhost:6379")
+            .unwrap()
+            .with_max_len(None)
+            .with_timestamp_as_stream_id(true);
+        let name = "test_redis_range";
+        let _: () = store
+            .with_connection(|conn| Ok(conn.del(store.channel_for(name))?))
+            .unwrap();
+        assert!(store
+            .capabilities()
+            .contains(Capabilities::RANGE | Capabilities::REMOVE));
+        let entries: Vec<Entry> = (1..11u8)
+            .map(|i| Entry::new_with_timestamp(i as i64 * 999, name, vec![i]))
+            .collect();
+        let ids = store.push_batch_with_ids(entries.iter().map(Cow::Borrowed)).unwrap();
+        assert_eq!(ids[0], "0-999");
+        assert_eq!(ids[1], "1-998");
+
+        // ids must be increasing
+        let entry = Entry::new_with_timestamp(9990, name, vec![]);
+        assert!(store.push(Cow::Owned(entry)).is_err());
+        let entry = Entry::new_with_timestamp(0, name, vec![]);
+        assert!(store.push(Cow::Owned(entry)).is_err());
+
+        assert_eq!(store.range(.., Some(name)).unwrap().count().unwrap(), 10);
+        assert_eq!(store.range(..=0, Some(name)).unwrap().count().unwrap(), 0);
+        assert_eq!(store.range(1998..3996, Some(name)).unwrap().count().unwrap(), 2);
+        let results: Vec<Entry> = store
+            .range(1998..=3996, Some(name))
+            .unwrap()
+            .iter()
+            .unwrap()
+            .collect::<Result<Vec<Entry>, _>>()
+            .unwrap();
+        assert_eq!(results, entries[1..4]);
+
+        store.range(2000.., Some(name)).unwrap().remove().unwrap();
+        assert_eq!(store.range(.., Some(name)).unwrap().count().unwrap(), 2);
+        store.range(.., Some(name)).unwrap().remove().unwrap();
+        assert_eq!(store.range(.., Some(name)).unwrap().count().unwrap(), 0);
+        assert!(store.range(.., Some(name)).unwrap().is_empty().unwrap());
+    }
+
+    #[test]
+    fn empty_ranges() {
+        let store = RedisStreamStore::new_with_prefix("redis://localhost:6379", "test_redis_empty_ranges:")
+            .unwrap()
+            .with_timestamp_as_stream_id(true);
+        let _: () = store
+            .with_connection(|conn| Ok(conn.del(store.channel_for("test_empty_ranges"))?))
+            .unwrap();
+        crate::testkit::empty_ranges(&store);
+    }
 }
 
 #[cfg(test)]
 #[cfg(feature = "benches")]
 mod benches {
-    use crate::{bench_store_impl, define_bench, RedisStreamStore};
-    bench_store_impl!(RedisStreamStore::new("redis://localhost:6379").unwrap());
+    use std::borrow::Cow;
+
+    use crate::{bench_store_impl, define_bench, Entry, RedisStreamStore, Store};
+    use test::Bencher;
+
+    bench_store_impl!(|_| RedisStreamStore::new("redis://localhost:6379")
+        .unwrap()
+        .with_max_len(Some(1)));
+
+    fn bench_entries() -> Vec<Entry> {
+        (0..1000)
+            .map(|i| Entry::new_with_timestamp(i, "bench_push_1000", vec![1, 2, 3]))
+            .collect()
+    }
+
+    // Pushing 1,000 entries one at a time vs in a single pipeline
+    #[bench]
+    fn push_1000_individually(b: &mut Bencher) {
+        let store = RedisStreamStore::new("redis://localhost:6379")
+            .unwrap()
+            .with_max_len(Some(1));
+        let entries = bench_entries();
+        b.iter(|| {
+            for entry in entries.iter() {
+                store.push(Cow::Borrowed(entry)).unwrap();
+            }
+        });
+    }
+
+    #[bench]
+    fn push_1000_pipelined(b: &mut Bencher) {
+        let store = RedisStreamStore::new("redis://localhost:6379")
+            .unwrap()
+            .with_max_len(Some(1));
+        let entries = bench_entries();
+        b.iter(|| store.push_many_pipelined(entries.iter().map(Cow::Borrowed)).unwrap());
+    }
 }
@@ -0,0 +1,86 @@
+use std::time::Duration;
+
+use crate::Error;
+
+use redis::{Client, Connection, ConnectionAddr, ConnectionInfo, ErrorKind, RedisConnectionInfo, RedisError};
+
+static SENTINEL_CONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Connects to whichever server the sentinels currently report as the master
+/// of a service.
+pub(crate) struct SentinelConnector {
+    sentinels: Vec<Client>,
+    service_name: String,
+    password: Option<String>,
+}
+
+impl SentinelConnector {
+    pub(crate) fn new(sentinels: Vec<String>, service_name: String, password: Option<String>) -> Result<Self, Error> {
+        if sentinels.is_empty() {
+            return Err(Error::InvalidConfig("at least one sentinel is required".to_string()));
+        }
+        let sentinels = sentinels
+            .iter()
+            .map(|sentinel| Client::open(sentinel.as_str()))
+            .collect::<Result<Vec<Client>, _>>()?;
+        Ok(Self {
+            sentinels,
+            service_name,
+            password,
+        })
+    }
+
+    fn query_master(&self, sentinel: &Client) -> Result<Option<Client>, Error> {
+        let mut conn = sentinel.get_connection_with_timeout(SENTINEL_CONNECT_TIMEOUT)?;
+        let addr: Option<(String, u16)> = redis::cmd("SENTINEL")
+            .arg("get-master-addr-by-name")
+            .arg(&self.service_name)
+            .query(&mut conn)?;
+        match addr {
+            Some((host, port)) => Ok(Some(Client::open(ConnectionInfo {
+                addr: ConnectionAddr::Tcp(host, port),
+                redis: RedisConnectionInfo {
+                    db: 0,
+                    username: None,
+                    password: self.password.clone(),
+                },
+            })?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns whether an error indicates that the master may have failed
+    /// over, i.e. the server was demoted to a read-only replica or went away.
+    pub(crate) fn is_failover_error(err: &Error) -> bool {
+        match err {
+            Error::Database(err) => match err.downcast_ref::<RedisError>() {
+                Some(err) => {
+                    err.kind() == ErrorKind::ReadOnly
+                        || err.is_io_error()
+                        || err.is_connection_dropped()
+                        || err.is_connection_refusal()
+                }
+                None => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Asks each sentinel in turn for the address of the current master, and
+    /// connects to it.
+    pub(crate) fn get_connection(&self) -> Result<Connection, Error> {
+        let mut last_err = None;
+        for sentinel in &self.sentinels {
+            match self.query_master(sentinel) {
+                Ok(Some(master)) => return Ok(master.get_connection()?),
+                Ok(None) => {
+                    last_err = Some(Error::Database(
+                        format!("sentinel does not know of the master {}", self.service_name).into(),
+                    ))
+                }
+                Err(err) => last_err = Some(err),
+            }
+        }
+        Err(last_err.unwrap_or_else(|| Error::InvalidConfig("at least one sentinel is required".to_string())))
+    }
+}
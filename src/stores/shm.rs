@@ -0,0 +1,1125 @@
+use std::borrow::Cow;
+use std::fs::{File, OpenOptions};
+use std::io::{Error as IoError, ErrorKind as IoErrorKind};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::ptr;
+use std::sync::atomic::{fence, AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{
+    Capabilities, Entry, Error, PushCallback, SlowOpKind, Store, StoreOptions, SubscribeableStore, Subscription,
+    SubscriptionDyn, Waker,
+};
+
+use string_cache::DefaultAtom as Atom;
+
+// Identifies ring files, alongside the version of their layout
+static MAGIC: u64 = u64::from_le_bytes(*b"binlogsh");
+static FORMAT_VERSION: u64 = 1;
+
+// The file header is a series of 8 byte fields. Offsets into the ring grow
+// forever; the head is the offset after the newest committed frame, and the
+// tail is the offset of the oldest frame that hasn't been overwritten.
+static MAGIC_FIELD: usize = 0;
+static VERSION_FIELD: usize = 8;
+static CAPACITY_FIELD: usize = 16;
+static HEAD_FIELD: usize = 24;
+static TAIL_FIELD: usize = 32;
+static HEADER_SIZE: usize = 64;
+
+// After the header are slots that point to the latest entry of each name, so
+// that `latest` doesn't have to scan the ring. Each slot is a sequence
+// number, the entry's offset and timestamp, and the name's length plus one
+// (zero for unclaimed slots), followed by the name itself.
+static LATEST_SLOTS: usize = 128;
+static LATEST_SLOT_SIZE: usize = 128;
+static LATEST_SLOT_NAME_LEN: usize = LATEST_SLOT_SIZE - 32;
+// How many times to retry reading a slot that's being written to, before
+// giving up on it, e.g. because its writer crashed mid-update
+static LATEST_SLOT_READ_ATTEMPTS: usize = 1000;
+
+// Then the frames. Each is a header of a commit flag (the frame's offset plus
+// one, once it's fully written), the timestamp, the name and value lengths,
+// and flags, followed by the name and value, padded to 8 bytes.
+static DATA_OFFSET: usize = HEADER_SIZE + LATEST_SLOTS * LATEST_SLOT_SIZE;
+static FRAME_HEADER_SIZE: u64 = 32;
+// The lengths of a frame that pads out the end of the ring
+static PADDING_LENS: u64 = u64::MAX;
+static SKEWED_FLAG: u64 = 1;
+
+static MIN_CAPACITY: u64 = 4096;
+static DEFAULT_SUBSCRIPTION_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// The number of bytes a frame holding an entry takes up.
+fn frame_size(name_len: usize, value_len: usize) -> u64 {
+    (FRAME_HEADER_SIZE + name_len as u64 + value_len as u64 + 7) & !7
+}
+
+fn latest_slot_position(index: usize) -> usize {
+    HEADER_SIZE + index * LATEST_SLOT_SIZE
+}
+
+/// A file mapped into memory, shared with every other process mapping it.
+struct Mapping {
+    ptr: *mut u8,
+    len: usize,
+    // Kept open for as long as it's mapped, which also holds the writer's
+    // lock
+    _file: File,
+}
+
+// The mapping is only accessed via atomics, or via copies that are validated
+// after they're made, so it's as safe to share across threads as it is
+// across processes.
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+impl Mapping {
+    fn new(file: File, len: usize, writable: bool) -> Result<Self, Error> {
+        let prot = if writable {
+            libc::PROT_READ | libc::PROT_WRITE
+        } else {
+            libc::PROT_READ
+        };
+        // Safety: the result is checked, and the mapping is unmapped on drop
+        let ptr = unsafe { libc::mmap(ptr::null_mut(), len, prot, libc::MAP_SHARED, file.as_raw_fd(), 0) };
+        if ptr == libc::MAP_FAILED {
+            return Err(IoError::last_os_error().into());
+        }
+        Ok(Self {
+            ptr: ptr as *mut u8,
+            len,
+            _file: file,
+        })
+    }
+
+    fn atomic(&self, position: usize) -> &AtomicU64 {
+        assert!(position.is_multiple_of(8) && position + 8 <= self.len);
+        // Safety: mappings are page aligned, so the position is aligned, and
+        // it's in bounds
+        unsafe { &*(self.ptr.add(position) as *const AtomicU64) }
+    }
+
+    /// Copies bytes out of the mapping. They may be concurrently
+    /// overwritten, so they must be validated after they're copied.
+    fn read(&self, position: usize, len: usize) -> Vec<u8> {
+        assert!(position + len <= self.len);
+        let mut bytes = vec![0; len];
+        // Safety: the range is in bounds
+        unsafe { ptr::copy_nonoverlapping(self.ptr.add(position), bytes.as_mut_ptr(), len) };
+        bytes
+    }
+
+    /// Copies bytes into the mapping. Must only be called by the writer.
+    fn write(&self, position: usize, bytes: &[u8]) {
+        assert!(position + bytes.len() <= self.len);
+        // Safety: the range is in bounds, and only the writer maps the file
+        // as writable
+        unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), self.ptr.add(position), bytes.len()) };
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        // Safety: the pointer and length are from a successful mmap
+        unsafe { libc::munmap(self.ptr as *mut libc::c_void, self.len) };
+    }
+}
+
+/// The header of a frame holding an entry.
+#[derive(Clone, Copy)]
+struct FrameHeader {
+    offset: u64,
+    position: usize,
+    timestamp: i64,
+    skewed: bool,
+    name_len: usize,
+    value_len: usize,
+}
+
+/// The entry a latest slot points to.
+struct LatestSlot {
+    offset: u64,
+    timestamp: i64,
+}
+
+/// Where a name's latest slot is.
+enum SlotLookup {
+    Found(usize),
+    /// The name has never been pushed, and can claim the slot at the index.
+    Missing(usize),
+    /// The name is too long for a slot, or every slot is taken.
+    Unavailable,
+}
+
+/// A ring of frames in a mapped file. Methods that write are only called by
+/// the writer, while holding its lock.
+struct Ring {
+    map: Mapping,
+    capacity: u64,
+}
+
+impl Ring {
+    /// Maps a ring file. If `capacity` is set, the file is mapped for
+    /// writing, and initialized if it's new.
+    fn open(file: File, path: &Path, capacity: Option<u64>) -> Result<Self, Error> {
+        let not_a_ring = || Error::InvalidConfig(format!("{} is not a binlog ring", path.display()));
+        let len = file.metadata()?.len();
+        if len < DATA_OFFSET as u64 + MIN_CAPACITY {
+            return Err(not_a_ring());
+        }
+        let map = Mapping::new(file, len as usize, capacity.is_some())?;
+        let data_len = len - DATA_OFFSET as u64;
+
+        // The magic number is written last, so a ring without one was never
+        // initialized, e.g. because its creator crashed
+        let magic = map.atomic(MAGIC_FIELD).load(Ordering::Acquire);
+        if magic == 0 && capacity == Some(data_len) {
+            map.atomic(VERSION_FIELD).store(FORMAT_VERSION, Ordering::Relaxed);
+            map.atomic(CAPACITY_FIELD).store(data_len, Ordering::Relaxed);
+            map.atomic(MAGIC_FIELD).store(MAGIC, Ordering::Release);
+        } else if magic != MAGIC
+            || map.atomic(VERSION_FIELD).load(Ordering::Relaxed) != FORMAT_VERSION
+            || map.atomic(CAPACITY_FIELD).load(Ordering::Relaxed) != data_len
+        {
+            return Err(not_a_ring());
+        }
+
+        Ok(Self {
+            map,
+            capacity: data_len,
+        })
+    }
+
+    fn head(&self) -> u64 {
+        self.map.atomic(HEAD_FIELD).load(Ordering::Acquire)
+    }
+
+    fn tail(&self) -> u64 {
+        self.map.atomic(TAIL_FIELD).load(Ordering::Acquire)
+    }
+
+    fn position(&self, offset: u64) -> usize {
+        DATA_OFFSET + (offset % self.capacity) as usize
+    }
+
+    /// The number of bytes between an offset and the end of the ring.
+    fn remaining(&self, offset: u64) -> u64 {
+        self.capacity - offset % self.capacity
+    }
+
+    /// Fails with `Error::Lagged` if the frame at `offset` has been
+    /// overwritten, e.g. while it was being read.
+    fn check_unchanged(&self, offset: u64) -> Result<(), Error> {
+        fence(Ordering::Acquire);
+        if offset < self.tail() {
+            Err(Error::Lagged)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Reads the header of the frame at `offset`, which must be before the
+    /// head. Returns the offset of the next frame, and the header unless the
+    /// frame is padding.
+    fn read_header(&self, offset: u64) -> Result<(u64, Option<FrameHeader>), Error> {
+        if offset < self.tail() {
+            return Err(Error::Lagged);
+        }
+        // Frames never wrap around the end of the ring. Gaps too small for a
+        // padding frame are skipped implicitly.
+        let remaining = self.remaining(offset);
+        if remaining < FRAME_HEADER_SIZE {
+            return Ok((offset + remaining, None));
+        }
+
+        let position = self.position(offset);
+        let commit = self.map.atomic(position).load(Ordering::Acquire);
+        let timestamp = self.map.atomic(position + 8).load(Ordering::Relaxed) as i64;
+        let lens = self.map.atomic(position + 16).load(Ordering::Relaxed);
+        let flags = self.map.atomic(position + 24).load(Ordering::Relaxed);
+        self.check_unchanged(offset)?;
+        if commit != offset + 1 {
+            return Err(Error::InvalidEntry(format!(
+                "the frame at offset {} is partially written",
+                offset
+            )));
+        }
+        if lens == PADDING_LENS {
+            return Ok((offset + remaining, None));
+        }
+
+        let name_len = (lens >> 32) as usize;
+        let value_len = (lens & u64::from(u32::MAX)) as usize;
+        let size = frame_size(name_len, value_len);
+        if size > remaining {
+            return Err(Error::InvalidEntry(format!(
+                "the frame at offset {} is corrupt",
+                offset
+            )));
+        }
+        let header = FrameHeader {
+            offset,
+            position,
+            timestamp,
+            skewed: flags & SKEWED_FLAG != 0,
+            name_len,
+            value_len,
+        };
+        Ok((offset + size, Some(header)))
+    }
+
+    fn read_name(&self, header: &FrameHeader) -> Result<Atom, Error> {
+        let name = self
+            .map
+            .read(header.position + FRAME_HEADER_SIZE as usize, header.name_len);
+        self.check_unchanged(header.offset)?;
+        match String::from_utf8(name) {
+            Ok(name) => Ok(name.into()),
+            Err(_) => Err(Error::InvalidEntry(format!(
+                "the frame at offset {} has a name that isn't utf-8",
+                header.offset
+            ))),
+        }
+    }
+
+    fn has_name(&self, header: &FrameHeader, name: &Atom) -> Result<bool, Error> {
+        if header.name_len != name.len() {
+            return Ok(false);
+        }
+        let matches = self
+            .map
+            .read(header.position + FRAME_HEADER_SIZE as usize, header.name_len)
+            == name.as_bytes();
+        self.check_unchanged(header.offset)?;
+        Ok(matches)
+    }
+
+    fn read_entry(&self, header: &FrameHeader) -> Result<Entry, Error> {
+        let value = self.map.read(
+            header.position + FRAME_HEADER_SIZE as usize + header.name_len,
+            header.value_len,
+        );
+        let mut entry = Entry::new_with_timestamp(header.timestamp, self.read_name(header)?, value);
+        entry.skewed = header.skewed;
+        Ok(entry)
+    }
+
+    /// Reads the frame at `offset`, returning the offset of the next frame,
+    /// and the frame's entry if it has the given name.
+    fn read_named(&self, offset: u64, name: &Atom) -> Result<(u64, Option<Entry>), Error> {
+        match self.read_header(offset)? {
+            (next, Some(header)) if self.has_name(&header, name)? => Ok((next, Some(self.read_entry(&header)?))),
+            (next, _) => Ok((next, None)),
+        }
+    }
+
+    /// Finds the latest frame with a name by scanning the whole ring.
+    fn scan_latest(&self, name: &Atom, ignore_skewed: bool) -> Result<Option<FrameHeader>, Error> {
+        let head = self.head();
+        let mut offset = self.tail();
+        let mut latest: Option<FrameHeader> = None;
+        while offset < head {
+            let (next, header) = self.read_header(offset)?;
+            if let Some(header) = header {
+                // Of the entries with the newest timestamp, the last one
+                // pushed wins
+                let newer = latest.is_none_or(|latest| header.timestamp >= latest.timestamp);
+                if newer && !(ignore_skewed && header.skewed) && self.has_name(&header, name)? {
+                    latest = Some(header);
+                }
+            }
+            offset = next;
+        }
+        Ok(latest)
+    }
+
+    fn latest(&self, name: &Atom, ignore_skewed: bool) -> Result<Option<Entry>, Error> {
+        loop {
+            match self.try_latest(name, ignore_skewed) {
+                // The frame was overwritten while it was read, so there's a
+                // newer one to find
+                Err(Error::Lagged) => continue,
+                result => return result,
+            }
+        }
+    }
+
+    fn try_latest(&self, name: &Atom, ignore_skewed: bool) -> Result<Option<Entry>, Error> {
+        let slotted = match self.lookup_slot(name) {
+            SlotLookup::Missing(_) => return Ok(None),
+            SlotLookup::Found(index) => match self.read_slot(index) {
+                Some(slot) if slot.offset >= self.tail() => match self.read_header(slot.offset)? {
+                    (_, Some(header)) if !(ignore_skewed && header.skewed) => Some(header),
+                    _ => None,
+                },
+                _ => None,
+            },
+            SlotLookup::Unavailable => None,
+        };
+        let latest = match slotted {
+            Some(header) => Some(header),
+            None => self.scan_latest(name, ignore_skewed)?,
+        };
+        match latest {
+            Some(header) => Ok(Some(self.read_entry(&header)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn latest_n(&self, name: &Atom, n: usize, ignore_skewed: bool) -> Result<Vec<Entry>, Error> {
+        loop {
+            match self.try_latest_n(name, n, ignore_skewed) {
+                // A frame was overwritten while it was read, so the ring has
+                // moved on
+                Err(Error::Lagged) => continue,
+                result => return result,
+            }
+        }
+    }
+
+    /// Gets up to the `n` latest entries with a name, oldest first, by
+    /// scanning the whole ring.
+    fn try_latest_n(&self, name: &Atom, n: usize, ignore_skewed: bool) -> Result<Vec<Entry>, Error> {
+        if n == 0 {
+            return Ok(Vec::new());
+        }
+        let head = self.head();
+        let mut offset = self.tail();
+        let mut headers = Vec::new();
+        while offset < head {
+            let (next, header) = self.read_header(offset)?;
+            if let Some(header) = header {
+                if !(ignore_skewed && header.skewed) && self.has_name(&header, name)? {
+                    headers.push(header);
+                }
+            }
+            offset = next;
+        }
+        // Frames are in push order, so a stable sort keeps entries that share
+        // a timestamp in the order they were pushed
+        headers.sort_by_key(|header| header.timestamp);
+        let skip = headers.len().saturating_sub(n);
+        headers[skip..].iter().map(|header| self.read_entry(header)).collect()
+    }
+
+    fn lookup_slot(&self, name: &Atom) -> SlotLookup {
+        if name.len() > LATEST_SLOT_NAME_LEN {
+            return SlotLookup::Unavailable;
+        }
+        // FNV-1a, which is stable across processes, unlike `DefaultHasher`
+        let hash = name.as_bytes().iter().fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+        });
+        for i in 0..LATEST_SLOTS {
+            let index = (hash as usize).wrapping_add(i) % LATEST_SLOTS;
+            let position = latest_slot_position(index);
+            // A slot's name is written before its length is set, and never
+            // changes after
+            let name_len = self.map.atomic(position + 24).load(Ordering::Acquire);
+            if name_len == 0 {
+                return SlotLookup::Missing(index);
+            }
+            if name_len == name.len() as u64 + 1 && self.map.read(position + 32, name.len()) == name.as_bytes() {
+                return SlotLookup::Found(index);
+            }
+        }
+        SlotLookup::Unavailable
+    }
+
+    fn read_slot(&self, index: usize) -> Option<LatestSlot> {
+        let position = latest_slot_position(index);
+        let seq = self.map.atomic(position);
+        for _ in 0..LATEST_SLOT_READ_ATTEMPTS {
+            // The sequence number is odd while the slot is being written
+            let start = seq.load(Ordering::Acquire);
+            if start.is_multiple_of(2) {
+                let offset = self.map.atomic(position + 8).load(Ordering::Relaxed);
+                let timestamp = self.map.atomic(position + 16).load(Ordering::Relaxed) as i64;
+                fence(Ordering::Acquire);
+                if seq.load(Ordering::Relaxed) == start {
+                    return Some(LatestSlot { offset, timestamp });
+                }
+            }
+            thread::yield_now();
+        }
+        None
+    }
+
+    fn write_slot(&self, index: usize, slot: &LatestSlot) {
+        let position = latest_slot_position(index);
+        let seq = self.map.atomic(position);
+        let start = seq.load(Ordering::Relaxed);
+        seq.store(start + 1, Ordering::Relaxed);
+        fence(Ordering::Release);
+        self.map.atomic(position + 8).store(slot.offset, Ordering::Relaxed);
+        self.map
+            .atomic(position + 16)
+            .store(slot.timestamp as u64, Ordering::Relaxed);
+        seq.store(start + 2, Ordering::Release);
+    }
+
+    /// Points a name's latest slot at a newly committed frame, if it's the
+    /// name's latest.
+    fn update_latest(&self, name: &Atom, offset: u64, timestamp: i64) -> Result<(), Error> {
+        let pushed = LatestSlot { offset, timestamp };
+        match self.lookup_slot(name) {
+            SlotLookup::Found(index) => {
+                let latest = match self.read_slot(index) {
+                    Some(slot) if slot.offset >= self.tail() => {
+                        if timestamp < slot.timestamp {
+                            return Ok(());
+                        }
+                        pushed
+                    }
+                    // The previous latest entry was overwritten, so the new
+                    // latest could be any of the name's remaining entries
+                    _ => match self.scan_latest(name, false)? {
+                        Some(header) => LatestSlot {
+                            offset: header.offset,
+                            timestamp: header.timestamp,
+                        },
+                        None => pushed,
+                    },
+                };
+                self.write_slot(index, &latest);
+            }
+            SlotLookup::Missing(index) => {
+                let position = latest_slot_position(index);
+                self.write_slot(index, &pushed);
+                self.map.write(position + 32, name.as_bytes());
+                self.map
+                    .atomic(position + 24)
+                    .store(name.len() as u64 + 1, Ordering::Release);
+            }
+            SlotLookup::Unavailable => {}
+        }
+        Ok(())
+    }
+
+    /// Advances the tail until there are `size` free bytes after the head,
+    /// so that they can be overwritten.
+    fn make_room(&self, head: u64, size: u64) -> Result<(), Error> {
+        let start = self.tail();
+        let mut tail = start;
+        while head + size - tail > self.capacity {
+            tail = self.read_header(tail)?.0;
+        }
+        if tail != start {
+            self.map.atomic(TAIL_FIELD).store(tail, Ordering::Relaxed);
+            // Readers must see the new tail before any overwritten bytes
+            fence(Ordering::Release);
+        }
+        Ok(())
+    }
+
+    /// Writes an entry's frame at `offset`, without committing it.
+    fn write_frame(&self, offset: u64, entry: &Entry) {
+        let position = self.position(offset);
+        let lens = (entry.name.len() as u64) << 32 | entry.value.len() as u64;
+        self.map.atomic(position).store(0, Ordering::Relaxed);
+        self.map
+            .atomic(position + 8)
+            .store(entry.timestamp as u64, Ordering::Relaxed);
+        self.map.atomic(position + 16).store(lens, Ordering::Relaxed);
+        let flags = if entry.skewed { SKEWED_FLAG } else { 0 };
+        self.map.atomic(position + 24).store(flags, Ordering::Relaxed);
+        let data = position + FRAME_HEADER_SIZE as usize;
+        self.map.write(data, entry.name.as_bytes());
+        self.map.write(data + entry.name.len(), &entry.value);
+    }
+
+    /// Commits the frame at the head, making it visible to readers.
+    fn commit(&self, offset: u64, size: u64) {
+        self.map
+            .atomic(self.position(offset))
+            .store(offset + 1, Ordering::Release);
+        self.map.atomic(HEAD_FIELD).store(offset + size, Ordering::Release);
+    }
+
+    /// Appends an entry, returning the offset of its frame.
+    fn append(&self, entry: &Entry) -> Result<u64, Error> {
+        let size = frame_size(entry.name.len(), entry.value.len());
+        if size > self.capacity {
+            return Err(Error::InvalidEntry(format!(
+                "entry takes up {} bytes, but the ring only has {}",
+                size, self.capacity
+            )));
+        }
+
+        let mut head = self.head();
+        let remaining = self.remaining(head);
+        if size > remaining {
+            self.make_room(head, remaining)?;
+            if remaining >= FRAME_HEADER_SIZE {
+                let position = self.position(head);
+                self.map.atomic(position + 16).store(PADDING_LENS, Ordering::Relaxed);
+            }
+            self.commit(head, remaining);
+            head += remaining;
+        }
+
+        self.make_room(head, size)?;
+        self.write_frame(head, entry);
+        self.commit(head, size);
+        Ok(head)
+    }
+
+    /// Discards any frames the previous writer didn't finish committing, and
+    /// rebuilds the latest slots from the frames that remain.
+    fn recover(&self) -> Result<(), Error> {
+        for index in 0..LATEST_SLOTS {
+            let position = latest_slot_position(index);
+            self.map.atomic(position + 24).store(0, Ordering::Release);
+            self.map.atomic(position).store(0, Ordering::Release);
+        }
+
+        let head = self.head();
+        let mut offset = self.tail();
+        while offset < head {
+            let (next, header) = match self.read_header(offset) {
+                Ok(frame) => frame,
+                Err(_) => break,
+            };
+            if let Some(header) = header {
+                let name = match self.read_name(&header) {
+                    Ok(name) => name,
+                    Err(_) => break,
+                };
+                self.update_latest(&name, offset, header.timestamp)?;
+            }
+            offset = next;
+        }
+        if offset != head {
+            log::warn!(
+                "discarding {} bytes of partially written entries at the end of a ring",
+                head - offset
+            );
+            self.map.atomic(HEAD_FIELD).store(offset, Ordering::Release);
+        }
+        Ok(())
+    }
+}
+
+/// A store backed by a ring buffer in a memory-mapped file, for sharing
+/// entries between processes on the same host without a server. One process
+/// pushes entries, while any number of others read them via `latest` and
+/// subscriptions.
+///
+/// The ring has a fixed capacity. Once it's full, each push overwrites the
+/// oldest entries, regardless of their name. `latest` usually reads the
+/// newest entry of a name via a small index at the start of the file, but
+/// falls back to scanning the ring for names longer than 96 bytes, for names
+/// beyond the first 128, and after the name's latest entry is overwritten.
+#[derive(Clone)]
+pub struct ShmStore {
+    ring: Arc<Ring>,
+    /// Serializes pushes within the writing process. Unset for readers.
+    writer: Option<Arc<Mutex<()>>>,
+    on_push: Option<PushCallback>,
+    options: StoreOptions,
+    subscription_poll_interval: Duration,
+}
+
+impl ShmStore {
+    /// Opens the ring at `path` for writing, creating it with room for
+    /// `capacity` bytes of entries if it doesn't exist. Capacities must be a
+    /// multiple of 8, and at least 4096. Each entry takes up 32 bytes plus
+    /// its name and value.
+    ///
+    /// Only one process may write to a ring at a time; if another has it
+    /// open for writing, this fails with `Error::InvalidConfig`. If the
+    /// previous writer crashed mid-push, the partially written entry is
+    /// discarded.
+    pub fn open<P: AsRef<Path>>(path: P, capacity: u64) -> Result<Self, Error> {
+        if capacity < MIN_CAPACITY || !capacity.is_multiple_of(8) {
+            return Err(Error::InvalidConfig(format!(
+                "ring capacity must be a multiple of 8, and at least {} bytes",
+                MIN_CAPACITY
+            )));
+        }
+
+        let path = path.as_ref();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        // Safety: flock only uses the descriptor, which is open
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            let err = IoError::last_os_error();
+            if err.kind() == IoErrorKind::WouldBlock {
+                return Err(Error::InvalidConfig(format!(
+                    "another process is writing to {}",
+                    path.display()
+                )));
+            }
+            return Err(err.into());
+        }
+        if file.metadata()?.len() == 0 {
+            file.set_len(DATA_OFFSET as u64 + capacity)?;
+        }
+
+        let ring = Ring::open(file, path, Some(capacity))?;
+        if ring.capacity != capacity {
+            return Err(Error::InvalidConfig(format!(
+                "{} has a capacity of {} bytes, not {}",
+                path.display(),
+                ring.capacity,
+                capacity
+            )));
+        }
+        ring.recover()?;
+        Ok(Self::new(ring, Some(Arc::new(Mutex::new(())))))
+    }
+
+    /// Opens an existing ring for reading. Pushes fail with
+    /// `Error::Unsupported`.
+    pub fn open_reader<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let ring = Ring::open(File::open(path)?, path, None)?;
+        Ok(Self::new(ring, None))
+    }
+
+    fn new(ring: Ring, writer: Option<Arc<Mutex<()>>>) -> Self {
+        Self {
+            ring: Arc::new(ring),
+            writer,
+            on_push: None,
+            options: StoreOptions::default(),
+            subscription_poll_interval: DEFAULT_SUBSCRIPTION_POLL_INTERVAL,
+        }
+    }
+
+    /// Sets a callback that is invoked after every successful push,
+    /// regardless of the entry's name.
+    pub fn with_on_push(mut self, callback: PushCallback) -> Self {
+        self.on_push = Some(callback);
+        self
+    }
+
+    /// Sets options, e.g. to guard against entries pushed with skewed
+    /// clocks.
+    pub fn with_options(mut self, options: StoreOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Sets how often subscriptions check for new entries. Defaults to 1ms.
+    pub fn with_subscription_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.subscription_poll_interval = poll_interval;
+        self
+    }
+
+    /// The number of bytes of entries the ring holds before it wraps around.
+    pub fn capacity(&self) -> u64 {
+        self.ring.capacity
+    }
+}
+
+impl Store for ShmStore {
+    fn push(&self, entry: Cow<Entry>) -> Result<(), Error> {
+        let writer = match &self.writer {
+            Some(writer) => writer,
+            None => return Err(Error::Unsupported("pushing to a ring opened for reading".to_string())),
+        };
+        let timer = self.options.start_op();
+        let entry = self.options.check_skew(entry)?;
+        {
+            let _guard = writer.lock().unwrap_or_else(PoisonError::into_inner);
+            let offset = self.ring.append(&entry)?;
+            self.ring.update_latest(&entry.name, offset, entry.timestamp)?;
+        }
+
+        if let Some(on_push) = &self.on_push {
+            on_push(&entry);
+        }
+        if let Some(timer) = timer {
+            timer.finish(SlowOpKind::Push, Some(&entry.name), 1, || None);
+        }
+        Ok(())
+    }
+
+    fn latest<A: Into<Atom>>(&self, name: A) -> Result<Option<Entry>, Error> {
+        let timer = self.options.start_op();
+        let name = name.into();
+        let latest = self.ring.latest(&name, self.options.ignores_skewed_in_latest())?;
+        if let Some(timer) = timer {
+            timer.finish(SlowOpKind::Query, Some(&name), latest.is_some() as u64, || None);
+        }
+        Ok(latest)
+    }
+
+    fn latest_n<A: Into<Atom>>(&self, name: A, n: usize) -> Result<Vec<Entry>, Error> {
+        let timer = self.options.start_op();
+        let name = name.into();
+        let latest = self.ring.latest_n(&name, n, self.options.ignores_skewed_in_latest())?;
+        if let Some(timer) = timer {
+            timer.finish(SlowOpKind::Query, Some(&name), latest.len() as u64, || None);
+        }
+        Ok(latest)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::SUBSCRIBE | Capabilities::DURABLE
+    }
+}
+
+impl SubscribeableStore for ShmStore {
+    type Subscription = ShmSubscription;
+
+    /// Subscribes to entries pushed after this call, by any process. Like
+    /// `SqliteStore`'s, subscriptions poll for new entries, at the interval
+    /// set via `with_subscription_poll_interval`, and yield every entry in
+    /// the order they were pushed. A subscription that falls so far behind
+    /// that entries it hasn't read are overwritten fails with
+    /// `Error::Lagged`, then resumes from the oldest entry left in the ring.
+    fn subscribe<A: Into<Atom>>(&self, name: A) -> Result<Self::Subscription, Error> {
+        Ok(ShmSubscription {
+            ring: self.ring.clone(),
+            name: name.into(),
+            poll_interval: self.subscription_poll_interval,
+            offset: Arc::new(AtomicU64::new(self.ring.head())),
+            watcher: None,
+        })
+    }
+}
+
+/// Polls for new frames on a dedicated thread, waking registered wakers
+/// whenever there are frames the subscription hasn't read. The thread exits
+/// shortly after the watcher is dropped.
+struct ShmWatcher {
+    wakers: Arc<Mutex<Vec<Waker>>>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl ShmWatcher {
+    fn spawn(ring: Arc<Ring>, offset: Arc<AtomicU64>, poll_interval: Duration) -> Self {
+        let wakers = Arc::new(Mutex::new(Vec::<Waker>::default()));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let thread_wakers = wakers.clone();
+        let thread_stopped = stopped.clone();
+
+        thread::spawn(move || {
+            while !thread_stopped.load(Ordering::SeqCst) {
+                if ring.head() > offset.load(Ordering::SeqCst) {
+                    thread_wakers
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .retain(|waker| waker.wake());
+                }
+                thread::sleep(poll_interval);
+            }
+        });
+
+        Self { wakers, stopped }
+    }
+
+    fn register(&self, waker: Waker) {
+        let mut wakers = self.wakers.lock().unwrap_or_else(PoisonError::into_inner);
+        wakers.retain(|waker| waker.is_alive());
+        wakers.push(waker);
+    }
+}
+
+impl Drop for ShmWatcher {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+}
+
+/// A subscription to a `ShmStore`, created via `ShmStore::subscribe`.
+pub struct ShmSubscription {
+    ring: Arc<Ring>,
+    name: Atom,
+    poll_interval: Duration,
+    /// The offset of the next frame to read, shared with the watcher.
+    offset: Arc<AtomicU64>,
+    watcher: Option<ShmWatcher>,
+}
+
+impl ShmSubscription {
+    /// Reads the next entry with the subscription's name, if one has been
+    /// pushed, skipping over other names' entries.
+    fn read_next(&mut self) -> Result<Option<Entry>, Error> {
+        let head = self.ring.head();
+        loop {
+            let offset = self.offset.load(Ordering::SeqCst);
+            if offset >= head {
+                return Ok(None);
+            }
+            match self.ring.read_named(offset, &self.name) {
+                Ok((next, entry)) => {
+                    self.offset.store(next, Ordering::SeqCst);
+                    if entry.is_some() {
+                        return Ok(entry);
+                    }
+                }
+                Err(Error::Lagged) => {
+                    self.offset.store(self.ring.tail(), Ordering::SeqCst);
+                    return Err(Error::Lagged);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+impl Subscription for ShmSubscription {
+    fn next(&mut self, timeout: Option<Duration>) -> Result<Option<Entry>, Error> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            if let Some(entry) = self.read_next()? {
+                return Ok(Some(entry));
+            }
+            let sleep = match deadline {
+                Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                    Some(remaining) if !remaining.is_zero() => remaining.min(self.poll_interval),
+                    _ => return Ok(None),
+                },
+                None => self.poll_interval,
+            };
+            thread::sleep(sleep);
+        }
+    }
+}
+
+impl SubscriptionDyn for ShmSubscription {
+    fn register_waker(&mut self, waker: Waker) -> Result<(), Error> {
+        if self.watcher.is_none() {
+            self.watcher = Some(ShmWatcher::spawn(
+                self.ring.clone(),
+                self.offset.clone(),
+                self.poll_interval,
+            ));
+        }
+        if let Some(watcher) = &self.watcher {
+            watcher.register(waker.clone());
+        }
+        // The waker is registered before checking for unread frames, so that
+        // frames pushed in between aren't missed
+        if self.ring.head() > self.offset.load(Ordering::SeqCst) {
+            waker.wake();
+        }
+        Ok(())
+    }
+
+    fn try_next(&mut self) -> Result<Option<Entry>, Error> {
+        self.read_next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::env;
+    use std::process::{Command, Stdio};
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+
+    use super::{frame_size, ShmStore, HEAD_FIELD, LATEST_SLOTS};
+    use crate::testkit::TempFiles;
+    use crate::{define_test, test_store_impl, Entry, Error, Store, SubscribeableStore, Subscription};
+
+    static CAPACITY: u64 = 1 << 20;
+    // Set by `two_processes` when it runs `child_writer` in a child process
+    static CHILD_PATH_VAR: &str = "BINLOG_SHM_CHILD_PATH";
+
+    test_store_impl!(|files: &TempFiles| ShmStore::open(files.path(), CAPACITY).unwrap());
+    define_test!(select, |files: &TempFiles| ShmStore::open(files.path(), CAPACITY)
+        .unwrap());
+    define_test!(subscribeable_capabilities, |files: &TempFiles| ShmStore::open(
+        files.path(),
+        CAPACITY
+    )
+    .unwrap());
+
+    #[test]
+    fn capacity() {
+        let files = TempFiles::new();
+        let path = files.path();
+        assert!(matches!(ShmStore::open(&path, 1024), Err(Error::InvalidConfig(_))));
+        assert!(matches!(ShmStore::open(&path, 4100), Err(Error::InvalidConfig(_))));
+
+        let store = ShmStore::open(&path, 4096).unwrap();
+        assert_eq!(store.capacity(), 4096);
+        let entry = Entry::new_with_timestamp(1, "test_capacity", vec![0; 4096]);
+        assert!(matches!(store.push(Cow::Owned(entry)), Err(Error::InvalidEntry(_))));
+        drop(store);
+        assert!(matches!(ShmStore::open(&path, 8192), Err(Error::InvalidConfig(_))));
+
+        assert!(matches!(ShmStore::open_reader(files.path()), Err(Error::Io(_))));
+        let not_a_ring = files.path();
+        std::fs::write(&not_a_ring, vec![1; 32768]).unwrap();
+        assert!(matches!(
+            ShmStore::open_reader(&not_a_ring),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn single_writer() {
+        let files = TempFiles::new();
+        let path = files.path();
+        let entry = Entry::new_with_timestamp(1, "test_single_writer", vec![1]);
+        let writer = ShmStore::open(&path, CAPACITY).unwrap();
+        assert!(matches!(ShmStore::open(&path, CAPACITY), Err(Error::InvalidConfig(_))));
+
+        let reader = ShmStore::open_reader(&path).unwrap();
+        assert!(matches!(reader.push(Cow::Borrowed(&entry)), Err(Error::Unsupported(_))));
+        writer.push(Cow::Borrowed(&entry)).unwrap();
+        assert_eq!(reader.latest("test_single_writer").unwrap(), Some(entry.clone()));
+
+        // the lock is released, and entries are kept, when the writer closes
+        drop(writer);
+        let writer = ShmStore::open(&path, CAPACITY).unwrap();
+        assert_eq!(writer.latest("test_single_writer").unwrap(), Some(entry));
+    }
+
+    #[test]
+    fn wraparound() {
+        let files = TempFiles::new();
+        let store = ShmStore::open(files.path(), 4096).unwrap();
+        let mut subscription = store.subscribe("test_wraparound").unwrap();
+
+        // the newest entry is pushed first, so that it's overwritten before
+        // the rest
+        let entry =
+            |timestamp: i64, value: u8| Entry::new_with_timestamp(timestamp, "test_wraparound", vec![value; 50]);
+        store.push(Cow::Owned(entry(100, 0))).unwrap();
+        for i in 0..200u8 {
+            store.push(Cow::Owned(entry(99 - i64::from(i % 10), i))).unwrap();
+            store
+                .push(Cow::Owned(Entry::new_with_timestamp(
+                    0,
+                    "test_wraparound_other",
+                    vec![i],
+                )))
+                .unwrap();
+        }
+        assert_eq!(store.latest("test_wraparound").unwrap(), Some(entry(99, 190)));
+        assert_eq!(
+            store.latest("test_wraparound_other").unwrap(),
+            Some(Entry::new_with_timestamp(0, "test_wraparound_other", vec![199]))
+        );
+
+        // the subscription fell behind, so it fails once, then resumes from
+        // the oldest entry left
+        assert!(matches!(subscription.next(Some(Duration::ZERO)), Err(Error::Lagged)));
+        let mut values = Vec::default();
+        while let Some(entry) = subscription.next(Some(Duration::ZERO)).unwrap() {
+            values.push(entry.value[0]);
+        }
+        assert!(!values.is_empty());
+        let first = values[0];
+        assert_eq!(values, (first..200).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn unslotted_names() {
+        // names too long for a latest slot, or beyond the slots available,
+        // are found by scanning the ring
+        let files = TempFiles::new();
+        let store = ShmStore::open(files.path(), CAPACITY).unwrap();
+        let long_name = "test_unslotted_names_".repeat(10);
+        for i in 0..(LATEST_SLOTS as i64 + 10) {
+            let entry = Entry::new_with_timestamp(i, format!("test_unslotted_names_{}", i), vec![]);
+            store.push(Cow::Owned(entry)).unwrap();
+            let entry = Entry::new_with_timestamp(i, long_name.as_str(), vec![]);
+            store.push(Cow::Owned(entry)).unwrap();
+        }
+        for i in 0..(LATEST_SLOTS as i64 + 10) {
+            let name = format!("test_unslotted_names_{}", i);
+            assert_eq!(store.latest(name.as_str()).unwrap().unwrap().timestamp, i);
+        }
+        assert_eq!(
+            store.latest(long_name.as_str()).unwrap().unwrap().timestamp,
+            LATEST_SLOTS as i64 + 9
+        );
+        assert_eq!(store.latest("test_unslotted_names_missing").unwrap(), None);
+    }
+
+    #[test]
+    fn partially_written_frames() {
+        let files = TempFiles::new();
+        let path = files.path();
+        let entry = |timestamp: i64| Entry::new_with_timestamp(timestamp, "test_partially_written_frames", vec![1; 10]);
+        let store = ShmStore::open(&path, CAPACITY).unwrap();
+        let reader = ShmStore::open_reader(&path).unwrap();
+        let mut subscription = reader.subscribe("test_partially_written_frames").unwrap();
+        store.push(Cow::Owned(entry(1))).unwrap();
+        assert_eq!(subscription.next(None).unwrap(), Some(entry(1)));
+
+        // a writer that crashes before committing a frame leaves one that
+        // readers never see
+        let head = store.ring.head();
+        store.ring.write_frame(head, &entry(2));
+        assert_eq!(subscription.next(Some(Duration::ZERO)).unwrap(), None);
+        assert_eq!(reader.latest("test_partially_written_frames").unwrap(), Some(entry(1)));
+
+        // if the head was advanced but the frame wasn't committed, e.g.
+        // because they were flushed out of order, readers detect it
+        let size = frame_size("test_partially_written_frames".len(), 10);
+        store.ring.map.atomic(HEAD_FIELD).store(head + size, Ordering::SeqCst);
+        assert!(matches!(
+            subscription.next(Some(Duration::ZERO)),
+            Err(Error::InvalidEntry(_))
+        ));
+
+        // and the next writer discards it
+        drop(store);
+        let store = ShmStore::open(&path, CAPACITY).unwrap();
+        assert_eq!(store.ring.head(), head);
+        store.push(Cow::Owned(entry(3))).unwrap();
+        assert_eq!(subscription.next(Some(Duration::from_secs(1))).unwrap(), Some(entry(3)));
+        assert_eq!(reader.latest("test_partially_written_frames").unwrap(), Some(entry(3)));
+    }
+
+    /// Pushes entries when run in a child process by `two_processes`, and
+    /// does nothing otherwise.
+    #[test]
+    fn child_writer() {
+        let path = match env::var_os(CHILD_PATH_VAR) {
+            Some(path) => path,
+            None => return,
+        };
+        let store = ShmStore::open(path, CAPACITY).unwrap();
+        for i in 0..100u8 {
+            let entry = Entry::new_with_timestamp(i.into(), "test_two_processes", vec![i]);
+            store.push(Cow::Owned(entry)).unwrap();
+        }
+    }
+
+    #[test]
+    fn two_processes() {
+        let files = TempFiles::new();
+        let path = files.path();
+        // create the ring, so it can be subscribed to before the child starts
+        drop(ShmStore::open(&path, CAPACITY).unwrap());
+        let reader = ShmStore::open_reader(&path).unwrap();
+        let mut subscription = reader.subscribe("test_two_processes").unwrap();
+
+        let mut child = Command::new(env::current_exe().unwrap())
+            .args(["--exact", "stores::shm::tests::child_writer"])
+            .env(CHILD_PATH_VAR, &path)
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+        for i in 0..100u8 {
+            let entry = subscription.next(Some(Duration::from_secs(10))).unwrap();
+            assert_eq!(
+                entry,
+                Some(Entry::new_with_timestamp(i.into(), "test_two_processes", vec![i]))
+            );
+        }
+        assert!(child.wait().unwrap().success());
+        assert_eq!(
+            reader.latest("test_two_processes").unwrap(),
+            Some(Entry::new_with_timestamp(99, "test_two_processes", vec![99]))
+        );
+    }
+}
@@ -1,33 +1,165 @@
 use std::borrow::Cow;
-use std::collections::VecDeque;
+use std::cell::{Ref, RefCell};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs;
+use std::io::{Error as IoError, ErrorKind as IoErrorKind};
 use std::ops::{Bound, RangeBounds};
 use std::path::Path;
-use std::vec::IntoIter as VecIter;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 
-use crate::{utils, Entry, Error, Range, RangeableStore, Store};
+#[cfg(feature = "sea-query")]
+use super::sqlite_schema::{self, LogIden};
+use crate::codec::{decode_value, encode_value};
+use crate::entry::now_micros;
+#[cfg(feature = "compression")]
+use crate::ZstdCodec;
+use crate::{
+    utils, Capabilities, Clock, Codec, CompactionReport, Entry, EntryMetadata, Error, IngestionLagStats, LazyEntry,
+    LazyValue, NoopCodec, OrderBy, PushCallback, Range, RangeableStore, SlowOpConfig, SlowOpKind, Store, StoreOptions,
+    StoredEntry, SubscribeableStore, Subscription, SubscriptionDyn, Waker,
+};
 
-use r2d2::{Error as R2d2Error, Pool};
+use r2d2::{Pool, PooledConnection};
 use r2d2_sqlite::SqliteConnectionManager;
-use rusqlite::{params, params_from_iter, Error as SqliteError, OptionalExtension, ParamsFromIter};
+use rusqlite::types::Value as SqliteValue;
+use rusqlite::{
+    params, params_from_iter, Connection, Error as SqliteError, OpenFlags, OptionalExtension, ParamsFromIter, Row,
+    ToSql, TransactionBehavior,
+};
 use string_cache::DefaultAtom as Atom;
-use zstd::bulk::{compress, Decompressor};
-
-static SCHEMA: &str = r#"
-create table if not exists log (
-    id integer primary key,
-    ts integer not null,
-    name text not null,
-    size integer not null,
-    value blob not null
-);
-
-create index idx_log_ts on log(ts);
-"#;
-
-// Do not compress entries smaller than this size
-static MIN_SIZE_TO_COMPRESS: usize = 32;
-static DEFAULT_COMPRESSION_LEVEL: i32 = 1;
+
+// Migrations are applied in order, tracked by the database's `user_version`.
+static MIGRATIONS: &[&str] = &[
+    r#"
+    create table if not exists log (
+        id integer primary key,
+        ts integer not null,
+        name text not null,
+        size integer not null,
+        value blob not null
+    );
+
+    create index if not exists idx_log_ts on log(ts);
+    "#,
+    // Values used to always be compressed with zstd when their size was
+    // recorded, and stored as-is otherwise
+    r#"
+    alter table log add column codec text not null default 'none';
+    update log set codec = 'zstd' where size > 0;
+    "#,
+    // Without autoincrement, sqlite reuses the ids of the newest rows after
+    // they're deleted, and ids double as sequence numbers, so they must
+    // never be reused
+    r#"
+    create table log_new (
+        id integer primary key autoincrement,
+        ts integer not null,
+        name text not null,
+        size integer not null,
+        value blob not null,
+        codec text not null default 'none'
+    );
+    insert into log_new (id, ts, name, size, value, codec) select id, ts, name, size, value, codec from log;
+    drop table log;
+    alter table log_new rename to log;
+    create index idx_log_ts on log(ts);
+    "#,
+    // Entries flagged by `SkewPolicy::Flag`
+    r#"
+    alter table log add column skewed integer not null default 0;
+    "#,
+    // When each entry was written, according to the store's clock. This is
+    // unknown for existing entries.
+    r#"
+    alter table log add column ingested_at integer;
+    "#,
+    // Metadata entries were pushed with, encoded as a MessagePack map
+    r#"
+    alter table log add column meta blob;
+    "#,
+    // What created the database, and user-defined properties. `store_info`
+    // has a single row, inserted by `migrate`.
+    r#"
+    create table store_info (
+        created_at integer not null,
+        created_by text not null
+    );
+    create table properties (
+        key text primary key,
+        value text not null
+    );
+    "#,
+    // Queries by name, e.g. `latest`, otherwise scan every entry via
+    // `idx_log_ts`. Lookups by name alone use the index's prefix, and the
+    // rowid is implicitly its last column, so it also covers `latest`'s
+    // `order by ts desc, id desc`.
+    r#"
+    create index if not exists idx_log_name_ts on log(name, ts);
+    "#,
+    // Entries pushed with `push_or_replace`, which are unique by timestamp
+    // and name. Entries pushed otherwise may share both, so they're left
+    // out of the index, and existing entries aren't replaceable.
+    r#"
+    alter table log add column replaceable integer not null default 0;
+    create unique index idx_log_replaceable on log(ts, name) where replaceable;
+    "#,
+];
+
 static PAGINATION_LIMIT: usize = 1000;
+// How many times in a row iterators try to read a page before giving up
+static MAX_PAGE_ATTEMPTS: u32 = 3;
+static PAGE_RETRY_BACKOFF: Duration = Duration::from_millis(10);
+// Entries are ordered by timestamp, then name, then id. Ids are assigned via
+// autoincrement, and kept by vacuums and backups, so the order doesn't depend
+// on which connection or thread pushed an entry, and is the same after the
+// database is reopened. Every statement that yields entries in order uses
+// this, rather than relying on the order rows happen to be stored in.
+static ENTRY_ORDER: &str = "order by ts, name, id";
+// Like `ENTRY_ORDER`, but for `OrderBy::NameThenTime`
+static NAME_ORDER: &str = "order by name, ts, id";
+static POOL_MONITOR_INTERVAL: Duration = Duration::from_millis(100);
+static DEFAULT_SUBSCRIPTION_POLL_INTERVAL: Duration = Duration::from_millis(50);
+static INSERT_STATEMENT: &str =
+    "insert into log (ts, name, size, value, codec, skewed, ingested_at) values (?, ?, ?, ?, ?, ?, ?)";
+// Replaces the value at the entry's timestamp and name if it was also pushed
+// by `push_or_replace`, via `idx_log_replaceable`
+static UPSERT_STATEMENT: &str = "insert into log (ts, name, size, value, codec, skewed, ingested_at, replaceable) \
+    values (?, ?, ?, ?, ?, ?, ?, 1) on conflict (ts, name) where replaceable do update set size = excluded.size, \
+    value = excluded.value, codec = excluded.codec, skewed = excluded.skewed, ingested_at = excluded.ingested_at, \
+    meta = null";
+static INSERT_RETURNING_STATEMENT: &str =
+    "insert into log (ts, name, size, value, codec, skewed, ingested_at) values (?, ?, ?, ?, ?, ?, ?) returning id";
+// Explicit ids past the autoincrement counter advance it, so that later
+// pushes are assigned ids after them
+static INSERT_AT_ID_STATEMENT: &str =
+    "insert or ignore into log (id, ts, name, size, value, codec, skewed, ingested_at) values (?, ?, ?, ?, ?, ?, ?, ?)";
+// `returning` clauses were added in sqlite 3.35.0
+static MIN_RETURNING_VERSION: i32 = 3_035_000;
+static INSERT_WITH_METADATA_STATEMENT: &str =
+    "insert into log (ts, name, size, value, codec, skewed, ingested_at, meta) values (?, ?, ?, ?, ?, ?, ?, ?)";
+// Pragmas that can be set via `SqliteStoreConfig::pragmas`. These only tune
+// performance or durability; others could break the store's assumptions,
+// e.g. `journal_mode` or `locking_mode`, which would stop pooled connections
+// from reading while another writes.
+static ALLOWED_PRAGMAS: &[&str] = &[
+    "analysis_limit",
+    "busy_timeout",
+    "cache_size",
+    "cache_spill",
+    "mmap_size",
+    "secure_delete",
+    "synchronous",
+    "temp_store",
+    "threads",
+];
+// Each inserted row binds 7 variables, and sqlite defaults to allowing at
+// most 999 variables per statement
+static MAX_VARIABLES_PER_STATEMENT: usize = 999;
+static DEFAULT_MAX_ROWS_PER_STATEMENT: usize = MAX_VARIABLES_PER_STATEMENT / 7;
 
 impl From<SqliteError> for Error {
     fn from(err: SqliteError) -> Self {
@@ -35,62 +167,491 @@ impl From<SqliteError> for Error {
     }
 }
 
-impl From<R2d2Error> for Error {
-    fn from(err: R2d2Error) -> Self {
-        Error::Database(Box::new(err))
+fn migrate(conn: &mut Connection) -> Result<(), Error> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let version: usize = tx.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", i + 1)?;
+    }
+    if version < MIGRATIONS.len() {
+        tx.execute(
+            "insert into store_info (created_at, created_by) select ?, ? where not exists (select 1 from store_info)",
+            params![now_micros(), env!("CARGO_PKG_VERSION")],
+        )?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Encodes metadata as a MessagePack map of strings, with sorted keys so that
+/// equal metadata is always encoded the same.
+fn encode_metadata(metadata: &EntryMetadata) -> Vec<u8> {
+    let mut pairs: Vec<(&String, &String)> = metadata.0.iter().collect();
+    pairs.sort();
+    let mut buf = Vec::default();
+    rmp::encode::write_map_len(&mut buf, pairs.len() as u32).expect("writing to a vec cannot fail");
+    for (key, value) in pairs {
+        rmp::encode::write_str(&mut buf, key).expect("writing to a vec cannot fail");
+        rmp::encode::write_str(&mut buf, value).expect("writing to a vec cannot fail");
+    }
+    buf
+}
+
+fn decode_metadata(mut buf: &[u8]) -> Result<EntryMetadata, Error> {
+    let invalid = || Error::Database("invalid entry metadata".into());
+    let len = rmp::decode::read_map_len(&mut buf).map_err(|_| invalid())?;
+    let mut metadata = EntryMetadata::default();
+    for _ in 0..len {
+        let (key, rest) = rmp::decode::read_str_from_slice(buf).map_err(|_| invalid())?;
+        let (value, rest) = rmp::decode::read_str_from_slice(rest).map_err(|_| invalid())?;
+        metadata.0.insert(key.to_string(), value.to_string());
+        buf = rest;
+    }
+    Ok(metadata)
+}
+
+/// Reads the names of rows as atoms. Interning a name takes a lock on the
+/// global atom table, so it's skipped where possible: if the query only
+/// matches one name, that name's atom is reused for every row, and otherwise
+/// each distinct name is interned once for every page of rows that's read
+/// with the same `RowNames`.
+struct RowNames {
+    known: Option<Atom>,
+    interned: HashMap<String, Atom>,
+}
+
+impl RowNames {
+    fn new(known: Option<&Atom>) -> Self {
+        Self {
+            known: known.cloned(),
+            interned: HashMap::default(),
+        }
+    }
+
+    fn get(&mut self, row: &Row, idx: usize) -> Result<Atom, Error> {
+        if let Some(known) = &self.known {
+            return Ok(known.clone());
+        }
+        let name = row
+            .get_ref(idx)?
+            .as_str()
+            .map_err(|err| Error::Database(Box::new(err)))?;
+        if let Some(atom) = self.interned.get(name) {
+            return Ok(atom.clone());
+        }
+        let atom = Atom::from(name);
+        self.interned.insert(name.to_string(), atom.clone());
+        Ok(atom)
+    }
+}
+
+fn stored_entry_from_row(codec: &dyn Codec, names: &mut RowNames, row: &Row) -> Result<StoredEntry, Error> {
+    let timestamp: i64 = row.get(0)?;
+    let name = names.get(row, 1)?;
+    let size: usize = row.get(2)?;
+    let blob: Vec<u8> = row.get(3)?;
+    let codec_name: String = row.get(4)?;
+    let id: i64 = row.get(5)?;
+    let skewed: bool = row.get(6)?;
+    let ingested_at: Option<i64> = row.get(7)?;
+    let meta: Option<Vec<u8>> = row.get(8)?;
+    let value = decode_value(codec, &codec_name, size, blob)?;
+    let mut entry = Entry::new_with_timestamp(timestamp, name, value);
+    entry.sequence_number = Some(id as u64);
+    entry.skewed = skewed;
+    Ok(StoredEntry {
+        entry,
+        ingested_at,
+        id: id as u64,
+        metadata: meta.as_deref().map(decode_metadata).transpose()?,
+    })
+}
+
+fn entry_from_row(codec: &dyn Codec, names: &mut RowNames, row: &Row) -> Result<Entry, Error> {
+    Ok(stored_entry_from_row(codec, names, row)?.entry)
+}
+
+/// Like `entry_from_row`, but leaves the value encoded until it's read.
+fn lazy_entry_from_row(codec: &Arc<dyn Codec>, names: &mut RowNames, row: &Row) -> Result<LazyEntry, Error> {
+    let id: i64 = row.get(5)?;
+    Ok(LazyEntry {
+        timestamp: row.get(0)?,
+        name: names.get(row, 1)?,
+        value: LazyValue::encoded(codec.clone(), row.get(4)?, row.get(2)?, row.get(3)?),
+        sequence_number: Some(id as u64),
+        skewed: row.get(6)?,
+    })
+}
+
+// The reverse of `ENTRY_ORDER`, for a single name
+fn latest_statement(ignore_skewed: bool) -> &'static str {
+    if ignore_skewed {
+        "select ts, name, size, value, codec, id, skewed, ingested_at, meta from log where name = ? and skewed = 0 order by ts desc, id desc"
+    } else {
+        "select ts, name, size, value, codec, id, skewed, ingested_at, meta from log where name = ? order by ts desc, id desc"
     }
 }
 
-fn entry_from_row<S: Into<Atom>>(
-    decompressor: &mut Decompressor<'_>,
-    timestamp: i64,
-    name: S,
-    size: usize,
-    blob: Vec<u8>,
-) -> Result<Entry, Error> {
-    if size > 0 {
-        let blob_decompressed = decompressor.decompress(&blob, size)?;
-        Ok(Entry::new_with_timestamp(timestamp, name.into(), blob_decompressed))
+/// Gets the latest entry with a name, skipping over entries flagged as skewed
+/// if `ignore_skewed` is set.
+fn select_latest(
+    conn: &Connection,
+    codec: &dyn Codec,
+    name: Atom,
+    ignore_skewed: bool,
+) -> Result<Option<Entry>, Error> {
+    let mut stmt = conn.prepare_cached(latest_statement(ignore_skewed))?;
+    let mut rows = stmt.query(params![name.as_ref()])?;
+    if let Some(row) = rows.next()? {
+        Ok(Some(entry_from_row(codec, &mut RowNames::new(Some(&name)), row)?))
     } else {
-        Ok(Entry::new_with_timestamp(timestamp, name.into(), blob))
+        Ok(None)
+    }
+}
+
+fn latest_n_statement(ignore_skewed: bool) -> &'static str {
+    if ignore_skewed {
+        "select ts, name, size, value, codec, id, skewed, ingested_at, meta from log where name = ? and skewed = 0 order by ts desc, id desc limit ?"
+    } else {
+        "select ts, name, size, value, codec, id, skewed, ingested_at, meta from log where name = ? order by ts desc, id desc limit ?"
+    }
+}
+
+/// Gets up to the `n` latest entries with a name, oldest first, skipping over
+/// entries flagged as skewed if `ignore_skewed` is set.
+fn select_latest_n(
+    conn: &Connection,
+    codec: &dyn Codec,
+    name: Atom,
+    n: usize,
+    ignore_skewed: bool,
+) -> Result<Vec<Entry>, Error> {
+    let mut stmt = conn.prepare_cached(latest_n_statement(ignore_skewed))?;
+    let limit = i64::try_from(n).unwrap_or(i64::MAX);
+    let mut rows = stmt.query(params![name.as_ref(), limit])?;
+    let mut names = RowNames::new(Some(&name));
+    let mut entries = Vec::with_capacity(n.min(1024));
+    while let Some(row) = rows.next()? {
+        entries.push(entry_from_row(codec, &mut names, row)?);
+    }
+    entries.reverse();
+    Ok(entries)
+}
+
+fn select_count(conn: &Connection, statement_builder: &StatementBuilder) -> Result<u64, Error> {
+    if statement_builder.is_unsatisfiable() {
+        return Ok(0);
+    }
+    #[cfg(feature = "sea-query")]
+    if statement_builder.sea_query {
+        let (statement, values) = sqlite_schema::build(&sqlite_schema::count_statement(statement_builder.condition()));
+        let len: u64 = conn
+            .prepare_cached(&statement)?
+            .query_row(params_from_iter(values), |row| row.get(0))?;
+        return Ok(len);
+    }
+    let mut stmt = conn.prepare_cached(&statement_builder.statement("select count(id) from log", ""))?;
+    let len: u64 = stmt.query_row(statement_builder.params(), |row| row.get(0))?;
+    Ok(len)
+}
+
+fn select_is_empty(conn: &Connection, statement_builder: &StatementBuilder) -> Result<bool, Error> {
+    if statement_builder.is_unsatisfiable() {
+        return Ok(true);
+    }
+    #[cfg(feature = "sea-query")]
+    if statement_builder.sea_query {
+        let (statement, values) = sqlite_schema::build(&sqlite_schema::exists_statement(statement_builder.condition()));
+        let exists: bool = conn
+            .prepare_cached(&statement)?
+            .query_row(params_from_iter(values), |row| row.get(0))?;
+        return Ok(!exists);
+    }
+    let mut stmt = conn.prepare_cached(&format!(
+        "select exists({})",
+        statement_builder.statement("select 1 from log", "limit 1")
+    ))?;
+    let exists: bool = stmt.query_row(statement_builder.params(), |row| row.get(0))?;
+    Ok(!exists)
+}
+
+/// The columns read by a page of a range.
+#[derive(Clone, Copy)]
+enum PageColumns {
+    Entries,
+    Keys,
+}
+
+impl PageColumns {
+    fn select(self) -> &'static str {
+        match self {
+            PageColumns::Entries => "select ts, name, size, value, codec, id, skewed, ingested_at, meta from log",
+            PageColumns::Keys => "select ts, name, id from log",
+        }
+    }
+
+    #[cfg(feature = "sea-query")]
+    fn idens(self) -> &'static [LogIden] {
+        match self {
+            PageColumns::Entries => sqlite_schema::ENTRY_COLUMNS,
+            PageColumns::Keys => sqlite_schema::KEY_COLUMNS,
+        }
+    }
+}
+
+/// Builds the statement that reads a page of entries starting at `offset`,
+/// alongside its parameters.
+fn page_statement(statement_builder: &StatementBuilder, offset: usize) -> (String, Vec<SqliteValue>) {
+    #[cfg(feature = "sea-query")]
+    if statement_builder.sea_query {
+        let statement = sqlite_schema::select_statement(
+            PageColumns::Entries.idens(),
+            statement_builder.condition(),
+            statement_builder.order,
+        )
+        .limit(PAGINATION_LIMIT as u64)
+        .offset(offset as u64)
+        .to_owned();
+        return sqlite_schema::build(&statement);
+    }
+    let statement = statement_builder
+        .statement(
+            PageColumns::Entries.select(),
+            &format!(
+                "{} limit {} offset ?",
+                statement_builder.order_clause(),
+                PAGINATION_LIMIT
+            ),
+        )
+        .into_owned();
+    let mut params = statement_builder.sqlite_values();
+    params.push(SqliteValue::Integer(offset as i64));
+    (statement, params)
+}
+
+/// Reads a page of entries starting at `offset`, returning whether there are
+/// no further pages. Entries are only added if the whole page is read, so
+/// that a failed page can be retried without duplicating entries.
+fn select_page(
+    conn: &Connection,
+    codec: &dyn Codec,
+    statement_builder: &StatementBuilder,
+    offset: usize,
+    entries: &mut VecDeque<StoredEntry>,
+) -> Result<bool, Error> {
+    if statement_builder.is_unsatisfiable() {
+        return Ok(true);
+    }
+    let (statement, params) = page_statement(statement_builder, offset);
+    let mut stmt = conn.prepare_cached(&statement)?;
+    let mut rows = stmt.query(params_from_iter(params))?;
+    let mut names = RowNames::new(statement_builder.name.as_ref());
+    let mut page = Vec::with_capacity(PAGINATION_LIMIT);
+    while let Some(row) = rows.next()? {
+        page.push(stored_entry_from_row(codec, &mut names, row)?);
     }
+    let done = page.len() < PAGINATION_LIMIT;
+    entries.extend(page);
+    Ok(done)
+}
+
+/// Tracks consecutive failures to read a page, so that iterators retry
+/// transient errors without spinning on persistent ones.
+#[derive(Default)]
+pub(crate) struct PageRetries {
+    failures: u32,
+}
+
+impl PageRetries {
+    /// Waits before retrying a page that failed, backing off exponentially.
+    pub(crate) fn backoff(&self) {
+        if self.failures > 0 {
+            thread::sleep(PAGE_RETRY_BACKOFF * 2u32.pow(self.failures - 1));
+        }
+    }
+
+    /// Records a page read, returning whether the iterator should give up.
+    pub(crate) fn record<T>(&mut self, result: &Result<T, Error>) -> bool {
+        if result.is_ok() {
+            self.failures = 0;
+            false
+        } else {
+            self.failures += 1;
+            self.failures >= MAX_PAGE_ATTEMPTS
+        }
+    }
+}
+
+/// Builds a statement that selects up to `limit` rows that sort after
+/// `cursor`, i.e. the timestamp, name and id of the last row already read,
+/// alongside its parameters. Unlike `page_statement`, this seeks straight to
+/// the cursor rather than skipping over every earlier row.
+fn page_after_statement(
+    statement_builder: &StatementBuilder,
+    columns: PageColumns,
+    cursor: Option<&(i64, Atom, u64)>,
+    limit: usize,
+) -> (String, Vec<SqliteValue>) {
+    #[cfg(feature = "sea-query")]
+    if statement_builder.sea_query {
+        let mut condition = statement_builder.condition();
+        if let Some(cursor) = cursor {
+            condition = sqlite_schema::after_cursor(condition, statement_builder.order, cursor);
+        }
+        let statement = sqlite_schema::select_statement(columns.idens(), condition, statement_builder.order)
+            .limit(limit as u64)
+            .to_owned();
+        return sqlite_schema::build(&statement);
+    }
+    let mut params = statement_builder.sqlite_values();
+    let clause = cursor.map(|(timestamp, name, id)| {
+        let (timestamp, name, id) = (
+            SqliteValue::Integer(*timestamp),
+            SqliteValue::Text(name.to_string()),
+            SqliteValue::Integer(*id as i64),
+        );
+        match statement_builder.order {
+            OrderBy::Time => {
+                params.extend([timestamp, name, id]);
+                "(ts, name, id) > (?, ?, ?)".to_string()
+            }
+            OrderBy::NameThenTime => {
+                params.extend([name, timestamp, id]);
+                "(name, ts, id) > (?, ?, ?)".to_string()
+            }
+        }
+    });
+    let suffix = format!("{} limit ?", statement_builder.order_clause());
+    params.push(SqliteValue::Integer(limit as i64));
+    let statement = statement_builder
+        .statement_with_clause(columns.select(), clause, &suffix)
+        .into_owned();
+    (statement, params)
+}
+
+/// Reads up to `limit` entries that sort after `cursor`.
+fn select_page_after(
+    conn: &Connection,
+    codec: &dyn Codec,
+    statement_builder: &StatementBuilder,
+    cursor: Option<&(i64, Atom, u64)>,
+    limit: usize,
+) -> Result<Vec<StoredEntry>, Error> {
+    if statement_builder.is_unsatisfiable() {
+        return Ok(Vec::default());
+    }
+    let (statement, params) = page_after_statement(statement_builder, PageColumns::Entries, cursor, limit);
+    let mut stmt = conn.prepare_cached(&statement)?;
+    let mut rows = stmt.query(params_from_iter(params))?;
+    let mut names = RowNames::new(statement_builder.name.as_ref());
+    let mut page = Vec::with_capacity(limit);
+    while let Some(row) = rows.next()? {
+        page.push(stored_entry_from_row(codec, &mut names, row)?);
+    }
+    Ok(page)
 }
 
 struct StatementBuilder {
+    /// The column the bounds apply to, i.e. `ts` or `id`.
+    column: &'static str,
     start_bound: Bound<i64>,
     end_bound: Bound<i64>,
     name: Option<Atom>,
+    order: OrderBy,
+    /// Whether range reads are built with sea-query.
+    #[cfg(feature = "sea-query")]
+    sea_query: bool,
 }
 
 impl StatementBuilder {
     fn new<R: RangeBounds<i64>>(range: R, name: Option<Atom>) -> StatementBuilder {
         Self {
+            column: "ts",
             start_bound: range.start_bound().cloned(),
             end_bound: range.end_bound().cloned(),
             name,
+            order: OrderBy::default(),
+            #[cfg(feature = "sea-query")]
+            sea_query: false,
         }
     }
 
-    fn params(&self) -> ParamsFromIter<VecIter<String>> {
-        if let Some(name) = &self.name {
-            params_from_iter(vec![name.to_string()].into_iter())
-        } else {
-            params_from_iter(vec![].into_iter())
+    fn new_ids(start_bound: Bound<i64>, end_bound: Bound<i64>, name: Option<Atom>) -> StatementBuilder {
+        Self {
+            column: "id",
+            start_bound,
+            end_bound,
+            name,
+            order: OrderBy::default(),
+            #[cfg(feature = "sea-query")]
+            sea_query: false,
+        }
+    }
+
+    #[cfg(feature = "sea-query")]
+    fn with_sea_query(mut self, sea_query: bool) -> Self {
+        self.sea_query = sea_query;
+        self
+    }
+
+    /// The bounds and name as a sea-query condition.
+    #[cfg(feature = "sea-query")]
+    fn condition(&self) -> sea_query::Condition {
+        let column = if self.column == "id" { LogIden::Id } else { LogIden::Ts };
+        sqlite_schema::range_condition(column, self.start_bound, self.end_bound, self.name.as_ref())
+    }
+
+    /// Returns whether no row can fall within the bounds, in which case
+    /// there's no need to query for them.
+    fn is_unsatisfiable(&self) -> bool {
+        utils::inclusive_bounds(self.start_bound.as_ref(), self.end_bound.as_ref()).is_none()
+    }
+
+    fn order_clause(&self) -> &'static str {
+        match self.order {
+            OrderBy::Time => ENTRY_ORDER,
+            OrderBy::NameThenTime => NAME_ORDER,
+        }
+    }
+
+    fn params(&self) -> ParamsFromIter<Vec<SqliteValue>> {
+        params_from_iter(self.sqlite_values())
+    }
+
+    /// The parameters of `statement`. Bounds are bound rather than formatted
+    /// into the statement, so that ranges with the same kinds of bounds share
+    /// a statement, which can then be reused from the connection's cache.
+    fn sqlite_values(&self) -> Vec<SqliteValue> {
+        let mut values = Vec::new();
+        if let Bound::Included(s) | Bound::Excluded(s) = self.start_bound {
+            values.push(SqliteValue::Integer(s));
+        }
+        if let Bound::Included(e) | Bound::Excluded(e) = self.end_bound {
+            values.push(SqliteValue::Integer(e));
         }
+        values.extend(self.name.iter().map(|name| SqliteValue::Text(name.to_string())));
+        values
     }
 
     fn statement<'a>(&self, prefix: &'a str, suffix: &'a str) -> Cow<'a, str> {
+        self.statement_with_clause(prefix, None, suffix)
+    }
+
+    /// Like `statement`, but with an extra condition. Any parameters in the
+    /// condition come after those of `params`.
+    fn statement_with_clause<'a>(&self, prefix: &'a str, clause: Option<String>, suffix: &'a str) -> Cow<'a, str> {
         let mut clauses = Vec::new();
 
         match self.start_bound {
-            Bound::Included(s) => clauses.push(format!("ts >= {}", s)),
-            Bound::Excluded(s) => clauses.push(format!("ts > {}", s)),
+            Bound::Included(_) => clauses.push(format!("{} >= ?", self.column)),
+            Bound::Excluded(_) => clauses.push(format!("{} > ?", self.column)),
             Bound::Unbounded => {}
         }
 
         match self.end_bound {
-            Bound::Included(e) => clauses.push(format!("ts <= {}", e)),
-            Bound::Excluded(e) => clauses.push(format!("ts < {}", e)),
+            Bound::Included(_) => clauses.push(format!("{} <= ?", self.column)),
+            Bound::Excluded(_) => clauses.push(format!("{} < ?", self.column)),
             Bound::Unbounded => {}
         }
 
@@ -98,6 +659,8 @@ impl StatementBuilder {
             clauses.push("name = ?".to_string());
         }
 
+        clauses.extend(clause);
+
         let where_clause = if clauses.is_empty() {
             "".to_string()
         } else {
@@ -112,188 +675,4779 @@ impl StatementBuilder {
     }
 }
 
-#[derive(Clone)]
-pub struct SqliteStore {
-    pool: Pool<SqliteConnectionManager>,
-    compression_level: i32,
+/// Runs `PRAGMA integrity_check`, returning an error if it reports any
+/// problems.
+fn check_integrity(conn: &Connection) -> Result<(), Error> {
+    let result: String = conn.query_row("pragma integrity_check(1)", params![], |row| row.get(0))?;
+    if result == "ok" {
+        Ok(())
+    } else {
+        Err(Error::Database(format!("integrity check failed: {}", result).into()))
+    }
 }
 
-impl SqliteStore {
-    pub fn new_with_pool(pool: Pool<SqliteConnectionManager>, compression_level: Option<i32>) -> Result<Self, Error> {
-        {
-            let conn = pool.get()?;
-            conn.pragma_update(None, "journal_mode", "wal2")?;
-            conn.execute(SCHEMA, params![])?;
+fn vacuum(conn: &Connection) -> Result<(), Error> {
+    conn.execute_batch("vacuum")?;
+    Ok(())
+}
+
+fn optimize(conn: &Connection) -> Result<(), Error> {
+    conn.execute_batch("pragma optimize")?;
+    Ok(())
+}
+
+#[cfg(feature = "compression")]
+fn default_codec(config: &SqliteStoreConfig) -> Arc<dyn Codec> {
+    Arc::new(ZstdCodec::new(config.compression_level))
+}
+
+#[cfg(not(feature = "compression"))]
+fn default_codec(_config: &SqliteStoreConfig) -> Arc<dyn Codec> {
+    Arc::new(NoopCodec)
+}
+
+/// Configuration for a `SqliteStore`.
+#[derive(Clone, Debug)]
+pub struct SqliteStoreConfig {
+    /// The zstd compression level used for values, unless another codec is
+    /// set via `SqliteStore::with_codec`.
+    #[cfg(feature = "compression")]
+    pub compression_level: i32,
+    /// Whether to check the integrity of the database when the store is
+    /// created, so that corruption is caught before the first real operation.
+    /// Note that this reads the entire database.
+    pub startup_health_check: bool,
+    /// The maximum number of rows inserted per statement by
+    /// `SqliteStore::push_bulk_values`. The default keeps statements within
+    /// sqlite's default limit on bound variables.
+    pub max_rows_per_statement: usize,
+    /// If set, `SqliteStore::optimize` is run on a background thread at this
+    /// interval, so that the query planner's statistics stay fresh. The
+    /// thread stops when the store and all of its clones are dropped.
+    pub optimize_interval: Option<Duration>,
+    /// If set, the database is vacuumed on a background thread once the
+    /// values removed via `Range::remove` since the last vacuum exceed this
+    /// many bytes, so that their storage is reclaimed.
+    pub auto_vacuum_threshold_bytes: Option<u64>,
+    /// If set, the write-ahead log is checkpointed automatically once it
+    /// reaches this many pages, rather than sqlite's default of 1000. This
+    /// is a per-connection setting, so for pools passed to
+    /// `SqliteStore::new_with_pool_and_config`, it's only applied to the
+    /// connection used to set up the store; set it when the pool's
+    /// connections are initialized instead.
+    pub wal_autocheckpoint: Option<u32>,
+    /// Acquiring a connection from the pool for longer than this invokes
+    /// the callback set via `SqliteStore::set_pool_timeout_callback`.
+    pub pool_timeout_threshold: Duration,
+    /// Pragmas set on each connection after the schema is created, as
+    /// `(pragma, value)` pairs, e.g. `("mmap_size", "268435456")` or
+    /// `("synchronous", "normal")`, to tune sqlite for the hardware. Only
+    /// pragmas that tune performance or durability are allowed; others fail
+    /// with `Error::InvalidConfig`. As with `wal_autocheckpoint`, for pools
+    /// passed to `SqliteStore::new_with_pool_and_config`, these are only set
+    /// on the connection used to set up the store.
+    pub pragmas: Vec<(String, String)>,
+    /// If set, `latest` results are cached in memory, shared by the store's
+    /// clones, for up to this long. Writes through the store (or its clones)
+    /// invalidate the names they touch, so they're always seen by `latest`
+    /// immediately; the ttl bounds how stale results can be when another
+    /// process writes to the database.
+    pub latest_cache_ttl: Option<Duration>,
+    /// How connections are protected from concurrent use. As with
+    /// `wal_autocheckpoint`, this is ignored for pools passed to
+    /// `SqliteStore::new_with_pool_and_config`; open their connections with
+    /// `SqliteConnectionManager::with_flags` instead.
+    pub thread_mode: ThreadMode,
+}
+
+/// How a `SqliteStore`'s connections are protected from concurrent use.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ThreadMode {
+    /// Each connection is guarded by its own mutex (`SQLITE_OPEN_FULLMUTEX`),
+    /// so it's safe for several threads to use it at once.
+    Serialized,
+    /// Connections have no mutex (`SQLITE_OPEN_NOMUTEX`), which saves locking
+    /// on every call into sqlite, so each connection must only be used by
+    /// one thread at a time. The store's pool hands each connection to one
+    /// thread at a time, and rusqlite's connections aren't `Sync`, so this
+    /// holds for the store's own connections; connections shared some other
+    /// way (e.g. via `unsafe` or ffi) need their own locking. This is
+    /// rusqlite's default, and so was the store's before it was configurable.
+    #[default]
+    MultiThread,
+    /// Like `MultiThread`, but with a pool of a single connection, so that
+    /// only one thread uses the database at a time, and the others wait for
+    /// it. sqlite's own single-thread mode, which also disables its global
+    /// mutexes, can only be chosen when sqlite is built or started, not per
+    /// connection.
+    ///
+    /// A snapshot holds the connection until it's dropped, including while
+    /// its ranges are iterated, so while one is open, every other operation
+    /// on the store, pushes included, waits for it, and fails once the
+    /// pool's connection timeout (30 seconds by default) elapses. Drop
+    /// snapshots promptly, or use another mode if they're long-lived.
+    SingleThread,
+}
+
+impl ThreadMode {
+    fn open_flags(self) -> OpenFlags {
+        let flags = OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_CREATE | OpenFlags::SQLITE_OPEN_URI;
+        match self {
+            ThreadMode::Serialized => flags | OpenFlags::SQLITE_OPEN_FULL_MUTEX,
+            ThreadMode::MultiThread | ThreadMode::SingleThread => flags | OpenFlags::SQLITE_OPEN_NO_MUTEX,
         }
-        Ok(Self {
-            pool,
-            compression_level: compression_level.unwrap_or(DEFAULT_COMPRESSION_LEVEL),
-        })
     }
 
-    pub fn new<P: AsRef<Path>>(path: P, compression_level: Option<i32>) -> Result<Self, Error> {
-        let manager = SqliteConnectionManager::file(path);
-        let pool = r2d2::Pool::new(manager)?;
-        Self::new_with_pool(pool, compression_level)
+    fn max_pool_size(self) -> Option<u32> {
+        match self {
+            ThreadMode::SingleThread => Some(1),
+            ThreadMode::Serialized | ThreadMode::MultiThread => None,
+        }
     }
 }
 
-impl Store for SqliteStore {
-    fn push(&self, entry: Cow<Entry>) -> Result<(), Error> {
-        let (blob_compressed, size) = if entry.value.len() >= MIN_SIZE_TO_COMPRESS {
-            (compress(&entry.value, self.compression_level)?, entry.value.len())
-        } else {
-            (Vec::default(), 0)
-        };
-        let blob_ref = if blob_compressed.is_empty() {
-            &entry.value
-        } else {
-            &blob_compressed
-        };
-
-        let conn = self.pool.get()?;
-        let mut stmt = conn.prepare_cached("insert into log (ts, name, size, value) values (?, ?, ?, ?)")?;
-        stmt.execute(params![entry.timestamp, entry.name.as_ref(), size, blob_ref])?;
+impl SqliteStoreConfig {
+    fn check_pragmas(&self) -> Result<(), Error> {
+        for (pragma, _) in self.pragmas.iter() {
+            if !ALLOWED_PRAGMAS.contains(&pragma.to_lowercase().as_str()) {
+                return Err(Error::InvalidConfig(format!("unsupported pragma: {}", pragma)));
+            }
+        }
         Ok(())
     }
+}
 
-    fn latest<A: Into<Atom>>(&self, name: A) -> Result<Option<Entry>, Error> {
-        let name = name.into();
-        let conn = self.pool.get()?;
-        let mut stmt = conn.prepare_cached("select ts, size, value from log where name = ? order by ts desc")?;
-        let row = stmt
-            .query_row(params![name.as_ref()], |row| {
-                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
-            })
-            .optional()?;
+fn set_pragmas(conn: &Connection, pragmas: &[(String, String)]) -> Result<(), rusqlite::Error> {
+    for (pragma, value) in pragmas {
+        conn.pragma_update(None, pragma, value)?;
+    }
+    Ok(())
+}
 
-        if let Some((timestamp, size, blob)) = row {
-            let mut decompressor = Decompressor::new()?;
-            let entry = entry_from_row(&mut decompressor, timestamp, name, size, blob)?;
-            Ok(Some(entry))
-        } else {
-            Ok(None)
+impl Default for SqliteStoreConfig {
+    fn default() -> Self {
+        Self {
+            #[cfg(feature = "compression")]
+            compression_level: ZstdCodec::DEFAULT_LEVEL,
+            startup_health_check: true,
+            max_rows_per_statement: DEFAULT_MAX_ROWS_PER_STATEMENT,
+            optimize_interval: None,
+            auto_vacuum_threshold_bytes: None,
+            wal_autocheckpoint: None,
+            pool_timeout_threshold: Duration::from_secs(1),
+            pragmas: Vec::new(),
+            latest_cache_ttl: None,
+            thread_mode: ThreadMode::default(),
         }
     }
 }
 
-impl RangeableStore for SqliteStore {
-    type Range = SqliteRange;
+/// The results of `SqliteStore::verify_compression`.
+#[derive(Debug, Default)]
+pub struct CompressionVerificationReport {
+    /// The number of compressed entries checked.
+    pub rows_checked: u64,
+    /// The ids of entries that failed to decompress to their original size,
+    /// alongside why.
+    pub failures: Vec<(u64, Error)>,
+}
 
-    fn range<A: Into<Atom>, R: RangeBounds<i64>>(&self, range: R, name: Option<A>) -> Result<Self::Range, Error> {
-        utils::check_bounds(range.start_bound(), range.end_bound())?;
-        Ok(SqliteRange {
-            pool: self.pool.clone(),
-            statement_builder: StatementBuilder::new(range, name.map(|n| n.into())),
-        })
+/// How `SqliteStore::aggregate` interprets values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValueType {
+    /// An 8-byte little-endian float.
+    F64,
+    /// An 8-byte little-endian signed integer.
+    I64,
+}
+
+/// An aggregate computed by `SqliteStore::aggregate`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Aggregate {
+    Min,
+    Max,
+    Sum,
+    Count,
+    Avg,
+}
+
+/// The results of `SqliteStore::aggregate`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AggregateReport {
+    /// The aggregate, or `None` if no entries were aggregated. Counts are
+    /// `Some(0.0)` instead.
+    pub value: Option<f64>,
+    /// The number of entries aggregated.
+    pub rows_aggregated: u64,
+    /// The ids of entries whose values aren't an uncompressed scalar of the
+    /// requested type, which aren't aggregated.
+    pub mismatched: Vec<u64>,
+}
+
+/// The compression statistics of a name's entries, from
+/// `SqliteStore::compression_stats_by_name`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NameCompressionStats {
+    pub name: Atom,
+    pub entry_count: u64,
+    /// The total size of the entries' values before compression.
+    pub total_uncompressed_bytes: u64,
+    /// The total size of the entries' values as stored.
+    pub total_stored_bytes: u64,
+    /// The stored size as a fraction of the uncompressed size, so lower is
+    /// better, and 1.0 or more means compression saved nothing. Values too
+    /// small to compress count as stored as-is.
+    pub ratio: f64,
+}
+
+/// What created a `SqliteStore`'s database, from `SqliteStore::info`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SqliteStoreInfo {
+    /// When the database was created, in microseconds since the unix epoch.
+    /// For databases created by versions of binlog that didn't record this,
+    /// it's when the database was first opened by one that does.
+    pub created_at: i64,
+    /// The version of binlog that created the database, with the same
+    /// caveat as `created_at`.
+    pub created_by: String,
+    /// The number of schema migrations applied to the database.
+    pub schema_version: u32,
+    /// The properties set via `SqliteStore::set_property`.
+    pub properties: BTreeMap<String, String>,
+}
+
+/// A handle to a background health check started via
+/// `SqliteStore::health_check_async_periodic`. The health check stops when
+/// the handle is dropped.
+pub struct HealthCheckHandle {
+    healthy: Arc<AtomicBool>,
+    _stop_sender: Sender<()>,
+}
+
+impl HealthCheckHandle {
+    /// Returns whether the most recent health check passed.
+    pub fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::SeqCst)
     }
 }
 
-pub struct SqliteRange {
-    pool: Pool<SqliteConnectionManager>,
-    statement_builder: StatementBuilder,
+/// Statistics gathered by a `PoolMonitor`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PoolStats {
+    /// The longest that acquiring a connection has taken, in milliseconds.
+    pub max_wait_ms: u64,
+    /// How long acquiring a connection has taken on average, in
+    /// milliseconds.
+    pub avg_wait_ms: f64,
+    /// The number of samples in which the pool had no idle connections,
+    /// i.e. further acquisitions had to wait.
+    pub exhaustion_count: u64,
 }
 
-impl Range for SqliteRange {
-    type Iter = SqliteRangeIterator;
+/// Samples a `SqliteStore`'s connection pool every 100ms on a background
+/// thread, created via `SqliteStore::monitor`. Sampling stops when the
+/// monitor is dropped.
+pub struct PoolMonitor {
+    waits: Arc<PoolWaits>,
+    exhaustion_count: Arc<AtomicU64>,
+    stop_sender: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
 
-    fn count(&self) -> Result<u64, Error> {
-        let conn = self.pool.get()?;
-        let mut stmt = conn.prepare(&self.statement_builder.statement("select count(id) from log", ""))?;
-        let len: u64 = stmt.query_row(self.statement_builder.params(), |row| row.get(0))?;
-        Ok(len)
+impl PoolMonitor {
+    fn spawn(pool: MonitoredPool) -> Self {
+        let exhaustion_count = Arc::new(AtomicU64::new(0));
+        let thread_exhaustion_count = exhaustion_count.clone();
+        let (stop_sender, stop_receiver) = channel::<()>();
+        let waits = pool.waits.clone();
+        let thread = thread::spawn(move || loop {
+            if pool.state().idle_connections == 0 {
+                thread_exhaustion_count.fetch_add(1, Ordering::SeqCst);
+            }
+            match stop_receiver.recv_timeout(POOL_MONITOR_INTERVAL) {
+                Err(RecvTimeoutError::Timeout) => {}
+                _ => break,
+            }
+        });
+        Self {
+            waits,
+            exhaustion_count,
+            stop_sender: Some(stop_sender),
+            thread: Some(thread),
+        }
     }
 
-    fn remove(self) -> Result<(), Error> {
-        let conn = self.pool.get()?;
-        let mut stmt = conn.prepare(&self.statement_builder.statement("delete from log", ""))?;
-        stmt.execute(self.statement_builder.params())?;
-        Ok(())
+    /// Returns the statistics gathered so far. Wait times cover every
+    /// connection acquired by the store since it was created, whereas
+    /// exhaustion is only sampled while the monitor is running.
+    pub fn stats(&self) -> PoolStats {
+        let count = self.waits.count.load(Ordering::SeqCst);
+        let total_micros = self.waits.total_micros.load(Ordering::SeqCst);
+        PoolStats {
+            max_wait_ms: self.waits.max_micros.load(Ordering::SeqCst) / 1000,
+            avg_wait_ms: if count == 0 {
+                0.0
+            } else {
+                total_micros as f64 / count as f64 / 1000.0
+            },
+            exhaustion_count: self.exhaustion_count.load(Ordering::SeqCst),
+        }
     }
+}
 
-    fn iter(self) -> Result<Self::Iter, Error> {
-        Ok(SqliteRangeIterator {
-            pool: self.pool,
-            statement_builder: self.statement_builder,
-            entries: VecDeque::default(),
-            offset: 0,
-            done: false,
-        })
+impl Drop for PoolMonitor {
+    fn drop(&mut self) {
+        // Dropping the sender wakes the thread up
+        self.stop_sender.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
     }
 }
-pub struct SqliteRangeIterator {
-    pool: Pool<SqliteConnectionManager>,
-    statement_builder: StatementBuilder,
-    entries: VecDeque<Entry>,
-    offset: usize,
-    done: bool,
+
+/// How long acquiring connections from a pool has taken.
+struct PoolWaits {
+    count: AtomicU64,
+    total_micros: AtomicU64,
+    max_micros: AtomicU64,
+    timeout_threshold: Duration,
+    timeout_callback: Mutex<Option<Arc<dyn Fn() + Send + Sync>>>,
 }
 
-impl SqliteRangeIterator {
-    fn fill_entries(&mut self) -> Result<(), Error> {
-        let conn = self.pool.get()?;
-        let mut stmt = conn.prepare(&self.statement_builder.statement(
-            "select ts, name, size, value from log",
-            &format!("order by ts limit {} offset {}", PAGINATION_LIMIT, self.offset),
-        ))?;
-        let mut rows = stmt.query(self.statement_builder.params())?;
-        let mut decompressor = Decompressor::new()?;
-        let mut added = 0;
-        while let Some(row) = rows.next()? {
-            let timestamp: i64 = row.get(0)?;
-            let name: String = row.get(1)?;
-            let size: usize = row.get(2)?;
-            let blob: Vec<u8> = row.get(3)?;
-            self.entries
-                .push_back(entry_from_row(&mut decompressor, timestamp, name, size, blob)?);
-            added += 1;
-        }
-        if added < PAGINATION_LIMIT {
-            self.done = true;
+impl PoolWaits {
+    fn record(&self, wait: Duration) {
+        let micros = u64::try_from(wait.as_micros()).unwrap_or(u64::MAX);
+        self.count.fetch_add(1, Ordering::SeqCst);
+        self.total_micros.fetch_add(micros, Ordering::SeqCst);
+        self.max_micros.fetch_max(micros, Ordering::SeqCst);
+        if wait > self.timeout_threshold {
+            // The callback is called without the lock held, so that a slow
+            // callback doesn't block other connections from being acquired
+            let callback = self.timeout_callback.lock().unwrap().clone();
+            if let Some(callback) = callback {
+                callback();
+            }
         }
-        self.offset += PAGINATION_LIMIT;
-        Ok(())
     }
 }
 
-impl Iterator for SqliteRangeIterator {
-    type Item = Result<Entry, Error>;
+/// A connection pool that records how long acquiring connections takes. With
+/// the `sqlite-cipher` feature, the underlying pool can be replaced after
+/// rekeying, for every clone at once; otherwise it's shared without a lock.
+#[derive(Clone)]
+struct MonitoredPool {
+    #[cfg(feature = "sqlite-cipher")]
+    pool: Arc<RwLock<Pool<SqliteConnectionManager>>>,
+    #[cfg(not(feature = "sqlite-cipher"))]
+    pool: Pool<SqliteConnectionManager>,
+    waits: Arc<PoolWaits>,
+}
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.entries.is_empty() && !self.done {
-            if let Err(err) = self.fill_entries() {
-                return Some(Err(err));
-            }
+impl MonitoredPool {
+    fn new(pool: Pool<SqliteConnectionManager>, timeout_threshold: Duration) -> Self {
+        Self {
+            #[cfg(feature = "sqlite-cipher")]
+            pool: Arc::new(RwLock::new(pool)),
+            #[cfg(not(feature = "sqlite-cipher"))]
+            pool,
+            waits: Arc::new(PoolWaits {
+                count: AtomicU64::new(0),
+                total_micros: AtomicU64::new(0),
+                max_micros: AtomicU64::new(0),
+                timeout_threshold,
+                timeout_callback: Mutex::new(None),
+            }),
         }
-        self.entries.pop_front().map(Ok)
+    }
+
+    /// Gets the current underlying pool. The lock isn't held while waiting
+    /// for a connection.
+    #[cfg(feature = "sqlite-cipher")]
+    fn current(&self) -> Pool<SqliteConnectionManager> {
+        self.pool.read().unwrap().clone()
+    }
+
+    #[cfg(not(feature = "sqlite-cipher"))]
+    fn current(&self) -> Pool<SqliteConnectionManager> {
+        self.pool.clone()
+    }
+
+    fn get(&self) -> Result<PooledConnection<SqliteConnectionManager>, Error> {
+        let pool = self.current();
+        let start = Instant::now();
+        let conn = pool.get();
+        self.waits.record(start.elapsed());
+        Ok(conn?)
+    }
+
+    fn state(&self) -> r2d2::State {
+        self.current().state()
+    }
+
+    /// Replaces the underlying pool. Connections already acquired from the
+    /// previous pool are closed once they're released.
+    #[cfg(feature = "sqlite-cipher")]
+    fn replace(&self, pool: Pool<SqliteConnectionManager>) {
+        *self.pool.write().unwrap() = pool;
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::{define_test, test_rangeable_store_impl, test_store_impl, SqliteStore};
-    use tempfile::NamedTempFile;
-    test_store_impl!({
-        let file = NamedTempFile::new().unwrap().into_temp_path();
-        SqliteStore::new(file, None).unwrap()
-    });
-    test_rangeable_store_impl!({
-        let file = NamedTempFile::new().unwrap().into_temp_path();
-        SqliteStore::new(file, None).unwrap()
-    });
+/// The key of a database encrypted with SQLCipher, which every connection
+/// sets as it's opened.
+#[cfg(feature = "sqlite-cipher")]
+struct CipherKey {
+    path: std::path::PathBuf,
+    /// Also serializes rekeying.
+    key: Mutex<String>,
 }
 
-#[cfg(test)]
-#[cfg(feature = "benches")]
-mod benches {
-    use crate::{bench_rangeable_store_impl, bench_store_impl, define_bench, SqliteStore};
-    use tempfile::NamedTempFile;
-    bench_store_impl!({
-        let file = NamedTempFile::new().unwrap().into_temp_path();
-        SqliteStore::new(file, None).unwrap()
-    });
-    bench_rangeable_store_impl!({
-        let file = NamedTempFile::new().unwrap().into_temp_path();
-        SqliteStore::new(file, None).unwrap()
-    });
+#[cfg(feature = "sqlite-cipher")]
+impl CipherKey {
+    fn pool(&self, key: &str) -> Result<Pool<SqliteConnectionManager>, Error> {
+        let key = key.to_string();
+        let manager =
+            SqliteConnectionManager::file(&self.path).with_init(move |conn| conn.pragma_update(None, "key", &key));
+        Ok(r2d2::Pool::new(manager)?)
+    }
+}
+
+/// Runs `pragma optimize` on a background thread at an interval, until it's
+/// dropped.
+struct PeriodicOptimizer {
+    stop_sender: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl PeriodicOptimizer {
+    fn spawn(pool: MonitoredPool, interval: Duration) -> Self {
+        let (stop_sender, stop_receiver) = channel::<()>();
+        let thread = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stop_receiver.recv_timeout(interval) {
+                let result = pool.get().and_then(|conn| optimize(&conn));
+                tracing::debug!("sqlite optimize finished: {:?}", result);
+            }
+        });
+        Self {
+            stop_sender: Some(stop_sender),
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for PeriodicOptimizer {
+    fn drop(&mut self) {
+        // Dropping the sender wakes the thread up
+        self.stop_sender.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Caches `latest` results by name, and by whether skewed entries were
+/// ignored. Every invalidation bumps a generation, and results are only
+/// cached if no invalidation happened while they were read, so a read that
+/// raced with a write can't cache what the write replaced. Expired results
+/// are evicted at most once per ttl, when a result is inserted, so names
+/// that stop being read don't stay cached.
+struct LatestCache {
+    ttl: Duration,
+    inner: RwLock<LatestCacheInner>,
+}
+
+struct LatestCacheInner {
+    entries: HashMap<(Atom, bool), (Instant, Option<Entry>)>,
+    generation: u64,
+    evicted_at: Instant,
+}
+
+impl LatestCache {
+    fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            inner: RwLock::new(LatestCacheInner {
+                entries: HashMap::default(),
+                generation: 0,
+                evicted_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Gets a cached result, if there's one that hasn't expired, or else the
+    /// current generation, to pass to `insert` once the result is read.
+    fn get(&self, name: &Atom, ignore_skewed: bool) -> Result<Option<Entry>, u64> {
+        let inner = self.inner.read().unwrap();
+        match inner.entries.get(&(name.clone(), ignore_skewed)) {
+            Some((cached_at, entry)) if cached_at.elapsed() < self.ttl => Ok(entry.clone()),
+            _ => Err(inner.generation),
+        }
+    }
+
+    fn insert(&self, name: Atom, ignore_skewed: bool, generation: u64, entry: Option<Entry>) {
+        let mut inner = self.inner.write().unwrap();
+        if inner.evicted_at.elapsed() >= self.ttl {
+            let ttl = self.ttl;
+            inner.entries.retain(|_, (cached_at, _)| cached_at.elapsed() < ttl);
+            inner.evicted_at = Instant::now();
+        }
+        if inner.generation == generation {
+            inner.entries.insert((name, ignore_skewed), (Instant::now(), entry));
+        }
+    }
+
+    /// Invalidates a name's results, or every result if `name` is `None`.
+    /// Must be called after the write that changed them is committed.
+    fn invalidate(&self, name: Option<&Atom>) {
+        let mut inner = self.inner.write().unwrap();
+        inner.generation += 1;
+        match name {
+            Some(name) => inner.entries.retain(|(cached_name, _), _| cached_name != name),
+            None => inner.entries.clear(),
+        }
+    }
+}
+
+/// Tracks the size of values removed since the database was last vacuumed,
+/// and vacuums it in the background once they exceed a threshold.
+struct VacuumTracker {
+    deleted_bytes: AtomicU64,
+    threshold_bytes: Option<u64>,
+    vacuuming: AtomicBool,
+}
+
+impl VacuumTracker {
+    fn new(threshold_bytes: Option<u64>) -> Self {
+        Self {
+            deleted_bytes: AtomicU64::new(0),
+            threshold_bytes,
+            vacuuming: AtomicBool::new(false),
+        }
+    }
+
+    fn record_deleted(self: &Arc<Self>, pool: &MonitoredPool, bytes: u64) {
+        let deleted_bytes = self.deleted_bytes.fetch_add(bytes, Ordering::SeqCst) + bytes;
+        let threshold_bytes = match self.threshold_bytes {
+            Some(threshold_bytes) => threshold_bytes,
+            None => return,
+        };
+        if deleted_bytes <= threshold_bytes
+            || self
+                .vacuuming
+                .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+                .is_err()
+        {
+            return;
+        }
+        let tracker = self.clone();
+        let pool = pool.clone();
+        thread::spawn(move || {
+            let result = tracker.vacuum(&pool);
+            tracing::debug!("sqlite vacuum finished: {:?}", result);
+            tracker.vacuuming.store(false, Ordering::SeqCst);
+        });
+    }
+
+    fn vacuum(&self, pool: &MonitoredPool) -> Result<(), Error> {
+        // Bytes deleted while vacuuming may not be reclaimed, so they're
+        // still counted afterwards
+        let deleted_bytes = self.deleted_bytes.load(Ordering::SeqCst);
+        let conn = pool.get()?;
+        vacuum(&conn)?;
+        self.deleted_bytes.fetch_sub(deleted_bytes, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct SqliteStore {
+    pool: MonitoredPool,
+    codec: Arc<dyn Codec>,
+    on_push: Option<PushCallback>,
+    max_snapshot_age: Option<Duration>,
+    subscription_poll_interval: Duration,
+    max_rows_per_statement: usize,
+    durable: bool,
+    options: StoreOptions,
+    _optimizer: Option<Arc<PeriodicOptimizer>>,
+    vacuum_tracker: Arc<VacuumTracker>,
+    latest_cache: Option<Arc<LatestCache>>,
+    verify_compression: bool,
+    /// The version of the sqlite library in use, e.g. 3035000 for 3.35.0.
+    sqlite_version: i32,
+    #[cfg(feature = "sqlite-cipher")]
+    cipher: Option<Arc<CipherKey>>,
+    #[cfg(feature = "sea-query")]
+    sea_query: bool,
+}
+
+impl SqliteStore {
+    /// Creates a store from a connection pool. `compression_level` is
+    /// ignored when the `compression` feature is disabled, in which case
+    /// values are stored uncompressed.
+    pub fn new_with_pool(pool: Pool<SqliteConnectionManager>, compression_level: Option<i32>) -> Result<Self, Error> {
+        #[allow(unused_mut)]
+        let mut config = SqliteStoreConfig::default();
+        #[cfg(feature = "compression")]
+        if let Some(compression_level) = compression_level {
+            config.compression_level = compression_level;
+        }
+        #[cfg(not(feature = "compression"))]
+        let _ = compression_level;
+        Self::new_with_pool_and_config(pool, config)
+    }
+
+    pub fn new<P: AsRef<Path>>(path: P, compression_level: Option<i32>) -> Result<Self, Error> {
+        let manager = SqliteConnectionManager::file(path);
+        let pool = r2d2::Pool::new(manager)?;
+        Self::new_with_pool(pool, compression_level)
+    }
+
+    pub fn new_with_config<P: AsRef<Path>>(path: P, config: SqliteStoreConfig) -> Result<Self, Error> {
+        config.check_pragmas()?;
+        let mut manager = SqliteConnectionManager::file(path).with_flags(config.thread_mode.open_flags());
+        let wal_autocheckpoint = config.wal_autocheckpoint;
+        let pragmas = config.pragmas.clone();
+        if wal_autocheckpoint.is_some() || !pragmas.is_empty() {
+            manager = manager.with_init(move |conn| {
+                if let Some(wal_autocheckpoint) = wal_autocheckpoint {
+                    conn.pragma_update(None, "wal_autocheckpoint", wal_autocheckpoint)?;
+                }
+                set_pragmas(conn, &pragmas)
+            });
+        }
+        let mut builder = r2d2::Pool::builder();
+        if let Some(max_size) = config.thread_mode.max_pool_size() {
+            builder = builder.max_size(max_size);
+        }
+        let pool = builder.build(manager)?;
+        Self::new_with_pool_and_config(pool, config)
+    }
+
+    pub fn new_with_pool_and_config(
+        pool: Pool<SqliteConnectionManager>,
+        config: SqliteStoreConfig,
+    ) -> Result<Self, Error> {
+        config.check_pragmas()?;
+        let durable = {
+            let mut conn = pool.get()?;
+            if config.startup_health_check {
+                check_integrity(&conn)?;
+            }
+            // wal2 is only available in some builds of sqlite, so fallback to
+            // wal, which still allows readers and writers to run concurrently
+            let journal_mode: String = conn.pragma_update_and_check(None, "journal_mode", "wal2", |row| row.get(0))?;
+            if journal_mode != "wal2" {
+                conn.pragma_update(None, "journal_mode", "wal")?;
+            }
+            migrate(&mut conn)?;
+            if let Some(wal_autocheckpoint) = config.wal_autocheckpoint {
+                conn.pragma_update(None, "wal_autocheckpoint", wal_autocheckpoint)?;
+            }
+            set_pragmas(&conn, &config.pragmas)?;
+            // in-memory and temporary databases have no file
+            let file: String = conn.query_row(
+                "select file from pragma_database_list where name = 'main'",
+                params![],
+                |row| row.get(0),
+            )?;
+            !file.is_empty()
+        };
+        let pool = MonitoredPool::new(pool, config.pool_timeout_threshold);
+        let optimizer = config
+            .optimize_interval
+            .map(|interval| Arc::new(PeriodicOptimizer::spawn(pool.clone(), interval)));
+        Ok(Self {
+            pool,
+            codec: default_codec(&config),
+            on_push: None,
+            max_snapshot_age: None,
+            subscription_poll_interval: DEFAULT_SUBSCRIPTION_POLL_INTERVAL,
+            max_rows_per_statement: config.max_rows_per_statement.max(1),
+            durable,
+            options: StoreOptions::default(),
+            _optimizer: optimizer,
+            vacuum_tracker: Arc::new(VacuumTracker::new(config.auto_vacuum_threshold_bytes)),
+            latest_cache: config.latest_cache_ttl.map(|ttl| Arc::new(LatestCache::new(ttl))),
+            verify_compression: false,
+            sqlite_version: rusqlite::version_number(),
+            #[cfg(feature = "sqlite-cipher")]
+            cipher: None,
+            #[cfg(feature = "sea-query")]
+            sea_query: false,
+        })
+    }
+
+    /// Like `new`, but range reads (counts, pages and iteration) build their
+    /// queries with sea-query rather than by concatenating strings. Results
+    /// are the same either way.
+    #[cfg(feature = "sea-query")]
+    pub fn new_with_sea_query<P: AsRef<Path>>(path: P, compression_level: Option<i32>) -> Result<Self, Error> {
+        let mut store = Self::new(path, compression_level)?;
+        store.sea_query = true;
+        Ok(store)
+    }
+
+    /// Opens a database encrypted with SQLCipher, creating it if it doesn't
+    /// exist. Opening a database with the wrong key, or opening an encrypted
+    /// database via `new`, fails with `Error::Database`.
+    ///
+    /// This requires the `sqlite-cipher` feature, which builds and links
+    /// SQLCipher (and OpenSSL's libcrypto) in place of standard sqlite, for
+    /// every store in the program.
+    #[cfg(feature = "sqlite-cipher")]
+    pub fn new_encrypted<P: AsRef<Path>>(path: P, key: &str, compression_level: Option<i32>) -> Result<Self, Error> {
+        let cipher = CipherKey {
+            path: path.as_ref().to_path_buf(),
+            key: Mutex::new(key.to_string()),
+        };
+        let mut store = Self::new_with_pool(cipher.pool(key)?, compression_level)?;
+        store.cipher = Some(Arc::new(cipher));
+        Ok(store)
+    }
+
+    /// Re-encrypts a database opened via `new_encrypted` with a new key. The
+    /// store, and its clones, switch to connections opened with the new key,
+    /// but ranges and subscriptions that are reading while the database is
+    /// rekeyed may fail. Stores that aren't encrypted fail with
+    /// `Error::Unsupported`.
+    #[cfg(feature = "sqlite-cipher")]
+    pub fn rekey(&self, new_key: &str) -> Result<(), Error> {
+        let cipher = match &self.cipher {
+            Some(cipher) => cipher,
+            None => return Err(Error::Unsupported("rekeying an unencrypted store".to_string())),
+        };
+        let mut key = cipher.key.lock().unwrap();
+        self.pool.get()?.pragma_update(None, "rekey", new_key)?;
+        *key = new_key.to_string();
+        // Connections opened with the old key can no longer read the database
+        self.pool.replace(cipher.pool(new_key)?);
+        Ok(())
+    }
+
+    /// Sets a callback that is invoked after every successful push,
+    /// regardless of the entry's name.
+    pub fn with_on_push(mut self, callback: PushCallback) -> Self {
+        self.on_push = Some(callback);
+        self
+    }
+
+    /// Sets options, e.g. to guard against entries pushed with skewed
+    /// clocks.
+    pub fn with_options(mut self, options: StoreOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Sets the clock that entries are checked against and stamped with
+    /// when they're ingested, e.g. a `ManualClock` for deterministic tests.
+    /// This replaces the clock in the store's options, so call it after
+    /// `with_options`.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.options.clock = clock;
+        self
+    }
+
+    /// Creates an entry timestamped with the store's clock.
+    pub fn new_entry<A: Into<Atom>>(&self, name: A, value: Vec<u8>) -> Entry {
+        Entry::new_with_clock(self.options.clock.as_ref(), name, value)
+    }
+
+    /// Sets the codec used to compress values. Values written with a
+    /// previous codec remain readable as long as it is this codec, or one of
+    /// the built-in codecs.
+    pub fn with_codec(mut self, codec: Box<dyn Codec>) -> Self {
+        self.codec = Arc::from(codec);
+        self
+    }
+
+    /// Sets whether every compressed value is decompressed and compared
+    /// against the original before it's written, so that a codec producing
+    /// unreadable blobs fails pushes with `Error::CompressionRoundTrip`
+    /// rather than corrupting the store. This roughly doubles the cost of
+    /// encoding values.
+    pub fn with_compression_verification(mut self, verify: bool) -> Self {
+        self.verify_compression = verify;
+        self
+    }
+
+    /// Checks that every compressed entry in a range still decompresses to
+    /// its original size, e.g. after upgrading a codec. Failures are
+    /// collected rather than stopping the scan.
+    pub fn verify_compression<A, R>(&self, range: R, name: Option<A>) -> Result<CompressionVerificationReport, Error>
+    where
+        A: Into<Atom>,
+        R: RangeBounds<i64>,
+    {
+        utils::check_bounds(range.start_bound(), range.end_bound())?;
+        let statement_builder = StatementBuilder::new(range, name.map(|n| n.into()));
+        let mut report = CompressionVerificationReport::default();
+
+        let conn = self.pool.get()?;
+        let mut stmt =
+            conn.prepare_cached(&statement_builder.statement("select id, size, value, codec from log", "order by id"))?;
+        let mut rows = stmt.query(statement_builder.params())?;
+        while let Some(row) = rows.next()? {
+            let codec_name: String = row.get(3)?;
+            if codec_name == NoopCodec::NAME {
+                continue;
+            }
+            let id: i64 = row.get(0)?;
+            let size: usize = row.get(1)?;
+            let blob: Vec<u8> = row.get(2)?;
+            report.rows_checked += 1;
+            match decode_value(self.codec.as_ref(), &codec_name, size, blob) {
+                Ok(value) if value.len() == size => {}
+                Ok(_) => report
+                    .failures
+                    .push((id as u64, Error::CompressionRoundTrip(codec_name))),
+                Err(err) => report.failures.push((id as u64, err)),
+            }
+        }
+        Ok(report)
+    }
+
+    /// Aggregates the values of entries in a range that are plain scalars,
+    /// e.g. to chart numeric sensor readings, without decoding each entry.
+    /// Values are read as-is, so only those stored uncompressed with the
+    /// size of `value_type` are aggregated; the ids of the rest are
+    /// reported. Integers are aggregated as floats, so those beyond 2^53
+    /// lose precision.
+    pub fn aggregate<A, R>(
+        &self,
+        range: R,
+        name: Option<A>,
+        value_type: ValueType,
+        aggregate: Aggregate,
+    ) -> Result<AggregateReport, Error>
+    where
+        A: Into<Atom>,
+        R: RangeBounds<i64>,
+    {
+        utils::check_bounds(range.start_bound(), range.end_bound())?;
+        let statement_builder = StatementBuilder::new(range, name.map(|n| n.into()));
+        let mut report = AggregateReport::default();
+        let (mut sum, mut min, mut max) = (0.0, f64::INFINITY, f64::NEG_INFINITY);
+
+        let conn = self.pool.get()?;
+        let mut stmt =
+            conn.prepare_cached(&statement_builder.statement("select id, value, codec from log", "order by id"))?;
+        let mut rows = stmt.query(statement_builder.params())?;
+        while let Some(row) = rows.next()? {
+            let blob = row
+                .get_ref(1)?
+                .as_blob()
+                .map_err(|err| Error::Database(Box::new(err)))?;
+            let bytes: Option<[u8; 8]> = match row.get_ref(2)?.as_str() {
+                Ok(NoopCodec::NAME) => blob.try_into().ok(),
+                _ => None,
+            };
+            let value = match (bytes, value_type) {
+                (Some(bytes), ValueType::F64) => f64::from_le_bytes(bytes),
+                (Some(bytes), ValueType::I64) => i64::from_le_bytes(bytes) as f64,
+                (None, _) => {
+                    let id: i64 = row.get(0)?;
+                    report.mismatched.push(id as u64);
+                    continue;
+                }
+            };
+            report.rows_aggregated += 1;
+            sum += value;
+            min = min.min(value);
+            max = max.max(value);
+        }
+
+        let count = report.rows_aggregated as f64;
+        report.value = match aggregate {
+            Aggregate::Count => Some(count),
+            _ if report.rows_aggregated == 0 => None,
+            Aggregate::Min => Some(min),
+            Aggregate::Max => Some(max),
+            Aggregate::Sum => Some(sum),
+            Aggregate::Avg => Some(sum / count),
+        };
+        Ok(report)
+    }
+
+    /// Checks the integrity of the database. This reads the entire database,
+    /// so it can be slow for large stores.
+    pub fn health_check(&self) -> Result<(), Error> {
+        let conn = self.pool.get()?;
+        check_integrity(&conn)
+    }
+
+    /// Runs `pragma optimize`, which refreshes the query planner's statistics
+    /// on tables and indexes that need it. This is cheap to run when the
+    /// statistics are already fresh. See `SqliteStoreConfig::optimize_interval`
+    /// to run it automatically.
+    pub fn optimize(&self) -> Result<(), Error> {
+        let conn = self.pool.get()?;
+        optimize(&conn)
+    }
+
+    /// Runs `vacuum`, which rebuilds the database to reclaim the storage of
+    /// removed entries. This rewrites the entire database, so it can be slow
+    /// for large stores. See `SqliteStoreConfig::auto_vacuum_threshold_bytes`
+    /// to run it automatically.
+    pub fn vacuum(&self) -> Result<(), Error> {
+        self.vacuum_tracker.vacuum(&self.pool)
+    }
+
+    /// Returns the size of the values removed via `Range::remove` since the
+    /// last vacuum, as stored (i.e. after compression.)
+    pub fn deleted_bytes_since_last_vacuum(&self) -> u64 {
+        self.vacuum_tracker.deleted_bytes.load(Ordering::SeqCst)
+    }
+
+    /// Returns the total size of the database in bytes, including its
+    /// write-ahead log, which holds recent writes until they're checkpointed
+    /// into the database file.
+    pub fn disk_usage(&self) -> Result<u64, Error> {
+        let conn = self.pool.get()?;
+        let page_count: u64 = conn.query_row("pragma page_count", params![], |row| row.get(0))?;
+        let page_size: u64 = conn.query_row("pragma page_size", params![], |row| row.get(0))?;
+        let file: String = conn.query_row(
+            "select file from pragma_database_list where name = 'main'",
+            params![],
+            |row| row.get(0),
+        )?;
+        let mut usage = page_count * page_size;
+        // in-memory and temporary databases have no file; wal2 mode
+        // alternates between two logs
+        if !file.is_empty() {
+            for suffix in ["-wal", "-wal2"] {
+                match fs::metadata(format!("{}{}", file, suffix)) {
+                    Ok(metadata) => usage += metadata.len(),
+                    Err(err) if err.kind() == IoErrorKind::NotFound => {}
+                    Err(err) => return Err(err.into()),
+                }
+            }
+        }
+        Ok(usage)
+    }
+
+    /// Returns a cheap upper bound on the number of entries, e.g. for
+    /// dashboards, from the span of entry ids. This only reads the ends of
+    /// the table's primary key, so any gap in the ids counts as entries:
+    /// removing or compacting entries other than the oldest, or pushing at
+    /// a far-off id with `push_at_id`, can make the bound arbitrarily loose.
+    /// Use `RangeableStore::count` for an exact count.
+    pub fn approx_count(&self) -> Result<u64, Error> {
+        let conn = self.pool.get()?;
+        let count: u64 = conn.query_row("select coalesce(max(id) - min(id) + 1, 0) from log", params![], |row| {
+            row.get(0)
+        })?;
+        Ok(count)
+    }
+
+    /// Returns what created the database and when, alongside its
+    /// properties, e.g. to identify a stray database file.
+    pub fn info(&self) -> Result<SqliteStoreInfo, Error> {
+        let conn = self.pool.get()?;
+        let (created_at, created_by) =
+            conn.query_row("select created_at, created_by from store_info", params![], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?;
+        let schema_version = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+        let mut stmt = conn.prepare("select key, value from properties")?;
+        let properties = stmt
+            .query_map(params![], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<_, _>>()?;
+        Ok(SqliteStoreInfo {
+            created_at,
+            created_by,
+            schema_version,
+            properties,
+        })
+    }
+
+    /// Sets a user-defined property, replacing its value if it's already
+    /// set. Properties are stored in the database, e.g. to record what an
+    /// application uses it for.
+    pub fn set_property<K: Into<String>, V: Into<String>>(&self, key: K, value: V) -> Result<(), Error> {
+        let conn = self.pool.get()?;
+        conn.execute(
+            "insert or replace into properties (key, value) values (?, ?)",
+            params![key.into(), value.into()],
+        )?;
+        Ok(())
+    }
+
+    /// Gets a property set via `set_property`.
+    pub fn get_property(&self, key: &str) -> Result<Option<String>, Error> {
+        let conn = self.pool.get()?;
+        let value = conn
+            .query_row("select value from properties where key = ?", params![key], |row| {
+                row.get(0)
+            })
+            .optional()?;
+        Ok(value)
+    }
+
+    /// Returns the compression statistics of each name, with the names that
+    /// compress worst first, e.g. to decide whether they'd be better off
+    /// with another codec. This reads every entry's size, so it can be slow
+    /// for large stores.
+    pub fn compression_stats_by_name(&self) -> Result<Vec<NameCompressionStats>, Error> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare(
+            "select name, count, uncompressed, stored, coalesce(cast(stored as real) / nullif(uncompressed, 0), 1.0) as ratio from (
+                select name, count(*) as count, sum(case when size > 0 then size else length(value) end) as uncompressed, sum(length(value)) as stored from log group by name
+            ) order by ratio desc, name",
+        )?;
+        let mut rows = stmt.query(params![])?;
+        let mut stats = Vec::new();
+        while let Some(row) = rows.next()? {
+            let name: String = row.get(0)?;
+            stats.push(NameCompressionStats {
+                name: Atom::from(name),
+                entry_count: row.get(1)?,
+                total_uncompressed_bytes: row.get(2)?,
+                total_stored_bytes: row.get(3)?,
+                ratio: row.get(4)?,
+            });
+        }
+        Ok(stats)
+    }
+
+    /// Starts sampling the connection pool in the background, e.g. to tell
+    /// whether it's regularly exhausted, in which case acquiring a
+    /// connection blocks until another is returned.
+    pub fn monitor(&self) -> PoolMonitor {
+        PoolMonitor::spawn(self.pool.clone())
+    }
+
+    /// Sets a callback that is invoked whenever acquiring a connection from
+    /// the pool takes longer than `SqliteStoreConfig::pool_timeout_threshold`,
+    /// replacing any previous callback. The callback applies to all clones
+    /// of the store, and may be called from several threads at once.
+    pub fn set_pool_timeout_callback<F: Fn() + Send + Sync + 'static>(&self, callback: F) {
+        *self.pool.waits.timeout_callback.lock().unwrap() = Some(Arc::new(callback));
+    }
+
+    /// Spawns a thread that runs `health_check` immediately, and then every
+    /// `interval`, until the returned handle is dropped.
+    pub fn health_check_async_periodic(&self, interval: Duration) -> HealthCheckHandle {
+        let healthy = Arc::new(AtomicBool::new(true));
+        let (stop_sender, stop_receiver) = channel::<()>();
+        let store = self.clone();
+        let thread_healthy = healthy.clone();
+        thread::spawn(move || loop {
+            thread_healthy.store(store.health_check().is_ok(), Ordering::SeqCst);
+            match stop_receiver.recv_timeout(interval) {
+                Err(RecvTimeoutError::Timeout) => {}
+                _ => break,
+            }
+        });
+        HealthCheckHandle {
+            healthy,
+            _stop_sender: stop_sender,
+        }
+    }
+
+    /// Sets the maximum age of snapshots, after which reading from them
+    /// fails. The first read after a snapshot expires ends its transaction
+    /// and returns its connection to the pool, rather than waiting for the
+    /// snapshot to be dropped. See `snapshot` for why long-lived snapshots
+    /// are costly.
+    pub fn with_max_snapshot_age(mut self, max_age: Duration) -> Self {
+        self.max_snapshot_age = Some(max_age);
+        self
+    }
+
+    /// Sets how often subscriptions check for new entries, 50ms by default.
+    /// Shorter intervals deliver entries sooner, at the cost of more queries.
+    pub fn with_subscription_poll_interval(mut self, interval: Duration) -> Self {
+        self.subscription_poll_interval = interval;
+        self
+    }
+
+    /// Opens a read-only view of the store as of now, which does not see
+    /// entries pushed or removed afterwards. The snapshot holds a pooled
+    /// connection with an open read transaction until it is dropped.
+    ///
+    /// While a snapshot is open, sqlite cannot checkpoint the write-ahead log
+    /// past the snapshot's starting point, so the log grows with every write
+    /// until the snapshot is dropped. Keep snapshots short-lived, or set a
+    /// limit via `with_max_snapshot_age`. With `ThreadMode::SingleThread`,
+    /// the snapshot holds the pool's only connection, so every other
+    /// operation waits for it to be dropped.
+    pub fn snapshot(&self) -> Result<SqliteSnapshot, Error> {
+        let conn = self.pool.get()?;
+        conn.execute_batch("begin deferred")?;
+        // A deferred transaction only pins the database state once it
+        // first reads.
+        conn.query_row("select 1 from log limit 1", params![], |_| Ok(()))
+            .optional()?;
+        Ok(SqliteSnapshot {
+            conn: RefCell::new(Some(conn)),
+            codec: self.codec.clone(),
+            ignore_skewed_in_latest: self.options.ignores_skewed_in_latest(),
+            created_at: Instant::now(),
+            max_age: self.max_snapshot_age,
+            #[cfg(feature = "sea-query")]
+            sea_query: self.sea_query,
+        })
+    }
+
+    /// Pushes an entry, returning the id sqlite assigned to it, as used by
+    /// `range_ids` and `remove_ids`. On sqlite 3.35.0 and later, the id is
+    /// read via a `returning` clause, and otherwise via `last_insert_rowid`
+    /// on the same connection.
+    ///
+    /// Ids are sqlite rowids, which are stored as an `i64`. They're exposed as
+    /// a `u64` to match `Entry::sequence_number`; since ids are only ever
+    /// assigned by sqlite or `push_at_id`, which rejects ids above
+    /// `i64::MAX`, they're never negative.
+    pub fn push_with_id(&self, entry: Cow<Entry>) -> Result<u64, Error> {
+        let timer = self.options.start_op();
+        let entry = self.options.check_skew(entry)?;
+        let (id, statement) = self.insert(entry.timestamp, &entry.name, &entry.value, entry.skewed)?;
+
+        if let Some(on_push) = &self.on_push {
+            on_push(&entry);
+        }
+        if let Some(timer) = timer {
+            timer.finish(SlowOpKind::Push, Some(&entry.name), 1, || Some(statement.to_string()));
+        }
+        Ok(id)
+    }
+
+    /// Inserts an entry that has already been checked against the skew
+    /// policy, returning its id and the statement that inserted it. Values
+    /// are bound as-is, so this doesn't allocate unless the value is
+    /// compressed.
+    fn insert(&self, timestamp: i64, name: &Atom, value: &[u8], skewed: bool) -> Result<(u64, &'static str), Error> {
+        let (blob, size, codec_name) = self.encode_value(value)?;
+        let ingested_at = self.options.clock.now_micros();
+        let params = params![timestamp, name.as_ref(), size, blob, codec_name, skewed, ingested_at];
+
+        let conn = self.pool.get()?;
+        let inserted = if self.sqlite_version >= MIN_RETURNING_VERSION {
+            let mut stmt = conn.prepare_cached(INSERT_RETURNING_STATEMENT)?;
+            let id: u64 = stmt.query_row(params, |row| row.get(0))?;
+            (id, INSERT_RETURNING_STATEMENT)
+        } else {
+            let mut stmt = conn.prepare_cached(INSERT_STATEMENT)?;
+            stmt.execute(params)?;
+            (conn.last_insert_rowid() as u64, INSERT_STATEMENT)
+        };
+        self.invalidate_latest(Some(name));
+        Ok(inserted)
+    }
+
+    /// Invalidates cached `latest` results for a name, or for every name if
+    /// `name` is `None`, after a write.
+    fn invalidate_latest(&self, name: Option<&Atom>) {
+        if let Some(latest_cache) = &self.latest_cache {
+            latest_cache.invalidate(name);
+        }
+    }
+
+    /// Pushes several entries in a single transaction, using multi-row
+    /// `insert` statements of up to `max_rows_per_statement` rows each.
+    pub fn push_bulk_values(&self, entries: &[Entry]) -> Result<(), Error> {
+        if entries.is_empty() {
+            return Ok(());
+        } else if entries.len() == 1 {
+            return self.push(Cow::Borrowed(&entries[0]));
+        }
+
+        let timer = self.options.start_op();
+        let entries = entries
+            .iter()
+            .map(|entry| self.options.check_skew(Cow::Borrowed(entry)))
+            .collect::<Result<Vec<Cow<Entry>>, Error>>()?;
+
+        let ingested_at = self.options.clock.now_micros();
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        for chunk in entries.chunks(self.max_rows_per_statement) {
+            let names: Vec<&str> = chunk.iter().map(|entry| entry.name.as_ref()).collect();
+            let encoded = chunk
+                .iter()
+                .map(|entry| self.encode_value(&entry.value))
+                .collect::<Result<Vec<_>, Error>>()?;
+            let mut params: Vec<&dyn ToSql> = Vec::with_capacity(chunk.len() * 7);
+            for ((entry, name), (blob, size, codec_name)) in chunk.iter().zip(names.iter()).zip(encoded.iter()) {
+                params.push(&entry.timestamp);
+                params.push(name);
+                params.push(size);
+                params.push(blob);
+                params.push(codec_name);
+                params.push(&entry.skewed);
+                params.push(&ingested_at);
+            }
+
+            let statement = format!(
+                "insert into log (ts, name, size, value, codec, skewed, ingested_at) values {}",
+                vec!["(?, ?, ?, ?, ?, ?, ?)"; chunk.len()].join(", ")
+            );
+            let mut stmt = tx.prepare_cached(&statement)?;
+            stmt.execute(params_from_iter(params))?;
+        }
+        tx.commit()?;
+        self.invalidate_latest(None);
+
+        if let Some(on_push) = &self.on_push {
+            for entry in entries.iter() {
+                on_push(entry);
+            }
+        }
+        if let Some(timer) = timer {
+            timer.finish(SlowOpKind::Push, None, entries.len() as u64, || None);
+        }
+        Ok(())
+    }
+
+    /// Removes the entries with the given ids, as returned by
+    /// `SqliteRange::iter_with_metadata`, within a single transaction.
+    /// Unknown ids, including any above `i64::MAX`, are ignored. Returns the
+    /// number of entries removed.
+    pub fn remove_ids(&self, ids: &[u64]) -> Result<u64, Error> {
+        let ids: Vec<i64> = ids.iter().filter_map(|id| i64::try_from(*id).ok()).collect();
+        let mut removed = 0;
+        let mut deleted_bytes = 0;
+        {
+            let mut conn = self.pool.get()?;
+            let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            for chunk in ids.chunks(MAX_VARIABLES_PER_STATEMENT) {
+                let placeholders = vec!["?"; chunk.len()].join(", ");
+                let bytes: u64 = tx.query_row(
+                    &format!(
+                        "select coalesce(sum(length(value)), 0) from log where id in ({})",
+                        placeholders
+                    ),
+                    params_from_iter(chunk),
+                    |row| row.get(0),
+                )?;
+                deleted_bytes += bytes;
+                removed += tx.execute(
+                    &format!("delete from log where id in ({})", placeholders),
+                    params_from_iter(chunk),
+                )? as u64;
+            }
+            tx.commit()?;
+        }
+        self.invalidate_latest(None);
+        self.vacuum_tracker.record_deleted(&self.pool, deleted_bytes);
+        Ok(removed)
+    }
+
+    /// Like `range`, but bounded by entry ids rather than timestamps, e.g. to
+    /// address a batch of entries whose timestamps overlap with others.
+    /// Entries are still iterated in timestamp order. Bounds above
+    /// `i64::MAX` are clamped to it, since no id can be larger.
+    pub fn range_ids<A: Into<Atom>, R: RangeBounds<u64>>(
+        &self,
+        range: R,
+        name: Option<A>,
+    ) -> Result<SqliteRange, Error> {
+        // sqlite ids are never larger than an i64
+        let id_bound = |bound: Bound<&u64>| bound.map(|id| i64::try_from(*id).unwrap_or(i64::MAX));
+        let start_bound = id_bound(range.start_bound());
+        let end_bound = id_bound(range.end_bound());
+        utils::check_bounds(start_bound.as_ref(), end_bound.as_ref())?;
+        let statement_builder = StatementBuilder::new_ids(start_bound, end_bound, name.map(|n| n.into()));
+        #[cfg(feature = "sea-query")]
+        let statement_builder = statement_builder.with_sea_query(self.sea_query);
+        Ok(SqliteRange {
+            pool: self.pool.clone(),
+            codec: self.codec.clone(),
+            vacuum_tracker: self.vacuum_tracker.clone(),
+            latest_cache: self.latest_cache.clone(),
+            slow_ops: self.options.slow_ops.clone(),
+            statement_builder,
+        })
+    }
+
+    /// Replaces the entries in a range with rollups of them, e.g. to
+    /// downsample old data. `rollup` is called once per name with all of the
+    /// name's entries in the range, ordered by timestamp.
+    ///
+    /// The entries are read, deleted and replaced within a single
+    /// transaction, so a failure at any point leaves the range untouched.
+    /// Since every entry in the range is read into memory, large histories
+    /// should be compacted in smaller ranges (e.g. an hour at a time), each of
+    /// which is atomic. Rollup entries do not invoke the `on_push` callback.
+    pub fn compact<A, R, F>(&self, range: R, name: Option<A>, rollup: F) -> Result<CompactionReport, Error>
+    where
+        A: Into<Atom>,
+        R: RangeBounds<i64>,
+        F: Fn(&[Entry]) -> Vec<Entry>,
+    {
+        utils::check_bounds(range.start_bound(), range.end_bound())?;
+        let statement_builder = StatementBuilder::new(range, name.map(|n| n.into()));
+        let mut report = CompactionReport::default();
+
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+
+        let mut outputs = Vec::default();
+        {
+            let mut stmt = tx.prepare_cached(&statement_builder.statement(
+                "select ts, name, size, value, codec, id, skewed, ingested_at, meta from log",
+                "order by name, ts, id",
+            ))?;
+            let mut rows = stmt.query(statement_builder.params())?;
+            let mut names = RowNames::new(statement_builder.name.as_ref());
+            let mut entries: Vec<Entry> = Vec::default();
+            while let Some(row) = rows.next()? {
+                let entry = entry_from_row(self.codec.as_ref(), &mut names, row)?;
+                if matches!(entries.last(), Some(last) if last.name != entry.name) {
+                    outputs.extend(rollup(&entries));
+                    entries.clear();
+                }
+                entries.push(entry);
+                report.rows_read += 1;
+            }
+            if !entries.is_empty() {
+                outputs.extend(rollup(&entries));
+            }
+        }
+
+        report.rows_deleted = tx
+            .prepare_cached(&statement_builder.statement("delete from log", ""))?
+            .execute(statement_builder.params())? as u64;
+
+        {
+            let ingested_at = self.options.clock.now_micros();
+            let mut stmt = tx.prepare_cached(INSERT_STATEMENT)?;
+            for entry in outputs.iter() {
+                let (blob, size, codec_name) = self.encode_value(&entry.value)?;
+                stmt.execute(params![
+                    entry.timestamp,
+                    entry.name.as_ref(),
+                    size,
+                    blob,
+                    codec_name,
+                    entry.skewed,
+                    ingested_at
+                ])?;
+            }
+        }
+        report.rows_written = outputs.len() as u64;
+
+        tx.commit()?;
+        self.invalidate_latest(statement_builder.name.as_ref());
+        Ok(report)
+    }
+
+    /// Like `codec::encode_value`, but checks the blob round trips if
+    /// compression verification is enabled.
+    fn encode_value<'a>(&self, value: &'a [u8]) -> Result<(Cow<'a, [u8]>, usize, &str), Error> {
+        let (blob, size, codec_name) = encode_value(self.codec.as_ref(), value)?;
+        if self.verify_compression && size > 0 && !matches!(self.codec.decompress(&blob, size), Ok(v) if v == value) {
+            return Err(Error::CompressionRoundTrip(codec_name.to_string()));
+        }
+        Ok((blob, size, codec_name))
+    }
+}
+
+impl Store for SqliteStore {
+    fn push(&self, entry: Cow<Entry>) -> Result<(), Error> {
+        self.push_with_id(entry)?;
+        Ok(())
+    }
+
+    /// The value is bound without copying it. An `Entry` is only built if
+    /// there's an `on_push` callback to pass it to.
+    fn push_parts(&self, timestamp: i64, name: &Atom, value: &[u8]) -> Result<(), Error> {
+        let timer = self.options.start_op();
+        let (timestamp, skewed) = self
+            .options
+            .check_skew_timestamp(timestamp)?
+            .unwrap_or((timestamp, false));
+        let (_, statement) = self.insert(timestamp, name, value, skewed)?;
+
+        if let Some(on_push) = &self.on_push {
+            let mut entry = Entry::new_with_timestamp(timestamp, name.clone(), value.to_vec());
+            entry.skewed = skewed;
+            on_push(&entry);
+        }
+        if let Some(timer) = timer {
+            timer.finish(SlowOpKind::Push, Some(name), 1, || Some(statement.to_string()));
+        }
+        Ok(())
+    }
+
+    fn push_or_replace(&self, entry: Cow<Entry>) -> Result<bool, Error> {
+        let timer = self.options.start_op();
+        let entry = self.options.check_skew(entry)?;
+        let (blob, size, codec_name) = self.encode_value(&entry.value)?;
+        let ingested_at = self.options.clock.now_micros();
+
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let replaced: bool = tx
+            .prepare_cached("select exists(select 1 from log where ts = ? and name = ? and replaceable)")?
+            .query_row(params![entry.timestamp, entry.name.as_ref()], |row| row.get(0))?;
+        tx.prepare_cached(UPSERT_STATEMENT)?.execute(params![
+            entry.timestamp,
+            entry.name.as_ref(),
+            size,
+            blob,
+            codec_name,
+            entry.skewed,
+            ingested_at
+        ])?;
+        tx.commit()?;
+        self.invalidate_latest(Some(&entry.name));
+
+        if let Some(on_push) = &self.on_push {
+            on_push(&entry);
+        }
+        if let Some(timer) = timer {
+            timer.finish(SlowOpKind::Push, Some(&entry.name), 1, || {
+                Some(UPSERT_STATEMENT.to_string())
+            });
+        }
+        Ok(replaced)
+    }
+
+    fn push_with_metadata(&self, entry: Cow<Entry>, metadata: EntryMetadata) -> Result<(), Error> {
+        let timer = self.options.start_op();
+        let entry = self.options.check_skew(entry)?;
+        let (blob, size, codec_name) = self.encode_value(&entry.value)?;
+        let ingested_at = self.options.clock.now_micros();
+
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare_cached(INSERT_WITH_METADATA_STATEMENT)?;
+        stmt.execute(params![
+            entry.timestamp,
+            entry.name.as_ref(),
+            size,
+            blob,
+            codec_name,
+            entry.skewed,
+            ingested_at,
+            encode_metadata(&metadata)
+        ])?;
+        self.invalidate_latest(Some(&entry.name));
+
+        if let Some(on_push) = &self.on_push {
+            on_push(&entry);
+        }
+        if let Some(timer) = timer {
+            timer.finish(SlowOpKind::Push, Some(&entry.name), 1, || {
+                Some(INSERT_WITH_METADATA_STATEMENT.to_string())
+            });
+        }
+        Ok(())
+    }
+
+    /// Ids are sqlite rowids, so they can't be larger than `i64::MAX`.
+    fn push_at_id(&self, id: u64, entry: Cow<Entry>) -> Result<bool, Error> {
+        let timer = self.options.start_op();
+        let id = i64::try_from(id).map_err(|_| Error::InvalidEntry(format!("id {} is too large", id)))?;
+        let (blob, size, codec_name) = self.encode_value(&entry.value)?;
+        let ingested_at = self.options.clock.now_micros();
+
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare_cached(INSERT_AT_ID_STATEMENT)?;
+        let inserted = stmt.execute(params![
+            id,
+            entry.timestamp,
+            entry.name.as_ref(),
+            size,
+            blob,
+            codec_name,
+            entry.skewed,
+            ingested_at
+        ])? > 0;
+
+        if inserted {
+            self.invalidate_latest(Some(&entry.name));
+            if let Some(on_push) = &self.on_push {
+                on_push(&entry);
+            }
+        }
+        if let Some(timer) = timer {
+            timer.finish(SlowOpKind::Push, Some(&entry.name), inserted as u64, || {
+                Some(INSERT_AT_ID_STATEMENT.to_string())
+            });
+        }
+        Ok(inserted)
+    }
+
+    /// Ids larger than `i64::MAX` can't exist, so they're never found.
+    fn get_by_id(&self, id: u64) -> Result<Option<Entry>, Error> {
+        let id = match i64::try_from(id) {
+            Ok(id) => id,
+            Err(_) => return Ok(None),
+        };
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare_cached(
+            "select ts, name, size, value, codec, id, skewed, ingested_at, meta from log where id = ?",
+        )?;
+        let mut rows = stmt.query(params![id])?;
+        match rows.next()? {
+            Some(row) => Ok(Some(entry_from_row(
+                self.codec.as_ref(),
+                &mut RowNames::new(None),
+                row,
+            )?)),
+            None => Ok(None),
+        }
+    }
+
+    fn latest<A: Into<Atom>>(&self, name: A) -> Result<Option<Entry>, Error> {
+        let timer = self.options.start_op();
+        let name = name.into();
+        let ignore_skewed = self.options.ignores_skewed_in_latest();
+        let generation = match self.latest_cache.as_ref().map(|cache| cache.get(&name, ignore_skewed)) {
+            Some(Ok(latest)) => return Ok(latest),
+            Some(Err(generation)) => Some(generation),
+            None => None,
+        };
+        let conn = self.pool.get()?;
+        let latest = select_latest(&conn, self.codec.as_ref(), name.clone(), ignore_skewed)?;
+        if let (Some(latest_cache), Some(generation)) = (&self.latest_cache, generation) {
+            latest_cache.insert(name.clone(), ignore_skewed, generation, latest.clone());
+        }
+        if let Some(timer) = timer {
+            timer.finish(SlowOpKind::Query, Some(&name), latest.is_some() as u64, || {
+                Some(latest_statement(ignore_skewed).to_string())
+            });
+        }
+        Ok(latest)
+    }
+
+    fn latest_n<A: Into<Atom>>(&self, name: A, n: usize) -> Result<Vec<Entry>, Error> {
+        let timer = self.options.start_op();
+        let name = name.into();
+        let ignore_skewed = self.options.ignores_skewed_in_latest();
+        let conn = self.pool.get()?;
+        let latest = select_latest_n(&conn, self.codec.as_ref(), name.clone(), n, ignore_skewed)?;
+        if let Some(timer) = timer {
+            timer.finish(SlowOpKind::Query, Some(&name), latest.len() as u64, || {
+                Some(latest_n_statement(ignore_skewed).to_string())
+            });
+        }
+        Ok(latest)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        let mut capabilities = Capabilities::RANGE
+            | Capabilities::SUBSCRIBE
+            | Capabilities::REMOVE
+            | Capabilities::METADATA
+            | Capabilities::IDS;
+        if self.durable {
+            capabilities |= Capabilities::DURABLE;
+        }
+        if self.codec.name() != NoopCodec::NAME {
+            capabilities |= Capabilities::COMPRESSED;
+        }
+        capabilities
+    }
+}
+
+impl RangeableStore for SqliteStore {
+    type Range = SqliteRange;
+
+    fn count(&self) -> Result<u64, Error> {
+        let conn = self.pool.get()?;
+        let count: u64 = conn.query_row("select count(*) from log", params![], |row| row.get(0))?;
+        Ok(count)
+    }
+
+    /// Entries are removed and returned by a single statement, or on sqlite
+    /// versions without `returning` clauses, read and then removed in the
+    /// same transaction, so none are lost to concurrent pushes.
+    fn drain_all(&self) -> Result<Vec<Entry>, Error> {
+        let returning = self.sqlite_version >= MIN_RETURNING_VERSION;
+        let mut entries = Vec::default();
+        let mut deleted_bytes = 0;
+        {
+            let mut conn = self.pool.get()?;
+            let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            {
+                let mut stmt = tx.prepare(if returning {
+                    "delete from log returning ts, name, size, value, codec, id, skewed, ingested_at, meta"
+                } else {
+                    "select ts, name, size, value, codec, id, skewed, ingested_at, meta from log"
+                })?;
+                let mut rows = stmt.query(params![])?;
+                let mut names = RowNames::new(None);
+                while let Some(row) = rows.next()? {
+                    deleted_bytes += row.get_ref(3)?.as_bytes().map_or(0, |value| value.len()) as u64;
+                    entries.push(stored_entry_from_row(self.codec.as_ref(), &mut names, row)?);
+                }
+            }
+            if !returning {
+                tx.execute("delete from log", params![])?;
+            }
+            tx.commit()?;
+        }
+        self.invalidate_latest(None);
+        self.vacuum_tracker.record_deleted(&self.pool, deleted_bytes);
+        // sqlite doesn't order the rows returned by a delete, nor the
+        // fallback's unordered select
+        entries.sort_by(|a, b| (a.entry.timestamp, &a.entry.name, a.id).cmp(&(b.entry.timestamp, &b.entry.name, b.id)));
+        Ok(entries.into_iter().map(|stored| stored.entry).collect())
+    }
+
+    /// Each page is read by a separate query, which seeks past the previous
+    /// page rather than re-reading it.
+    fn paginate<A: Into<Atom>, R: RangeBounds<i64>>(
+        &self,
+        range: R,
+        name: Option<A>,
+        page_size: usize,
+    ) -> Result<impl Iterator<Item = Result<Vec<Entry>, Error>>, Error> {
+        utils::check_bounds(range.start_bound(), range.end_bound())?;
+        let statement_builder = StatementBuilder::new(range, name.map(|n| n.into()));
+        #[cfg(feature = "sea-query")]
+        let statement_builder = statement_builder.with_sea_query(self.sea_query);
+        Ok(SqlitePages {
+            pool: self.pool.clone(),
+            codec: self.codec.clone(),
+            slow_ops: self.options.slow_ops.clone(),
+            statement_builder,
+            page_size: page_size.max(1),
+            cursor: None,
+            done: false,
+            retries: PageRetries::default(),
+        })
+    }
+
+    fn rename<A: Into<Atom>, B: Into<Atom>>(&self, old: A, new: B) -> Result<u64, Error> {
+        let (old, new) = (old.into(), new.into());
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let in_use: bool = tx.query_row(
+            "select exists(select 1 from log where name = ?)",
+            params![new.as_ref()],
+            |row| row.get(0),
+        )?;
+        if in_use {
+            return Err(Error::NameInUse(new.to_string()));
+        }
+        let count = tx.execute(
+            "update log set name = ? where name = ?",
+            params![new.as_ref(), old.as_ref()],
+        )?;
+        tx.commit()?;
+        self.invalidate_latest(Some(&old));
+        self.invalidate_latest(Some(&new));
+        Ok(count as u64)
+    }
+
+    fn range<A: Into<Atom>, R: RangeBounds<i64>>(&self, range: R, name: Option<A>) -> Result<Self::Range, Error> {
+        utils::check_bounds(range.start_bound(), range.end_bound())?;
+        let statement_builder = StatementBuilder::new(range, name.map(|n| n.into()));
+        #[cfg(feature = "sea-query")]
+        let statement_builder = statement_builder.with_sea_query(self.sea_query);
+        Ok(SqliteRange {
+            pool: self.pool.clone(),
+            codec: self.codec.clone(),
+            vacuum_tracker: self.vacuum_tracker.clone(),
+            latest_cache: self.latest_cache.clone(),
+            slow_ops: self.options.slow_ops.clone(),
+            statement_builder,
+        })
+    }
+}
+
+pub struct SqliteRange {
+    pool: MonitoredPool,
+    codec: Arc<dyn Codec>,
+    vacuum_tracker: Arc<VacuumTracker>,
+    latest_cache: Option<Arc<LatestCache>>,
+    slow_ops: Option<SlowOpConfig>,
+    statement_builder: StatementBuilder,
+}
+
+impl Range for SqliteRange {
+    type Iter = SqliteRangeIterator;
+
+    fn count(&self) -> Result<u64, Error> {
+        let conn = self.pool.get()?;
+        select_count(&conn, &self.statement_builder)
+    }
+
+    fn is_empty(&self) -> Result<bool, Error> {
+        let conn = self.pool.get()?;
+        select_is_empty(&conn, &self.statement_builder)
+    }
+
+    fn remove(self) -> Result<(), Error> {
+        if self.statement_builder.is_unsatisfiable() {
+            return Ok(());
+        }
+        let deleted_bytes = {
+            let mut conn = self.pool.get()?;
+            let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+            let deleted_bytes: u64 = tx
+                .prepare_cached(
+                    &self
+                        .statement_builder
+                        .statement("select coalesce(sum(length(value)), 0) from log", ""),
+                )?
+                .query_row(self.statement_builder.params(), |row| row.get(0))?;
+            tx.prepare_cached(&self.statement_builder.statement("delete from log", ""))?
+                .execute(self.statement_builder.params())?;
+            tx.commit()?;
+            deleted_bytes
+        };
+        if let Some(latest_cache) = &self.latest_cache {
+            latest_cache.invalidate(self.statement_builder.name.as_ref());
+        }
+        self.vacuum_tracker.record_deleted(&self.pool, deleted_bytes);
+        Ok(())
+    }
+
+    fn iter(self) -> Result<Self::Iter, Error> {
+        Ok(self.into_iter())
+    }
+
+    fn iter_timestamps(self) -> Result<impl Iterator<Item = Result<i64, Error>>, Error> {
+        Ok(self.key_iter().map(|key| key.map(|(timestamp, _)| timestamp)))
+    }
+
+    fn iter_names_and_timestamps(self) -> Result<impl Iterator<Item = Result<(Atom, i64), Error>>, Error> {
+        Ok(self
+            .key_iter()
+            .map(|key| key.map(|(timestamp, name)| (name, timestamp))))
+    }
+
+    /// Values are read as stored, and only decompressed when they're read.
+    fn iter_lazy(self) -> Result<impl Iterator<Item = Result<LazyEntry, Error>>, Error> {
+        Ok(SqliteLazyIterator {
+            pool: self.pool,
+            codec: self.codec,
+            statement_builder: self.statement_builder,
+            cursor: None,
+            entries: VecDeque::default(),
+            done: false,
+            retries: PageRetries::default(),
+        })
+    }
+
+    /// Has sqlite order entries by name, so each name's entries arrive
+    /// together.
+    fn zip_by_name(self) -> Result<HashMap<Atom, Vec<Entry>>, Error> {
+        let mut entries_by_name = HashMap::default();
+        let mut current: Vec<Entry> = Vec::default();
+        for entry in self.order_by(OrderBy::NameThenTime).iter()? {
+            let entry = entry?;
+            if current.last().is_some_and(|last| last.name != entry.name) {
+                let entries = std::mem::take(&mut current);
+                entries_by_name.insert(entries[0].name.clone(), entries);
+            }
+            current.push(entry);
+        }
+        if let Some(first) = current.first() {
+            entries_by_name.insert(first.name.clone(), current);
+        }
+        Ok(entries_by_name)
+    }
+}
+
+/// Iterates like `iter`, e.g. in `for` loops. Entries are read lazily, so
+/// this can't fail up front; errors are yielded by the iterator instead.
+impl IntoIterator for SqliteRange {
+    type Item = Result<Entry, Error>;
+    type IntoIter = SqliteRangeIterator;
+
+    fn into_iter(self) -> Self::IntoIter {
+        SqliteRangeIterator {
+            inner: self.metadata_iter(),
+        }
+    }
+}
+
+impl SqliteRange {
+    /// Sets the order entries are iterated in. Only timestamps are indexed,
+    /// so ordering by name requires sqlite to sort the range.
+    pub fn order_by(mut self, order: OrderBy) -> Self {
+        self.statement_builder.order = order;
+        self
+    }
+
+    /// Yields the timestamp and name of each entry, in the range's order,
+    /// without reading or decompressing values, e.g. to build an index over
+    /// a large range.
+    pub fn iter_keys(self) -> Result<impl Iterator<Item = Result<(i64, Atom), Error>>, Error> {
+        Ok(self.key_iter())
+    }
+
+    fn key_iter(self) -> SqliteKeyIterator {
+        SqliteKeyIterator {
+            pool: self.pool,
+            statement_builder: self.statement_builder,
+            cursor: None,
+            keys: VecDeque::default(),
+            done: false,
+            retries: PageRetries::default(),
+        }
+    }
+
+    /// Like `iter`, but also yields when each entry was pushed, according to
+    /// the store's clock.
+    pub fn iter_with_metadata(self) -> Result<SqliteRangeMetadataIterator, Error> {
+        Ok(self.metadata_iter())
+    }
+
+    /// Computes the ingestion lag percentiles of the range's entries.
+    /// Entries written before ingestion times were tracked aren't counted.
+    pub fn ingestion_lag_stats(self) -> Result<IngestionLagStats, Error> {
+        IngestionLagStats::from_range(self.metadata_iter())
+    }
+
+    fn metadata_iter(self) -> SqliteRangeMetadataIterator {
+        SqliteRangeMetadataIterator {
+            pool: self.pool,
+            codec: self.codec,
+            slow_ops: self.slow_ops,
+            statement_builder: self.statement_builder,
+            entries: VecDeque::default(),
+            offset: 0,
+            done: false,
+            retries: PageRetries::default(),
+        }
+    }
+
+    /// Like `iter`, but reads the first page before returning, so that
+    /// errors (e.g. from a missing table or an unreadable database) are
+    /// returned here rather than yielded by the iterator. This distinguishes
+    /// a query that can't run from a range without entries.
+    pub fn prepare(self) -> Result<SqliteRangeIterator, Error> {
+        Ok(SqliteRangeIterator {
+            inner: self.prepare_with_metadata()?,
+        })
+    }
+
+    /// Like `prepare`, but also yields when each entry was pushed, as
+    /// `iter_with_metadata` does.
+    pub fn prepare_with_metadata(self) -> Result<SqliteRangeMetadataIterator, Error> {
+        let mut iter = self.iter_with_metadata()?;
+        iter.fill_entries()?;
+        Ok(iter)
+    }
+}
+
+/// Iterates over a range, reading a page of entries at a time. If reading a
+/// page fails, the error is yielded, and the next call retries the page
+/// after a backoff. The iterator ends after three failures in a row.
+pub struct SqliteRangeIterator {
+    inner: SqliteRangeMetadataIterator,
+}
+
+impl Iterator for SqliteRangeIterator {
+    type Item = Result<Entry, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner
+            .next()
+            .map(|stored_entry| stored_entry.map(|stored_entry| stored_entry.entry))
+    }
+}
+
+/// Like `SqliteRangeIterator`, but yields `StoredEntry`s.
+pub struct SqliteRangeMetadataIterator {
+    pool: MonitoredPool,
+    codec: Arc<dyn Codec>,
+    slow_ops: Option<SlowOpConfig>,
+    statement_builder: StatementBuilder,
+    entries: VecDeque<StoredEntry>,
+    offset: usize,
+    done: bool,
+    retries: PageRetries,
+}
+
+impl SqliteRangeMetadataIterator {
+    fn fill_entries(&mut self) -> Result<(), Error> {
+        let timer = self.slow_ops.as_ref().map(SlowOpConfig::start);
+        let conn = self.pool.get()?;
+        let buffered = self.entries.len();
+        self.done = select_page(
+            &conn,
+            self.codec.as_ref(),
+            &self.statement_builder,
+            self.offset,
+            &mut self.entries,
+        )?;
+        if let Some(timer) = timer {
+            timer.finish(
+                SlowOpKind::Query,
+                self.statement_builder.name.as_ref(),
+                (self.entries.len() - buffered) as u64,
+                || Some(page_statement(&self.statement_builder, self.offset).0),
+            );
+        }
+        self.offset += PAGINATION_LIMIT;
+        Ok(())
+    }
+}
+
+impl Iterator for SqliteRangeMetadataIterator {
+    type Item = Result<StoredEntry, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.entries.is_empty() && !self.done {
+            self.retries.backoff();
+            let result = self.fill_entries();
+            if self.retries.record(&result) {
+                self.done = true;
+            }
+            if let Err(err) = result {
+                return Some(Err(err));
+            }
+        }
+        self.entries.pop_front().map(Ok)
+    }
+}
+
+/// Iterates over the pages of a range, created via `SqliteStore::paginate`.
+/// Failed pages are retried like `SqliteRangeIterator`'s.
+struct SqlitePages {
+    pool: MonitoredPool,
+    codec: Arc<dyn Codec>,
+    slow_ops: Option<SlowOpConfig>,
+    statement_builder: StatementBuilder,
+    page_size: usize,
+    cursor: Option<(i64, Atom, u64)>,
+    done: bool,
+    retries: PageRetries,
+}
+
+impl SqlitePages {
+    fn read_page(&mut self) -> Result<Vec<StoredEntry>, Error> {
+        let timer = self.slow_ops.as_ref().map(SlowOpConfig::start);
+        let conn = self.pool.get()?;
+        let page = select_page_after(
+            &conn,
+            self.codec.as_ref(),
+            &self.statement_builder,
+            self.cursor.as_ref(),
+            self.page_size,
+        )?;
+        if let Some(timer) = timer {
+            timer.finish(
+                SlowOpKind::Query,
+                self.statement_builder.name.as_ref(),
+                page.len() as u64,
+                || None,
+            );
+        }
+        Ok(page)
+    }
+}
+
+impl Iterator for SqlitePages {
+    type Item = Result<Vec<Entry>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        self.retries.backoff();
+        let result = self.read_page();
+        if self.retries.record(&result) {
+            self.done = true;
+        }
+        let page = match result {
+            Ok(page) => page,
+            Err(err) => return Some(Err(err)),
+        };
+        self.done = page.len() < self.page_size;
+        let last = page.last()?;
+        self.cursor = Some((last.entry.timestamp, last.entry.name.clone(), last.id));
+        Some(Ok(page.into_iter().map(|stored_entry| stored_entry.entry).collect()))
+    }
+}
+
+impl SubscribeableStore for SqliteStore {
+    type Subscription = SqliteSubscription;
+
+    /// Subscribes to entries pushed after this call. sqlite can't notify
+    /// readers of writes, so the subscription polls for new entries, at the
+    /// interval set via `with_subscription_poll_interval`. Unlike
+    /// `MemoryStore`'s subscriptions, which only see the newest entry, every
+    /// entry is yielded, in the order they were pushed. Values replaced via
+    /// `push_or_replace` are not seen.
+    fn subscribe<A: Into<Atom>>(&self, name: A) -> Result<Self::Subscription, Error> {
+        // ids are never reused, so every entry pushed from now on has a
+        // larger id
+        let last_id: u64 = self
+            .pool
+            .get()?
+            .query_row("select coalesce(max(id), 0) from log", params![], |row| row.get(0))?;
+        Ok(SqliteSubscription {
+            pool: self.pool.clone(),
+            codec: self.codec.clone(),
+            name: name.into(),
+            poll_interval: self.subscription_poll_interval,
+            last_id: Arc::new(AtomicU64::new(last_id)),
+            entries: VecDeque::default(),
+            watcher: None,
+        })
+    }
+}
+
+/// Checks whether a name has entries with ids after `last_id`.
+fn select_has_entries_after(conn: &Connection, name: &Atom, last_id: u64) -> Result<bool, Error> {
+    let mut stmt = conn.prepare_cached("select exists(select 1 from log where name = ? and id > ?)")?;
+    let exists: bool = stmt.query_row(params![name.as_ref(), last_id], |row| row.get(0))?;
+    Ok(exists)
+}
+
+/// Polls for new entries on a dedicated thread, waking registered wakers
+/// whenever there are entries the subscription hasn't read. The thread exits
+/// shortly after the watcher is dropped.
+struct SqliteWatcher {
+    wakers: Arc<Mutex<Vec<Waker>>>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl SqliteWatcher {
+    fn spawn(pool: MonitoredPool, name: Atom, last_id: Arc<AtomicU64>, poll_interval: Duration) -> Self {
+        let wakers = Arc::new(Mutex::new(Vec::<Waker>::default()));
+        let stopped = Arc::new(AtomicBool::new(false));
+        let thread_wakers = wakers.clone();
+        let thread_stopped = stopped.clone();
+
+        thread::spawn(move || {
+            while !thread_stopped.load(Ordering::SeqCst) {
+                let result = pool
+                    .get()
+                    .and_then(|conn| select_has_entries_after(&conn, &name, last_id.load(Ordering::SeqCst)));
+                // Errors wake up selects too, so that they surface when
+                // reading from the subscription
+                if !matches!(result, Ok(false)) {
+                    thread_wakers.lock().unwrap().retain(|waker| waker.wake());
+                }
+                thread::sleep(poll_interval);
+            }
+        });
+
+        Self { wakers, stopped }
+    }
+
+    fn register(&self, waker: Waker) {
+        let mut wakers = self.wakers.lock().unwrap();
+        wakers.retain(|waker| waker.is_alive());
+        wakers.push(waker);
+    }
+}
+
+impl Drop for SqliteWatcher {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+}
+
+/// A subscription to a `SqliteStore`, created via
+/// `SqliteStore::subscribe`.
+pub struct SqliteSubscription {
+    pool: MonitoredPool,
+    codec: Arc<dyn Codec>,
+    name: Atom,
+    poll_interval: Duration,
+    /// The id of the newest entry read, shared with the watcher.
+    last_id: Arc<AtomicU64>,
+    entries: VecDeque<Entry>,
+    watcher: Option<SqliteWatcher>,
+}
+
+impl SqliteSubscription {
+    /// Reads entries pushed since the last poll. Entries are only buffered
+    /// if every one of them is read, so that a failed poll can be retried.
+    fn poll(&mut self) -> Result<(), Error> {
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare_cached(
+            "select ts, name, size, value, codec, id, skewed, ingested_at, meta from log where name = ? and id > ? \
+             order by id limit ?",
+        )?;
+        let mut rows = stmt.query(params![
+            self.name.as_ref(),
+            self.last_id.load(Ordering::SeqCst),
+            PAGINATION_LIMIT as i64
+        ])?;
+        let mut names = RowNames::new(Some(&self.name));
+        let mut page = Vec::default();
+        while let Some(row) = rows.next()? {
+            page.push(stored_entry_from_row(self.codec.as_ref(), &mut names, row)?);
+        }
+        if let Some(last) = page.last() {
+            self.last_id.store(last.id, Ordering::SeqCst);
+        }
+        self.entries
+            .extend(page.into_iter().map(|stored_entry| stored_entry.entry));
+        Ok(())
+    }
+}
+
+impl Subscription for SqliteSubscription {
+    fn next(&mut self, timeout: Option<Duration>) -> Result<Option<Entry>, Error> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        loop {
+            if let Some(entry) = self.entries.pop_front() {
+                return Ok(Some(entry));
+            }
+            self.poll()?;
+            if !self.entries.is_empty() {
+                continue;
+            }
+            let sleep = match deadline {
+                Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                    Some(remaining) if !remaining.is_zero() => remaining.min(self.poll_interval),
+                    _ => return Ok(None),
+                },
+                None => self.poll_interval,
+            };
+            thread::sleep(sleep);
+        }
+    }
+}
+
+impl SubscriptionDyn for SqliteSubscription {
+    fn register_waker(&mut self, waker: Waker) -> Result<(), Error> {
+        if self.watcher.is_none() {
+            self.watcher = Some(SqliteWatcher::spawn(
+                self.pool.clone(),
+                self.name.clone(),
+                self.last_id.clone(),
+                self.poll_interval,
+            ));
+        }
+        if let Some(watcher) = &self.watcher {
+            watcher.register(waker.clone());
+        }
+        // The waker is registered before checking for an unread entry, so
+        // that entries pushed in between aren't missed
+        if self.entries.is_empty() {
+            self.poll()?;
+        }
+        if !self.entries.is_empty() {
+            waker.wake();
+        }
+        Ok(())
+    }
+
+    fn try_next(&mut self) -> Result<Option<Entry>, Error> {
+        if self.entries.is_empty() {
+            self.poll()?;
+        }
+        Ok(self.entries.pop_front())
+    }
+}
+
+/// Iterates over the timestamps and names of the entries in a range, a page
+/// at a time, without reading their values. Failed pages are retried like
+/// `SqliteRangeIterator`'s.
+struct SqliteKeyIterator {
+    pool: MonitoredPool,
+    statement_builder: StatementBuilder,
+    /// The timestamp, name and id of the last entry read.
+    cursor: Option<(i64, Atom, u64)>,
+    keys: VecDeque<(i64, Atom)>,
+    done: bool,
+    retries: PageRetries,
+}
+
+impl SqliteKeyIterator {
+    fn fill_keys(&mut self) -> Result<(), Error> {
+        if self.statement_builder.is_unsatisfiable() {
+            self.done = true;
+            return Ok(());
+        }
+        let (statement, params) = page_after_statement(
+            &self.statement_builder,
+            PageColumns::Keys,
+            self.cursor.as_ref(),
+            PAGINATION_LIMIT,
+        );
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare_cached(&statement)?;
+        let mut rows = stmt.query(params_from_iter(params))?;
+        let mut names = RowNames::new(self.statement_builder.name.as_ref());
+        let mut page = Vec::with_capacity(PAGINATION_LIMIT);
+        while let Some(row) = rows.next()? {
+            page.push((row.get::<_, i64>(0)?, names.get(row, 1)?, row.get::<_, u64>(2)?));
+        }
+        self.done = page.len() < PAGINATION_LIMIT;
+        if let Some((timestamp, name, id)) = page.last() {
+            self.cursor = Some((*timestamp, name.clone(), *id));
+        }
+        self.keys
+            .extend(page.into_iter().map(|(timestamp, name, _)| (timestamp, name)));
+        Ok(())
+    }
+}
+
+impl Iterator for SqliteKeyIterator {
+    type Item = Result<(i64, Atom), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.keys.is_empty() && !self.done {
+            self.retries.backoff();
+            let result = self.fill_keys();
+            if self.retries.record(&result) {
+                self.done = true;
+            }
+            if let Err(err) = result {
+                return Some(Err(err));
+            }
+        }
+        self.keys.pop_front().map(Ok)
+    }
+}
+
+/// Iterates over a range for `iter_lazy`, reading a page of entries at a
+/// time, without decoding their values. Failed pages are retried like
+/// `SqliteRangeIterator`'s.
+struct SqliteLazyIterator {
+    pool: MonitoredPool,
+    codec: Arc<dyn Codec>,
+    statement_builder: StatementBuilder,
+    /// The timestamp, name and id of the last entry read.
+    cursor: Option<(i64, Atom, u64)>,
+    entries: VecDeque<LazyEntry>,
+    done: bool,
+    retries: PageRetries,
+}
+
+impl SqliteLazyIterator {
+    fn fill_entries(&mut self) -> Result<(), Error> {
+        if self.statement_builder.is_unsatisfiable() {
+            self.done = true;
+            return Ok(());
+        }
+        let (statement, params) = page_after_statement(
+            &self.statement_builder,
+            PageColumns::Entries,
+            self.cursor.as_ref(),
+            PAGINATION_LIMIT,
+        );
+        let conn = self.pool.get()?;
+        let mut stmt = conn.prepare_cached(&statement)?;
+        let mut rows = stmt.query(params_from_iter(params))?;
+        let mut names = RowNames::new(self.statement_builder.name.as_ref());
+        let mut page = Vec::with_capacity(PAGINATION_LIMIT);
+        while let Some(row) = rows.next()? {
+            page.push(lazy_entry_from_row(&self.codec, &mut names, row)?);
+        }
+        self.done = page.len() < PAGINATION_LIMIT;
+        if let Some(last) = page.last() {
+            let id = last.sequence_number.expect("sqlite entries have ids");
+            self.cursor = Some((last.timestamp, last.name.clone(), id));
+        }
+        self.entries.extend(page);
+        Ok(())
+    }
+}
+
+impl Iterator for SqliteLazyIterator {
+    type Item = Result<LazyEntry, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.entries.is_empty() && !self.done {
+            self.retries.backoff();
+            let result = self.fill_entries();
+            if self.retries.record(&result) {
+                self.done = true;
+            }
+            if let Err(err) = result {
+                return Some(Err(err));
+            }
+        }
+        self.entries.pop_front().map(Ok)
+    }
+}
+
+/// A read-only view of a `SqliteStore` at a point in time, created via
+/// `SqliteStore::snapshot`.
+pub struct SqliteSnapshot {
+    // Taken once the snapshot expires, which ends its read transaction and
+    // returns the connection to the pool
+    conn: RefCell<Option<PooledConnection<SqliteConnectionManager>>>,
+    codec: Arc<dyn Codec>,
+    ignore_skewed_in_latest: bool,
+    created_at: Instant,
+    max_age: Option<Duration>,
+    #[cfg(feature = "sea-query")]
+    sea_query: bool,
+}
+
+impl SqliteSnapshot {
+    fn conn(&self) -> Result<Ref<'_, Connection>, Error> {
+        if self.max_age.is_some_and(|max_age| self.created_at.elapsed() > max_age) {
+            self.release();
+        }
+        Ref::filter_map(self.conn.borrow(), |conn| conn.as_deref())
+            .map_err(|_| IoError::new(IoErrorKind::TimedOut, "snapshot exceeded its maximum age").into())
+    }
+
+    /// Ends the read transaction, and returns the connection to the pool.
+    fn release(&self) {
+        if let Some(conn) = self.conn.borrow_mut().take() {
+            // This can only fail if the transaction never started.
+            let _ = conn.execute_batch("rollback");
+        }
+    }
+
+    /// Gets the latest entry with the given name, as of the snapshot.
+    pub fn latest<A: Into<Atom>>(&self, name: A) -> Result<Option<Entry>, Error> {
+        select_latest(
+            &*self.conn()?,
+            self.codec.as_ref(),
+            name.into(),
+            self.ignore_skewed_in_latest,
+        )
+    }
+
+    /// Gets up to the `n` latest entries with the given name, oldest first,
+    /// as of the snapshot.
+    pub fn latest_n<A: Into<Atom>>(&self, name: A, n: usize) -> Result<Vec<Entry>, Error> {
+        select_latest_n(
+            &*self.conn()?,
+            self.codec.as_ref(),
+            name.into(),
+            n,
+            self.ignore_skewed_in_latest,
+        )
+    }
+
+    /// Gets a range of entries, as of the snapshot.
+    pub fn range<A: Into<Atom>, R: RangeBounds<i64>>(
+        &self,
+        range: R,
+        name: Option<A>,
+    ) -> Result<SqliteSnapshotRange<'_>, Error> {
+        utils::check_bounds(range.start_bound(), range.end_bound())?;
+        let statement_builder = StatementBuilder::new(range, name.map(|n| n.into()));
+        #[cfg(feature = "sea-query")]
+        let statement_builder = statement_builder.with_sea_query(self.sea_query);
+        Ok(SqliteSnapshotRange {
+            snapshot: self,
+            statement_builder,
+        })
+    }
+}
+
+impl Drop for SqliteSnapshot {
+    fn drop(&mut self) {
+        // Release the read transaction before the connection goes back to
+        // the pool
+        self.release();
+    }
+}
+
+/// A range of entries in a `SqliteSnapshot`.
+pub struct SqliteSnapshotRange<'a> {
+    snapshot: &'a SqliteSnapshot,
+    statement_builder: StatementBuilder,
+}
+
+impl<'a> SqliteSnapshotRange<'a> {
+    pub fn count(&self) -> Result<u64, Error> {
+        select_count(&*self.snapshot.conn()?, &self.statement_builder)
+    }
+
+    pub fn is_empty(&self) -> Result<bool, Error> {
+        select_is_empty(&*self.snapshot.conn()?, &self.statement_builder)
+    }
+
+    pub fn iter(self) -> Result<SqliteSnapshotRangeIterator<'a>, Error> {
+        Ok(SqliteSnapshotRangeIterator {
+            snapshot: self.snapshot,
+            statement_builder: self.statement_builder,
+            entries: VecDeque::default(),
+            offset: 0,
+            done: false,
+            retries: PageRetries::default(),
+        })
+    }
+}
+
+/// Failed pages are retried like `SqliteRangeIterator`'s.
+pub struct SqliteSnapshotRangeIterator<'a> {
+    snapshot: &'a SqliteSnapshot,
+    statement_builder: StatementBuilder,
+    entries: VecDeque<StoredEntry>,
+    offset: usize,
+    done: bool,
+    retries: PageRetries,
+}
+
+impl<'a> SqliteSnapshotRangeIterator<'a> {
+    fn fill_entries(&mut self) -> Result<(), Error> {
+        let conn = self.snapshot.conn()?;
+        self.done = select_page(
+            &conn,
+            self.snapshot.codec.as_ref(),
+            &self.statement_builder,
+            self.offset,
+            &mut self.entries,
+        )?;
+        self.offset += PAGINATION_LIMIT;
+        Ok(())
+    }
+}
+
+impl<'a> Iterator for SqliteSnapshotRangeIterator<'a> {
+    type Item = Result<Entry, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.entries.is_empty() && !self.done {
+            self.retries.backoff();
+            let result = self.fill_entries();
+            if self.retries.record(&result) {
+                self.done = true;
+            }
+            if let Err(err) = result {
+                return Some(Err(err));
+            }
+        }
+        self.entries.pop_front().map(|stored_entry| Ok(stored_entry.entry))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::fs::OpenOptions;
+    use std::io::{Seek, SeekFrom, Write};
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread::sleep;
+    use std::time::{Duration, Instant};
+
+    use crate::testkit::TempFiles;
+    #[cfg(feature = "compression")]
+    use crate::ZstdCodec;
+    use crate::{
+        define_test, test_rangeable_store_impl, test_store_impl, Aggregate, AggregateReport, Capabilities, Codec,
+        CompactionReport, Entry, Error, LazyEntry, ManualClock, NoopCodec, OrderBy, Range, RangeableStore, SkewPolicy,
+        SlowOp, SlowOpConfig, SlowOpKind, SqliteRange, SqliteStore, SqliteStoreConfig, Store, StoreOptions,
+        StoredEntry, SubscribeableStore, Subscription, SubscriptionDyn, ValueType,
+    };
+    use rusqlite::{params, Connection};
+    use string_cache::DefaultAtom as Atom;
+    use tempfile::NamedTempFile;
+    test_store_impl!(|files: &TempFiles| SqliteStore::new(files.path(), None).unwrap());
+    test_rangeable_store_impl!(|files: &TempFiles| SqliteStore::new(files.path(), None).unwrap());
+    // entries are pushed 20ms apart, so they must be polled for more often
+    // to be delivered in turn
+    define_test!(select, |files: &TempFiles| SqliteStore::new(files.path(), None)
+        .unwrap()
+        .with_subscription_poll_interval(Duration::from_millis(5)));
+    define_test!(subscribeable_capabilities, |files: &TempFiles| SqliteStore::new(
+        files.path(),
+        None
+    )
+    .unwrap());
+
+    #[test]
+    fn ingested_at() {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        crate::testkit::ingested_at(
+            |options| SqliteStore::new(&file, None).unwrap().with_options(options),
+            |store| {
+                let range = store.range(.., Some("test_ingested_at")).unwrap();
+                range.iter_with_metadata().unwrap().map(Result::unwrap).collect()
+            },
+        );
+
+        // the ingestion time of entries written before it was tracked is
+        // unknown
+        let conn = Connection::open(&file).unwrap();
+        conn.execute(
+            "insert into log (ts, name, size, value) values (?, ?, ?, ?)",
+            params![1, "test_ingested_at_legacy", 0, vec![1u8]],
+        )
+        .unwrap();
+        let store = SqliteStore::new(&file, None).unwrap();
+        let stored: Vec<StoredEntry> = store
+            .range(.., Some("test_ingested_at_legacy"))
+            .unwrap()
+            .iter_with_metadata()
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].ingested_at, None);
+        assert_eq!(stored[0].ingestion_lag(), None);
+        let stats = store
+            .range(.., Option::<Atom>::None)
+            .unwrap()
+            .ingestion_lag_stats()
+            .unwrap();
+        assert_eq!((stats.count, stats.p50, stats.max), (2, 100, 150));
+    }
+
+    #[test]
+    fn with_clock() {
+        let clock = Arc::new(ManualClock::new(1_000));
+        let store = SqliteStore::new(NamedTempFile::new().unwrap().path(), None)
+            .unwrap()
+            .with_clock(clock.clone());
+        store
+            .push(Cow::Owned(store.new_entry("test_with_clock", vec![1])))
+            .unwrap();
+        clock.set(5_000);
+        store
+            .push(Cow::Owned(store.new_entry("test_with_clock", vec![2])))
+            .unwrap();
+        let stored: Vec<(i64, Option<i64>)> = store
+            .range(.., Some("test_with_clock"))
+            .unwrap()
+            .iter_with_metadata()
+            .unwrap()
+            .map(|stored| {
+                let stored = stored.unwrap();
+                (stored.entry.timestamp, stored.ingested_at)
+            })
+            .collect();
+        assert_eq!(stored, vec![(1_000, Some(1_000)), (5_000, Some(5_000))]);
+    }
+
+    #[test]
+    fn slow_ops() {
+        let files = TempFiles::new();
+        let path = files.path();
+        let make_store = |options| SqliteStore::new(&path, None).unwrap().with_options(options);
+        let ops = crate::testkit::slow_ops(make_store);
+        assert_eq!(ops[0].sql.as_deref(), Some(super::INSERT_RETURNING_STATEMENT));
+        assert!(ops[1].sql.as_ref().unwrap().contains("where name = ?"));
+
+        // pages of ranges are reported individually, with their own lengths
+        let reported = Arc::new(std::sync::Mutex::new(Vec::default()));
+        let callback_reported = reported.clone();
+        let store = make_store(StoreOptions {
+            slow_ops: Some(SlowOpConfig {
+                push_threshold: Duration::from_secs(3600),
+                query_threshold: Duration::ZERO,
+                callback: Arc::new(move |op: &SlowOp| {
+                    assert_eq!(op.kind, SlowOpKind::Query);
+                    assert!(op.sql.as_ref().unwrap().contains("limit 1000 offset"));
+                    callback_reported.lock().unwrap().push(op.rows);
+                }),
+            }),
+            ..StoreOptions::default()
+        });
+        let entries: Vec<Entry> = (0..1500)
+            .map(|i| Entry::new_with_timestamp(i, "test_slow_ops_pages", vec![]))
+            .collect();
+        store.push_bulk_values(&entries).unwrap();
+        let range = store.range(.., Some("test_slow_ops_pages")).unwrap();
+        assert_eq!(range.iter().unwrap().count(), 1500);
+        assert_eq!(*reported.lock().unwrap(), vec![1000, 500]);
+    }
+
+    #[test]
+    fn iter_lazy_decodes_on_get() {
+        let files = TempFiles::new();
+        let store = SqliteStore::new(files.path(), None).unwrap();
+        let name = "test_iter_lazy_decodes_on_get";
+        store
+            .push(Cow::Owned(Entry::new_with_timestamp(1, name, vec![1; 4096])))
+            .unwrap();
+        store
+            .push(Cow::Owned(Entry::new_with_timestamp(2, name, vec![2])))
+            .unwrap();
+
+        // compressed values stay compressed until they're read, while values
+        // stored as-is need no decoding
+        let lazy: Vec<LazyEntry> = store
+            .range(.., Some(name))
+            .unwrap()
+            .iter_lazy()
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(lazy[0].value.is_decoded(), cfg!(not(feature = "compression")));
+        assert_eq!(lazy[0].value.len(), 4096);
+        assert!(lazy[1].value.is_decoded());
+
+        // corrupt the compressed value, so that decoding it fails
+        store
+            .pool
+            .get()
+            .unwrap()
+            .execute("update log set value = x'00' where ts = 1", params![])
+            .unwrap();
+        #[cfg(feature = "compression")]
+        assert!(store
+            .range(.., Some(name))
+            .unwrap()
+            .iter()
+            .unwrap()
+            .next()
+            .unwrap()
+            .is_err());
+        let mut lazy = store.range(.., Some(name)).unwrap().iter_lazy().unwrap();
+        let corrupt = lazy.next().unwrap().unwrap();
+        assert_eq!(corrupt.timestamp, 1);
+        #[cfg(feature = "compression")]
+        {
+            assert!(corrupt.value.get().is_err());
+            assert!(corrupt.into_entry().is_err());
+        }
+        assert_eq!(lazy.next().unwrap().unwrap().value.get().unwrap().as_ref(), &[2]);
+    }
+
+    #[test]
+    fn range_statements_are_shared() {
+        // ranges with the same kinds of bounds differ only in their
+        // parameters, so they're prepared once per connection
+        let name = Some(Atom::from("test_range_statements_are_shared"));
+        let a = super::StatementBuilder::new(1..5, name.clone());
+        let b = super::StatementBuilder::new(10..20, name.clone());
+        assert_eq!(
+            a.statement("select 1 from log", ""),
+            b.statement("select 1 from log", "")
+        );
+        assert_eq!(super::page_statement(&a, 0).0, super::page_statement(&b, 1000).0);
+        let cursor = (3, Atom::from("test_range_statements_are_shared"), 7);
+        assert_eq!(
+            super::page_after_statement(&a, super::PageColumns::Keys, None, 10).0,
+            super::page_after_statement(&b, super::PageColumns::Keys, None, 100).0
+        );
+        assert_eq!(
+            super::page_after_statement(&a, super::PageColumns::Keys, Some(&cursor), 10).0,
+            super::page_after_statement(&b, super::PageColumns::Keys, Some(&cursor), 10).0
+        );
+        assert_ne!(
+            a.statement("select 1 from log", ""),
+            super::StatementBuilder::new(1..=5, name).statement("select 1 from log", "")
+        );
+
+        let files = TempFiles::new();
+        let store = SqliteStore::new(files.path(), None).unwrap();
+        let entries: Vec<Entry> = (0..100)
+            .map(|i| Entry::new_with_timestamp(i, format!("test_range_statements_are_shared_{}", i % 2), vec![]))
+            .collect();
+        store.push_bulk_values(&entries).unwrap();
+        for start in 0..10 {
+            let range = store
+                .range(start..start + 10, Some("test_range_statements_are_shared_0"))
+                .unwrap();
+            assert_eq!(range.count().unwrap(), 5);
+            let timestamps: Vec<i64> = range.iter().unwrap().map(|entry| entry.unwrap().timestamp).collect();
+            let expected: Vec<i64> = (start..start + 10).filter(|i| i % 2 == 0).collect();
+            assert_eq!(timestamps, expected);
+        }
+        let range = store.range(..50, None::<&str>).unwrap();
+        assert_eq!(range.count().unwrap(), 50);
+        range.remove().unwrap();
+        assert_eq!(store.range(.., None::<&str>).unwrap().count().unwrap(), 50);
+    }
+
+    #[test]
+    fn metadata() {
+        let files = TempFiles::new();
+        crate::testkit::metadata(SqliteStore::new(files.path(), None).unwrap(), |store| {
+            let range = store.range(.., Some("test_metadata")).unwrap();
+            range.iter_with_metadata().unwrap().map(Result::unwrap).collect()
+        });
+    }
+
+    #[test]
+    fn push_at_id() {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        crate::testkit::push_at_id(SqliteStore::new(&file, None).unwrap());
+
+        // the autoincrement counter survives reopening the store
+        let store = SqliteStore::new(&file, None).unwrap();
+        let id = store
+            .push_with_id(Cow::Owned(Entry::new_with_timestamp(1, "test_push_at_id", vec![1])))
+            .unwrap();
+        assert!(id > 9);
+        assert!(matches!(
+            store.push_at_id(
+                u64::MAX,
+                Cow::Owned(Entry::new_with_timestamp(1, "test_push_at_id", vec![1]))
+            ),
+            Err(Error::InvalidEntry(_))
+        ));
+        assert_eq!(store.get_by_id(u64::MAX).unwrap(), None);
+    }
+
+    #[test]
+    fn skew_policies() {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        crate::testkit::skew_policies(|options| SqliteStore::new(&file, None).unwrap().with_options(options));
+
+        // flags survive reopening the store, and apply to bulk pushes
+        let store = SqliteStore::new(&file, None).unwrap();
+        let latest = store.latest("test_skew_flag").unwrap().unwrap();
+        assert!(latest.skewed);
+        let options = StoreOptions {
+            max_future_skew: Some(Duration::from_secs(60)),
+            skew_policy: SkewPolicy::Flag { ignore_in_latest: true },
+            ..StoreOptions::default()
+        };
+        let store = store.with_options(options);
+        let entries = vec![
+            Entry::new("test_skew_bulk", vec![1]),
+            Entry::new_with_timestamp(i64::MAX, "test_skew_bulk", vec![2]),
+        ];
+        store.push_bulk_values(&entries).unwrap();
+        let skewed: Vec<bool> = store
+            .range(.., Some("test_skew_bulk"))
+            .unwrap()
+            .iter()
+            .unwrap()
+            .map(|entry| entry.unwrap().skewed)
+            .collect();
+        assert_eq!(skewed, vec![false, true]);
+        assert_eq!(store.latest("test_skew_bulk").unwrap(), Some(entries[0].clone()));
+        assert_eq!(
+            store.snapshot().unwrap().latest("test_skew_bulk").unwrap(),
+            Some(entries[0].clone())
+        );
+    }
+
+    #[test]
+    fn optimize() {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        let store = SqliteStore::new(&file, None).unwrap();
+        store.optimize().unwrap();
+        for i in 0..100 {
+            store
+                .push(Cow::Owned(Entry::new_with_timestamp(i, "test_optimize", vec![])))
+                .unwrap();
+        }
+        store.optimize().unwrap();
+    }
+
+    #[test]
+    fn optimize_periodically() {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        let config = SqliteStoreConfig {
+            optimize_interval: Some(Duration::from_millis(5)),
+            ..SqliteStoreConfig::default()
+        };
+        let store = SqliteStore::new_with_config(&file, config).unwrap();
+        for i in 0..10 {
+            store
+                .push(Cow::Owned(Entry::new_with_timestamp(i, "test_optimize", vec![])))
+                .unwrap();
+            sleep(Duration::from_millis(5));
+        }
+        assert_eq!(store.count().unwrap(), 10);
+        drop(store);
+
+        // the thread stops as soon as the store and its clones are dropped,
+        // rather than after the interval
+        let config = SqliteStoreConfig {
+            optimize_interval: Some(Duration::from_secs(3600)),
+            ..SqliteStoreConfig::default()
+        };
+        let store = SqliteStore::new_with_config(&file, config).unwrap();
+        let clone = store.clone();
+        drop(store);
+        let start = Instant::now();
+        drop(clone);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn prepare() {
+        let files = TempFiles::new();
+        let path = files.path();
+        let store = SqliteStore::new(&path, None).unwrap();
+        let entries: Vec<Entry> = (0..1500)
+            .map(|i| Entry::new_with_timestamp(i, "test_prepare", vec![1]))
+            .collect();
+        store.push_bulk_values(&entries).unwrap();
+
+        let range = || store.range(.., Some("test_prepare")).unwrap();
+        let prepared: Vec<Entry> = range().prepare().unwrap().map(Result::unwrap).collect();
+        assert_eq!(prepared, entries);
+        let stored_entries: Vec<StoredEntry> = range().prepare_with_metadata().unwrap().map(Result::unwrap).collect();
+        assert_eq!(stored_entries.len(), 1500);
+        let empty = store.range(.., Some("test_prepare_empty")).unwrap();
+        assert_eq!(empty.prepare().unwrap().count(), 0);
+
+        // a query that can't run fails up front, rather than on the first
+        // call to `next`
+        Connection::open(&path)
+            .unwrap()
+            .execute_batch("alter table log rename to log_renamed")
+            .unwrap();
+        let mut iter = range().iter().unwrap();
+        assert!(matches!(iter.next(), Some(Err(Error::Database(_)))));
+        assert!(matches!(range().prepare(), Err(Error::Database(_))));
+        assert!(matches!(range().prepare_with_metadata(), Err(Error::Database(_))));
+    }
+
+    #[cfg(feature = "sqlite-cipher")]
+    #[test]
+    fn encrypted() {
+        let files = TempFiles::new();
+        let path = files.path();
+        let entries: Vec<Entry> = (0..10)
+            .map(|i| Entry::new_with_timestamp(i, "test_encrypted", vec![i as u8; 100]))
+            .collect();
+        let read = |store: &SqliteStore| -> Vec<Entry> {
+            store
+                .range(.., Some("test_encrypted"))
+                .unwrap()
+                .iter()
+                .unwrap()
+                .map(Result::unwrap)
+                .collect()
+        };
+        let store = SqliteStore::new_encrypted(&path, "test key", None).unwrap();
+        store.push_bulk_values(&entries).unwrap();
+        drop(store);
+
+        // entries are readable with the key, but not without it
+        let store = SqliteStore::new_encrypted(&path, "test key", None).unwrap();
+        assert_eq!(read(&store), entries);
+        assert!(matches!(SqliteStore::new(&path, None), Err(Error::Database(_))));
+        assert!(matches!(
+            SqliteStore::new_encrypted(&path, "wrong key", None),
+            Err(Error::Database(_))
+        ));
+
+        // after rekeying, the store and its clones keep working
+        let clone = store.clone();
+        store.rekey("new key").unwrap();
+        assert_eq!(read(&clone), entries);
+        let entry = Entry::new_with_timestamp(10, "test_encrypted", vec![10]);
+        clone.push(Cow::Borrowed(&entry)).unwrap();
+        drop((store, clone));
+        assert!(matches!(
+            SqliteStore::new_encrypted(&path, "test key", None),
+            Err(Error::Database(_))
+        ));
+        let store = SqliteStore::new_encrypted(&path, "new key", None).unwrap();
+        assert_eq!(read(&store).len(), 11);
+
+        let unencrypted = SqliteStore::new(files.path(), None).unwrap();
+        assert!(matches!(unencrypted.rekey("key"), Err(Error::Unsupported(_))));
+    }
+
+    #[test]
+    fn iter_keys() {
+        let store = SqliteStore::new(NamedTempFile::new().unwrap().path(), None).unwrap();
+        let entries: Vec<Entry> = (0..1500)
+            .map(|i| Entry::new_with_timestamp(i / 2, format!("test_iter_keys_{}", i % 2), vec![1; 100]))
+            .collect();
+        store.push_bulk_values(&entries).unwrap();
+        let keys: Vec<(i64, Atom)> = store
+            .range(10.., Option::<Atom>::None)
+            .unwrap()
+            .iter_keys()
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        let expected: Vec<(i64, Atom)> = entries[20..]
+            .iter()
+            .map(|entry| (entry.timestamp, entry.name.clone()))
+            .collect();
+        assert_eq!(keys, expected);
+    }
+
+    #[test]
+    fn into_iter() {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        let store = SqliteStore::new(&file, None).unwrap();
+        let entries: Vec<Entry> = (1..4)
+            .map(|i| Entry::new_with_timestamp(i, "test_into_iter", vec![i as u8]))
+            .collect();
+        store.push_bulk_values(&entries).unwrap();
+        let mut results = Vec::default();
+        for entry in store.range(.., Some("test_into_iter")).unwrap() {
+            results.push(entry.unwrap());
+        }
+        assert_eq!(results, entries);
+
+        // failures are yielded rather than raised
+        Connection::open(&file)
+            .unwrap()
+            .execute_batch("drop table log")
+            .unwrap();
+        let mut iter = store.range(.., Some("test_into_iter")).unwrap().into_iter();
+        assert!(matches!(iter.next(), Some(Err(Error::Database(_)))));
+    }
+
+    #[test]
+    fn order_by() {
+        let store = SqliteStore::new(NamedTempFile::new().unwrap().path(), None).unwrap();
+        // enough entries for several pages, so that paging picks up where
+        // each page left off in the chosen order
+        let entries: Vec<Entry> = (0..2500)
+            .map(|i| {
+                let name = format!("test_order_by_{}", i % 3);
+                Entry::new_with_timestamp(i % 1000, name, vec![(i / 1000) as u8])
+            })
+            .collect();
+        store.push_bulk_values(&entries).unwrap();
+
+        let mut expected = entries;
+        expected.sort_by(|a, b| (&a.name, a.timestamp).cmp(&(&b.name, b.timestamp)));
+        let range = || {
+            store
+                .range(.., Option::<Atom>::None)
+                .unwrap()
+                .order_by(OrderBy::NameThenTime)
+        };
+        let actual: Vec<Entry> = range().iter().unwrap().map(Result::unwrap).collect();
+        assert_eq!(actual, expected);
+        let keys: Vec<(Atom, i64)> = range()
+            .iter_names_and_timestamps()
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        let expected_keys: Vec<(Atom, i64)> = expected
+            .iter()
+            .map(|entry| (entry.name.clone(), entry.timestamp))
+            .collect();
+        assert_eq!(keys, expected_keys);
+
+        expected.sort_by(|a, b| (a.timestamp, &a.name).cmp(&(b.timestamp, &b.name)));
+        let range = store.range(.., Option::<Atom>::None).unwrap().order_by(OrderBy::Time);
+        let actual: Vec<Entry> = range.iter().unwrap().map(Result::unwrap).collect();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn row_names() {
+        let store = SqliteStore::new(NamedTempFile::new().unwrap().path(), None).unwrap();
+        let entries: Vec<Entry> = (0..30)
+            .map(|i| {
+                let name = match i % 3 {
+                    0 => "test_row_names_a",
+                    _ => "test_row_names_b",
+                };
+                Entry::new_with_timestamp(i / 2, name, vec![i as u8])
+            })
+            .collect();
+        store.push_bulk_values(&entries).unwrap();
+
+        let mut expected = entries.clone();
+        expected.sort_by(|a, b| (a.timestamp, &a.name).cmp(&(b.timestamp, &b.name)));
+        let range = || store.range(.., Option::<Atom>::None).unwrap();
+        let actual: Vec<Entry> = range().iter().unwrap().map(Result::unwrap).collect();
+        assert_eq!(actual, expected);
+        let keys: Vec<(Atom, i64)> = range()
+            .iter_names_and_timestamps()
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        let expected_keys: Vec<(Atom, i64)> = expected
+            .iter()
+            .map(|entry| (entry.name.clone(), entry.timestamp))
+            .collect();
+        assert_eq!(keys, expected_keys);
+
+        let range = store.range(.., Some("test_row_names_a")).unwrap();
+        let actual: Vec<Entry> = range.iter().unwrap().map(Result::unwrap).collect();
+        expected.retain(|entry| &*entry.name == "test_row_names_a");
+        assert_eq!(actual, expected);
+        assert_eq!(store.latest("test_row_names_a").unwrap().as_ref(), expected.last());
+    }
+
+    #[test]
+    fn stable_order() {
+        let files = TempFiles::new();
+        let path = files.path();
+        let order = |store: &SqliteStore| -> Vec<(u64, Entry)> {
+            store
+                .range(.., Option::<Atom>::None)
+                .unwrap()
+                .iter_with_metadata()
+                .unwrap()
+                .map(|stored_entry| {
+                    let stored_entry = stored_entry.unwrap();
+                    (stored_entry.id, stored_entry.entry)
+                })
+                .collect()
+        };
+        let latest = |store: &SqliteStore| -> Vec<Option<Entry>> {
+            ["test_stable_order_a", "test_stable_order_b"]
+                .iter()
+                .map(|name| store.latest(*name).unwrap())
+                .collect()
+        };
+
+        // entries sharing timestamps, pushed every which way by several
+        // threads
+        let store = SqliteStore::new(&path, None).unwrap();
+        let handles: Vec<_> = (0..4u8)
+            .map(|thread| {
+                let store = store.clone();
+                std::thread::spawn(move || {
+                    for i in 0..60u8 {
+                        let name = if i % 2 == 0 {
+                            "test_stable_order_a"
+                        } else {
+                            "test_stable_order_b"
+                        };
+                        let entry = Entry::new_with_timestamp(i64::from(i % 5), name, vec![thread, i]);
+                        match i % 3 {
+                            0 => store.push(Cow::Owned(entry)).unwrap(),
+                            1 => store.push_bulk_values(&[entry.clone(), entry]).unwrap(),
+                            _ => {
+                                store.push_or_replace(Cow::Owned(entry)).unwrap();
+                            }
+                        }
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        let written = order(&store);
+        let written_latest = latest(&store);
+        drop(store);
+
+        // the order is the same once the file is reopened
+        let store = SqliteStore::new(&path, None).unwrap();
+        assert_eq!(order(&store), written);
+        assert_eq!(latest(&store), written_latest);
+
+        // and after a vacuum rebuilds the file
+        store.range(..2, Some("test_stable_order_a")).unwrap().remove().unwrap();
+        let remaining: Vec<(u64, Entry)> = written
+            .into_iter()
+            .filter(|(_, entry)| !(&*entry.name == "test_stable_order_a" && entry.timestamp < 2))
+            .collect();
+        store.vacuum().unwrap();
+        drop(store);
+        let store = SqliteStore::new(&path, None).unwrap();
+        assert_eq!(order(&store), remaining);
+        assert_eq!(latest(&store), written_latest);
+
+        // and after restoring a backup
+        let backup = files.path();
+        store
+            .pool
+            .get()
+            .unwrap()
+            .execute("vacuum into ?", params![backup.to_str().unwrap()])
+            .unwrap();
+        drop(store);
+        let store = SqliteStore::new(&backup, None).unwrap();
+        assert_eq!(order(&store), remaining);
+        assert_eq!(latest(&store), written_latest);
+    }
+
+    #[test]
+    fn vacuum() {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        let store = SqliteStore::new(&file, None).unwrap();
+        let entries: Vec<Entry> = (0..1000)
+            .map(|i| Entry::new_with_timestamp(i, "test_vacuum", vec![1; 100]))
+            .collect();
+        store.push_bulk_values(&entries).unwrap();
+        assert_eq!(store.deleted_bytes_since_last_vacuum(), 0);
+        store.range(..900, Some("test_vacuum")).unwrap().remove().unwrap();
+        assert!(store.deleted_bytes_since_last_vacuum() > 0);
+        store.vacuum().unwrap();
+        assert_eq!(store.deleted_bytes_since_last_vacuum(), 0);
+        assert_eq!(store.count().unwrap(), 100);
+    }
+
+    #[test]
+    fn auto_vacuum() {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        let config = SqliteStoreConfig {
+            auto_vacuum_threshold_bytes: Some(750),
+            ..SqliteStoreConfig::default()
+        };
+        let store = SqliteStore::new_with_config(&file, config).unwrap();
+        let entries: Vec<Entry> = (0..100)
+            .map(|i| Entry::new_with_timestamp(i, "test_auto_vacuum", vec![1; 10]))
+            .collect();
+        store.push_bulk_values(&entries).unwrap();
+
+        // below the threshold
+        store.range(..50, Some("test_auto_vacuum")).unwrap().remove().unwrap();
+        sleep(Duration::from_millis(50));
+        assert_eq!(store.deleted_bytes_since_last_vacuum(), 500);
+
+        // past the threshold, a vacuum is triggered in the background
+        store.range(..100, Some("test_auto_vacuum")).unwrap().remove().unwrap();
+        let start = Instant::now();
+        while store.deleted_bytes_since_last_vacuum() > 0 {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn pool_monitor() {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        let pool = r2d2::Pool::builder()
+            .max_size(1)
+            .build(r2d2_sqlite::SqliteConnectionManager::file(&file))
+            .unwrap();
+        let config = SqliteStoreConfig {
+            pool_timeout_threshold: Duration::from_millis(50),
+            ..SqliteStoreConfig::default()
+        };
+        let store = SqliteStore::new_with_pool_and_config(pool, config).unwrap();
+        let timeouts = Arc::new(AtomicUsize::new(0));
+        let callback_timeouts = timeouts.clone();
+        store.set_pool_timeout_callback(move || {
+            callback_timeouts.fetch_add(1, Ordering::SeqCst);
+        });
+        let monitor = store.monitor();
+
+        // each thread holds the only connection for a while, so whichever
+        // acquires it second has to wait
+        let threads: Vec<_> = (0..2)
+            .map(|_| {
+                let store = store.clone();
+                std::thread::spawn(move || {
+                    let _conn = store.pool.get().unwrap();
+                    sleep(Duration::from_millis(250));
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let stats = monitor.stats();
+        assert!(stats.exhaustion_count > 0);
+        assert!(stats.max_wait_ms >= 100);
+        assert!(stats.avg_wait_ms > 0.0);
+        assert_eq!(timeouts.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn disk_usage() {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        let wal_len = || std::fs::metadata(format!("{}-wal", file.display())).map_or(0, |metadata| metadata.len());
+        let store = SqliteStore::new(&file, None).unwrap().with_codec(Box::new(NoopCodec));
+        let initial_usage = store.disk_usage().unwrap();
+        assert!(initial_usage > 0);
+
+        let entries: Vec<Entry> = (0..100)
+            .map(|i| Entry::new_with_timestamp(i, "test_disk_usage", vec![i as u8; 1024]))
+            .collect();
+        store.push_bulk_values(&entries).unwrap();
+        assert!(wal_len() > 0);
+        let usage = store.disk_usage().unwrap();
+        assert!(usage >= initial_usage + 100 * 1024);
+        assert!(usage > wal_len());
+
+        store
+            .pool
+            .get()
+            .unwrap()
+            .execute_batch("pragma wal_checkpoint(truncate)")
+            .unwrap();
+        assert_eq!(wal_len(), 0);
+        assert_eq!(store.disk_usage().unwrap(), std::fs::metadata(&file).unwrap().len());
+
+        let store = SqliteStore::new_with_pool(
+            r2d2::Pool::new(r2d2_sqlite::SqliteConnectionManager::memory()).unwrap(),
+            None,
+        )
+        .unwrap();
+        assert!(store.disk_usage().unwrap() > 0);
+    }
+
+    #[test]
+    fn aggregate() {
+        let files = TempFiles::new();
+        let store = SqliteStore::new(files.path(), None).unwrap();
+        for i in 1..=10 {
+            let value = (i as f64 * 1.5).to_le_bytes().to_vec();
+            store
+                .push(Cow::Owned(Entry::new_with_timestamp(i, "test_aggregate_f64", value)))
+                .unwrap();
+            let value = (i - 5).to_le_bytes().to_vec();
+            store
+                .push(Cow::Owned(Entry::new_with_timestamp(i, "test_aggregate_i64", value)))
+                .unwrap();
+        }
+        let aggregate = |range: std::ops::Range<i64>, name: &str, value_type, aggregate| {
+            store.aggregate(range, Some(name), value_type, aggregate).unwrap()
+        };
+
+        for (kind, expected) in [
+            (Aggregate::Min, 1.5),
+            (Aggregate::Max, 15.0),
+            (Aggregate::Sum, 82.5),
+            (Aggregate::Count, 10.0),
+            (Aggregate::Avg, 8.25),
+        ] {
+            let report = aggregate(0..20, "test_aggregate_f64", ValueType::F64, kind);
+            assert_eq!(report.value, Some(expected));
+            assert_eq!(report.rows_aggregated, 10);
+            assert!(report.mismatched.is_empty());
+        }
+        for (kind, expected) in [
+            (Aggregate::Min, -2.0),
+            (Aggregate::Max, 0.0),
+            (Aggregate::Sum, -3.0),
+            (Aggregate::Count, 3.0),
+            (Aggregate::Avg, -1.0),
+        ] {
+            let report = aggregate(3..6, "test_aggregate_i64", ValueType::I64, kind);
+            assert_eq!(report.value, Some(expected));
+        }
+
+        // nothing to aggregate
+        let report = aggregate(20..30, "test_aggregate_f64", ValueType::F64, Aggregate::Avg);
+        assert_eq!(report, AggregateReport::default());
+        let report = aggregate(20..30, "test_aggregate_f64", ValueType::F64, Aggregate::Count);
+        assert_eq!(report.value, Some(0.0));
+
+        // values of the wrong size, or that are compressed, are reported
+        let mut ids = Vec::default();
+        for value in [
+            2.0f64.to_le_bytes().to_vec(),
+            vec![1, 2, 3],
+            vec![0; 64],
+            4.0f64.to_le_bytes().to_vec(),
+        ] {
+            let entry = Entry::new_with_timestamp(1, "test_aggregate_mixed", value);
+            ids.push(store.push_with_id(Cow::Owned(entry)).unwrap());
+        }
+        let report = aggregate(0..20, "test_aggregate_mixed", ValueType::F64, Aggregate::Sum);
+        assert_eq!(report.value, Some(6.0));
+        assert_eq!(report.rows_aggregated, 2);
+        assert_eq!(report.mismatched, vec![ids[1], ids[2]]);
+
+        assert!(matches!(
+            store.aggregate(1..1, None::<String>, ValueType::F64, Aggregate::Sum),
+            Err(Error::BadRange)
+        ));
+    }
+
+    #[test]
+    fn approx_count() {
+        let files = TempFiles::new();
+        let store = SqliteStore::new(files.path(), None).unwrap();
+        assert_eq!(store.approx_count().unwrap(), 0);
+        for i in 0..10 {
+            let entry = Entry::new_with_timestamp(i, "test_approx_count", vec![i as u8]);
+            store.push(Cow::Owned(entry)).unwrap();
+        }
+        assert_eq!(store.approx_count().unwrap(), 10);
+
+        // removing the oldest entries is reflected, but gaps aren't
+        store.range(..3, None::<String>).unwrap().remove().unwrap();
+        assert_eq!(store.approx_count().unwrap(), 7);
+        store.range(4..6, None::<String>).unwrap().remove().unwrap();
+        assert_eq!(store.approx_count().unwrap(), 7);
+        assert_eq!(store.count().unwrap(), 5);
+
+        // a far-off id only ever loosens the bound
+        let entry = Entry::new_with_timestamp(10, "test_approx_count", vec![10]);
+        assert!(store.push_at_id(1_000, Cow::Owned(entry)).unwrap());
+        assert_eq!(store.count().unwrap(), 6);
+        assert_eq!(store.approx_count().unwrap(), 997);
+
+        store.range(.., None::<String>).unwrap().remove().unwrap();
+        assert_eq!(store.approx_count().unwrap(), 0);
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn compression_stats_by_name() {
+        let files = TempFiles::new();
+        let store = SqliteStore::new(files.path(), None).unwrap();
+        assert_eq!(store.compression_stats_by_name().unwrap(), vec![]);
+
+        // xorshift output, which zstd can't compress
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut random = || {
+            (0..1024)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    state as u8
+                })
+                .collect::<Vec<u8>>()
+        };
+        for i in 0..10 {
+            store
+                .push(Cow::Owned(Entry::new_with_timestamp(i, "random", random())))
+                .unwrap();
+            store
+                .push(Cow::Owned(Entry::new_with_timestamp(i, "repeated", vec![7; 1024])))
+                .unwrap();
+        }
+        store
+            .push(Cow::Owned(Entry::new_with_timestamp(0, "small", vec![1, 2, 3])))
+            .unwrap();
+        store
+            .push(Cow::Owned(Entry::new_with_timestamp(0, "empty", vec![])))
+            .unwrap();
+
+        let stats = store.compression_stats_by_name().unwrap();
+        let names: Vec<&str> = stats.iter().map(|stats| &*stats.name).collect();
+        // zstd's framing would make incompressible values slightly bigger,
+        // so they're stored as-is
+        assert_eq!(names, vec!["empty", "random", "small", "repeated"]);
+
+        let (empty, random, small, repeated) = (&stats[0], &stats[1], &stats[2], &stats[3]);
+        assert_eq!(random.entry_count, 10);
+        assert_eq!(random.total_uncompressed_bytes, 10 * 1024);
+        assert_eq!(random.total_stored_bytes, random.total_uncompressed_bytes);
+        assert_eq!(random.ratio, 1.0);
+        assert_eq!(empty.entry_count, 1);
+        assert_eq!(empty.total_uncompressed_bytes, 0);
+        assert_eq!(empty.ratio, 1.0);
+        assert_eq!(small.total_uncompressed_bytes, 3);
+        assert_eq!(small.total_stored_bytes, 3);
+        assert_eq!(small.ratio, 1.0);
+        assert_eq!(repeated.entry_count, 10);
+        assert_eq!(repeated.total_uncompressed_bytes, 10 * 1024);
+        assert!(repeated.total_stored_bytes < repeated.total_uncompressed_bytes / 10);
+        assert_eq!(
+            repeated.ratio,
+            repeated.total_stored_bytes as f64 / repeated.total_uncompressed_bytes as f64
+        );
+    }
+
+    #[test]
+    fn subscribe() {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        let store = SqliteStore::new(&file, None)
+            .unwrap()
+            .with_subscription_poll_interval(Duration::from_millis(5));
+        let push = |store: &SqliteStore, timestamp: i64, name: &str| {
+            store
+                .push(Cow::Owned(Entry::new_with_timestamp(timestamp, name, vec![1])))
+                .unwrap();
+        };
+        push(&store, 1, "test_subscribe");
+        let mut subscription = store.subscribe("test_subscribe").unwrap();
+        assert_eq!(subscription.next(Some(Duration::from_millis(10))).unwrap(), None);
+
+        // every entry is seen, in the order they were pushed, including
+        // entries pushed via other connections to the database
+        let other_store = SqliteStore::new(&file, None).unwrap();
+        push(&store, 3, "test_subscribe");
+        push(&store, 2, "test_subscribe_other");
+        push(&other_store, 2, "test_subscribe");
+        let timestamps: Vec<i64> = (0..2)
+            .map(|_| subscription.next(None).unwrap().unwrap().timestamp)
+            .collect();
+        assert_eq!(timestamps, vec![3, 2]);
+        assert_eq!(subscription.try_next().unwrap(), None);
+
+        let pusher = std::thread::spawn(move || {
+            sleep(Duration::from_millis(20));
+            push(&other_store, 4, "test_subscribe");
+        });
+        let entry = subscription.next(Some(Duration::from_secs(5))).unwrap().unwrap();
+        assert_eq!(entry, Entry::new_with_timestamp(4, "test_subscribe", vec![1]));
+        pusher.join().unwrap();
+    }
+
+    #[test]
+    fn wal_autocheckpoint() {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        let wal_autocheckpoints = |store: &SqliteStore| -> Vec<u32> {
+            // hold several connections at once, so that each is a different
+            // one from the pool
+            let conns: Vec<_> = (0..3).map(|_| store.pool.get().unwrap()).collect();
+            conns
+                .iter()
+                .map(|conn| conn.query_row("pragma wal_autocheckpoint", params![], |row| row.get(0)))
+                .collect::<Result<_, _>>()
+                .unwrap()
+        };
+        let store = SqliteStore::new(&file, None).unwrap();
+        assert_eq!(wal_autocheckpoints(&store), vec![1000; 3]);
+        let config = SqliteStoreConfig {
+            wal_autocheckpoint: Some(10_000),
+            ..SqliteStoreConfig::default()
+        };
+        let store = SqliteStore::new_with_config(&file, config).unwrap();
+        assert_eq!(wal_autocheckpoints(&store), vec![10_000; 3]);
+    }
+
+    #[test]
+    fn pragmas() {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        let config = |pragmas: &[(&str, &str)]| SqliteStoreConfig {
+            pragmas: pragmas
+                .iter()
+                .map(|(pragma, value)| (pragma.to_string(), value.to_string()))
+                .collect(),
+            ..SqliteStoreConfig::default()
+        };
+        let store = SqliteStore::new_with_config(
+            &file,
+            config(&[
+                ("cache_size", "-4000"),
+                ("SYNCHRONOUS", "normal"),
+                ("mmap_size", "1048576"),
+            ]),
+        )
+        .unwrap();
+        // every connection in the pool is configured
+        let conns: Vec<_> = (0..3).map(|_| store.pool.get().unwrap()).collect();
+        for conn in conns.iter() {
+            let pragma = |name: &str| -> i64 { conn.pragma_query_value(None, name, |row| row.get(0)).unwrap() };
+            assert_eq!(pragma("cache_size"), -4000);
+            assert_eq!(pragma("synchronous"), 1);
+            assert_eq!(pragma("mmap_size"), 1_048_576);
+        }
+        drop(conns);
+        store
+            .push(Cow::Owned(Entry::new_with_timestamp(1, "test_pragmas", vec![1])))
+            .unwrap();
+
+        for pragma in ["journal_mode", "locking_mode", "writable_schema"] {
+            assert!(matches!(
+                SqliteStore::new_with_config(&file, config(&[(pragma, "off")])),
+                Err(Error::InvalidConfig(_))
+            ));
+        }
+        let pool = r2d2::Pool::new(r2d2_sqlite::SqliteConnectionManager::memory()).unwrap();
+        assert!(matches!(
+            SqliteStore::new_with_pool_and_config(pool, config(&[("journal_mode", "delete")])),
+            Err(Error::InvalidConfig(_))
+        ));
+    }
+
+    #[test]
+    fn capabilities() {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        let store = SqliteStore::new(&file, None).unwrap();
+        assert!(store.capabilities().contains(Capabilities::DURABLE));
+        assert!(store.capabilities().contains(Capabilities::SUBSCRIBE));
+        #[cfg(feature = "compression")]
+        assert!(store.capabilities().contains(Capabilities::COMPRESSED));
+        let store = store.with_codec(Box::new(NoopCodec));
+        assert!(!store.capabilities().contains(Capabilities::COMPRESSED));
+
+        let pool = r2d2::Pool::new(r2d2_sqlite::SqliteConnectionManager::memory()).unwrap();
+        let store = SqliteStore::new_with_pool(pool, None).unwrap();
+        assert!(!store.capabilities().contains(Capabilities::DURABLE));
+    }
+
+    #[test]
+    fn on_push() {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        let pushed = Arc::new(AtomicUsize::new(0));
+        let pushed_clone = pushed.clone();
+        let store = SqliteStore::new(&file, None)
+            .unwrap()
+            .with_on_push(Arc::new(move |entry: &Entry| {
+                assert_eq!(entry.name.as_ref(), "test_on_push");
+                pushed_clone.fetch_add(1, Ordering::SeqCst);
+            }));
+        let entry = Entry::new_with_timestamp(1, "test_on_push", vec![1]);
+        store.push(Cow::Borrowed(&entry)).unwrap();
+        store.push(Cow::Owned(entry)).unwrap();
+        assert_eq!(pushed.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn push_or_replace() {
+        let files = TempFiles::new();
+        crate::testkit::push_or_replace(SqliteStore::new(files.path(), None).unwrap());
+    }
+
+    #[test]
+    fn health_check() {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        let store = SqliteStore::new_with_config(&file, SqliteStoreConfig::default()).unwrap();
+        for i in 0..1000 {
+            store
+                .push(Cow::Owned(Entry::new_with_timestamp(i, "test_health_check", vec![1])))
+                .unwrap();
+        }
+        store.health_check().unwrap();
+        let handle = store.health_check_async_periodic(Duration::from_millis(10));
+        sleep(Duration::from_millis(50));
+        assert!(handle.is_healthy());
+
+        // Move everything into the main database file, then clobber the pages
+        // after the first.
+        store
+            .pool
+            .get()
+            .unwrap()
+            .execute_batch("pragma wal_checkpoint(truncate)")
+            .unwrap();
+        let mut f = OpenOptions::new().write(true).open(&file).unwrap();
+        f.seek(SeekFrom::Start(4096)).unwrap();
+        f.write_all(&[0xff; 8192]).unwrap();
+        f.sync_all().unwrap();
+
+        sleep(Duration::from_millis(50));
+        assert!(!handle.is_healthy());
+        assert!(store.health_check().is_err());
+        assert!(SqliteStore::new_with_config(&file, SqliteStoreConfig::default()).is_err());
+        let config = SqliteStoreConfig {
+            startup_health_check: false,
+            ..SqliteStoreConfig::default()
+        };
+        assert!(SqliteStore::new_with_config(&file, config).is_ok());
+    }
+
+    struct ReverseCodec;
+
+    impl Codec for ReverseCodec {
+        fn name(&self) -> &str {
+            "reverse"
+        }
+
+        fn compress(&self, value: &[u8]) -> Result<Vec<u8>, Error> {
+            Ok(value.iter().rev().cloned().collect())
+        }
+
+        fn decompress(&self, blob: &[u8], _size: usize) -> Result<Vec<u8>, Error> {
+            Ok(blob.iter().rev().cloned().collect())
+        }
+    }
+
+    fn values(store: &SqliteStore) -> Result<Vec<Vec<u8>>, Error> {
+        store
+            .range(.., Some("test_codecs"))?
+            .iter()?
+            .map(|entry| entry.map(|entry| entry.value))
+            .collect()
+    }
+
+    #[test]
+    fn codecs() {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        let value: Vec<u8> = (0..64).collect();
+        let push = |store: &SqliteStore, timestamp: i64| {
+            let entry = Entry::new_with_timestamp(timestamp, "test_codecs", value.clone());
+            store.push(Cow::Owned(entry)).unwrap();
+        };
+
+        push(&SqliteStore::new(&file, None).unwrap(), 1);
+        push(
+            &SqliteStore::new(&file, None).unwrap().with_codec(Box::new(NoopCodec)),
+            2,
+        );
+        let store = SqliteStore::new(&file, None)
+            .unwrap()
+            .with_codec(Box::new(ReverseCodec));
+        push(&store, 3);
+        assert_eq!(values(&store).unwrap(), vec![value.clone(); 3]);
+
+        // the custom codec is unknown to other stores
+        let store = SqliteStore::new(&file, None).unwrap();
+        assert!(matches!(values(&store), Err(Error::UnsupportedCodec(name)) if name == "reverse"));
+        store.range(3.., Some("test_codecs")).unwrap().remove().unwrap();
+        assert_eq!(values(&store).unwrap(), vec![value; 2]);
+    }
+
+    /// A codec whose blobs can't be decompressed, once `broken` is set.
+    struct FaultyCodec {
+        broken: Arc<AtomicBool>,
+    }
+
+    impl Codec for FaultyCodec {
+        fn name(&self) -> &str {
+            "faulty"
+        }
+
+        fn compress(&self, value: &[u8]) -> Result<Vec<u8>, Error> {
+            Ok(value.to_vec())
+        }
+
+        fn decompress(&self, blob: &[u8], _size: usize) -> Result<Vec<u8>, Error> {
+            if self.broken.load(Ordering::SeqCst) {
+                Ok(blob[1..].to_vec())
+            } else {
+                Ok(blob.to_vec())
+            }
+        }
+    }
+
+    #[test]
+    fn compression_verification() {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        let broken = Arc::new(AtomicBool::new(false));
+        let store = SqliteStore::new(&file, None)
+            .unwrap()
+            .with_codec(Box::new(FaultyCodec { broken: broken.clone() }));
+        let verified_store = store.clone().with_compression_verification(true);
+        let entry =
+            |timestamp: i64, len: usize| Entry::new_with_timestamp(timestamp, "test_verification", vec![1; len]);
+
+        verified_store.push(Cow::Owned(entry(1, 64))).unwrap();
+        store.push(Cow::Owned(entry(2, 64))).unwrap();
+        let report = store.verify_compression(.., Some("test_verification")).unwrap();
+        assert_eq!(report.rows_checked, 2);
+        assert!(report.failures.is_empty());
+
+        broken.store(true, Ordering::SeqCst);
+        assert!(matches!(
+            verified_store.push(Cow::Owned(entry(3, 64))),
+            Err(Error::CompressionRoundTrip(name)) if name == "faulty"
+        ));
+        assert!(matches!(
+            verified_store.push_bulk_values(&[entry(3, 64), entry(4, 64)]),
+            Err(Error::CompressionRoundTrip(_))
+        ));
+        // values too small to compress aren't affected
+        verified_store.push(Cow::Owned(entry(3, 1))).unwrap();
+        store.push(Cow::Owned(entry(4, 64))).unwrap();
+
+        // every bad row is reported
+        let report = store.verify_compression(.., Some("test_verification")).unwrap();
+        assert_eq!(report.rows_checked, 3);
+        assert_eq!(report.failures.len(), 3);
+        assert!(report
+            .failures
+            .iter()
+            .all(|(_, err)| matches!(err, Error::CompressionRoundTrip(_))));
+        let report = store.verify_compression(2..4, Some("test_verification")).unwrap();
+        assert_eq!(report.rows_checked, 1);
+    }
+
+    /// A codec that fails to decompress a value once, as if transiently.
+    struct FlakyCodec {
+        failed: AtomicBool,
+    }
+
+    impl Codec for FlakyCodec {
+        fn name(&self) -> &str {
+            "flaky"
+        }
+
+        fn compress(&self, value: &[u8]) -> Result<Vec<u8>, Error> {
+            Ok(value.to_vec())
+        }
+
+        fn decompress(&self, blob: &[u8], _size: usize) -> Result<Vec<u8>, Error> {
+            if blob[0] == 1 && !self.failed.swap(true, Ordering::SeqCst) {
+                Err(Error::Database("flaky".into()))
+            } else {
+                Ok(blob.to_vec())
+            }
+        }
+    }
+
+    #[test]
+    fn projections_skip_values() {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        let store = SqliteStore::new(&file, None).unwrap().with_codec(Box::new(FaultyCodec {
+            broken: Arc::new(AtomicBool::new(false)),
+        }));
+        let entries: Vec<Entry> = (0..2500)
+            .map(|i| Entry::new_with_timestamp(i / 2, "test_projections_skip_values", vec![1; 32]))
+            .collect();
+        store.push_bulk_values(&entries).unwrap();
+
+        // values can't be decoded without the codec they were written with,
+        // but projections never decode them
+        let store = SqliteStore::new(&file, None).unwrap();
+        let range = || store.range(.., Some("test_projections_skip_values")).unwrap();
+        assert!(matches!(
+            range().iter().unwrap().next(),
+            Some(Err(Error::UnsupportedCodec(_)))
+        ));
+        let timestamps: Vec<i64> = range().iter_timestamps().unwrap().collect::<Result<_, _>>().unwrap();
+        assert_eq!(
+            timestamps,
+            entries.iter().map(|entry| entry.timestamp).collect::<Vec<i64>>()
+        );
+        let names_and_timestamps: Vec<(Atom, i64)> = range()
+            .iter_names_and_timestamps()
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(names_and_timestamps.len(), 2500);
+        assert!(range().exists().unwrap());
+    }
+
+    #[test]
+    fn iter_retries_failed_pages() {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        let store = SqliteStore::new(&file, None).unwrap().with_codec(Box::new(FlakyCodec {
+            failed: AtomicBool::new(false),
+        }));
+        // the flaky value is in the middle of the second page
+        let entries: Vec<Entry> = (0..2500)
+            .map(|i| Entry::new_with_timestamp(i, "test_iter_retries", vec![(i == 1500) as u8; 32]))
+            .collect();
+        store.push_bulk_values(&entries).unwrap();
+
+        let mut iter = store.range(.., Some("test_iter_retries")).unwrap().iter().unwrap();
+        let mut timestamps = Vec::default();
+        let mut errors = 0;
+        for result in iter.by_ref() {
+            match result {
+                Ok(entry) => timestamps.push(entry.timestamp),
+                Err(_) => errors += 1,
+            }
+        }
+        assert_eq!(errors, 1);
+        assert_eq!(timestamps, (0..2500).collect::<Vec<i64>>());
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn iter_gives_up_on_persistent_failures() {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        let pool = r2d2::Pool::builder()
+            .max_size(1)
+            .connection_timeout(Duration::from_millis(50))
+            .build(r2d2_sqlite::SqliteConnectionManager::file(&file))
+            .unwrap();
+        let store = SqliteStore::new_with_pool(pool, None).unwrap();
+        let entries: Vec<Entry> = (0..2500)
+            .map(|i| Entry::new_with_timestamp(i, "test_iter_gives_up", vec![]))
+            .collect();
+        store.push_bulk_values(&entries).unwrap();
+
+        // after the first page is read, hold the only connection so every
+        // later page fails
+        let range = || store.range(.., Some("test_iter_gives_up")).unwrap();
+        let check = |mut iter: Box<dyn Iterator<Item = Result<i64, Error>>>| {
+            let first: Vec<i64> = iter.by_ref().take(1).collect::<Result<_, _>>().unwrap();
+            assert_eq!(first, vec![0]);
+            let _conn = store.pool.get().unwrap();
+            let mut timestamps = first;
+            let mut errors = 0;
+            for result in iter.by_ref() {
+                match result {
+                    Ok(timestamp) => timestamps.push(timestamp),
+                    Err(err) => {
+                        assert!(matches!(err, Error::Database(_)));
+                        errors += 1;
+                    }
+                }
+            }
+            assert_eq!(errors, 3);
+            assert_eq!(timestamps, (0..1000).collect::<Vec<i64>>());
+            assert!(iter.next().is_none());
+        };
+        check(Box::new(
+            range().iter().unwrap().map(|entry| entry.map(|entry| entry.timestamp)),
+        ));
+        check(Box::new(range().iter_timestamps().unwrap()));
+
+        // an error resets once a page is read
+        let mut iter = range().iter().unwrap();
+        iter.by_ref().take(1000).for_each(|entry| drop(entry.unwrap()));
+        {
+            let _conn = store.pool.get().unwrap();
+            assert!(iter.next().unwrap().is_err());
+            assert!(iter.next().unwrap().is_err());
+        }
+        assert_eq!(iter.count(), 1500);
+    }
+
+    #[test]
+    fn uncompressed_reads() {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        let value: Vec<u8> = (0..64).collect();
+        let store = SqliteStore::new(&file, None).unwrap().with_codec(Box::new(NoopCodec));
+        store
+            .push(Cow::Owned(Entry::new_with_timestamp(1, "test_codecs", value.clone())))
+            .unwrap();
+
+        // uncompressed rows are readable regardless of features
+        let store = SqliteStore::new(&file, None).unwrap();
+        assert_eq!(values(&store).unwrap(), vec![value.clone()]);
+
+        // compressed rows are only readable with the compression feature
+        #[cfg(feature = "compression")]
+        let blob = ZstdCodec::default().compress(&value).unwrap();
+        #[cfg(not(feature = "compression"))]
+        let blob = vec![0u8; 8];
+        let conn = Connection::open(&file).unwrap();
+        conn.execute(
+            "insert into log (ts, name, size, value, codec) values (?, ?, ?, ?, ?)",
+            params![2, "test_codecs", value.len(), blob, "zstd"],
+        )
+        .unwrap();
+        let result = values(&store);
+        #[cfg(feature = "compression")]
+        assert_eq!(result.unwrap(), vec![value.clone(); 2]);
+        #[cfg(not(feature = "compression"))]
+        assert!(matches!(result, Err(Error::UnsupportedCodec(name)) if name == "zstd"));
+    }
+
+    #[test]
+    fn encoded_values_interchangeable() {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        let store = SqliteStore::new(&file, None).unwrap();
+        let value: Vec<u8> = (0..64).collect();
+        store
+            .push(Cow::Owned(Entry::new_with_timestamp(1, "test_codecs", value.clone())))
+            .unwrap();
+
+        // blobs written by the store decode with the public helpers, and
+        // vice versa
+        let conn = Connection::open(&file).unwrap();
+        let (size, blob, codec_name): (usize, Vec<u8>, String) = conn
+            .query_row("select size, value, codec from log", [], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })
+            .unwrap();
+        assert_eq!(
+            crate::codec::decode_value(store.codec.as_ref(), &codec_name, size, blob).unwrap(),
+            value
+        );
+        let (blob, size, codec_name) = crate::codec::encode_value(store.codec.as_ref(), &value).unwrap();
+        conn.execute(
+            "insert into log (ts, name, size, value, codec) values (?, ?, ?, ?, ?)",
+            params![2, "test_codecs", size, blob, codec_name],
+        )
+        .unwrap();
+        assert_eq!(values(&store).unwrap(), vec![value.clone(); 2]);
+    }
+
+    #[test]
+    fn properties() {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        let start = crate::entry::now_micros();
+        let store = SqliteStore::new(&file, None).unwrap();
+        assert_eq!(store.get_property("owner").unwrap(), None);
+        store.set_property("owner", "a").unwrap();
+        store.set_property("owner", "b").unwrap();
+        store.set_property("purpose", "tests").unwrap();
+        let info = store.info().unwrap();
+        assert!(info.created_at >= start && info.created_at <= crate::entry::now_micros());
+        assert_eq!(info.created_by, env!("CARGO_PKG_VERSION"));
+        assert_eq!(info.schema_version as usize, super::MIGRATIONS.len());
+        drop(store);
+
+        // properties and the creation time persist across reopens
+        let store = SqliteStore::new(&file, None).unwrap();
+        assert_eq!(store.get_property("owner").unwrap(), Some("b".to_string()));
+        let reopened_info = store.info().unwrap();
+        assert_eq!(reopened_info, info);
+        assert_eq!(
+            reopened_info.properties.into_iter().collect::<Vec<_>>(),
+            vec![
+                ("owner".to_string(), "b".to_string()),
+                ("purpose".to_string(), "tests".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn latest_uses_name_index() {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        let store = SqliteStore::new(&file, None).unwrap();
+        let conn = store.pool.get().unwrap();
+        for ignore_skewed in [false, true] {
+            let mut stmt = conn
+                .prepare(&format!(
+                    "explain query plan {}",
+                    super::latest_statement(ignore_skewed)
+                ))
+                .unwrap();
+            let plan: Vec<String> = stmt
+                .query_map(params!["test_latest_uses_name_index"], |row| row.get(3))
+                .unwrap()
+                .collect::<Result<Vec<String>, _>>()
+                .unwrap();
+            assert_eq!(
+                plan,
+                vec!["SEARCH log USING INDEX idx_log_name_ts (name=?)".to_string()]
+            );
+        }
+    }
+
+    #[cfg(feature = "compression")]
+    #[test]
+    fn migrate_legacy_schema() {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        let value: Vec<u8> = (0..64).collect();
+        {
+            let conn = Connection::open(&file).unwrap();
+            conn.execute(
+                "create table log (id integer primary key, ts integer not null, name text not null, size integer not null, value blob not null)",
+                params![],
+            )
+            .unwrap();
+            let mut stmt = conn
+                .prepare("insert into log (ts, name, size, value) values (?, ?, ?, ?)")
+                .unwrap();
+            let blob = ZstdCodec::default().compress(&value).unwrap();
+            stmt.execute(params![1, "test_codecs", value.len(), blob]).unwrap();
+            stmt.execute(params![2, "test_codecs", 0, vec![1u8]]).unwrap();
+        }
+
+        let store = SqliteStore::new(&file, None).unwrap();
+        assert_eq!(values(&store).unwrap(), vec![value, vec![1]]);
+        let sequence_numbers: Vec<Option<u64>> = store
+            .range(.., Some("test_codecs"))
+            .unwrap()
+            .iter()
+            .unwrap()
+            .map(|entry| entry.unwrap().sequence_number)
+            .collect();
+        assert_eq!(sequence_numbers, vec![Some(1), Some(2)]);
+        // reopening doesn't re-run migrations
+        let created_at = store.info().unwrap().created_at;
+        let store = SqliteStore::new(&file, None).unwrap();
+        assert_eq!(values(&store).unwrap().len(), 2);
+        assert_eq!(store.info().unwrap().created_at, created_at);
+    }
+
+    fn mean_per_second(entries: &[Entry]) -> Vec<Entry> {
+        let mut rollups: Vec<(i64, u64, u64)> = Vec::new();
+        for entry in entries {
+            let second = entry.timestamp.div_euclid(1_000_000) * 1_000_000;
+            match rollups.last_mut() {
+                Some((last_second, sum, count)) if *last_second == second => {
+                    *sum += entry.value[0] as u64;
+                    *count += 1;
+                }
+                _ => rollups.push((second, entry.value[0] as u64, 1)),
+            }
+        }
+        rollups
+            .into_iter()
+            .map(|(second, sum, count)| {
+                Entry::new_with_timestamp(second, entries[0].name.clone(), vec![(sum / count) as u8])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn compact() {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        let store = SqliteStore::new(&file, None).unwrap();
+        // 1khz for 3 seconds, with a mean of 4.5 (truncated to 4) each second
+        for i in 0..3000 {
+            let entry = Entry::new_with_timestamp(i * 1000, "test_compact", vec![(i % 10) as u8]);
+            store.push(Cow::Owned(entry)).unwrap();
+        }
+        let other = Entry::new_with_timestamp(1, "test_compact_other", vec![1]);
+        store.push(Cow::Borrowed(&other)).unwrap();
+
+        // compact the first second
+        let report = store
+            .compact(..1_000_000, Some("test_compact"), mean_per_second)
+            .unwrap();
+        assert_eq!(
+            report,
+            CompactionReport {
+                rows_read: 1000,
+                rows_written: 1,
+                rows_deleted: 1000,
+            }
+        );
+
+        // simulate a crash while compacting the second second
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            store.compact(
+                1_000_000..2_000_000,
+                Some("test_compact"),
+                |_: &[Entry]| -> Vec<Entry> { panic!("crash") },
+            )
+        }));
+        assert!(result.is_err());
+        assert_eq!(
+            store
+                .range(1_000_000..2_000_000, Some("test_compact"))
+                .unwrap()
+                .count()
+                .unwrap(),
+            1000
+        );
+
+        // resume compacting the remaining seconds
+        let report = store
+            .compact(1_000_000.., Some("test_compact"), mean_per_second)
+            .unwrap();
+        assert_eq!(report.rows_read, 2000);
+        assert_eq!(report.rows_written, 2);
+
+        let entries: Vec<Entry> = store
+            .range(.., Some("test_compact"))
+            .unwrap()
+            .iter()
+            .unwrap()
+            .collect::<Result<Vec<Entry>, _>>()
+            .unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                Entry::new_with_timestamp(0, "test_compact", vec![4]),
+                Entry::new_with_timestamp(1_000_000, "test_compact", vec![4]),
+                Entry::new_with_timestamp(2_000_000, "test_compact", vec![4]),
+            ]
+        );
+        assert_eq!(store.latest("test_compact_other").unwrap(), Some(other));
+    }
+
+    #[test]
+    fn push_bulk_values() {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        let config = SqliteStoreConfig {
+            max_rows_per_statement: 7,
+            ..SqliteStoreConfig::default()
+        };
+        let store = SqliteStore::new_with_config(&file, config).unwrap();
+        let entries: Vec<Entry> = (0..100u8)
+            .map(|i| Entry::new_with_timestamp(i.into(), "test_push_bulk_values", vec![i; i as usize]))
+            .collect();
+        store.push_bulk_values(&[]).unwrap();
+        store.push_bulk_values(&entries[..1]).unwrap();
+        store.push_bulk_values(&entries[1..]).unwrap();
+        let results: Vec<Entry> = store
+            .range(.., Some("test_push_bulk_values"))
+            .unwrap()
+            .iter()
+            .unwrap()
+            .collect::<Result<Vec<Entry>, _>>()
+            .unwrap();
+        assert_eq!(results, entries);
+    }
+
+    #[test]
+    fn remove_ids() {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        let store = SqliteStore::new(&file, None).unwrap();
+        let entries: Vec<Entry> = (0..10)
+            .map(|i| Entry::new_with_timestamp(i, "test_remove_ids", vec![1]))
+            .collect();
+        store.push_bulk_values(&entries).unwrap();
+        let ids: Vec<u64> = store
+            .range(.., Some("test_remove_ids"))
+            .unwrap()
+            .iter_with_metadata()
+            .unwrap()
+            .map(|stored_entry| stored_entry.unwrap().id)
+            .collect();
+        assert_eq!(store.remove_ids(&[]).unwrap(), 0);
+
+        // spans multiple statements, and includes unknown ids
+        let mut removeable_ids: Vec<u64> = ids.iter().step_by(2).copied().collect();
+        removeable_ids.extend(1_000_000..1_002_000);
+        removeable_ids.extend([i64::MAX as u64 + 1, u64::MAX]);
+        assert_eq!(store.remove_ids(&removeable_ids).unwrap(), 5);
+        assert_eq!(store.remove_ids(&removeable_ids).unwrap(), 0);
+        let timestamps: Vec<i64> = store
+            .range(.., Some("test_remove_ids"))
+            .unwrap()
+            .iter()
+            .unwrap()
+            .map(|entry| entry.unwrap().timestamp)
+            .collect();
+        assert_eq!(timestamps, vec![1, 3, 5, 7, 9]);
+        assert_eq!(store.deleted_bytes_since_last_vacuum(), 5);
+    }
+
+    #[test]
+    fn drain_all_without_returning() {
+        let files = TempFiles::new();
+        let mut store = SqliteStore::new(files.path(), None).unwrap();
+        store.sqlite_version = 3_034_000;
+        crate::testkit::drain_all(&store);
+    }
+
+    #[test]
+    fn push_with_id() {
+        let files = TempFiles::new();
+        for sqlite_version in [rusqlite::version_number(), 3_034_000] {
+            let mut store = SqliteStore::new(files.path(), None).unwrap();
+            store.sqlite_version = sqlite_version;
+            let store = Arc::new(store);
+            let handles: Vec<_> = (0..4u8)
+                .map(|thread| {
+                    let store = store.clone();
+                    std::thread::spawn(move || {
+                        (0..50)
+                            .map(|i| {
+                                let entry = Entry::new_with_timestamp(i, "test_push_with_id", vec![thread, i as u8]);
+                                let id = store.push_with_id(Cow::Borrowed(&entry)).unwrap();
+                                (id, entry)
+                            })
+                            .collect::<Vec<(u64, Entry)>>()
+                    })
+                })
+                .collect();
+            let pushed: Vec<(u64, Entry)> = handles.into_iter().flat_map(|h| h.join().unwrap()).collect();
+
+            let mut ids: Vec<u64> = pushed.iter().map(|(id, _)| *id).collect();
+            ids.sort_unstable();
+            ids.dedup();
+            assert_eq!(ids.len(), 200);
+            for (id, entry) in pushed {
+                let entries: Vec<Entry> = store
+                    .range_ids(id..=id, Some("test_push_with_id"))
+                    .unwrap()
+                    .iter()
+                    .unwrap()
+                    .map(Result::unwrap)
+                    .collect();
+                assert_eq!(entries, vec![entry]);
+            }
+            store.range(.., Some("test_push_with_id")).unwrap().remove().unwrap();
+        }
+    }
+
+    #[test]
+    fn range_ids() {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        let store = SqliteStore::new(&file, None).unwrap();
+        let batch = |value: u8| -> Vec<Entry> {
+            (0..10)
+                .map(|i| Entry::new_with_timestamp(i, "test_range_ids", vec![value]))
+                .collect()
+        };
+        let ids = |range: SqliteRange| -> Vec<u64> {
+            range
+                .iter_with_metadata()
+                .unwrap()
+                .map(|stored_entry| stored_entry.unwrap().id)
+                .collect()
+        };
+        store.push_bulk_values(&batch(1)).unwrap();
+        let good_ids = ids(store.range(.., Some("test_range_ids")).unwrap());
+        store.push_bulk_values(&batch(2)).unwrap();
+        let bad_ids: Vec<u64> = ids(store.range(.., Some("test_range_ids")).unwrap())
+            .into_iter()
+            .filter(|id| !good_ids.contains(id))
+            .collect();
+        let (first, last) = (bad_ids[0], bad_ids[9]);
+
+        let bad_range = store.range_ids(first..=last, Some("test_range_ids")).unwrap();
+        assert_eq!(bad_range.count().unwrap(), 10);
+        assert!(!bad_range.is_empty().unwrap());
+        let values: Vec<Entry> = bad_range.iter().unwrap().map(Result::unwrap).collect();
+        assert!(values.iter().all(|entry| entry.value == vec![2]));
+        assert!(store
+            .range_ids(last + 1.., Some("test_range_ids"))
+            .unwrap()
+            .is_empty()
+            .unwrap());
+        assert_eq!(
+            store.range_ids(..first, Option::<Atom>::None).unwrap().count().unwrap(),
+            10
+        );
+        assert_eq!(
+            store
+                .range_ids(first..=u64::MAX, Some("test_range_ids"))
+                .unwrap()
+                .count()
+                .unwrap(),
+            10
+        );
+        assert!(matches!(
+            store.range_ids(last..first, Some("test_range_ids")),
+            Err(Error::BadRange)
+        ));
+
+        store
+            .range_ids(first..=last, Some("test_range_ids"))
+            .unwrap()
+            .remove()
+            .unwrap();
+        let entries: Vec<Entry> = store
+            .range(.., Some("test_range_ids"))
+            .unwrap()
+            .iter()
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(entries, batch(1));
+        assert_eq!(ids(store.range(.., Some("test_range_ids")).unwrap()), good_ids);
+    }
+
+    #[test]
+    fn snapshot() {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        let store = SqliteStore::new(&file, None).unwrap();
+        for i in 0..10 {
+            store
+                .push(Cow::Owned(Entry::new_with_timestamp(i, "test_snapshot", vec![1])))
+                .unwrap();
+        }
+
+        let snapshot = store.snapshot().unwrap();
+        for i in 10..110 {
+            store
+                .push(Cow::Owned(Entry::new_with_timestamp(i, "test_snapshot", vec![2])))
+                .unwrap();
+        }
+
+        assert_eq!(store.range(.., Some("test_snapshot")).unwrap().count().unwrap(), 110);
+        assert_eq!(snapshot.range(.., Some("test_snapshot")).unwrap().count().unwrap(), 10);
+        assert_eq!(snapshot.range(5.., Option::<Atom>::None).unwrap().count().unwrap(), 5);
+        assert!(snapshot.range(11.., Option::<Atom>::None).unwrap().is_empty().unwrap());
+        let entries: Vec<Entry> = snapshot
+            .range(.., Some("test_snapshot"))
+            .unwrap()
+            .iter()
+            .unwrap()
+            .collect::<Result<Vec<Entry>, _>>()
+            .unwrap();
+        assert_eq!(entries.len(), 10);
+        assert_eq!(
+            snapshot.latest("test_snapshot").unwrap(),
+            Some(Entry::new_with_timestamp(9, "test_snapshot", vec![1]))
+        );
+
+        drop(snapshot);
+        let snapshot = store.snapshot().unwrap();
+        assert_eq!(snapshot.range(.., Some("test_snapshot")).unwrap().count().unwrap(), 110);
+    }
+
+    #[test]
+    fn snapshot_max_age() {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        let store = SqliteStore::new(&file, None)
+            .unwrap()
+            .with_max_snapshot_age(Duration::from_millis(1));
+        let snapshot = store.snapshot().unwrap();
+        sleep(Duration::from_millis(10));
+        assert!(snapshot.latest("test_snapshot_max_age").is_err());
+        assert!(snapshot
+            .range(.., Some("test_snapshot_max_age"))
+            .unwrap()
+            .count()
+            .is_err());
+        // the expired snapshot gave its connection back, without being
+        // dropped
+        let state = store.pool.state();
+        assert_eq!(state.connections, state.idle_connections);
+        store
+            .push(Cow::Owned(Entry::new_with_timestamp(
+                1,
+                "test_snapshot_max_age",
+                vec![1],
+            )))
+            .unwrap();
+        assert!(snapshot.latest("test_snapshot_max_age").is_err());
+    }
+    #[cfg(feature = "sea-query")]
+    mod sea_query {
+        use std::ops::Bound;
+
+        use crate::testkit::TempFiles;
+        use crate::{test_rangeable_store_impl, test_store_impl, Entry, OrderBy, Range, RangeableStore, SqliteStore};
+
+        test_store_impl!(|files: &TempFiles| SqliteStore::new_with_sea_query(files.path(), None).unwrap());
+        test_rangeable_store_impl!(|files: &TempFiles| SqliteStore::new_with_sea_query(files.path(), None).unwrap());
+
+        #[test]
+        fn same_results() {
+            let files = TempFiles::new();
+            let path = files.path();
+            let store = SqliteStore::new(&path, None).unwrap();
+            let sea_query_store = SqliteStore::new_with_sea_query(&path, None).unwrap();
+            let entries: Vec<Entry> = (0..2500)
+                .map(|i| Entry::new_with_timestamp(i / 3, format!("test_same_results_{}", i % 3), vec![i as u8]))
+                .collect();
+            store.push_bulk_values(&entries).unwrap();
+
+            let bounds = [
+                (Bound::Unbounded, Bound::Unbounded),
+                (Bound::Included(10), Bound::Excluded(700)),
+                (Bound::Excluded(10), Bound::Included(700)),
+                (Bound::Included(900), Bound::Unbounded),
+            ];
+            for bounds in bounds {
+                for name in [None, Some("test_same_results_1"), Some("test_same_results_missing")] {
+                    let read = |store: &SqliteStore, order: OrderBy| {
+                        let range = store.range(bounds, name).unwrap().order_by(order);
+                        let (count, is_empty) = (range.count().unwrap(), range.is_empty().unwrap());
+                        let entries: Vec<Entry> = range.iter().unwrap().map(Result::unwrap).collect();
+                        let keys: Vec<_> = store
+                            .range(bounds, name)
+                            .unwrap()
+                            .order_by(order)
+                            .iter_names_and_timestamps()
+                            .unwrap()
+                            .map(Result::unwrap)
+                            .collect();
+                        let pages: Vec<Vec<Entry>> =
+                            store.paginate(bounds, name, 400).unwrap().map(Result::unwrap).collect();
+                        (count, is_empty, entries, keys, pages)
+                    };
+                    for order in [OrderBy::Time, OrderBy::NameThenTime] {
+                        assert!(read(&store, order) == read(&sea_query_store, order));
+                    }
+                }
+            }
+
+            let ids = |store: &SqliteStore| -> Vec<Entry> {
+                let range = store.range_ids(5..=20, Some("test_same_results_2")).unwrap();
+                range.iter().unwrap().map(Result::unwrap).collect()
+            };
+            assert_eq!(ids(&store), ids(&sea_query_store));
+            assert_eq!(ids(&store).len(), 5);
+        }
+    }
+
+    mod latest_cache {
+        use std::borrow::Cow;
+        use std::thread::sleep;
+        use std::time::Duration;
+
+        use crate::testkit::TempFiles;
+        use crate::{
+            test_rangeable_store_impl, test_store_impl, Entry, Range, RangeableStore, SqliteStore, SqliteStoreConfig,
+            Store,
+        };
+
+        fn cached_store<P: AsRef<std::path::Path>>(path: P, ttl: Duration) -> SqliteStore {
+            let config = SqliteStoreConfig {
+                latest_cache_ttl: Some(ttl),
+                ..SqliteStoreConfig::default()
+            };
+            SqliteStore::new_with_config(path, config).unwrap()
+        }
+
+        test_store_impl!(|files: &TempFiles| cached_store(files.path(), Duration::from_secs(60)));
+        test_rangeable_store_impl!(|files: &TempFiles| cached_store(files.path(), Duration::from_secs(60)));
+
+        fn push(store: &SqliteStore, timestamp: i64, name: &str) {
+            let entry = Entry::new_with_timestamp(timestamp, name, vec![timestamp as u8]);
+            store.push(Cow::Owned(entry)).unwrap();
+        }
+
+        fn latest_timestamp(store: &SqliteStore, name: &str) -> Option<i64> {
+            store.latest(name).unwrap().map(|entry| entry.timestamp)
+        }
+
+        #[test]
+        fn invalidated_on_push() {
+            let files = TempFiles::new();
+            let store = cached_store(files.path(), Duration::from_secs(60));
+            let clone = store.clone();
+            let name = "test_latest_cache_invalidated_on_push";
+            assert_eq!(latest_timestamp(&store, name), None);
+            for timestamp in (1..10).step_by(2) {
+                push(&store, timestamp, name);
+                assert_eq!(latest_timestamp(&store, name), Some(timestamp));
+                // clones share the cache, and see pushes through each other
+                push(&clone, timestamp + 1, name);
+                assert_eq!(latest_timestamp(&store, name), Some(timestamp + 1));
+                assert_eq!(latest_timestamp(&clone, name), Some(timestamp + 1));
+            }
+            store
+                .push_bulk_values(&[
+                    Entry::new_with_timestamp(20, name, vec![1]),
+                    Entry::new_with_timestamp(21, name, vec![2]),
+                ])
+                .unwrap();
+            assert_eq!(latest_timestamp(&clone, name), Some(21));
+        }
+
+        #[test]
+        fn invalidated_on_remove() {
+            let files = TempFiles::new();
+            let store = cached_store(files.path(), Duration::from_secs(60));
+            let (a, b) = (
+                "test_latest_cache_invalidated_on_remove_a",
+                "test_latest_cache_invalidated_on_remove_b",
+            );
+            for timestamp in 1..5 {
+                push(&store, timestamp, a);
+                push(&store, timestamp, b);
+            }
+            assert_eq!(latest_timestamp(&store, a), Some(4));
+            assert_eq!(latest_timestamp(&store, b), Some(4));
+            store.range(3.., Some(a)).unwrap().remove().unwrap();
+            assert_eq!(latest_timestamp(&store, a), Some(2));
+            store.range(2.., None::<&str>).unwrap().remove().unwrap();
+            assert_eq!(latest_timestamp(&store, a), Some(1));
+            assert_eq!(latest_timestamp(&store, b), Some(1));
+            store.rename(a, "test_latest_cache_invalidated_on_remove_c").unwrap();
+            assert_eq!(latest_timestamp(&store, a), None);
+        }
+
+        #[test]
+        fn expires() {
+            let files = TempFiles::new();
+            let path = files.path();
+            let store = cached_store(&path, Duration::from_millis(100));
+            // writes from another process aren't seen until the cache expires
+            let other = SqliteStore::new(&path, None).unwrap();
+            let name = "test_latest_cache_expires";
+            push(&store, 1, name);
+            assert_eq!(latest_timestamp(&store, name), Some(1));
+            push(&other, 2, name);
+            assert_eq!(latest_timestamp(&store, name), Some(1));
+            sleep(Duration::from_millis(150));
+            assert_eq!(latest_timestamp(&store, name), Some(2));
+        }
+
+        #[test]
+        fn evicts_expired() {
+            let files = TempFiles::new();
+            let store = cached_store(files.path(), Duration::from_millis(100));
+            let cached = |store: &SqliteStore| {
+                let cache = store.latest_cache.as_ref().unwrap();
+                cache.inner.read().unwrap().entries.len()
+            };
+            for name in [
+                "test_latest_cache_evicts_expired_a",
+                "test_latest_cache_evicts_expired_b",
+            ] {
+                push(&store, 1, name);
+                assert_eq!(latest_timestamp(&store, name), Some(1));
+            }
+            assert_eq!(cached(&store), 2);
+            // names that aren't read again are dropped on a later insert
+            sleep(Duration::from_millis(150));
+            assert_eq!(latest_timestamp(&store, "test_latest_cache_evicts_expired_c"), None);
+            assert_eq!(cached(&store), 1);
+        }
+    }
+
+    mod thread_mode {
+        use std::borrow::Cow;
+        use std::time::Duration;
+
+        use crate::testkit::TempFiles;
+        use crate::{
+            test_rangeable_store_impl, test_store_impl, Entry, SqliteStore, SqliteStoreConfig, Store,
+            SubscribeableStore, Subscription, ThreadMode,
+        };
+
+        fn store_with_thread_mode<P: AsRef<std::path::Path>>(path: P, thread_mode: ThreadMode) -> SqliteStore {
+            let config = SqliteStoreConfig {
+                thread_mode,
+                ..SqliteStoreConfig::default()
+            };
+            SqliteStore::new_with_config(path, config).unwrap()
+        }
+
+        // with a single connection, anything holding onto one while getting
+        // another would deadlock
+        test_store_impl!(|files: &TempFiles| store_with_thread_mode(files.path(), ThreadMode::SingleThread));
+        test_rangeable_store_impl!(|files: &TempFiles| store_with_thread_mode(files.path(), ThreadMode::SingleThread));
+
+        #[test]
+        fn single_thread_subscribe() {
+            let files = TempFiles::new();
+            let store = store_with_thread_mode(files.path(), ThreadMode::SingleThread)
+                .with_subscription_poll_interval(Duration::from_millis(5));
+            let mut subscription = store.subscribe("test_single_thread_subscribe").unwrap();
+            let pusher = {
+                let store = store.clone();
+                std::thread::spawn(move || {
+                    for timestamp in 1..=3 {
+                        let entry = Entry::new_with_timestamp(timestamp, "test_single_thread_subscribe", vec![1]);
+                        store.push(Cow::Owned(entry)).unwrap();
+                    }
+                })
+            };
+            let timestamps: Vec<i64> = (0..3)
+                .map(|_| {
+                    let entry = subscription.next(Some(Duration::from_secs(5))).unwrap().unwrap();
+                    entry.timestamp
+                })
+                .collect();
+            assert_eq!(timestamps, vec![1, 2, 3]);
+            pusher.join().unwrap();
+        }
+
+        #[test]
+        fn sets_connection_mutex() {
+            for (thread_mode, has_mutex, max_size) in [
+                (ThreadMode::Serialized, true, 10),
+                (ThreadMode::MultiThread, false, 10),
+                (ThreadMode::SingleThread, false, 1),
+            ] {
+                let files = TempFiles::new();
+                let store = store_with_thread_mode(files.path(), thread_mode);
+                let pool = store.pool.current();
+                assert_eq!(pool.max_size(), max_size);
+                let conn = pool.get().unwrap();
+                let mutex = unsafe { rusqlite::ffi::sqlite3_db_mutex(conn.handle()) };
+                assert_eq!(!mutex.is_null(), has_mutex, "{:?}", thread_mode);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "benches")]
+mod benches {
+    use std::borrow::Cow;
+
+    use crate::testkit::TempFiles;
+    use crate::{
+        bench_rangeable_store_impl, bench_store_impl, define_bench, Entry, Range, RangeableStore, SqliteStore,
+        SqliteStoreConfig, Store, ThreadMode,
+    };
+    use string_cache::DefaultAtom as Atom;
+    use tempfile::NamedTempFile;
+    use test::Bencher;
+    bench_store_impl!(|files: &TempFiles| SqliteStore::new(files.path(), None).unwrap());
+
+    #[test]
+    fn push_parts_allocations() {
+        let files = TempFiles::new();
+        let store = SqliteStore::new(files.path(), None).unwrap();
+        // The value isn't copied, which saves one of the two allocations of
+        // pushing an `Entry` built from the same buffer. The other is the
+        // key rusqlite 0.27 allocates for every `prepare_cached`, even when
+        // the statement is already cached.
+        assert_eq!(crate::benches::push_parts_allocations(&store), 1.0);
+    }
+    bench_rangeable_store_impl!(|files: &TempFiles| SqliteStore::new(files.path(), None).unwrap());
+
+    fn bench_push_bulk_values(b: &mut Bencher, max_rows_per_statement: usize) {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        let config = SqliteStoreConfig {
+            max_rows_per_statement,
+            ..SqliteStoreConfig::default()
+        };
+        let store = SqliteStore::new_with_config(&file, config).unwrap();
+        let entries: Vec<Entry> = (0..10_000)
+            .map(|i| Entry::new_with_timestamp(i, "bench_push_bulk_values", vec![1, 2, 3]))
+            .collect();
+        b.iter(|| {
+            store.push_bulk_values(&entries).unwrap();
+            // Keep the database from growing across iterations
+            store.range(.., Option::<Atom>::None).unwrap().remove().unwrap();
+        });
+    }
+
+    // Inserting 10,000 entries in a single transaction (and then removing
+    // them), with one row per statement vs. the default of 199
+    #[bench]
+    fn push_bulk_values_loop(b: &mut Bencher) {
+        bench_push_bulk_values(b, 1);
+    }
+
+    #[bench]
+    fn push_bulk_values_multi(b: &mut Bencher) {
+        bench_push_bulk_values(b, SqliteStoreConfig::default().max_rows_per_statement);
+    }
+
+    fn bench_push_compressed(b: &mut Bencher, verify: bool) {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        let store = SqliteStore::new(&file, None)
+            .unwrap()
+            .with_compression_verification(verify);
+        let entries: Vec<Entry> = (0..1000)
+            .map(|i| Entry::new_with_timestamp(i, "bench_push_compressed", vec![(i % 256) as u8; 4096]))
+            .collect();
+        b.iter(|| {
+            store.push_bulk_values(&entries).unwrap();
+            store.range(.., Option::<Atom>::None).unwrap().remove().unwrap();
+        });
+    }
+
+    // `latest` for one of 100 names among 1,000,000 entries, and for a name
+    // with a single, old entry
+    #[bench]
+    fn latest_among_names(b: &mut Bencher) {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        let store = SqliteStore::new(&file, None).unwrap();
+        let names: Vec<String> = (0..100).map(|i| format!("bench_latest_among_names_{}", i)).collect();
+        for chunk in (0..1_000_000i64).collect::<Vec<i64>>().chunks(10_000) {
+            let entries: Vec<Entry> = chunk
+                .iter()
+                .map(|i| Entry::new_with_timestamp(*i, names[*i as usize % 100].as_str(), vec![1, 2, 3]))
+                .collect();
+            store.push_bulk_values(&entries).unwrap();
+        }
+        // a name whose only entry is older than all the others, so it can't be
+        // found by scanning back from the newest entries
+        let entry = Entry::new_with_timestamp(-1, "bench_latest_among_names_old", vec![1, 2, 3]);
+        store.push(Cow::Owned(entry)).unwrap();
+        b.iter(|| {
+            assert_eq!(store.latest(names[42].as_str()).unwrap().unwrap().timestamp, 999_942);
+            assert_eq!(
+                store.latest("bench_latest_among_names_old").unwrap().unwrap().timestamp,
+                -1
+            );
+        });
+    }
+
+    // Counting a different 10-entry range each time
+    #[bench]
+    fn count_ranges(b: &mut Bencher) {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        let store = SqliteStore::new(&file, None).unwrap();
+        let entries: Vec<Entry> = (0..1000)
+            .map(|i| Entry::new_with_timestamp(i, "bench_count_ranges", vec![1, 2, 3]))
+            .collect();
+        store.push_bulk_values(&entries).unwrap();
+        let mut start = 0;
+        b.iter(|| {
+            start = (start + 1) % 990;
+            let range = store.range(start..start + 10, Some("bench_count_ranges")).unwrap();
+            assert_eq!(range.count().unwrap(), 10);
+        });
+    }
+
+    fn bench_iter_sparse_values(b: &mut Bencher, lazy: bool) {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        let store = SqliteStore::new(&file, None).unwrap();
+        let entries: Vec<Entry> = (0..10_000)
+            .map(|i| Entry::new_with_timestamp(i, "bench_iter_sparse_values", vec![(i % 256) as u8; 4096]))
+            .collect();
+        store.push_bulk_values(&entries).unwrap();
+        b.iter(|| {
+            let range = store.range(.., Some("bench_iter_sparse_values")).unwrap();
+            let mut read = 0;
+            if lazy {
+                for entry in range.iter_lazy().unwrap() {
+                    let entry = entry.unwrap();
+                    if entry.timestamp % 1000 == 0 {
+                        read += entry.value.get().unwrap().len();
+                    }
+                }
+            } else {
+                for entry in range.iter().unwrap() {
+                    let entry = entry.unwrap();
+                    if entry.timestamp % 1000 == 0 {
+                        read += entry.value.len();
+                    }
+                }
+            }
+            assert_eq!(read, 10 * 4096);
+        });
+    }
+
+    // Iterating over 10,000 compressed 4kb values, of which only one in
+    // 1,000 is read
+    #[bench]
+    fn iter_sparse_values_eager(b: &mut Bencher) {
+        bench_iter_sparse_values(b, false);
+    }
+
+    #[bench]
+    fn iter_sparse_values_lazy(b: &mut Bencher) {
+        bench_iter_sparse_values(b, true);
+    }
+
+    fn bench_push_parallel(b: &mut Bencher, thread_mode: ThreadMode) {
+        let files = TempFiles::new();
+        let config = SqliteStoreConfig {
+            thread_mode,
+            ..SqliteStoreConfig::default()
+        };
+        let store = SqliteStore::new_with_config(files.path(), config).unwrap();
+        crate::benches::push_parallel(b, &store);
+    }
+
+    // `push_parallel` in each `ThreadMode`
+    #[bench]
+    fn push_parallel_serialized(b: &mut Bencher) {
+        bench_push_parallel(b, ThreadMode::Serialized);
+    }
+
+    #[bench]
+    fn push_parallel_multi_thread(b: &mut Bencher) {
+        bench_push_parallel(b, ThreadMode::MultiThread);
+    }
+
+    #[bench]
+    fn push_parallel_single_thread(b: &mut Bencher) {
+        bench_push_parallel(b, ThreadMode::SingleThread);
+    }
+
+    // `latest` for a name whose result is cached, vs. the uncached `latest`
+    // bench
+    #[bench]
+    fn latest_cached(b: &mut Bencher) {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        let config = SqliteStoreConfig {
+            latest_cache_ttl: Some(std::time::Duration::from_secs(60)),
+            ..SqliteStoreConfig::default()
+        };
+        let store = SqliteStore::new_with_config(&file, config).unwrap();
+        let name = Atom::from("bench_latest_cached");
+        store
+            .push(Cow::Owned(Entry::new_with_timestamp(1, name.clone(), vec![1, 2, 3])))
+            .unwrap();
+        b.iter(|| {
+            store.latest(name.clone()).unwrap();
+        });
+    }
+
+    fn bench_iter_large_values(b: &mut Bencher, timestamps_only: bool) {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        let store = SqliteStore::new(&file, None).unwrap();
+        let entries: Vec<Entry> = (0..1000)
+            .map(|i| Entry::new_with_timestamp(i, "bench_iter_large_values", vec![(i % 256) as u8; 65536]))
+            .collect();
+        store.push_bulk_values(&entries).unwrap();
+        b.iter(|| {
+            let range = store.range(.., Option::<Atom>::None).unwrap();
+            if timestamps_only {
+                assert_eq!(range.iter_timestamps().unwrap().count(), 1000);
+            } else {
+                assert_eq!(range.iter().unwrap().count(), 1000);
+            }
+        });
+    }
+
+    // Iterating over 1,000 compressed 64kb entries, in full vs. only their
+    // timestamps
+    #[bench]
+    fn iter_large_values(b: &mut Bencher) {
+        bench_iter_large_values(b, false);
+    }
+
+    #[bench]
+    fn iter_timestamps_large_values(b: &mut Bencher) {
+        bench_iter_large_values(b, true);
+    }
+
+    // Ranges are paginated here, rather than iterated, so that the time
+    // spent skipping over earlier pages with `offset` doesn't drown out the
+    // time spent reading rows
+    fn bench_iter_names(b: &mut Bencher, named: bool, keys_only: bool) {
+        let file = NamedTempFile::new().unwrap().into_temp_path();
+        let store = SqliteStore::new(&file, None).unwrap();
+        for chunk in (0..1_000_000).collect::<Vec<i64>>().chunks(10_000) {
+            let entries: Vec<Entry> = chunk
+                .iter()
+                .map(|i| {
+                    let name = if named {
+                        "bench_iter_names".to_string()
+                    } else {
+                        format!("bench_iter_names_{}", i % 100_000)
+                    };
+                    Entry::new_with_timestamp(*i, name, vec![1])
+                })
+                .collect();
+            store.push_bulk_values(&entries).unwrap();
+        }
+        let name = if named { Some("bench_iter_names") } else { None };
+        b.iter(|| {
+            if keys_only {
+                let range = store.range(.., name).unwrap();
+                assert_eq!(range.iter_names_and_timestamps().unwrap().count(), 1_000_000);
+            } else {
+                let pages = store.paginate(.., name, 1000).unwrap();
+                assert_eq!(pages.map(|page| page.unwrap().len()).sum::<usize>(), 1_000_000);
+            }
+        });
+    }
+
+    // Reading 1,000,000 entries spread over 100,000 names, or limited to one
+    // name, whose atom is reused rather than interning every row's name
+    #[bench]
+    fn iter_many_names(b: &mut Bencher) {
+        bench_iter_names(b, false, false);
+    }
+
+    #[bench]
+    fn iter_one_name(b: &mut Bencher) {
+        bench_iter_names(b, true, false);
+    }
+
+    #[bench]
+    fn iter_keys_many_names(b: &mut Bencher) {
+        bench_iter_names(b, false, true);
+    }
+
+    // Inserting 1,000 compressible 4kb entries in a single transaction (and
+    // then removing them), without and with compression verification
+    #[bench]
+    fn push_compressed(b: &mut Bencher) {
+        bench_push_compressed(b, false);
+    }
+
+    #[bench]
+    fn push_compressed_verified(b: &mut Bencher) {
+        bench_push_compressed(b, true);
+    }
 }
@@ -0,0 +1,859 @@
+use std::borrow::Cow;
+use std::collections::{HashSet, VecDeque};
+use std::ops::RangeBounds;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use super::sqlite::PageRetries;
+use crate::codec::{decode_value, encode_value};
+#[cfg(feature = "compression")]
+use crate::ZstdCodec;
+use crate::{utils, Capabilities, Codec, Entry, Error, NoopCodec, Range, RangeableStore, Store};
+
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::types::Value as SqliteValue;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Row, TransactionBehavior};
+use string_cache::DefaultAtom as Atom;
+
+static SCHEMA: &str = "
+    create table if not exists partitions (
+        table_name text primary key,
+        start integer not null,
+        end integer not null
+    );
+    create table if not exists partition_sequence (
+        id integer primary key check (id = 0),
+        next integer not null
+    );
+    insert or ignore into partition_sequence (id, next) values (0, 1);
+";
+static PAGINATION_LIMIT: usize = 1000;
+static MICROS_PER_DAY: i128 = 86_400_000_000;
+
+/// A month of entries, stored in its own table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Partition {
+    /// The table's name, e.g. `log_202405` for May 2024, or `log_n004412`
+    /// for December 44 BC.
+    pub table: String,
+    /// The first timestamp in the month.
+    pub start: i64,
+    /// The last timestamp in the month.
+    pub end: i64,
+}
+
+impl Partition {
+    /// Gets the partition for the month (in UTC) a timestamp falls in.
+    fn containing(timestamp: i64) -> Self {
+        let (year, month) = year_month(timestamp);
+        let (next_year, next_month) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+        let clamp = |micros: i128| micros.clamp(i64::MIN.into(), i64::MAX.into()) as i64;
+        let table = if year >= 0 {
+            format!("log_{:04}{:02}", year, month)
+        } else {
+            format!("log_n{:04}{:02}", -year, month)
+        };
+        Self {
+            table,
+            start: clamp(days_from_civil(year, month) as i128 * MICROS_PER_DAY),
+            end: clamp(days_from_civil(next_year, next_month) as i128 * MICROS_PER_DAY - 1),
+        }
+    }
+
+    fn from_row(row: &Row) -> Result<Self, rusqlite::Error> {
+        Ok(Self {
+            table: row.get(0)?,
+            start: row.get(1)?,
+            end: row.get(2)?,
+        })
+    }
+
+    fn create_statement(&self) -> String {
+        format!(
+            "create table if not exists \"{table}\" (
+                id integer primary key,
+                ts integer not null,
+                name text not null,
+                size integer not null,
+                value blob not null,
+                codec text not null
+            );
+            create index if not exists \"{table}_ts\" on \"{table}\" (ts, name, id);
+            create index if not exists \"{table}_name\" on \"{table}\" (name, ts, id);",
+            table = self.table
+        )
+    }
+}
+
+/// Splits a timestamp into the year and month (1-12) it falls in, in UTC,
+/// via the proleptic gregorian calendar.
+fn year_month(timestamp: i64) -> (i64, u32) {
+    let days = (timestamp as i128).div_euclid(MICROS_PER_DAY) as i64;
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month as u32)
+}
+
+/// Gets the number of days between the unix epoch and the first day of a
+/// month.
+fn days_from_civil(year: i64, month: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (i64::from(month) + 9) % 12;
+    let doy = (153 * mp + 2) / 5;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Builds the condition selecting rows within inclusive bounds, and with a
+/// name if given, adding its parameters to `params`.
+fn condition(bounds: (i64, i64), name: Option<&Atom>, params: &mut Vec<SqliteValue>) -> String {
+    params.push(SqliteValue::Integer(bounds.0));
+    params.push(SqliteValue::Integer(bounds.1));
+    match name {
+        Some(name) => {
+            params.push(SqliteValue::Text(name.to_string()));
+            "ts >= ? and ts <= ? and name = ?".to_string()
+        }
+        None => "ts >= ? and ts <= ?".to_string(),
+    }
+}
+
+/// Gets the partitions that overlap inclusive bounds, oldest first.
+fn overlapping_partitions(conn: &Connection, bounds: (i64, i64)) -> Result<Vec<Partition>, Error> {
+    let mut stmt = conn
+        .prepare_cached("select table_name, start, end from partitions where start <= ? and end >= ? order by start")?;
+    let partitions = stmt
+        .query_map(params![bounds.1, bounds.0], Partition::from_row)?
+        .collect::<Result<Vec<Partition>, _>>()?;
+    Ok(partitions)
+}
+
+fn entry_from_row(codec: &dyn Codec, row: &Row) -> Result<Entry, Error> {
+    let timestamp: i64 = row.get(0)?;
+    let name: String = row.get(1)?;
+    let size: usize = row.get(2)?;
+    let blob: Vec<u8> = row.get(3)?;
+    let codec_name: String = row.get(4)?;
+    let id: i64 = row.get(5)?;
+    let mut entry = Entry::new_with_timestamp(timestamp, name, decode_value(codec, &codec_name, size, blob)?);
+    entry.sequence_number = Some(id as u64);
+    Ok(entry)
+}
+
+#[cfg(feature = "compression")]
+fn default_codec(compression_level: Option<i32>) -> Arc<dyn Codec> {
+    Arc::new(ZstdCodec::new(compression_level.unwrap_or(ZstdCodec::DEFAULT_LEVEL)))
+}
+
+#[cfg(not(feature = "compression"))]
+fn default_codec(_compression_level: Option<i32>) -> Arc<dyn Codec> {
+    Arc::new(NoopCodec)
+}
+
+/// A sqlite store that keeps each month of entries (by timestamp, in UTC)
+/// in its own table, so that old months can be dropped outright via
+/// `drop_partitions` rather than deleted row by row and vacuumed away.
+///
+/// Partitions are created on demand as entries are pushed, and recorded in
+/// a catalog table, so reopening a database finds them again. Ranges visit
+/// every partition that overlaps their bounds, oldest first, and `latest`
+/// checks partitions newest first. Entry sequence numbers are shared across
+/// partitions. Unlike `SqliteStore`, it doesn't support subscriptions,
+/// metadata or explicit ids, nor `StoreOptions`, so pushes never check
+/// timestamps for skew, report slow operations or invoke push callbacks.
+#[derive(Clone)]
+pub struct PartitionedSqliteStore {
+    pool: Pool<SqliteConnectionManager>,
+    codec: Arc<dyn Codec>,
+    /// Partitions this store knows exist, so that pushes only create tables
+    /// when they're first needed.
+    known: Arc<RwLock<HashSet<String>>>,
+}
+
+impl PartitionedSqliteStore {
+    /// Creates a store from a connection pool. `compression_level` is
+    /// ignored when the `compression` feature is disabled, in which case
+    /// values are stored uncompressed.
+    pub fn new_with_pool(pool: Pool<SqliteConnectionManager>, compression_level: Option<i32>) -> Result<Self, Error> {
+        let known = {
+            let conn = pool.get()?;
+            let journal_mode: String = conn.pragma_update_and_check(None, "journal_mode", "wal", |row| row.get(0))?;
+            if journal_mode != "wal" {
+                log::warn!("partitioned binlog store is using the {} journal mode", journal_mode);
+            }
+            conn.execute_batch(SCHEMA)?;
+            let mut stmt = conn.prepare("select table_name from partitions")?;
+            let known = stmt
+                .query_map(params![], |row| row.get(0))?
+                .collect::<Result<HashSet<String>, _>>()?;
+            known
+        };
+        Ok(Self {
+            pool,
+            codec: default_codec(compression_level),
+            known: Arc::new(RwLock::new(known)),
+        })
+    }
+
+    pub fn new<P: AsRef<Path>>(path: P, compression_level: Option<i32>) -> Result<Self, Error> {
+        let manager = SqliteConnectionManager::file(path);
+        let pool = r2d2::Pool::new(manager)?;
+        Self::new_with_pool(pool, compression_level)
+    }
+
+    /// Lists every partition, oldest first.
+    pub fn partitions(&self) -> Result<Vec<Partition>, Error> {
+        let conn = self.pool.get()?;
+        overlapping_partitions(&conn, (i64::MIN, i64::MAX))
+    }
+
+    /// Drops every partition whose month ends before `older_than`, returning
+    /// them. Each partition is dropped as a whole table, so this takes about
+    /// as long however many entries the partitions hold. Partitions that
+    /// straddle `older_than` are kept, entries and all.
+    pub fn drop_partitions(&self, older_than: i64) -> Result<Vec<Partition>, Error> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let dropped = {
+            let mut stmt = tx.prepare("select table_name, start, end from partitions where end < ? order by start")?;
+            let dropped = stmt
+                .query_map(params![older_than], Partition::from_row)?
+                .collect::<Result<Vec<Partition>, _>>()?;
+            dropped
+        };
+        for partition in dropped.iter() {
+            tx.execute_batch(&format!("drop table if exists \"{}\"", partition.table))?;
+            tx.execute("delete from partitions where table_name = ?", params![partition.table])?;
+        }
+        tx.commit()?;
+        let mut known = self.known.write().unwrap();
+        for partition in dropped.iter() {
+            known.remove(&partition.table);
+        }
+        Ok(dropped)
+    }
+
+    /// Inserts an encoded entry into its partition, first creating the
+    /// partition if `create` is set.
+    fn insert(
+        &self,
+        partition: &Partition,
+        create: bool,
+        entry: &Entry,
+        blob: &[u8],
+        size: usize,
+        codec_name: &str,
+    ) -> Result<(), Error> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        if create {
+            tx.execute_batch(&partition.create_statement())?;
+            tx.execute(
+                "insert or ignore into partitions (table_name, start, end) values (?, ?, ?)",
+                params![partition.table, partition.start, partition.end],
+            )?;
+        }
+        let id: i64 = tx.query_row("select next from partition_sequence", params![], |row| row.get(0))?;
+        tx.execute("update partition_sequence set next = next + 1", params![])?;
+        tx.execute(
+            &format!(
+                "insert into \"{}\" (id, ts, name, size, value, codec) values (?, ?, ?, ?, ?, ?)",
+                partition.table
+            ),
+            params![id, entry.timestamp, entry.name.as_ref(), size, blob, codec_name],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+/// Whether a database error is sqlite reporting that a table doesn't exist.
+fn is_missing_table(err: &(dyn std::error::Error + Send + Sync + 'static)) -> bool {
+    matches!(
+        err.downcast_ref::<rusqlite::Error>(),
+        Some(rusqlite::Error::SqliteFailure(_, Some(msg))) if msg.starts_with("no such table")
+    )
+}
+
+impl Store for PartitionedSqliteStore {
+    /// If a partition this store knows about was dropped elsewhere, e.g. by
+    /// `drop_partitions` on another handle, the push is retried once, this
+    /// time creating the partition.
+    fn push(&self, entry: Cow<Entry>) -> Result<(), Error> {
+        let partition = Partition::containing(entry.timestamp);
+        let (blob, size, codec_name) = encode_value(self.codec.as_ref(), &entry.value)?;
+        let is_known = self.known.read().unwrap().contains(&partition.table);
+        let created = match self.insert(&partition, !is_known, &entry, &blob, size, codec_name) {
+            Err(Error::Database(err)) if is_known && is_missing_table(&*err) => {
+                self.insert(&partition, true, &entry, &blob, size, codec_name)?;
+                true
+            }
+            result => {
+                result?;
+                !is_known
+            }
+        };
+        if created {
+            self.known.write().unwrap().insert(partition.table);
+        }
+        Ok(())
+    }
+
+    /// The catalog and partitions are read within one transaction, so
+    /// partitions dropped concurrently are either read in full or skipped.
+    fn latest<A: Into<Atom>>(&self, name: A) -> Result<Option<Entry>, Error> {
+        let name = name.into();
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        let mut partitions = overlapping_partitions(&tx, (i64::MIN, i64::MAX))?;
+        partitions.reverse();
+        for partition in partitions {
+            let mut stmt = tx.prepare_cached(&format!(
+                "select ts, name, size, value, codec, id from \"{}\" where name = ? order by ts desc, id desc limit 1",
+                partition.table
+            ))?;
+            let entry = stmt
+                .query_row(params![name.as_ref()], |row| {
+                    Ok(entry_from_row(self.codec.as_ref(), row))
+                })
+                .optional()?;
+            if let Some(entry) = entry {
+                return entry.map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Like `latest`, partitions are read within one transaction.
+    fn latest_n<A: Into<Atom>>(&self, name: A, n: usize) -> Result<Vec<Entry>, Error> {
+        let name = name.into();
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction()?;
+        let mut partitions = overlapping_partitions(&tx, (i64::MIN, i64::MAX))?;
+        partitions.reverse();
+        let mut entries = Vec::new();
+        for partition in partitions {
+            if entries.len() == n {
+                break;
+            }
+            let mut stmt = tx.prepare_cached(&format!(
+                "select ts, name, size, value, codec, id from \"{}\" where name = ? order by ts desc, id desc limit ?",
+                partition.table
+            ))?;
+            let limit = i64::try_from(n - entries.len()).unwrap_or(i64::MAX);
+            let mut rows = stmt.query(params![name.as_ref(), limit])?;
+            while let Some(row) = rows.next()? {
+                entries.push(entry_from_row(self.codec.as_ref(), row)?);
+            }
+        }
+        entries.reverse();
+        Ok(entries)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        let mut capabilities = Capabilities::RANGE | Capabilities::REMOVE | Capabilities::DURABLE;
+        if self.codec.name() != NoopCodec::NAME {
+            capabilities |= Capabilities::COMPRESSED;
+        }
+        capabilities
+    }
+}
+
+impl RangeableStore for PartitionedSqliteStore {
+    type Range = PartitionedSqliteRange;
+
+    fn range<A: Into<Atom>, R: RangeBounds<i64>>(&self, range: R, name: Option<A>) -> Result<Self::Range, Error> {
+        utils::check_bounds(range.start_bound(), range.end_bound())?;
+        Ok(PartitionedSqliteRange {
+            store: self.clone(),
+            bounds: utils::inclusive_bounds(range.start_bound(), range.end_bound()),
+            name: name.map(|n| n.into()),
+        })
+    }
+
+    /// Entries are read and then removed from every partition within one
+    /// transaction, so none are lost to concurrent pushes. Partitions left
+    /// empty are kept.
+    fn drain_all(&self) -> Result<Vec<Entry>, Error> {
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let mut entries = Vec::default();
+        for partition in overlapping_partitions(&tx, (i64::MIN, i64::MAX))? {
+            {
+                let mut stmt = tx.prepare(&format!(
+                    "select ts, name, size, value, codec, id from \"{}\" order by ts, name, id",
+                    partition.table
+                ))?;
+                let mut rows = stmt.query(params![])?;
+                while let Some(row) = rows.next()? {
+                    entries.push(entry_from_row(self.codec.as_ref(), row)?);
+                }
+            }
+            tx.execute_batch(&format!("delete from \"{}\"", partition.table))?;
+        }
+        tx.commit()?;
+        Ok(entries)
+    }
+
+    /// Entries are renamed in every partition within one transaction.
+    fn rename<A: Into<Atom>, B: Into<Atom>>(&self, old: A, new: B) -> Result<u64, Error> {
+        let (old, new) = (old.into(), new.into());
+        let mut conn = self.pool.get()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let partitions = overlapping_partitions(&tx, (i64::MIN, i64::MAX))?;
+        for partition in partitions.iter() {
+            let in_use: bool = tx.query_row(
+                &format!("select exists(select 1 from \"{}\" where name = ?)", partition.table),
+                params![new.as_ref()],
+                |row| row.get(0),
+            )?;
+            if in_use {
+                return Err(Error::NameInUse(new.to_string()));
+            }
+        }
+        let mut count = 0;
+        for partition in partitions.iter() {
+            count += tx.execute(
+                &format!("update \"{}\" set name = ? where name = ?", partition.table),
+                params![new.as_ref(), old.as_ref()],
+            )? as u64;
+        }
+        tx.commit()?;
+        Ok(count)
+    }
+}
+
+pub struct PartitionedSqliteRange {
+    store: PartitionedSqliteStore,
+    bounds: Option<(i64, i64)>,
+    name: Option<Atom>,
+}
+
+impl PartitionedSqliteRange {
+    /// Runs a statement against each partition overlapping the range, with
+    /// `{table}` replaced by the partition's table and `{condition}` by the
+    /// range's condition. Stops early once `f` returns false. `conn` should
+    /// be in a transaction, so that the partitions listed still exist when
+    /// they're read.
+    fn for_each_partition<F>(&self, conn: &Connection, statement: &str, mut f: F) -> Result<(), Error>
+    where
+        F: FnMut(&Connection, &str, &[SqliteValue]) -> Result<bool, Error>,
+    {
+        let bounds = match self.bounds {
+            Some(bounds) => bounds,
+            None => return Ok(()),
+        };
+        for partition in overlapping_partitions(conn, bounds)? {
+            let mut params = Vec::default();
+            let condition = condition(bounds, self.name.as_ref(), &mut params);
+            let statement = statement
+                .replace("{table}", &partition.table)
+                .replace("{condition}", &condition);
+            if !f(conn, &statement, &params)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Range for PartitionedSqliteRange {
+    type Iter = PartitionedSqliteRangeIterator;
+
+    fn count(&self) -> Result<u64, Error> {
+        let mut conn = self.store.pool.get()?;
+        let tx = conn.transaction()?;
+        let mut count = 0;
+        self.for_each_partition(
+            &tx,
+            "select count(id) from \"{table}\" where {condition}",
+            |conn, statement, params| {
+                let partition_count: u64 = conn
+                    .prepare_cached(statement)?
+                    .query_row(params_from_iter(params), |row| row.get(0))?;
+                count += partition_count;
+                Ok(true)
+            },
+        )?;
+        Ok(count)
+    }
+
+    /// Stops at the first partition with entries in the range.
+    fn is_empty(&self) -> Result<bool, Error> {
+        let mut conn = self.store.pool.get()?;
+        let tx = conn.transaction()?;
+        let mut is_empty = true;
+        self.for_each_partition(
+            &tx,
+            "select exists(select 1 from \"{table}\" where {condition})",
+            |conn, statement, params| {
+                let exists: bool = conn
+                    .prepare_cached(statement)?
+                    .query_row(params_from_iter(params), |row| row.get(0))?;
+                is_empty = !exists;
+                Ok(is_empty)
+            },
+        )?;
+        Ok(is_empty)
+    }
+
+    /// Entries are removed from every partition within one transaction.
+    /// Partitions left empty are kept; use `drop_partitions` to drop them.
+    fn remove(self) -> Result<(), Error> {
+        let mut conn = self.store.pool.get()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        self.for_each_partition(
+            &tx,
+            "delete from \"{table}\" where {condition}",
+            |conn, statement, params| {
+                conn.prepare_cached(statement)?.execute(params_from_iter(params))?;
+                Ok(true)
+            },
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Partitions hold disjoint months, so entries are read a page at a
+    /// time from each partition in turn, and are still ordered by
+    /// timestamp, then name, then insertion order. Partitions dropped while
+    /// iterating are skipped from the next page on.
+    fn iter(self) -> Result<Self::Iter, Error> {
+        Ok(PartitionedSqliteRangeIterator {
+            store: self.store,
+            bounds: self.bounds,
+            name: self.name,
+            partitions: None,
+            cursor: None,
+            entries: VecDeque::default(),
+            done: false,
+            retries: PageRetries::default(),
+        })
+    }
+}
+
+pub struct PartitionedSqliteRangeIterator {
+    store: PartitionedSqliteStore,
+    bounds: Option<(i64, i64)>,
+    name: Option<Atom>,
+    /// The partitions left to read, which are listed on the first read.
+    partitions: Option<VecDeque<Partition>>,
+    /// The timestamp, name and id of the last entry read from the front
+    /// partition.
+    cursor: Option<(i64, Atom, u64)>,
+    entries: VecDeque<Entry>,
+    done: bool,
+    retries: PageRetries,
+}
+
+impl PartitionedSqliteRangeIterator {
+    fn fill_entries(&mut self) -> Result<(), Error> {
+        let bounds = match self.bounds {
+            Some(bounds) => bounds,
+            None => {
+                self.done = true;
+                return Ok(());
+            }
+        };
+        let mut conn = self.store.pool.get()?;
+        // The first page is read alongside the catalog, so it can't miss a
+        // partition dropped in between
+        let tx = conn.transaction()?;
+        if self.partitions.is_none() {
+            self.partitions = Some(overlapping_partitions(&tx, bounds)?.into());
+        }
+        let partitions = self.partitions.as_mut().unwrap();
+        let partition = match partitions.front() {
+            Some(partition) => partition,
+            None => {
+                self.done = true;
+                return Ok(());
+            }
+        };
+
+        let mut params = Vec::default();
+        let mut condition = condition(bounds, self.name.as_ref(), &mut params);
+        if let Some((timestamp, name, id)) = &self.cursor {
+            condition.push_str(" and (ts, name, id) > (?, ?, ?)");
+            params.push(SqliteValue::Integer(*timestamp));
+            params.push(SqliteValue::Text(name.to_string()));
+            params.push(SqliteValue::Integer(*id as i64));
+        }
+        let statement = format!(
+            "select ts, name, size, value, codec, id from \"{}\" where {} order by ts, name, id limit {}",
+            partition.table, condition, PAGINATION_LIMIT
+        );
+        let read_page = || -> Result<Vec<Entry>, Error> {
+            let mut stmt = tx.prepare_cached(&statement)?;
+            let mut rows = stmt.query(params_from_iter(params))?;
+            let mut page = Vec::with_capacity(PAGINATION_LIMIT);
+            while let Some(row) = rows.next()? {
+                page.push(entry_from_row(self.store.codec.as_ref(), row)?);
+            }
+            Ok(page)
+        };
+        let page = match read_page() {
+            // A partition dropped since it was listed has nothing left to read
+            Err(Error::Database(err)) if is_missing_table(&*err) => Vec::default(),
+            result => result?,
+        };
+
+        if page.len() < PAGINATION_LIMIT {
+            partitions.pop_front();
+            self.cursor = None;
+        } else if let Some(last) = page.last() {
+            self.cursor = Some((
+                last.timestamp,
+                last.name.clone(),
+                last.sequence_number.unwrap_or_default(),
+            ));
+        }
+        self.entries.extend(page);
+        Ok(())
+    }
+}
+
+impl Iterator for PartitionedSqliteRangeIterator {
+    type Item = Result<Entry, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.entries.is_empty() && !self.done {
+            self.retries.backoff();
+            let result = self.fill_entries();
+            if self.retries.record(&result) {
+                self.done = true;
+            }
+            if let Err(err) = result {
+                return Some(Err(err));
+            }
+        }
+        self.entries.pop_front().map(Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::{days_from_civil, year_month, Partition};
+    use crate::testkit::TempFiles;
+    use crate::{
+        test_rangeable_store_impl, test_store_impl, Entry, PartitionedSqliteStore, Range, RangeableStore, Store,
+    };
+    use string_cache::DefaultAtom as Atom;
+
+    test_store_impl!(|files: &TempFiles| PartitionedSqliteStore::new(files.path(), None).unwrap());
+    test_rangeable_store_impl!(|files: &TempFiles| PartitionedSqliteStore::new(files.path(), None).unwrap());
+
+    // 2024-01-31T23:59:59.999999Z, the last microsecond of January 2024
+    static END_OF_JANUARY: i64 = 1_706_745_599_999_999;
+
+    #[test]
+    fn partitions() {
+        assert_eq!(year_month(0), (1970, 1));
+        assert_eq!(year_month(-1), (1969, 12));
+        assert_eq!(year_month(END_OF_JANUARY), (2024, 1));
+        assert_eq!(year_month(END_OF_JANUARY + 1), (2024, 2));
+        // 2024-02-29, a leap day
+        assert_eq!(year_month(1_709_164_800_000_000), (2024, 2));
+        assert_eq!(year_month(1_709_251_200_000_000), (2024, 3));
+        assert_eq!(days_from_civil(1970, 1), 0);
+        assert_eq!(days_from_civil(2000, 3), 11_017);
+
+        assert_eq!(
+            Partition::containing(END_OF_JANUARY),
+            Partition {
+                table: "log_202401".to_string(),
+                start: 1_704_067_200_000_000,
+                end: END_OF_JANUARY,
+            }
+        );
+        assert_eq!(Partition::containing(-1).table, "log_196912");
+        let first = Partition::containing(i64::MIN);
+        assert_eq!(first.start, i64::MIN);
+        assert!(first.table.starts_with("log_n"));
+        assert_eq!(Partition::containing(i64::MAX).end, i64::MAX);
+    }
+
+    #[test]
+    fn straddling_ranges() {
+        let files = TempFiles::new();
+        let store = PartitionedSqliteStore::new(files.path(), None).unwrap();
+        // entries on either side of the boundary between january and
+        // february, pushed out of order, and enough of them to span pages
+        let mut entries = Vec::default();
+        for i in 0..1500i64 {
+            for timestamp in [END_OF_JANUARY - i, END_OF_JANUARY + 1 + i] {
+                let name = if i % 2 == 0 {
+                    "test_straddling_a"
+                } else {
+                    "test_straddling_b"
+                };
+                entries.push(Entry::new_with_timestamp(timestamp, name, vec![(i % 256) as u8]));
+            }
+        }
+        for entry in entries.iter().rev() {
+            store.push(Cow::Borrowed(entry)).unwrap();
+        }
+        let tables: Vec<String> = store.partitions().unwrap().into_iter().map(|p| p.table).collect();
+        assert_eq!(tables, vec!["log_202401", "log_202402"]);
+
+        let keys = |range: std::ops::RangeInclusive<i64>, name: Option<&str>| -> Vec<(i64, Atom)> {
+            store
+                .range(range, name)
+                .unwrap()
+                .iter()
+                .unwrap()
+                .map(|entry| {
+                    let entry = entry.unwrap();
+                    (entry.timestamp, entry.name)
+                })
+                .collect()
+        };
+        let mut expected: Vec<(i64, Atom)> = entries
+            .iter()
+            .map(|entry| (entry.timestamp, entry.name.clone()))
+            .collect();
+        expected.sort();
+        assert_eq!(keys(i64::MIN..=i64::MAX, None), expected);
+        let straddling = (END_OF_JANUARY - 10)..=(END_OF_JANUARY + 10);
+        let expected_straddling: Vec<(i64, Atom)> = expected
+            .iter()
+            .filter(|(timestamp, _)| straddling.contains(timestamp))
+            .cloned()
+            .collect();
+        assert_eq!(keys(straddling.clone(), None), expected_straddling);
+        assert_eq!(
+            store.range(straddling.clone(), None::<Atom>).unwrap().count().unwrap(),
+            21
+        );
+        assert_eq!(
+            store
+                .range(straddling.clone(), Some("test_straddling_b"))
+                .unwrap()
+                .count()
+                .unwrap(),
+            10
+        );
+
+        // the latest entry is in the newest partition, even though it was
+        // pushed first
+        let latest = store.latest("test_straddling_b").unwrap().unwrap();
+        assert_eq!(latest.timestamp, END_OF_JANUARY + 1 + 1499);
+
+        store.range(straddling.clone(), None::<Atom>).unwrap().remove().unwrap();
+        assert!(store.range(straddling, None::<Atom>).unwrap().is_empty().unwrap());
+        assert_eq!(store.count().unwrap(), 3000 - 21);
+    }
+
+    #[test]
+    fn drop_partitions() {
+        let files = TempFiles::new();
+        let path = files.path();
+        let store = PartitionedSqliteStore::new(&path, None).unwrap();
+        for timestamp in [END_OF_JANUARY - 1, END_OF_JANUARY, END_OF_JANUARY + 1] {
+            let entry = Entry::new_with_timestamp(timestamp, "test_drop_partitions", vec![1]);
+            store.push(Cow::Owned(entry)).unwrap();
+        }
+
+        // partitions that straddle the cutoff are kept
+        assert!(store.drop_partitions(END_OF_JANUARY).unwrap().is_empty());
+        let dropped = store.drop_partitions(END_OF_JANUARY + 1).unwrap();
+        assert_eq!(dropped, vec![Partition::containing(END_OF_JANUARY)]);
+        assert_eq!(store.count().unwrap(), 1);
+
+        // reopening finds the remaining partition, and pushing to a dropped
+        // month recreates it
+        let store = PartitionedSqliteStore::new(&path, None).unwrap();
+        let tables: Vec<String> = store.partitions().unwrap().into_iter().map(|p| p.table).collect();
+        assert_eq!(tables, vec!["log_202402"]);
+        let entry = Entry::new_with_timestamp(END_OF_JANUARY, "test_drop_partitions", vec![2]);
+        store.push(Cow::Owned(entry)).unwrap();
+        assert_eq!(store.partitions().unwrap().len(), 2);
+        let latest = store.latest("test_drop_partitions").unwrap().unwrap();
+        assert_eq!(latest.timestamp, END_OF_JANUARY + 1);
+        assert!(latest.sequence_number.unwrap() < 4);
+    }
+
+    #[test]
+    fn push_after_drop_elsewhere() {
+        let files = TempFiles::new();
+        let path = files.path();
+        let store = PartitionedSqliteStore::new(&path, None).unwrap();
+        let other = PartitionedSqliteStore::new(&path, None).unwrap();
+        let entry = Entry::new_with_timestamp(END_OF_JANUARY, "test_push_after_drop_elsewhere", vec![1]);
+        store.push(Cow::Borrowed(&entry)).unwrap();
+        assert_eq!(other.drop_partitions(END_OF_JANUARY + 1).unwrap().len(), 1);
+
+        // the first store still thinks january exists
+        store.push(Cow::Borrowed(&entry)).unwrap();
+        assert_eq!(store.count().unwrap(), 1);
+        assert_eq!(store.partitions().unwrap(), vec![Partition::containing(END_OF_JANUARY)]);
+    }
+
+    #[test]
+    fn drop_while_ranging() {
+        let files = TempFiles::new();
+        let path = files.path();
+        let store = PartitionedSqliteStore::new(&path, None).unwrap();
+        let other = PartitionedSqliteStore::new(&path, None).unwrap();
+        // 2024-03-01T00:00:00Z
+        let march = 1_709_251_200_000_000;
+        for i in 0..1500 {
+            let entry = Entry::new_with_timestamp(END_OF_JANUARY - i, "test_drop_while_ranging", vec![1]);
+            store.push(Cow::Owned(entry)).unwrap();
+        }
+        for timestamp in [END_OF_JANUARY + 1, march] {
+            for i in 0..10 {
+                let entry = Entry::new_with_timestamp(timestamp + i, "test_drop_while_ranging", vec![2]);
+                store.push(Cow::Owned(entry)).unwrap();
+            }
+        }
+
+        // january is dropped after its first page is read, so the rest of
+        // it is skipped, as is february
+        let mut iter = store
+            .range(.., Some("test_drop_while_ranging"))
+            .unwrap()
+            .iter()
+            .unwrap();
+        iter.next().unwrap().unwrap();
+        assert_eq!(other.drop_partitions(march).unwrap().len(), 2);
+        let rest: Vec<Entry> = iter.map(Result::unwrap).collect();
+        assert_eq!(rest.len(), 999 + 10);
+        assert!(rest[999..].iter().all(|entry| entry.timestamp >= march));
+
+        // reads that race drops see each partition in full or not at all
+        let dropper = std::thread::spawn(move || {
+            for i in 0..50 {
+                let timestamp = END_OF_JANUARY - i;
+                let entry = Entry::new_with_timestamp(timestamp, "test_drop_while_ranging", vec![3]);
+                other.push(Cow::Owned(entry)).unwrap();
+                other.drop_partitions(march).unwrap();
+            }
+        });
+        while !dropper.is_finished() {
+            let range = store.range(.., Some("test_drop_while_ranging")).unwrap();
+            assert!(matches!(range.count().unwrap(), 10 | 11));
+            assert!(!range.is_empty().unwrap());
+            let entries: Vec<Entry> = range.iter().unwrap().map(Result::unwrap).collect();
+            assert!(matches!(entries.len(), 10 | 11));
+            assert_eq!(
+                store.latest("test_drop_while_ranging").unwrap().unwrap().timestamp,
+                march + 9
+            );
+            let latest = store.latest_n("test_drop_while_ranging", 20).unwrap();
+            assert!(matches!(latest.len(), 10 | 11));
+        }
+        dropper.join().unwrap();
+    }
+}
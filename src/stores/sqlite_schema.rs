@@ -0,0 +1,200 @@
+//! The sqlite store's schema as sea-query identifiers, so that range queries
+//! can be built from typed parts rather than by concatenating strings.
+
+use std::ops::Bound;
+
+use crate::OrderBy;
+
+use rusqlite::types::Value as SqliteValue;
+use sea_query::{Cond, Condition, Expr, Iden, Order, Query, SelectStatement, SqliteQueryBuilder, Value};
+use string_cache::DefaultAtom as Atom;
+
+/// The `log` table.
+#[derive(Iden)]
+pub(crate) struct Log;
+
+/// The columns of the `log` table.
+#[derive(Iden, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum LogIden {
+    Id,
+    Ts,
+    Name,
+    Size,
+    Value,
+    Codec,
+    Skewed,
+    IngestedAt,
+    Meta,
+}
+
+/// The columns entries are read from, in the order `stored_entry_from_row`
+/// expects.
+pub(crate) static ENTRY_COLUMNS: &[LogIden] = &[
+    LogIden::Ts,
+    LogIden::Name,
+    LogIden::Size,
+    LogIden::Value,
+    LogIden::Codec,
+    LogIden::Id,
+    LogIden::Skewed,
+    LogIden::IngestedAt,
+    LogIden::Meta,
+];
+
+/// The columns keys are read from, i.e. the sort order of entries.
+pub(crate) static KEY_COLUMNS: &[LogIden] = &[LogIden::Ts, LogIden::Name, LogIden::Id];
+
+/// Builds the condition selecting rows whose `column` is within the bounds,
+/// and that have the given name, if any.
+pub(crate) fn range_condition(
+    column: LogIden,
+    start_bound: Bound<i64>,
+    end_bound: Bound<i64>,
+    name: Option<&Atom>,
+) -> Condition {
+    let mut condition = Cond::all();
+    match start_bound {
+        Bound::Included(s) => condition = condition.add(Expr::col(column).gte(s)),
+        Bound::Excluded(s) => condition = condition.add(Expr::col(column).gt(s)),
+        Bound::Unbounded => {}
+    }
+    match end_bound {
+        Bound::Included(e) => condition = condition.add(Expr::col(column).lte(e)),
+        Bound::Excluded(e) => condition = condition.add(Expr::col(column).lt(e)),
+        Bound::Unbounded => {}
+    }
+    if let Some(name) = name {
+        condition = condition.add(Expr::col(LogIden::Name).eq(name.as_ref()));
+    }
+    condition
+}
+
+/// The columns rows are sorted by, for each order.
+fn order_columns(order: OrderBy) -> [LogIden; 3] {
+    match order {
+        OrderBy::Time => [LogIden::Ts, LogIden::Name, LogIden::Id],
+        OrderBy::NameThenTime => [LogIden::Name, LogIden::Ts, LogIden::Id],
+    }
+}
+
+/// Counts the rows matching `condition`.
+pub(crate) fn count_statement(condition: Condition) -> SelectStatement {
+    Query::select()
+        .expr(Expr::col(LogIden::Id).count())
+        .from(Log)
+        .cond_where(condition)
+        .to_owned()
+}
+
+/// Selects whether any row matches `condition`.
+pub(crate) fn exists_statement(condition: Condition) -> SelectStatement {
+    let inner = Query::select()
+        .expr(Expr::val(1))
+        .from(Log)
+        .cond_where(condition)
+        .limit(1)
+        .to_owned();
+    Query::select().expr(Expr::exists(inner)).to_owned()
+}
+
+/// Selects `columns` of the rows matching `condition`, sorted by `order`.
+pub(crate) fn select_statement(columns: &[LogIden], condition: Condition, order: OrderBy) -> SelectStatement {
+    let mut statement = Query::select()
+        .columns(columns.iter().copied())
+        .from(Log)
+        .cond_where(condition)
+        .to_owned();
+    for column in order_columns(order) {
+        statement.order_by(column, Order::Asc);
+    }
+    statement
+}
+
+/// Restricts `condition` to rows that sort after `cursor`, i.e. the
+/// timestamp, name and id of the last row already read.
+pub(crate) fn after_cursor(condition: Condition, order: OrderBy, cursor: &(i64, Atom, u64)) -> Condition {
+    let (timestamp, name, id) = cursor;
+    let values = order_columns(order).map(|column| match column {
+        LogIden::Ts => Expr::val(*timestamp).into(),
+        LogIden::Name => Expr::val(name.as_ref()).into(),
+        _ => Expr::val(*id).into(),
+    });
+    let columns = order_columns(order).map(|column| Expr::col(column).into());
+    condition.add(Expr::tuple(columns).gt(Expr::tuple(values)))
+}
+
+/// Renders a statement to sql with `?` placeholders, alongside the values to
+/// bind to them.
+pub(crate) fn build(statement: &SelectStatement) -> (String, Vec<SqliteValue>) {
+    let (sql, values) = statement.build(SqliteQueryBuilder);
+    let values = values
+        .into_iter()
+        .map(|value| match value {
+            Value::Int(Some(i)) => SqliteValue::Integer(i.into()),
+            Value::BigInt(Some(i)) => SqliteValue::Integer(i),
+            // Ids are stored as signed integers
+            Value::BigUnsigned(Some(i)) => SqliteValue::Integer(i as i64),
+            Value::String(Some(s)) => SqliteValue::Text(*s),
+            value => unreachable!("unexpected value in a log query: {:?}", value),
+        })
+        .collect();
+    (sql, values)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Bound;
+
+    use super::{
+        after_cursor, count_statement, exists_statement, range_condition, select_statement, LogIden, ENTRY_COLUMNS,
+        KEY_COLUMNS,
+    };
+    use crate::OrderBy;
+
+    use sea_query::SqliteQueryBuilder;
+    use string_cache::DefaultAtom as Atom;
+
+    #[test]
+    fn range_conditions() {
+        let name = Atom::from("test_range_conditions");
+        let condition = range_condition(LogIden::Ts, Bound::Included(1), Bound::Excluded(5), Some(&name));
+        assert_eq!(
+            count_statement(condition).to_string(SqliteQueryBuilder),
+            r#"SELECT COUNT("id") FROM "log" WHERE "ts" >= 1 AND "ts" < 5 AND "name" = 'test_range_conditions'"#
+        );
+        let condition = range_condition(LogIden::Id, Bound::Excluded(1), Bound::Included(5), None);
+        assert_eq!(
+            exists_statement(condition).to_string(SqliteQueryBuilder),
+            r#"SELECT EXISTS(SELECT 1 FROM "log" WHERE "id" > 1 AND "id" <= 5 LIMIT 1)"#
+        );
+        // an empty condition still renders, but selects every row
+        let condition = range_condition(LogIden::Ts, Bound::Unbounded, Bound::Unbounded, None);
+        assert_eq!(
+            count_statement(condition).to_string(SqliteQueryBuilder),
+            r#"SELECT COUNT("id") FROM "log" WHERE TRUE"#
+        );
+    }
+
+    #[test]
+    fn select_statements() {
+        let condition = range_condition(LogIden::Ts, Bound::Included(1), Bound::Unbounded, None);
+        let statement = select_statement(ENTRY_COLUMNS, condition, OrderBy::Time)
+            .limit(1000)
+            .offset(2000)
+            .to_owned();
+        assert_eq!(
+            statement.to_string(SqliteQueryBuilder),
+            r#"SELECT "ts", "name", "size", "value", "codec", "id", "skewed", "ingested_at", "meta" FROM "log" WHERE "ts" >= 1 ORDER BY "ts" ASC, "name" ASC, "id" ASC LIMIT 1000 OFFSET 2000"#
+        );
+
+        let condition = range_condition(LogIden::Ts, Bound::Unbounded, Bound::Unbounded, None);
+        let condition = after_cursor(condition, OrderBy::NameThenTime, &(2, Atom::from("a"), 3));
+        let statement = select_statement(KEY_COLUMNS, condition, OrderBy::NameThenTime)
+            .limit(10)
+            .to_owned();
+        assert_eq!(
+            statement.to_string(SqliteQueryBuilder),
+            r#"SELECT "ts", "name", "id" FROM "log" WHERE ("name", "ts", "id") > ('a', 2, 3) ORDER BY "name" ASC, "ts" ASC, "id" ASC LIMIT 10"#
+        );
+    }
+}
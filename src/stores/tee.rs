@@ -0,0 +1,352 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+use std::time::Duration;
+
+use crate::{Capabilities, Entry, Error, Store};
+
+use string_cache::DefaultAtom as Atom;
+
+/// A callback invoked with entries that a `TeeStore` couldn't push to its
+/// secondary store, alongside why.
+pub type TeeErrorCallback = Arc<dyn Fn(&Entry, &Error) + Send + Sync>;
+
+/// How a `TeeStore` handles failures. Entries are always pushed to the
+/// primary store first, and pushes fail if the primary store fails, in which
+/// case the secondary store isn't pushed to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TeePolicy {
+    /// Fail the push if the secondary store fails too. The entry remains in
+    /// the primary store.
+    FailFast,
+    /// Pass secondary store failures to the error callback.
+    Primary,
+    /// Queue entries the secondary store fails to push, and retry them in
+    /// order from a background thread every `retry_interval`. Entries pushed
+    /// while others are queued are queued behind them. Once `max_queued`
+    /// entries are queued, further entries are passed to the error callback
+    /// instead, alongside the secondary store's error, or `Error::QueueFull`
+    /// if they were never tried. Queued entries are lost when the store is
+    /// dropped.
+    Durable {
+        max_queued: usize,
+        retry_interval: Duration,
+    },
+}
+
+#[derive(Default)]
+struct RetryQueue {
+    entries: VecDeque<Entry>,
+    // Whether the front entry has been taken off the queue to be retried
+    in_flight: bool,
+}
+
+struct TeeSecondary<B> {
+    store: B,
+    queue: Mutex<RetryQueue>,
+}
+
+impl<B: Store + 'static> TeeSecondary<B> {
+    /// Pushes queued entries until the queue is empty, or a push fails.
+    fn drain(&self) {
+        loop {
+            let entry = {
+                let mut queue = self.queue.lock().unwrap();
+                match queue.entries.pop_front() {
+                    Some(entry) => {
+                        queue.in_flight = true;
+                        entry
+                    }
+                    None => return,
+                }
+            };
+            let result = self.store.push(Cow::Borrowed(&entry));
+            let mut queue = self.queue.lock().unwrap();
+            queue.in_flight = false;
+            if result.is_err() {
+                queue.entries.push_front(entry);
+                return;
+            }
+        }
+    }
+
+    fn spawn_retries(self: &Arc<Self>, retry_interval: Duration) {
+        let secondary: Weak<Self> = Arc::downgrade(self);
+        thread::spawn(move || loop {
+            thread::sleep(retry_interval);
+            match secondary.upgrade() {
+                Some(secondary) => secondary.drain(),
+                None => return,
+            }
+        });
+    }
+}
+
+/// A store that pushes entries to two stores, e.g. to redis for live
+/// subscribers and to sqlite for history. `latest` reads from the primary
+/// store. Use `primary` and `secondary` to range over or subscribe to
+/// either.
+pub struct TeeStore<A, B> {
+    primary: A,
+    secondary: Arc<TeeSecondary<B>>,
+    policy: TeePolicy,
+    on_secondary_error: Option<TeeErrorCallback>,
+}
+
+impl<A: Store, B: Store + 'static> TeeStore<A, B> {
+    pub fn new(primary: A, secondary: B, policy: TeePolicy) -> Self {
+        let secondary = Arc::new(TeeSecondary {
+            store: secondary,
+            queue: Mutex::new(RetryQueue::default()),
+        });
+        if let TeePolicy::Durable { retry_interval, .. } = policy {
+            secondary.spawn_retries(retry_interval);
+        }
+        Self {
+            primary,
+            secondary,
+            policy,
+            on_secondary_error: None,
+        }
+    }
+
+    /// Sets the callback invoked with entries that couldn't be pushed to
+    /// the secondary store under the `Primary` and `Durable` policies. By
+    /// default, they're logged as warnings via the `log` crate.
+    pub fn with_on_secondary_error(mut self, callback: TeeErrorCallback) -> Self {
+        self.on_secondary_error = Some(callback);
+        self
+    }
+
+    pub fn primary(&self) -> &A {
+        &self.primary
+    }
+
+    pub fn secondary(&self) -> &B {
+        &self.secondary.store
+    }
+
+    /// Returns the number of entries waiting to be retried against the
+    /// secondary store.
+    pub fn queued(&self) -> usize {
+        let queue = self.secondary.queue.lock().unwrap();
+        queue.entries.len() + queue.in_flight as usize
+    }
+
+    fn secondary_failed(&self, entry: &Entry, err: &Error) {
+        match &self.on_secondary_error {
+            Some(callback) => callback(entry, err),
+            None => log::warn!("failed to push {:?} to the secondary binlog store: {}", entry.name, err),
+        }
+    }
+}
+
+impl<A: Clone, B> Clone for TeeStore<A, B> {
+    fn clone(&self) -> Self {
+        Self {
+            primary: self.primary.clone(),
+            secondary: self.secondary.clone(),
+            policy: self.policy,
+            on_secondary_error: self.on_secondary_error.clone(),
+        }
+    }
+}
+
+impl<A: Store, B: Store + 'static> Store for TeeStore<A, B> {
+    fn push(&self, entry: Cow<Entry>) -> Result<(), Error> {
+        self.primary.push(Cow::Borrowed(&entry))?;
+        match self.policy {
+            TeePolicy::FailFast => self.secondary.store.push(entry),
+            TeePolicy::Primary => {
+                if let Err(err) = self.secondary.store.push(Cow::Borrowed(&entry)) {
+                    self.secondary_failed(&entry, &err);
+                }
+                Ok(())
+            }
+            TeePolicy::Durable { max_queued, .. } => {
+                let waiting = {
+                    let queue = self.secondary.queue.lock().unwrap();
+                    !queue.entries.is_empty() || queue.in_flight
+                };
+                let err = if waiting {
+                    None
+                } else {
+                    match self.secondary.store.push(Cow::Borrowed(&entry)) {
+                        Ok(()) => return Ok(()),
+                        Err(err) => Some(err),
+                    }
+                };
+
+                let mut queue = self.secondary.queue.lock().unwrap();
+                if queue.entries.len() + (queue.in_flight as usize) < max_queued {
+                    queue.entries.push_back(entry.into_owned());
+                } else {
+                    drop(queue);
+                    self.secondary_failed(&entry, &err.unwrap_or(Error::QueueFull));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn latest<N: Into<Atom>>(&self, name: N) -> Result<Option<Entry>, Error> {
+        self.primary.latest(name)
+    }
+
+    fn latest_n<N: Into<Atom>>(&self, name: N, n: usize) -> Result<Vec<Entry>, Error> {
+        self.primary.latest_n(name, n)
+    }
+
+    /// Only the primary store's durability and compression carry over,
+    /// since ranges and subscriptions go through `primary` or `secondary`.
+    fn capabilities(&self) -> Capabilities {
+        self.primary.capabilities() & (Capabilities::DURABLE | Capabilities::COMPRESSED)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::io::{Error as IoError, ErrorKind as IoErrorKind};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    use crate::{
+        test_store_impl, Capabilities, Entry, Error, MemoryStore, Range, RangeableStore, Store, TeePolicy, TeeStore,
+    };
+    use string_cache::DefaultAtom as Atom;
+
+    test_store_impl!(|_| TeeStore::new(MemoryStore::default(), MemoryStore::default(), TeePolicy::FailFast));
+
+    /// A memory store that fails pushes while `failing` is set.
+    #[derive(Clone, Default)]
+    struct FlakyStore {
+        store: MemoryStore,
+        failing: Arc<AtomicBool>,
+    }
+
+    impl FlakyStore {
+        fn set_failing(&self, failing: bool) {
+            self.failing.store(failing, Ordering::SeqCst);
+        }
+
+        fn values(&self) -> Vec<u8> {
+            let entries = self.store.range(.., None::<String>).unwrap().iter().unwrap();
+            entries.map(|entry| entry.unwrap().value[0]).collect()
+        }
+    }
+
+    impl Store for FlakyStore {
+        fn push(&self, entry: Cow<Entry>) -> Result<(), Error> {
+            if self.failing.load(Ordering::SeqCst) {
+                Err(Error::Io(IoError::new(IoErrorKind::ConnectionRefused, "down")))
+            } else {
+                self.store.push(entry)
+            }
+        }
+
+        fn latest<A: Into<Atom>>(&self, name: A) -> Result<Option<Entry>, Error> {
+            self.store.latest(name)
+        }
+
+        fn capabilities(&self) -> Capabilities {
+            self.store.capabilities()
+        }
+    }
+
+    fn entry(i: u8) -> Cow<'static, Entry> {
+        Cow::Owned(Entry::new_with_timestamp(i.into(), "test_tee", vec![i]))
+    }
+
+    /// Returns a tee store, and the values of the entries passed to its
+    /// error callback.
+    fn tee(policy: TeePolicy) -> (TeeStore<FlakyStore, FlakyStore>, Arc<Mutex<Vec<u8>>>) {
+        let failed = Arc::new(Mutex::new(Vec::default()));
+        let callback_failed = failed.clone();
+        let store = TeeStore::new(FlakyStore::default(), FlakyStore::default(), policy).with_on_secondary_error(
+            Arc::new(move |entry: &Entry, _: &Error| callback_failed.lock().unwrap().push(entry.value[0])),
+        );
+        (store, failed)
+    }
+
+    #[test]
+    fn fail_fast() {
+        let (store, failed) = tee(TeePolicy::FailFast);
+        store.push(entry(1)).unwrap();
+        store.secondary().set_failing(true);
+        assert!(matches!(store.push(entry(2)), Err(Error::Io(_))));
+        store.primary().set_failing(true);
+        assert!(matches!(store.push(entry(3)), Err(Error::Io(_))));
+
+        assert_eq!(store.primary().values(), vec![1, 2]);
+        assert_eq!(store.secondary().values(), vec![1]);
+        assert!(failed.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn primary() {
+        let (store, failed) = tee(TeePolicy::Primary);
+        store.push(entry(1)).unwrap();
+        store.secondary().set_failing(true);
+        store.push(entry(2)).unwrap();
+        store.secondary().set_failing(false);
+        store.primary().set_failing(true);
+        assert!(matches!(store.push(entry(3)), Err(Error::Io(_))));
+
+        assert_eq!(store.primary().values(), vec![1, 2]);
+        assert_eq!(store.secondary().values(), vec![1]);
+        assert_eq!(*failed.lock().unwrap(), vec![2]);
+    }
+
+    #[test]
+    fn durable() {
+        let (store, failed) = tee(TeePolicy::Durable {
+            max_queued: 2,
+            retry_interval: Duration::from_millis(10),
+        });
+        store.push(entry(1)).unwrap();
+        assert_eq!(store.queued(), 0);
+
+        // entries are queued until the secondary store recovers, then
+        // retried in order, ahead of later entries
+        store.secondary().set_failing(true);
+        for i in 2..5 {
+            store.push(entry(i)).unwrap();
+        }
+        assert_eq!(store.queued(), 2);
+        assert_eq!(*failed.lock().unwrap(), vec![4]);
+        thread::sleep(Duration::from_millis(30));
+        assert_eq!(store.queued(), 2);
+
+        store.secondary().set_failing(false);
+        let start = Instant::now();
+        while store.queued() > 0 {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(5));
+        }
+        for i in 5..7 {
+            store.push(entry(i)).unwrap();
+        }
+        assert_eq!(store.queued(), 0);
+
+        assert_eq!(store.primary().values(), vec![1, 2, 3, 4, 5, 6]);
+        assert_eq!(store.secondary().values(), vec![1, 2, 3, 5, 6]);
+        assert_eq!(*failed.lock().unwrap(), vec![4]);
+
+        store.primary().set_failing(true);
+        assert!(matches!(store.push(entry(7)), Err(Error::Io(_))));
+        assert_eq!(store.secondary().values(), vec![1, 2, 3, 5, 6]);
+    }
+
+    #[test]
+    fn latest_reads_primary() {
+        let (store, _) = tee(TeePolicy::Primary);
+        store.push(entry(1)).unwrap();
+        store.secondary().store.push(entry(2)).unwrap();
+        assert_eq!(store.latest("test_tee").unwrap(), Some(entry(1).into_owned()));
+        assert_eq!(store.capabilities(), Capabilities::empty());
+    }
+}
@@ -1,26 +1,285 @@
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::ops::RangeBounds;
-use std::time::Duration;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
-use crate::{Entry, Error};
+use crate::{utils, Entry, EntryMetadata, Error, LazyEntry, Waker};
 
 use string_cache::DefaultAtom as Atom;
 
+/// A callback invoked with every entry after it has been successfully pushed.
+pub type PushCallback = Arc<dyn Fn(&Entry) + Send + Sync>;
+
+/// Statistics on a compaction, which replaces entries with rollups of them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CompactionReport {
+    /// The number of entries passed to the rollup function.
+    pub rows_read: u64,
+    /// The number of rollup entries written.
+    pub rows_written: u64,
+    /// The number of entries deleted.
+    pub rows_deleted: u64,
+}
+
+/// The order a range's entries are iterated in. Entries that tie are
+/// iterated in the order they were pushed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OrderBy {
+    /// Ascending timestamp, then ascending name.
+    #[default]
+    Time,
+    /// Ascending name, then ascending timestamp, so that each name's entries
+    /// are iterated together.
+    NameThenTime,
+}
+
+bitflags! {
+    /// Features supported by a store, for generic code that needs to check
+    /// them at runtime.
+    pub struct Capabilities: u8 {
+        /// The store implements `RangeableStore`.
+        const RANGE = 1;
+        /// The store implements `SubscribeableStore`.
+        const SUBSCRIBE = 1 << 1;
+        /// Entries can be removed via `Range::remove`.
+        const REMOVE = 1 << 2;
+        /// Entries outlive the process that pushed them.
+        const DURABLE = 1 << 3;
+        /// Values are compressed at rest.
+        const COMPRESSED = 1 << 4;
+        /// Entries can be pushed with metadata via `Store::push_with_metadata`.
+        const METADATA = 1 << 5;
+        /// Entries can be pushed at, and read by, their sequence numbers via
+        /// `Store::push_at_id` and `Store::get_by_id`.
+        const IDS = 1 << 6;
+    }
+}
+
 pub trait Store: Send + Sync {
+    /// Pushes an entry. Entries aren't validated, other than against the
+    /// store's own limits; build them via `Entry::builder`, or check them
+    /// with `Entry::validate`, to reject what some stores can't hold.
     fn push(&self, entry: Cow<Entry>) -> Result<(), Error>;
+
+    /// Pushes an entry from its parts, e.g. for ingest loops that reuse a
+    /// buffer for values. By default, this builds an `Entry`, copying the
+    /// value. Stores that only need the value while pushing it implement this
+    /// without the copy.
+    fn push_parts(&self, timestamp: i64, name: &Atom, value: &[u8]) -> Result<(), Error> {
+        self.push(Cow::Owned(Entry::new_with_timestamp(
+            timestamp,
+            name.clone(),
+            value.to_vec(),
+        )))
+    }
+
     fn latest<A: Into<Atom>>(&self, name: A) -> Result<Option<Entry>, Error>;
+
+    /// Gets the features the store supports. By default, none are reported.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::empty()
+    }
+
+    /// Gets up to the `n` latest entries with a name, oldest first, i.e. the
+    /// entry `latest` returns and the ones it'd return as each newer one were
+    /// removed. Like `latest`, entries flagged as skewed are skipped if the
+    /// store's options ignore them in `latest`. Stores that don't support
+    /// this fail with `Error::Unsupported`.
+    fn latest_n<A: Into<Atom>>(&self, name: A, n: usize) -> Result<Vec<Entry>, Error> {
+        let _ = (name.into(), n);
+        Err(Error::Unsupported("latest_n".to_string()))
+    }
+
+    /// Pushes an entry alongside metadata, which is read back via the
+    /// store's `iter_with_metadata`. Stores that don't support metadata fail
+    /// with `Error::Unsupported`.
+    fn push_with_metadata(&self, entry: Cow<Entry>, metadata: EntryMetadata) -> Result<(), Error> {
+        let _ = (entry, metadata);
+        Err(Error::Unsupported("entry metadata".to_string()))
+    }
+
+    /// Pushes an entry, replacing the value at its timestamp and name if one
+    /// was pushed there by `push_or_replace`, so there's at most one such
+    /// value per timestamp and name. Values pushed otherwise are never
+    /// replaced. Returns whether a value was replaced. Stores that can't
+    /// replace values fail with `Error::Unsupported`.
+    fn push_or_replace(&self, entry: Cow<Entry>) -> Result<bool, Error> {
+        let _ = entry;
+        Err(Error::Unsupported("replacing values".to_string()))
+    }
+
+    /// Pushes an entry with an explicit sequence number, e.g. to replay
+    /// entries with the ids they had in another store. Returns whether the
+    /// entry was pushed, which it isn't if an entry with the id already
+    /// exists. Later pushes are assigned ids after the largest one pushed
+    /// this way. Replayed entries aren't checked against the store's skew
+    /// bounds, and aren't guaranteed to reach subscribers. Stores that don't
+    /// support explicit ids fail with `Error::Unsupported`.
+    fn push_at_id(&self, id: u64, entry: Cow<Entry>) -> Result<bool, Error> {
+        let _ = (id, entry);
+        Err(Error::Unsupported("explicit ids".to_string()))
+    }
+
+    /// Gets the entry with a sequence number. Stores that don't support
+    /// reading by id fail with `Error::Unsupported`.
+    fn get_by_id(&self, id: u64) -> Result<Option<Entry>, Error> {
+        let _ = id;
+        Err(Error::Unsupported("explicit ids".to_string()))
+    }
 }
 
 pub trait RangeableStore: Store {
     type Range: Range;
     fn range<A: Into<Atom>, R: RangeBounds<i64>>(&self, range: R, name: Option<A>) -> Result<Self::Range, Error>;
+
+    /// Like `range`, but with bounds given as system times rather than
+    /// timestamps. Fails with `Error::TimeTooLarge` if a bound is too far
+    /// from the unix epoch to be a timestamp. Bounds with fractions of a
+    /// microsecond are rounded so that the range only includes timestamps
+    /// within them.
+    fn range_time<A: Into<Atom>, R: RangeBounds<SystemTime>>(
+        &self,
+        range: R,
+        name: Option<A>,
+    ) -> Result<Self::Range, Error> {
+        let start = utils::start_bound_micros(utils::system_time_bound(range.start_bound())?);
+        let end = utils::end_bound_micros(utils::system_time_bound(range.end_bound())?);
+        self.range((start, end), name)
+    }
+
+    /// Like `range`, but with bounds given as datetimes rather than
+    /// timestamps. Like `range_time`, bounds with fractions of a microsecond
+    /// are rounded so that the range only includes timestamps within them.
+    #[cfg(feature = "time")]
+    fn range_offset_datetime<A: Into<Atom>, R: RangeBounds<time::OffsetDateTime>>(
+        &self,
+        range: R,
+        name: Option<A>,
+    ) -> Result<Self::Range, Error> {
+        let start = utils::start_bound_micros(utils::offset_datetime_bound(range.start_bound()));
+        let end = utils::end_bound_micros(utils::offset_datetime_bound(range.end_bound()));
+        self.range((start, end), name)
+    }
+
+    /// Counts every entry in the store.
+    fn count(&self) -> Result<u64, Error> {
+        self.range(.., Option::<Atom>::None)?.count()
+    }
+
+    /// Removes every entry in the store, returning them in ascending
+    /// timestamp order, then ascending name order, then insertion order.
+    /// By default, entries are read and then removed in two steps, so
+    /// entries pushed in between are removed without being returned; every
+    /// built-in store overrides this to avoid that.
+    fn drain_all(&self) -> Result<Vec<Entry>, Error> {
+        let entries = self
+            .range(.., Option::<Atom>::None)?
+            .iter()?
+            .collect::<Result<Vec<Entry>, Error>>()?;
+        self.range(.., Option::<Atom>::None)?.remove()?;
+        Ok(entries)
+    }
+
+    /// Like `range`, but yields the entries in pages of up to `page_size`
+    /// entries each, so that large ranges can be processed in batches.
+    fn paginate<A: Into<Atom>, R: RangeBounds<i64>>(
+        &self,
+        range: R,
+        name: Option<A>,
+        page_size: usize,
+    ) -> Result<impl Iterator<Item = Result<Vec<Entry>, Error>>, Error> {
+        Ok(utils::Pages::new(self.range(range, name)?.iter()?, page_size))
+    }
+
+    /// Atomically moves every entry named `old` to `new`, returning the
+    /// number of entries moved. Histories are never merged: if `new` already
+    /// has entries, this fails with `Error::NameInUse` and nothing is moved.
+    /// Subscriptions to `old` are not transferred, and won't see entries
+    /// pushed under `new`. Stores that can't rename fail with
+    /// `Error::Unsupported`.
+    fn rename<A: Into<Atom>, B: Into<Atom>>(&self, old: A, new: B) -> Result<u64, Error> {
+        let _ = (old.into(), new.into());
+        Err(Error::Unsupported("renaming".to_string()))
+    }
 }
 
+/// A range of entries. Ranges that match no entries, whether because their
+/// name has none or because no timestamp falls within their bounds, are
+/// empty rather than errors: `count` is zero, `is_empty` is true, `iter`
+/// yields nothing, `paginate` yields no pages, and `remove` succeeds without
+/// doing anything. Stores skip reading from their backend when the bounds
+/// alone show the range is empty.
 pub trait Range {
+    /// Yields the range's entries. Stores that read entries lazily may fail
+    /// part way through, in which case they yield the error and retry the
+    /// failed read on the next call, ending after a bounded number of
+    /// failures in a row. So callers can either stop at the first error, or
+    /// log errors and keep iterating without spinning forever.
     type Iter: Iterator<Item = Result<Entry, Error>>;
     fn count(&self) -> Result<u64, Error>;
+    /// Returns whether the range has no entries. Stores should override this
+    /// when they can answer without counting every entry.
+    fn is_empty(&self) -> Result<bool, Error> {
+        Ok(self.count()? == 0)
+    }
+    /// Returns whether the range has any entries.
+    fn exists(&self) -> Result<bool, Error> {
+        Ok(!self.is_empty()?)
+    }
     fn remove(self) -> Result<(), Error>;
+    /// Iterates over the entries in the range, ordered by timestamp, then
+    /// name, then insertion order. Stores that assign sequence numbers never
+    /// reuse them, so entries sharing a timestamp and name are also ordered
+    /// by sequence number.
     fn iter(self) -> Result<Self::Iter, Error>;
+
+    /// Like `iter`, but only yields each entry's timestamp. Stores should
+    /// override this when they can avoid reading values.
+    fn iter_timestamps(self) -> Result<impl Iterator<Item = Result<i64, Error>>, Error>
+    where
+        Self: Sized,
+    {
+        Ok(self.iter()?.map(|entry| entry.map(|entry| entry.timestamp)))
+    }
+
+    /// Like `iter`, but only yields each entry's name and timestamp. Stores
+    /// should override this when they can avoid reading values.
+    fn iter_names_and_timestamps(self) -> Result<impl Iterator<Item = Result<(Atom, i64), Error>>, Error>
+    where
+        Self: Sized,
+    {
+        Ok(self
+            .iter()?
+            .map(|entry| entry.map(|entry| (entry.name, entry.timestamp))))
+    }
+
+    /// Like `iter`, but values are only decoded when they're read via
+    /// `LazyValue::get`, e.g. to scan timestamps and names while reading only
+    /// a few values. Decoding errors are returned by `get`. Stores should
+    /// override this when they store values encoded; by default, values are
+    /// decoded up front.
+    fn iter_lazy(self) -> Result<impl Iterator<Item = Result<LazyEntry, Error>>, Error>
+    where
+        Self: Sized,
+    {
+        Ok(self.iter()?.map(|entry| entry.map(LazyEntry::from)))
+    }
+
+    /// Collects the range's entries into one vector per name, each ordered
+    /// by timestamp, then insertion order. Stores should override this when
+    /// they can read each name's entries together.
+    fn zip_by_name(self) -> Result<HashMap<Atom, Vec<Entry>>, Error>
+    where
+        Self: Sized,
+    {
+        let mut entries_by_name: HashMap<Atom, Vec<Entry>> = HashMap::default();
+        for entry in self.iter()? {
+            let entry = entry?;
+            entries_by_name.entry(entry.name.clone()).or_default().push(entry);
+        }
+        Ok(entries_by_name)
+    }
 }
 
 pub trait SubscribeableStore: Store {
@@ -31,3 +290,12 @@ pub trait SubscribeableStore: Store {
 pub trait Subscription {
     fn next(&mut self, timeout: Option<Duration>) -> Result<Option<Entry>, Error>;
 }
+
+/// A subscription that can be waited on alongside others via `select`.
+pub trait SubscriptionDyn {
+    /// Registers a waker that is woken whenever a new entry may be available,
+    /// including immediately if one already is. Wakeups may be spurious.
+    fn register_waker(&mut self, waker: Waker) -> Result<(), Error>;
+    /// Gets the next entry if one is available, without blocking.
+    fn try_next(&mut self) -> Result<Option<Entry>, Error>;
+}
@@ -0,0 +1,1166 @@
+//! Test suites that check a store implementation's behavior, for use by the
+//! built-in stores and by downstream crates certifying their own stores.
+//! Enabled via the `testkit` feature.
+//!
+//! Each suite takes a factory that creates a fresh store. Factories are
+//! passed a `TempFiles`, which hands out paths for stores that need a file,
+//! and outlives every store created during the suite:
+//!
+//! ```ignore
+//! #[test]
+//! fn rangeable_suite() {
+//!     binlog::testkit::run_rangeable_suite(|files| MyStore::open(files.path()).unwrap());
+//! }
+//! ```
+//!
+//! Alternatively, `test_store_impl!`, `test_rangeable_store_impl!` and
+//! `test_subscribeable_store_impl!` define a separate test for each check,
+//! so that failures are reported individually:
+//!
+//! ```
+//! mod tests {
+//!     use binlog::MemoryStore;
+//!
+//!     binlog::test_store_impl!(|_| MemoryStore::default());
+//!     binlog::test_rangeable_store_impl!(|_| MemoryStore::default());
+//!     binlog::test_subscribeable_store_impl!(|_| MemoryStore::default());
+//! }
+//!
+//! binlog::testkit::run_rangeable_suite(|_| binlog::MemoryStore::default());
+//! ```
+//!
+//! Stores backed by a shared server should isolate each created store (e.g.
+//! with a unique key prefix), since the tests assume they start empty.
+
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::ops::{Bound, RangeBounds};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use crate::{
+    Capabilities, Entry, EntryMetadata, Error, IngestionLagStats, LazyEntry, ManualClock, Range, RangeableStore,
+    SkewPolicy, SlowOp, SlowOpConfig, SlowOpKind, Store, StoreOptions, StoredEntry, SubscribeableStore, Subscription,
+    SubscriptionDyn, UNNAMED,
+};
+
+use string_cache::DefaultAtom as Atom;
+use tempfile::TempDir;
+
+/// A temporary directory that hands out unique file paths. The directory,
+/// and everything in it, is deleted when this is dropped, so it must outlive
+/// any store using one of its paths.
+pub struct TempFiles {
+    dir: TempDir,
+    next_index: AtomicUsize,
+}
+
+impl TempFiles {
+    pub fn new() -> Self {
+        Self {
+            dir: TempDir::new().expect("failed to create a temporary directory"),
+            next_index: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns a path in the directory that no other call has returned. The
+    /// file does not exist yet.
+    pub fn path(&self) -> PathBuf {
+        let index = self.next_index.fetch_add(1, Ordering::SeqCst);
+        self.dir.path().join(format!("store-{}.db", index))
+    }
+}
+
+impl Default for TempFiles {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Runs every test that applies to all stores, each against a fresh store.
+pub fn run_store_suite<S, F>(make_store: F)
+where
+    S: Store + Clone,
+    F: Fn(&TempFiles) -> S,
+{
+    let files = TempFiles::new();
+    latest(&make_store(&files));
+    latest_ties(&make_store(&files));
+    latest_n(&make_store(&files));
+    push_parts(&make_store(&files));
+    unnamed(&make_store(&files));
+}
+
+/// Runs every test that applies to rangeable stores, each against a fresh
+/// store.
+pub fn run_rangeable_suite<S, F>(make_store: F)
+where
+    S: RangeableStore + Clone + Send + 'static,
+    F: Fn(&TempFiles) -> S,
+{
+    let files = TempFiles::new();
+    remove(&make_store(&files));
+    iter(&make_store(&files));
+    bounds(&make_store(&files));
+    extreme_bounds(&make_store(&files));
+    range_time(&make_store(&files));
+    insertion_order(&make_store(&files));
+    is_empty(&make_store(&files));
+    empty_ranges(&make_store(&files));
+    rangeable_capabilities(&make_store(&files));
+    count(&make_store(&files));
+    insertion_order_after_remove(&make_store(&files));
+    concurrent_push(&make_store(&files));
+    rename(&make_store(&files));
+    paginate(&make_store(&files));
+    projections(&make_store(&files));
+    zip_by_name(&make_store(&files));
+    drain_all(&make_store(&files));
+    unnamed_ranges(&make_store(&files));
+    iter_lazy(&make_store(&files));
+}
+
+/// Runs every test that applies to subscribeable stores, each against a
+/// fresh store.
+pub fn run_subscribeable_suite<S, F>(make_store: F)
+where
+    S: SubscribeableStore + Clone + 'static,
+    S::Subscription: SubscriptionDyn,
+    F: Fn(&TempFiles) -> S,
+{
+    let files = TempFiles::new();
+    pubsub(&make_store(&files));
+    select(&make_store(&files));
+    subscribeable_capabilities(&make_store(&files));
+    unnamed_pubsub(&make_store(&files));
+}
+
+/// Defines a unit test function. The store constructor is called with a
+/// `TempFiles` that outlives the store.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! define_test {
+    ($name:ident, $store_constructor:expr) => {
+        #[test]
+        fn $name() {
+            let files = $crate::testkit::TempFiles::new();
+            let store = ($store_constructor)(&files);
+            $crate::testkit::$name(&store);
+        }
+    };
+}
+
+/// Defines a unit test for each check in `run_store_suite`, so that failures
+/// are reported per check. Takes the same store factory.
+#[macro_export]
+macro_rules! test_store_impl {
+    ($code:expr) => {
+        $crate::define_test!(latest, $code);
+        $crate::define_test!(latest_ties, $code);
+        $crate::define_test!(latest_n, $code);
+        $crate::define_test!(push_parts, $code);
+        $crate::define_test!(unnamed, $code);
+    };
+}
+
+/// Defines a unit test for each check in `run_rangeable_suite`.
+#[macro_export]
+macro_rules! test_rangeable_store_impl {
+    ($code:expr) => {
+        $crate::define_test!(remove, $code);
+        $crate::define_test!(iter, $code);
+        $crate::define_test!(bounds, $code);
+        $crate::define_test!(extreme_bounds, $code);
+        $crate::define_test!(range_time, $code);
+        $crate::define_test!(insertion_order, $code);
+        $crate::define_test!(is_empty, $code);
+        $crate::define_test!(empty_ranges, $code);
+        $crate::define_test!(rangeable_capabilities, $code);
+        $crate::define_test!(count, $code);
+        $crate::define_test!(insertion_order_after_remove, $code);
+        $crate::define_test!(concurrent_push, $code);
+        $crate::define_test!(rename, $code);
+        $crate::define_test!(paginate, $code);
+        $crate::define_test!(projections, $code);
+        $crate::define_test!(zip_by_name, $code);
+        $crate::define_test!(drain_all, $code);
+        $crate::define_test!(unnamed_ranges, $code);
+        $crate::define_test!(iter_lazy, $code);
+    };
+}
+
+/// Defines a unit test for each check in `run_subscribeable_suite`.
+#[macro_export]
+macro_rules! test_subscribeable_store_impl {
+    ($code:expr) => {
+        $crate::define_test!(pubsub, $code);
+        $crate::define_test!(select, $code);
+        $crate::define_test!(subscribeable_capabilities, $code);
+        $crate::define_test!(unnamed_pubsub, $code);
+    };
+}
+
+fn insert_sample_data<S: Store>(store: &S, name: &str) -> Result<(), Error> {
+    for i in 1..11 {
+        let entry = Entry::new_with_timestamp(i.into(), name, vec![i]);
+        store.push(Cow::Owned(entry))?;
+    }
+    Ok(())
+}
+
+fn check_sample_data(mut results: VecDeque<Result<Entry, Error>>, name: &str) -> Result<(), Error> {
+    assert_eq!(results.len(), 10);
+    for i in 1..11u8 {
+        let result = results.pop_front().unwrap()?;
+        assert_eq!(result, Entry::new_with_timestamp(i.into(), name, vec![i]));
+    }
+    Ok(())
+}
+
+pub fn remove<S: RangeableStore>(store: &S) {
+    insert_sample_data(store, "test_remove").unwrap();
+    assert_eq!(store.range(.., Option::<Atom>::None).unwrap().count().unwrap(), 10);
+    store.range(2.., Option::<Atom>::None).unwrap().remove().unwrap();
+    assert_eq!(store.range(.., Option::<Atom>::None).unwrap().count().unwrap(), 1);
+    store.range(.., Some("test_remove")).unwrap().remove().unwrap();
+    assert_eq!(store.range(.., Option::<Atom>::None).unwrap().count().unwrap(), 0);
+}
+
+pub fn iter<S: RangeableStore>(store: &S) {
+    insert_sample_data(store, "test_iter").unwrap();
+    let results: VecDeque<Result<Entry, Error>> =
+        store.range(.., Option::<Atom>::None).unwrap().iter().unwrap().collect();
+    check_sample_data(results, "test_iter").unwrap();
+}
+
+pub fn bounds<S: RangeableStore>(store: &S) {
+    // Several entries share each timestamp, including the boundaries, so that
+    // off-by-one errors on either end are caught.
+    for i in 1..6i64 {
+        for name in ["test_bounds_a", "test_bounds_b"] {
+            store
+                .push(Cow::Owned(Entry::new_with_timestamp(i, name, vec![i as u8])))
+                .unwrap();
+        }
+    }
+
+    let bounds = [
+        Bound::Included(2),
+        Bound::Excluded(2),
+        Bound::Included(4),
+        Bound::Excluded(4),
+        Bound::Unbounded,
+    ];
+    for start_bound in bounds {
+        for end_bound in bounds {
+            let range = (start_bound, end_bound);
+            let expected: Vec<Entry> = (1..6i64)
+                .filter(|i| range.contains(i))
+                .flat_map(|i| {
+                    ["test_bounds_a", "test_bounds_b"]
+                        .into_iter()
+                        .map(move |name| Entry::new_with_timestamp(i, name, vec![i as u8]))
+                })
+                .collect();
+            let count = match store.range(range, Option::<Atom>::None) {
+                Ok(range) => range.count().unwrap(),
+                Err(Error::BadRange) => {
+                    assert!(expected.is_empty(), "bad range {:?}", range);
+                    continue;
+                }
+                Err(err) => panic!("unexpected error: {}", err),
+            };
+            assert_eq!(count, expected.len() as u64, "count of {:?}", range);
+            let mut results: Vec<Entry> = store
+                .range(range, Option::<Atom>::None)
+                .unwrap()
+                .iter()
+                .unwrap()
+                .collect::<Result<Vec<Entry>, Error>>()
+                .unwrap();
+            results.sort_by(|a, b| (a.timestamp, &*a.name).cmp(&(b.timestamp, &*b.name)));
+            assert_eq!(results, expected, "entries of {:?}", range);
+        }
+    }
+
+    store
+        .range((Bound::Excluded(2), Bound::Excluded(4)), Some("test_bounds_a"))
+        .unwrap()
+        .remove()
+        .unwrap();
+    assert_eq!(store.range(.., Some("test_bounds_a")).unwrap().count().unwrap(), 4);
+    store
+        .range((Bound::Excluded(2), Bound::Unbounded), Option::<Atom>::None)
+        .unwrap()
+        .remove()
+        .unwrap();
+    assert_eq!(store.range(.., Option::<Atom>::None).unwrap().count().unwrap(), 4);
+}
+
+pub fn extreme_bounds<S: RangeableStore>(store: &S) {
+    let name = "test_extreme_bounds";
+    for timestamp in [i64::MIN, i64::MAX] {
+        store
+            .push(Cow::Owned(Entry::new_with_timestamp(timestamp, name, vec![])))
+            .unwrap();
+    }
+    let count = |range: (Bound<i64>, Bound<i64>)| match store.range(range, Some(name)) {
+        Ok(range) => range.count().unwrap(),
+        Err(Error::BadRange) => 0,
+        Err(err) => panic!("unexpected error: {}", err),
+    };
+    assert_eq!(count((Bound::Excluded(i64::MAX), Bound::Unbounded)), 0);
+    assert_eq!(count((Bound::Unbounded, Bound::Excluded(i64::MIN))), 0);
+    assert_eq!(count((Bound::Excluded(i64::MIN), Bound::Unbounded)), 1);
+    assert_eq!(count((Bound::Unbounded, Bound::Excluded(i64::MAX))), 1);
+    assert_eq!(count((Bound::Included(i64::MIN), Bound::Included(i64::MAX))), 2);
+    assert_eq!(count((Bound::Unbounded, Bound::Unbounded)), 2);
+}
+
+pub fn range_time<S: RangeableStore>(store: &S) {
+    let name = "test_range_time";
+    for timestamp in [-1_000_000, 1_000_000, 2_000_000] {
+        store
+            .push(Cow::Owned(Entry::new_with_timestamp(timestamp, name, vec![])))
+            .unwrap();
+    }
+    let count = |range: (Bound<_>, Bound<_>)| store.range_time(range, Some(name)).unwrap().count().unwrap();
+    let second = UNIX_EPOCH + Duration::from_secs(1);
+    assert_eq!(count((Bound::Unbounded, Bound::Unbounded)), 3);
+    assert_eq!(count((Bound::Unbounded, Bound::Excluded(UNIX_EPOCH))), 1);
+    assert_eq!(count((Bound::Included(second), Bound::Unbounded)), 2);
+    assert_eq!(count((Bound::Excluded(second), Bound::Unbounded)), 1);
+    // platforms with a narrower `SystemTime` can't represent this at all
+    if let Some(far) = UNIX_EPOCH.checked_add(Duration::from_secs(u64::MAX / 2)) {
+        assert!(matches!(store.range_time(far.., Some(name)), Err(Error::TimeTooLarge)));
+    }
+}
+
+pub fn insertion_order<S: RangeableStore>(store: &S) {
+    for i in 0..5u8 {
+        let entry = Entry::new_with_timestamp(0, "test_insertion_order", vec![4 - i]);
+        store.push(Cow::Owned(entry)).unwrap();
+    }
+    let results: Vec<Entry> = store
+        .range(.., Some("test_insertion_order"))
+        .unwrap()
+        .iter()
+        .unwrap()
+        .collect::<Result<Vec<Entry>, Error>>()
+        .unwrap();
+    let values: Vec<u8> = results.iter().map(|entry| entry.value[0]).collect();
+    assert_eq!(values, vec![4, 3, 2, 1, 0]);
+    // Entries with equal timestamps and names are only ordered by insertion
+    // if the store assigns sequence numbers
+    if results.iter().all(|entry| entry.sequence_number.is_some()) {
+        for pair in results.windows(2) {
+            assert!(pair[0].sequence_number.unwrap() < pair[1].sequence_number.unwrap());
+            assert!(pair[0] < pair[1]);
+        }
+    }
+    let latest = store.latest("test_insertion_order").unwrap().unwrap();
+    assert_eq!(latest.sequence_number, results[4].sequence_number);
+}
+
+pub fn insertion_order_after_remove<S: RangeableStore>(store: &S) {
+    let name = "test_insertion_order_after_remove";
+    let push = |timestamp: i64, value: u8| {
+        let entry = Entry::new_with_timestamp(timestamp, name, vec![value]);
+        store.push(Cow::Owned(entry)).unwrap();
+    };
+    let entries = || -> Vec<Entry> {
+        store
+            .range(.., Some(name))
+            .unwrap()
+            .iter()
+            .unwrap()
+            .collect::<Result<Vec<Entry>, Error>>()
+            .unwrap()
+    };
+
+    push(0, 0);
+    push(1, 1);
+    let removed_sequence_number = entries()[1].sequence_number;
+    // removing the newest entry mustn't let its sequence number be reused
+    store.range(1.., Some(name)).unwrap().remove().unwrap();
+    push(0, 2);
+    let results = entries();
+    let values: Vec<u8> = results.iter().map(|entry| entry.value[0]).collect();
+    assert_eq!(values, vec![0, 2]);
+    if let Some(removed_sequence_number) = removed_sequence_number {
+        assert!(results[1].sequence_number.unwrap() > removed_sequence_number);
+        assert!(results[0] < results[1]);
+    }
+}
+
+pub fn empty_ranges<S: RangeableStore>(store: &S) {
+    insert_sample_data(store, "test_empty_ranges").unwrap();
+    // names without entries, and bounds that no timestamp falls within, are
+    // empty rather than errors
+    let cases = [
+        ((Bound::Unbounded, Bound::Unbounded), Some("test_empty_ranges_other")),
+        ((Bound::Excluded(1), Bound::Excluded(2)), Some("test_empty_ranges")),
+        ((Bound::Excluded(i64::MAX), Bound::Unbounded), None),
+        ((Bound::Unbounded, Bound::Excluded(i64::MIN)), None),
+    ];
+    for (bounds, name) in cases {
+        let range = || store.range(bounds, name).unwrap();
+        assert_eq!(range().count().unwrap(), 0, "count of {:?}", bounds);
+        assert!(range().is_empty().unwrap(), "is_empty of {:?}", bounds);
+        assert!(!range().exists().unwrap(), "exists of {:?}", bounds);
+        assert_eq!(range().iter().unwrap().count(), 0, "entries of {:?}", bounds);
+        assert_eq!(range().iter_timestamps().unwrap().count(), 0);
+        assert_eq!(range().iter_names_and_timestamps().unwrap().count(), 0);
+        assert_eq!(store.paginate(bounds, name, 3).unwrap().count(), 0);
+        range().remove().unwrap();
+    }
+    assert_eq!(store.range(.., Some("test_empty_ranges")).unwrap().count().unwrap(), 10);
+}
+
+pub fn is_empty<S: RangeableStore>(store: &S) {
+    assert!(store.range(.., Some("test_is_empty")).unwrap().is_empty().unwrap());
+    insert_sample_data(store, "test_is_empty").unwrap();
+    assert!(!store.range(.., Some("test_is_empty")).unwrap().is_empty().unwrap());
+    assert!(!store.range(10.., Some("test_is_empty")).unwrap().is_empty().unwrap());
+    assert!(store.range(11.., Some("test_is_empty")).unwrap().is_empty().unwrap());
+    assert!(store.range(..1, Option::<Atom>::None).unwrap().is_empty().unwrap());
+    assert!(store
+        .range(.., Some("test_is_empty_other"))
+        .unwrap()
+        .is_empty()
+        .unwrap());
+}
+
+pub fn count<S: RangeableStore>(store: &S) {
+    let range_count = || store.range(.., Option::<Atom>::None).unwrap().count().unwrap();
+    assert_eq!(store.count().unwrap(), 0);
+    for (i, name) in ["test_count_a", "test_count_b", "test_count_a"].into_iter().enumerate() {
+        insert_sample_data(store, name).unwrap();
+        assert_eq!(store.count().unwrap(), 10 * (i as u64 + 1));
+        assert_eq!(store.count().unwrap(), range_count());
+    }
+    store.range(5.., Some("test_count_a")).unwrap().remove().unwrap();
+    assert_eq!(store.count().unwrap(), 18);
+    assert_eq!(store.count().unwrap(), range_count());
+}
+
+pub fn drain_all<S: RangeableStore>(store: &S) {
+    assert!(store.drain_all().unwrap().is_empty());
+    for i in 0..100i64 {
+        let name = if i % 2 == 0 {
+            "test_drain_all_a"
+        } else {
+            "test_drain_all_b"
+        };
+        let entry = Entry::new_with_timestamp((i * 37) % 100, name, vec![i as u8]);
+        store.push(Cow::Owned(entry)).unwrap();
+    }
+    let entries = store.drain_all().unwrap();
+    assert_eq!(entries.len(), 100);
+    let timestamps: Vec<i64> = entries.iter().map(|entry| entry.timestamp).collect();
+    assert_eq!(timestamps, (0..100).collect::<Vec<i64>>());
+    assert_eq!(entries[1].name, Atom::from("test_drain_all_b"));
+    assert_eq!(entries[1].value, vec![73]);
+    assert_eq!(store.count().unwrap(), 0);
+    assert!(store.range(.., Option::<Atom>::None).unwrap().is_empty().unwrap());
+    assert!(store.drain_all().unwrap().is_empty());
+}
+
+pub fn unnamed_ranges<S: RangeableStore>(store: &S) {
+    for i in 1..4i64 {
+        store
+            .push(Cow::Owned(Entry::new_unnamed_with_timestamp(i, vec![i as u8])))
+            .unwrap();
+        let entry = Entry::new_with_timestamp(i, "test_unnamed_ranges", vec![i as u8]);
+        store.push(Cow::Owned(entry)).unwrap();
+    }
+    let keys = |range: S::Range| -> Vec<(i64, Atom)> {
+        range
+            .iter()
+            .unwrap()
+            .map(|entry| {
+                let entry = entry.unwrap();
+                (entry.timestamp, entry.name)
+            })
+            .collect()
+    };
+    // unnamed entries sort before named ones with the same timestamp, and
+    // are included by ranges over every name that start at their timestamp
+    let all = keys(store.range(2.., Option::<Atom>::None).unwrap());
+    assert_eq!(
+        all,
+        vec![
+            (2, Atom::from(UNNAMED)),
+            (2, Atom::from("test_unnamed_ranges")),
+            (3, Atom::from(UNNAMED)),
+            (3, Atom::from("test_unnamed_ranges")),
+        ]
+    );
+    let unnamed = keys(store.range(2.., Some(UNNAMED)).unwrap());
+    assert_eq!(unnamed, vec![(2, Atom::from(UNNAMED)), (3, Atom::from(UNNAMED))]);
+    assert_eq!(store.range(.., Some(UNNAMED)).unwrap().count().unwrap(), 3);
+    store.range(..3, Some(UNNAMED)).unwrap().remove().unwrap();
+    assert_eq!(store.range(.., Some(UNNAMED)).unwrap().count().unwrap(), 1);
+    assert_eq!(store.count().unwrap(), 4);
+}
+
+pub fn iter_lazy<S: RangeableStore>(store: &S) {
+    // enough entries to span several pages, with values that are compressed
+    // and values that are too small to be
+    for i in 0..2500i64 {
+        let name = if i % 2 == 0 {
+            "test_iter_lazy_a"
+        } else {
+            "test_iter_lazy_b"
+        };
+        let value = if i % 3 == 0 { vec![i as u8; 4096] } else { vec![i as u8] };
+        store
+            .push(Cow::Owned(Entry::new_with_timestamp(i, name, value)))
+            .unwrap();
+    }
+    for name in [None, Some("test_iter_lazy_a")] {
+        let eager: Vec<Entry> = store
+            .range(.., name)
+            .unwrap()
+            .iter()
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        let lazy: Vec<LazyEntry> = store
+            .range(.., name)
+            .unwrap()
+            .iter_lazy()
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(lazy.len(), eager.len());
+        for (lazy, eager) in lazy.into_iter().zip(eager) {
+            assert_eq!(lazy.value.len(), eager.value.len());
+            assert_eq!(lazy.value.get().unwrap().as_ref(), eager.value.as_slice());
+            assert!(lazy.value.is_decoded());
+            let entry = lazy.into_entry().unwrap();
+            assert_eq!(entry.sequence_number, eager.sequence_number);
+            assert_eq!(entry, eager);
+        }
+    }
+}
+
+pub fn concurrent_push<S: RangeableStore + Clone + Send + 'static>(store: &S) {
+    let threads: Vec<thread::JoinHandle<()>> = (0..4i64)
+        .map(|i| {
+            let store = store.clone();
+            thread::spawn(move || {
+                for j in 0..50i64 {
+                    let entry = Entry::new_with_timestamp(j, "test_concurrent_push", vec![i as u8]);
+                    store.push(Cow::Owned(entry)).unwrap();
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let results: Vec<Entry> = store
+        .range(.., Some("test_concurrent_push"))
+        .unwrap()
+        .iter()
+        .unwrap()
+        .collect::<Result<Vec<Entry>, Error>>()
+        .unwrap();
+    assert_eq!(results.len(), 200);
+    for pair in results.windows(2) {
+        assert!(pair[0].timestamp <= pair[1].timestamp);
+    }
+    // every entry is pushed exactly once, and sequence numbers are unique
+    for j in 0..50i64 {
+        let mut values: Vec<u8> = results
+            .iter()
+            .filter(|entry| entry.timestamp == j)
+            .map(|entry| entry.value[0])
+            .collect();
+        values.sort_unstable();
+        assert_eq!(values, vec![0, 1, 2, 3]);
+    }
+    let mut sequence_numbers: Vec<u64> = results.iter().filter_map(|entry| entry.sequence_number).collect();
+    let len = sequence_numbers.len();
+    sequence_numbers.sort_unstable();
+    sequence_numbers.dedup();
+    assert_eq!(sequence_numbers.len(), len);
+}
+
+pub fn rename<S: RangeableStore>(store: &S) {
+    let count = |name: &str| store.range(.., Some(name)).unwrap().count().unwrap();
+    insert_sample_data(store, "test_rename_old").unwrap();
+    insert_sample_data(store, "test_rename_other").unwrap();
+    assert_eq!(store.rename("test_rename_old", "test_rename_new").unwrap(), 10);
+    assert_eq!(count("test_rename_old"), 0);
+    assert!(store.latest("test_rename_old").unwrap().is_none());
+    let results: VecDeque<Result<Entry, Error>> = store
+        .range(.., Some("test_rename_new"))
+        .unwrap()
+        .iter()
+        .unwrap()
+        .collect();
+    check_sample_data(results, "test_rename_new").unwrap();
+    assert_eq!(store.latest("test_rename_new").unwrap().unwrap().value, vec![10]);
+
+    // Renaming onto a name that has entries fails, rather than merging
+    assert!(matches!(
+        store.rename("test_rename_new", "test_rename_other"),
+        Err(Error::NameInUse(_))
+    ));
+    assert_eq!(count("test_rename_new"), 10);
+    assert_eq!(count("test_rename_other"), 10);
+
+    assert_eq!(store.rename("test_rename_missing", "test_rename_old").unwrap(), 0);
+}
+
+pub fn paginate<S: RangeableStore>(store: &S) {
+    // Timestamps and names are shared between entries, including across page
+    // boundaries
+    for i in 0..250i64 {
+        let name = if i % 2 == 0 {
+            "test_paginate_a"
+        } else {
+            "test_paginate_b"
+        };
+        let entry = Entry::new_with_timestamp(i / 3, name, vec![i as u8]);
+        store.push(Cow::Owned(entry)).unwrap();
+    }
+    let expected: Vec<Entry> = store
+        .range(.., Option::<Atom>::None)
+        .unwrap()
+        .iter()
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+
+    let pages: Vec<Vec<Entry>> = store
+        .paginate(.., Option::<Atom>::None, 100)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    let page_sizes: Vec<usize> = pages.iter().map(Vec::len).collect();
+    assert_eq!(page_sizes, vec![100, 100, 50]);
+    assert_eq!(pages.concat(), expected);
+
+    let pages: Vec<Vec<Entry>> = store
+        .paginate(10..20, Some("test_paginate_a"), 7)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    let page_sizes: Vec<usize> = pages.iter().map(Vec::len).collect();
+    assert_eq!(page_sizes, vec![7, 7, 1]);
+    assert!(pages.concat().iter().all(|entry| &*entry.name == "test_paginate_a"));
+
+    assert_eq!(store.paginate(300.., Option::<Atom>::None, 100).unwrap().count(), 0);
+}
+
+pub fn projections<S: RangeableStore>(store: &S) {
+    for i in 0..30i64 {
+        let name = ["test_projections_a", "test_projections_b", "test_projections_c"][i as usize % 3];
+        store
+            .push(Cow::Owned(Entry::new_with_timestamp(i / 2, name, vec![i as u8])))
+            .unwrap();
+    }
+    let check = |bounds: (Bound<i64>, Bound<i64>), name: Option<&str>| {
+        let range = || store.range(bounds, name).unwrap();
+        let expected: Vec<(Atom, i64)> = range()
+            .iter()
+            .unwrap()
+            .map(|entry| entry.map(|entry| (entry.name, entry.timestamp)))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let names_and_timestamps: Vec<(Atom, i64)> = range()
+            .iter_names_and_timestamps()
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(names_and_timestamps, expected);
+        let timestamps: Vec<i64> = range().iter_timestamps().unwrap().collect::<Result<_, _>>().unwrap();
+        let expected_timestamps: Vec<i64> = expected.iter().map(|(_, timestamp)| *timestamp).collect();
+        assert_eq!(timestamps, expected_timestamps);
+    };
+    check((Bound::Unbounded, Bound::Unbounded), None);
+    check((Bound::Included(3), Bound::Excluded(9)), None);
+    check((Bound::Excluded(3), Bound::Included(9)), Some("test_projections_b"));
+    check((Bound::Included(100), Bound::Unbounded), None);
+
+    assert!(store.range(.., Some("test_projections_a")).unwrap().exists().unwrap());
+    assert!(store.range(14.., Option::<Atom>::None).unwrap().exists().unwrap());
+    assert!(!store.range(15.., Option::<Atom>::None).unwrap().exists().unwrap());
+    assert!(!store.range(.., Some("test_projections_d")).unwrap().exists().unwrap());
+}
+
+pub fn zip_by_name<S: RangeableStore>(store: &S) {
+    for i in 0..15i64 {
+        let (timestamp, name) = if i < 10 {
+            (10 - i, "test_zip_by_name_a")
+        } else {
+            (i, "test_zip_by_name_b")
+        };
+        store
+            .push(Cow::Owned(Entry::new_with_timestamp(timestamp, name, vec![i as u8])))
+            .unwrap();
+    }
+    let entries_by_name = store.range(.., Option::<Atom>::None).unwrap().zip_by_name().unwrap();
+    assert_eq!(entries_by_name.len(), 2);
+    let timestamps = |name: &str| -> Vec<i64> {
+        entries_by_name[&Atom::from(name)]
+            .iter()
+            .map(|entry| entry.timestamp)
+            .collect()
+    };
+    assert_eq!(timestamps("test_zip_by_name_a"), (1..11).collect::<Vec<i64>>());
+    assert_eq!(timestamps("test_zip_by_name_b"), (10..15).collect::<Vec<i64>>());
+
+    let entries_by_name = store
+        .range(5..11, Some("test_zip_by_name_b"))
+        .unwrap()
+        .zip_by_name()
+        .unwrap();
+    assert_eq!(entries_by_name.len(), 1);
+    assert_eq!(entries_by_name[&Atom::from("test_zip_by_name_b")].len(), 1);
+    assert!(store
+        .range(100.., Option::<Atom>::None)
+        .unwrap()
+        .zip_by_name()
+        .unwrap()
+        .is_empty());
+}
+
+pub fn rangeable_capabilities<S: RangeableStore>(store: &S) {
+    assert!(store
+        .capabilities()
+        .contains(Capabilities::RANGE | Capabilities::REMOVE));
+}
+
+pub fn subscribeable_capabilities<S: SubscribeableStore>(store: &S) {
+    assert!(store.capabilities().contains(Capabilities::SUBSCRIBE));
+}
+
+pub fn pubsub<S: SubscribeableStore + Clone>(store: &S) {
+    let mut subscriber = store.subscribe("test_pubsub").unwrap();
+    insert_sample_data(store, "test_pubsub").unwrap();
+    // should get just the last entry
+    let entry = subscriber.next(None).unwrap().unwrap();
+    assert_eq!(entry, Entry::new_with_timestamp(10, "test_pubsub", vec![10]));
+    // should timeout
+    let entry = subscriber.next(Some(Duration::from_millis(10))).unwrap();
+    assert!(entry.is_none());
+}
+
+pub fn unnamed_pubsub<S: SubscribeableStore + Clone>(store: &S) {
+    let mut subscriber = store.subscribe(UNNAMED).unwrap();
+    let entry = Entry::new_with_timestamp(1, "test_unnamed_pubsub", vec![1]);
+    store.push(Cow::Owned(entry)).unwrap();
+    store
+        .push(Cow::Owned(Entry::new_unnamed_with_timestamp(2, vec![2])))
+        .unwrap();
+    let entry = subscriber.next(Some(Duration::from_secs(5))).unwrap().unwrap();
+    assert_eq!(entry, Entry::new_unnamed_with_timestamp(2, vec![2]));
+}
+
+pub fn select<S>(store: &S)
+where
+    S: SubscribeableStore + Clone + 'static,
+    S::Subscription: SubscriptionDyn,
+{
+    let names = ["test_select_0", "test_select_1", "test_select_2"];
+    let mut subscribers: Vec<S::Subscription> = names.iter().map(|name| store.subscribe(*name).unwrap()).collect();
+    macro_rules! select {
+        ($timeout:expr) => {{
+            let mut subscribers: Vec<&mut dyn SubscriptionDyn> = subscribers
+                .iter_mut()
+                .map(|subscriber| subscriber as &mut dyn SubscriptionDyn)
+                .collect();
+            crate::select(&mut subscribers, $timeout).unwrap()
+        }};
+    }
+
+    // drain entries left behind by previous runs against persistent stores,
+    // then it should timeout
+    while select!(Some(Duration::from_millis(10))).is_some() {}
+
+    // interleaved pushes should be delivered promptly, by the right
+    // subscription
+    let pusher_store = store.clone();
+    let pusher = thread::spawn(move || {
+        for i in 1..10i64 {
+            thread::sleep(Duration::from_millis(20));
+            let entry = Entry::new_with_timestamp(i, names[i as usize % 3], vec![i as u8]);
+            pusher_store.push(Cow::Owned(entry)).unwrap();
+        }
+    });
+    let start = Instant::now();
+    for i in 1..10i64 {
+        let (index, entry) = select!(Some(Duration::from_secs(5))).unwrap();
+        assert_eq!(index, i as usize % 3);
+        assert_eq!(entry, Entry::new_with_timestamp(i, names[index], vec![i as u8]));
+    }
+    assert!(start.elapsed() < Duration::from_secs(2));
+    pusher.join().unwrap();
+
+    // when several subscriptions are ready, each is returned in turn
+    for (i, name) in names.iter().enumerate() {
+        let timestamp = 10 + i as i64;
+        store
+            .push(Cow::Owned(Entry::new_with_timestamp(timestamp, *name, vec![])))
+            .unwrap();
+    }
+    let mut indices: Vec<usize> = (0..3).map(|_| select!(None).unwrap().0).collect();
+    indices.sort_unstable();
+    assert_eq!(indices, vec![0, 1, 2]);
+    assert_eq!(select!(Some(Duration::from_millis(10))), None);
+}
+
+pub fn latest<S: Store + Clone>(store: &S) {
+    assert_eq!(store.latest("test_latest").unwrap(), None);
+    insert_sample_data(store, "test_latest").unwrap();
+    assert_eq!(
+        store.latest("test_latest").unwrap(),
+        Some(Entry::new_with_timestamp(10, "test_latest", vec![10]))
+    );
+}
+
+pub fn unnamed<S: Store>(store: &S) {
+    assert_eq!(store.latest(UNNAMED).unwrap(), None);
+    store
+        .push(Cow::Owned(Entry::new_unnamed_with_timestamp(1, vec![1])))
+        .unwrap();
+    let entry = Entry::new_with_timestamp(2, "test_unnamed", vec![2]);
+    store.push(Cow::Owned(entry)).unwrap();
+    let latest = store.latest(UNNAMED).unwrap().unwrap();
+    assert!(latest.is_unnamed());
+    assert_eq!(latest, Entry::new_unnamed_with_timestamp(1, vec![1]));
+}
+
+pub fn latest_ties<S: Store>(store: &S) {
+    // of the entries with the newest timestamp, the last one pushed wins
+    for i in 0..3u8 {
+        let entry = Entry::new_with_timestamp(1, "test_latest_ties", vec![i]);
+        store.push(Cow::Owned(entry)).unwrap();
+    }
+    assert_eq!(
+        store.latest("test_latest_ties").unwrap(),
+        Some(Entry::new_with_timestamp(1, "test_latest_ties", vec![2]))
+    );
+}
+
+/// Checks `latest_n`, which is skipped for stores that don't support it.
+pub fn latest_n<S: Store>(store: &S) {
+    match store.latest_n("test_latest_n", 3) {
+        Err(Error::Unsupported(_)) => return,
+        result => assert_eq!(result.unwrap(), vec![]),
+    }
+    insert_sample_data(store, "test_latest_n").unwrap();
+    let expected = |timestamps: std::ops::RangeInclusive<u8>| -> Vec<Entry> {
+        timestamps
+            .map(|i| Entry::new_with_timestamp(i.into(), "test_latest_n", vec![i]))
+            .collect()
+    };
+    assert_eq!(store.latest_n("test_latest_n", 3).unwrap(), expected(8..=10));
+    assert_eq!(store.latest_n("test_latest_n", 100).unwrap(), expected(1..=10));
+    assert_eq!(store.latest_n("test_latest_n", 0).unwrap(), vec![]);
+
+    // entries that share a timestamp are returned in the order they were
+    // pushed, keeping the last ones pushed
+    for i in 11..14u8 {
+        let entry = Entry::new_with_timestamp(11, "test_latest_n", vec![i]);
+        store.push(Cow::Owned(entry)).unwrap();
+    }
+    assert_eq!(
+        store.latest_n("test_latest_n", 2).unwrap(),
+        vec![
+            Entry::new_with_timestamp(11, "test_latest_n", vec![12]),
+            Entry::new_with_timestamp(11, "test_latest_n", vec![13]),
+        ]
+    );
+}
+
+pub fn push_parts<S: Store>(store: &S) {
+    // a buffer reused for every value, as an ingest loop would
+    let name = Atom::from("test_push_parts");
+    let mut value = Vec::default();
+    for i in 0..3u8 {
+        value.clear();
+        value.extend_from_slice(&[i, i + 1]);
+        store.push_parts(i64::from(i) + 1, &name, &value).unwrap();
+    }
+    assert_eq!(
+        store.latest("test_push_parts").unwrap(),
+        Some(Entry::new_with_timestamp(3, "test_push_parts", vec![2, 3]))
+    );
+}
+
+/// Checks each skew policy against stores created by `make_store`, which is
+/// called once per policy, using a clock fixed at a timestamp of 1000.
+/// Entries more than 100 microseconds away from it are out of bounds.
+pub fn skew_policies<S: Store, F: Fn(StoreOptions) -> S>(make_store: F) {
+    let options = |skew_policy: SkewPolicy| StoreOptions {
+        max_future_skew: Some(Duration::from_micros(100)),
+        max_past_skew: Some(Duration::from_micros(100)),
+        skew_policy,
+        clock: Arc::new(ManualClock::new(1000)),
+        ..StoreOptions::default()
+    };
+    let in_bounds = |name: &str| Entry::new_with_timestamp(950, name, vec![1]);
+    let future = |name: &str| Entry::new_with_timestamp(1_000_000, name, vec![2]);
+
+    let store = make_store(options(SkewPolicy::Reject));
+    store.push(Cow::Owned(in_bounds("test_skew_reject"))).unwrap();
+    assert!(matches!(
+        store.push(Cow::Owned(future("test_skew_reject"))),
+        Err(Error::TimestampOutOfBounds(1_000_000))
+    ));
+    assert!(matches!(
+        store.push(Cow::Owned(Entry::new_with_timestamp(899, "test_skew_reject", vec![]))),
+        Err(Error::TimestampOutOfBounds(899))
+    ));
+    assert!(matches!(
+        store.push_parts(1_000_000, &Atom::from("test_skew_reject"), &[2]),
+        Err(Error::TimestampOutOfBounds(1_000_000))
+    ));
+    assert_eq!(
+        store.latest("test_skew_reject").unwrap(),
+        Some(in_bounds("test_skew_reject"))
+    );
+
+    let store = make_store(options(SkewPolicy::Clamp));
+    store.push(Cow::Owned(future("test_skew_clamp"))).unwrap();
+    let latest = store.latest("test_skew_clamp").unwrap().unwrap();
+    assert_eq!(latest, Entry::new_with_timestamp(1000, "test_skew_clamp", vec![2]));
+    assert!(!latest.skewed);
+    store
+        .push_parts(1_000_000, &Atom::from("test_skew_clamp_parts"), &[2])
+        .unwrap();
+    let latest = store.latest("test_skew_clamp_parts").unwrap().unwrap();
+    assert_eq!(
+        latest,
+        Entry::new_with_timestamp(1000, "test_skew_clamp_parts", vec![2])
+    );
+
+    // without ignoring flagged entries, a future-dated entry shadows the
+    // real data
+    let store = make_store(options(SkewPolicy::Flag {
+        ignore_in_latest: false,
+    }));
+    store.push(Cow::Owned(in_bounds("test_skew_flag"))).unwrap();
+    store.push(Cow::Owned(future("test_skew_flag"))).unwrap();
+    let latest = store.latest("test_skew_flag").unwrap().unwrap();
+    assert_eq!(latest, future("test_skew_flag"));
+    assert!(latest.skewed);
+    store
+        .push_parts(1_000_000, &Atom::from("test_skew_flag_parts"), &[2])
+        .unwrap();
+    assert!(store.latest("test_skew_flag_parts").unwrap().unwrap().skewed);
+
+    let store = make_store(options(SkewPolicy::Flag { ignore_in_latest: true }));
+    assert_eq!(store.latest("test_skew_flag_ignored").unwrap(), None);
+    store.push(Cow::Owned(future("test_skew_flag_ignored"))).unwrap();
+    assert_eq!(store.latest("test_skew_flag_ignored").unwrap(), None);
+    store.push(Cow::Owned(in_bounds("test_skew_flag_ignored"))).unwrap();
+    store.push(Cow::Owned(future("test_skew_flag_ignored"))).unwrap();
+    let latest = store.latest("test_skew_flag_ignored").unwrap().unwrap();
+    assert_eq!(latest, in_bounds("test_skew_flag_ignored"));
+    assert!(!latest.skewed);
+    match store.latest_n("test_skew_flag_ignored", 10) {
+        Err(Error::Unsupported(_)) => {}
+        result => assert_eq!(result.unwrap(), vec![in_bounds("test_skew_flag_ignored")]),
+    }
+}
+
+/// Checks that stores created by `make_store` report slow pushes and
+/// queries. Returns the reported operations, for store-specific checks.
+pub fn slow_ops<S: Store, F: Fn(StoreOptions) -> S>(make_store: F) -> Vec<SlowOp> {
+    let ops = Arc::new(std::sync::Mutex::new(Vec::<SlowOp>::default()));
+    let slow_ops = |threshold: Duration| {
+        let ops = ops.clone();
+        SlowOpConfig {
+            push_threshold: threshold,
+            query_threshold: threshold,
+            callback: Arc::new(move |op: &SlowOp| ops.lock().unwrap().push(op.clone())),
+        }
+    };
+
+    // nothing is that slow
+    let store = make_store(StoreOptions {
+        slow_ops: Some(slow_ops(Duration::from_secs(3600))),
+        ..StoreOptions::default()
+    });
+    store
+        .push(Cow::Owned(Entry::new_with_timestamp(1, "test_slow_ops", vec![1])))
+        .unwrap();
+    store.latest("test_slow_ops").unwrap();
+    assert!(ops.lock().unwrap().is_empty());
+
+    // everything is
+    let store = make_store(StoreOptions {
+        slow_ops: Some(slow_ops(Duration::ZERO)),
+        ..StoreOptions::default()
+    });
+    store
+        .push(Cow::Owned(Entry::new_with_timestamp(2, "test_slow_ops", vec![2])))
+        .unwrap();
+    store.latest("test_slow_ops").unwrap();
+    store.latest("test_slow_ops_missing").unwrap();
+    let ops = ops.lock().unwrap().clone();
+    let summary: Vec<(SlowOpKind, Option<&str>, u64)> =
+        ops.iter().map(|op| (op.kind, op.name.as_deref(), op.rows)).collect();
+    assert_eq!(
+        summary,
+        vec![
+            (SlowOpKind::Push, Some("test_slow_ops"), 1),
+            (SlowOpKind::Query, Some("test_slow_ops"), 1),
+            (SlowOpKind::Query, Some("test_slow_ops_missing"), 0),
+        ]
+    );
+    assert!(ops.iter().all(|op| op.duration > Duration::ZERO));
+    ops
+}
+
+/// Checks that a store replays entries at explicit ids, and assigns later
+/// pushes ids after them.
+pub fn push_at_id<S: Store>(store: S) {
+    assert!(store.capabilities().contains(Capabilities::IDS));
+    let entries = [
+        (5, Entry::new_with_timestamp(2, "test_push_at_id", vec![5])),
+        (2, Entry::new_with_timestamp(2, "test_push_at_id", vec![2])),
+        (9, Entry::new_with_timestamp(1, "test_push_at_id_other", vec![9])),
+    ];
+    for (id, entry) in entries.iter() {
+        assert!(store.push_at_id(*id, Cow::Borrowed(entry)).unwrap());
+    }
+    // taken ids are left alone
+    let duplicate = Entry::new_with_timestamp(3, "test_push_at_id", vec![0]);
+    assert!(!store.push_at_id(5, Cow::Owned(duplicate)).unwrap());
+
+    for (id, entry) in entries.iter() {
+        let actual = store.get_by_id(*id).unwrap().unwrap();
+        assert_eq!(&actual, entry);
+        assert_eq!(actual.sequence_number, Some(*id));
+    }
+    assert_eq!(store.get_by_id(3).unwrap(), None);
+    assert_eq!(store.latest("test_push_at_id").unwrap().unwrap().value, vec![5]);
+
+    let entry = Entry::new_with_timestamp(4, "test_push_at_id", vec![10]);
+    store.push(Cow::Borrowed(&entry)).unwrap();
+    assert_eq!(store.get_by_id(10).unwrap(), Some(entry));
+}
+
+/// Checks that `push_or_replace` keeps one value per timestamp and name, and
+/// leaves values pushed otherwise alone.
+pub fn push_or_replace<S: RangeableStore>(store: S) {
+    let pushed = Entry::new_with_timestamp(1, "test_push_or_replace", vec![1]);
+    store.push(Cow::Borrowed(&pushed)).unwrap();
+    let a = Entry::new_with_timestamp(1, "test_push_or_replace", vec![2]);
+    let b = Entry::new_with_timestamp(1, "test_push_or_replace", vec![3; 64]);
+    let c = Entry::new_with_timestamp(2, "test_push_or_replace", vec![4]);
+    assert!(!store.push_or_replace(Cow::Borrowed(&a)).unwrap());
+    assert!(store.push_or_replace(Cow::Borrowed(&b)).unwrap());
+    assert!(!store.push_or_replace(Cow::Borrowed(&c)).unwrap());
+
+    let range = store.range(.., Some("test_push_or_replace")).unwrap();
+    let values: Vec<Vec<u8>> = range.iter().unwrap().map(|entry| entry.unwrap().value).collect();
+    assert_eq!(values, vec![pushed.value, b.value, c.value.clone()]);
+    assert_eq!(store.latest("test_push_or_replace").unwrap(), Some(c));
+}
+
+/// Checks that a store keeps the metadata entries were pushed with, as read
+/// back by `stored_entries`.
+pub fn metadata<S, I>(store: S, stored_entries: I)
+where
+    S: RangeableStore,
+    I: Fn(&S) -> Vec<StoredEntry>,
+{
+    assert!(store.capabilities().contains(Capabilities::METADATA));
+    let metadata = EntryMetadata(
+        [("source", "sensor-1"), ("priority", "high"), ("empty", "")]
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect(),
+    );
+    store
+        .push_with_metadata(
+            Cow::Owned(Entry::new_with_timestamp(1, "test_metadata", vec![1])),
+            metadata.clone(),
+        )
+        .unwrap();
+    store
+        .push(Cow::Owned(Entry::new_with_timestamp(2, "test_metadata", vec![2])))
+        .unwrap();
+    store
+        .push_with_metadata(
+            Cow::Owned(Entry::new_with_timestamp(3, "test_metadata", vec![3])),
+            EntryMetadata::default(),
+        )
+        .unwrap();
+
+    let stored = stored_entries(&store);
+    let values: Vec<u8> = stored.iter().map(|stored_entry| stored_entry.entry.value[0]).collect();
+    assert_eq!(values, vec![1, 2, 3]);
+    assert_eq!(stored[0].metadata, Some(metadata));
+    assert_eq!(stored[1].metadata, None);
+    assert_eq!(stored[2].metadata, Some(EntryMetadata::default()));
+    assert_eq!(
+        store.latest("test_metadata").unwrap(),
+        Some(Entry::new_with_timestamp(3, "test_metadata", vec![3]))
+    );
+}
+
+/// Checks that stores created by `make_store` record when entries were
+/// pushed according to their clock, as read back by `stored_entries`.
+pub fn ingested_at<S, M, I>(make_store: M, stored_entries: I)
+where
+    S: RangeableStore,
+    M: Fn(StoreOptions) -> S,
+    I: Fn(&S) -> Vec<StoredEntry>,
+{
+    let clock = Arc::new(ManualClock::new(1000));
+    let store = make_store(StoreOptions {
+        clock: clock.clone(),
+        ..StoreOptions::default()
+    });
+    store
+        .push(Cow::Owned(Entry::new_with_timestamp(900, "test_ingested_at", vec![0])))
+        .unwrap();
+    clock.advance(Duration::from_micros(100));
+    store
+        .push(Cow::Owned(Entry::new_with_timestamp(950, "test_ingested_at", vec![1])))
+        .unwrap();
+
+    let stored = stored_entries(&store);
+    let metadata: Vec<(Option<i64>, Option<i64>)> = stored
+        .iter()
+        .map(|stored_entry| (stored_entry.ingested_at, stored_entry.ingestion_lag()))
+        .collect();
+    assert_eq!(metadata, vec![(Some(1000), Some(100)), (Some(1100), Some(150))]);
+    assert_eq!(
+        IngestionLagStats::from_entries(&stored),
+        IngestionLagStats {
+            count: 2,
+            p50: 100,
+            p90: 150,
+            p99: 150,
+            max: 150,
+        }
+    );
+    for stored_entry in stored.iter() {
+        assert_eq!(stored_entry.entry.sequence_number, Some(stored_entry.id));
+    }
+
+    // plain iteration is unaffected
+    let entries: Vec<Entry> = store
+        .range(.., Some("test_ingested_at"))
+        .unwrap()
+        .iter()
+        .unwrap()
+        .collect::<Result<Vec<Entry>, Error>>()
+        .unwrap();
+    let stored_entries: Vec<Entry> = stored.into_iter().map(|stored_entry| stored_entry.entry).collect();
+    assert_eq!(entries, stored_entries);
+}
@@ -0,0 +1,385 @@
+use std::ops::{Bound, Range, RangeBounds};
+use std::str::FromStr;
+
+use crate::entry::now_micros;
+use crate::{utils, Error};
+
+static MICROS_PER_SECOND: i64 = 1_000_000;
+static MICROS_PER_DAY: i64 = 86_400 * MICROS_PER_SECOND;
+
+/// A range of timestamps parsed from an expression like
+/// `2024-01-01..2024-02-01` or `-1h..`, e.g. as accepted by a command line
+/// or config file. It implements `RangeBounds<i64>`, so it can be passed
+/// straight to `RangeableStore::range`.
+///
+/// Either side of the `..` can be left out to leave that side unbounded. The
+/// end is exclusive, unless written as `..=`. Each side is one of:
+///
+/// * `now`.
+/// * A time relative to now: a sign followed by amounts with units, e.g.
+///   `-1h`, `-1h30m` or `+500ms`. The units are `w`, `d`, `h`, `m`, `s`, `ms`
+///   and `us`.
+/// * A timestamp, in microseconds since the unix epoch.
+/// * A date, e.g. `2024-01-01`, or an RFC 3339 datetime, e.g.
+///   `2024-01-01T12:30:00.5+02:00`. Dates, and datetimes without an offset,
+///   are in UTC. Fractions of a microsecond are rounded so that the range
+///   only includes timestamps within it, i.e. up for starts and down for
+///   ends.
+///
+/// Parsing via `FromStr` resolves relative times against the system clock,
+/// and `parse_at` against a given time, e.g. a store's `Clock`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeRange {
+    pub start: Bound<i64>,
+    pub end: Bound<i64>,
+}
+
+impl TimeRange {
+    /// Parses a range expression, resolving relative times against `now`, in
+    /// microseconds since the unix epoch.
+    pub fn parse_at(expr: &str, now: i64) -> Result<Self, Error> {
+        let invalid = || Error::BadRangeExpr(expr.to_string());
+        let (start, end, inclusive) = if let Some((start, end)) = expr.split_once("..=") {
+            (start.trim(), end.trim(), true)
+        } else if let Some((start, end)) = expr.split_once("..") {
+            (start.trim(), end.trim(), false)
+        } else {
+            return Err(invalid());
+        };
+
+        let start = match start {
+            "" => Bound::Unbounded,
+            start => Bound::Included(parse_time(start, now).ok_or_else(invalid)?),
+        };
+        let end = match end {
+            "" if inclusive => return Err(invalid()),
+            "" => Bound::Unbounded,
+            end => {
+                let end = parse_time(end, now).ok_or_else(invalid)?;
+                if inclusive {
+                    Bound::Included(end)
+                } else {
+                    Bound::Excluded(end)
+                }
+            }
+        };
+        Ok(Self {
+            start: utils::start_bound_micros(start),
+            end: utils::end_bound_micros(end),
+        })
+    }
+}
+
+impl FromStr for TimeRange {
+    type Err = Error;
+
+    fn from_str(expr: &str) -> Result<Self, Error> {
+        Self::parse_at(expr, now_micros())
+    }
+}
+
+impl RangeBounds<i64> for TimeRange {
+    fn start_bound(&self) -> Bound<&i64> {
+        self.start.as_ref()
+    }
+
+    fn end_bound(&self) -> Bound<&i64> {
+        self.end.as_ref()
+    }
+}
+
+/// Parses a time into microseconds since the unix epoch, rounded down, and
+/// whether rounding dropped a fraction of a microsecond.
+fn parse_time(s: &str, now: i64) -> Option<(i64, bool)> {
+    if s == "now" {
+        Some((now, false))
+    } else if let Ok(timestamp) = s.parse() {
+        Some((timestamp, false))
+    } else if let Some(duration) = s.strip_prefix('-') {
+        Some((now.checked_sub(parse_duration(duration)?)?, false))
+    } else if let Some(duration) = s.strip_prefix('+') {
+        Some((now.checked_add(parse_duration(duration)?)?, false))
+    } else {
+        parse_datetime(s)
+    }
+}
+
+/// Parses amounts with units, e.g. `1h30m`, into microseconds.
+fn parse_duration(mut s: &str) -> Option<i64> {
+    if s.is_empty() {
+        return None;
+    }
+    let mut total: i64 = 0;
+    while !s.is_empty() {
+        let digits = s.bytes().take_while(u8::is_ascii_digit).count();
+        let amount: i64 = s[..digits].parse().ok()?;
+        s = &s[digits..];
+        let letters = s.bytes().take_while(u8::is_ascii_alphabetic).count();
+        let unit = match &s[..letters] {
+            "us" => 1,
+            "ms" => 1_000,
+            "s" => MICROS_PER_SECOND,
+            "m" => 60 * MICROS_PER_SECOND,
+            "h" => 3_600 * MICROS_PER_SECOND,
+            "d" => MICROS_PER_DAY,
+            "w" => 7 * MICROS_PER_DAY,
+            _ => return None,
+        };
+        s = &s[letters..];
+        total = total.checked_add(amount.checked_mul(unit)?)?;
+    }
+    Some(total)
+}
+
+/// Parses a number made up of exactly the digits in `range` of `s`.
+fn digits(s: &str, range: Range<usize>) -> Option<i64> {
+    let part = s.get(range)?;
+    if part.bytes().all(|b| b.is_ascii_digit()) {
+        part.parse().ok()
+    } else {
+        None
+    }
+}
+
+/// Parses a date, or an RFC 3339 datetime, into microseconds since the unix
+/// epoch, rounded down, and whether rounding dropped a fraction of a
+/// microsecond.
+fn parse_datetime(s: &str) -> Option<(i64, bool)> {
+    if s.get(4..5) != Some("-") || s.get(7..8) != Some("-") {
+        return None;
+    }
+    let (year, month, day) = (digits(s, 0..4)?, digits(s, 5..7)?, digits(s, 8..10)?);
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return None;
+    }
+    let date = days_from_civil(year, month, day) * MICROS_PER_DAY;
+    let time = match &s[10..] {
+        "" => return Some((date, false)),
+        time => time.strip_prefix(['T', 't', ' '])?,
+    };
+
+    if time.get(2..3) != Some(":") {
+        return None;
+    }
+    let (hour, minute) = (digits(time, 0..2)?, digits(time, 3..5)?);
+    let mut rest = &time[5..];
+    let (mut second, mut fraction, mut rounded) = (0, 0, false);
+    if let Some(seconds) = rest.strip_prefix(':') {
+        second = digits(seconds, 0..2)?;
+        rest = &seconds[2..];
+        if let Some(fraction_digits) = rest.strip_prefix('.') {
+            let len = fraction_digits.bytes().take_while(u8::is_ascii_digit).count();
+            if len == 0 {
+                return None;
+            }
+            // Only the first six digits are microseconds
+            for (i, b) in fraction_digits.bytes().take(6).enumerate().take(len) {
+                fraction += i64::from(b - b'0') * 10i64.pow(5 - i as u32);
+            }
+            rounded = fraction_digits.bytes().take(len).skip(6).any(|b| b != b'0');
+            rest = &fraction_digits[len..];
+        }
+    }
+    if hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+
+    let offset = match rest {
+        "" | "Z" | "z" => 0,
+        offset => {
+            let sign = match offset.get(0..1)? {
+                "+" => 1,
+                "-" => -1,
+                _ => return None,
+            };
+            if offset.len() != 6 || offset.get(3..4) != Some(":") {
+                return None;
+            }
+            let (hours, minutes) = (digits(offset, 1..3)?, digits(offset, 4..6)?);
+            if hours > 23 || minutes > 59 {
+                return None;
+            }
+            sign * (hours * 60 + minutes) * 60 * MICROS_PER_SECOND
+        }
+    };
+
+    Some((
+        date + ((hour * 60 + minute) * 60 + second) * MICROS_PER_SECOND + fraction - offset,
+        rounded,
+    ))
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// The number of days between the unix epoch and a date in the proleptic
+/// Gregorian calendar, per Howard Hinnant's `days_from_civil`.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_index = (month + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::ops::Bound;
+
+    use super::TimeRange;
+    use crate::{Entry, Error, MemoryStore, Range, RangeableStore, Store};
+
+    use string_cache::DefaultAtom as Atom;
+
+    static NOW: i64 = 1_700_000_000_000_000;
+    static HOUR: i64 = 3_600_000_000;
+
+    fn parse(expr: &str) -> (Bound<i64>, Bound<i64>) {
+        let range = TimeRange::parse_at(expr, NOW).unwrap();
+        (range.start, range.end)
+    }
+
+    #[test]
+    fn relative() {
+        assert_eq!(parse("-1h.."), (Bound::Included(NOW - HOUR), Bound::Unbounded));
+        assert_eq!(parse("..now"), (Bound::Unbounded, Bound::Excluded(NOW)));
+        assert_eq!(
+            parse("-1h30m..=+500ms"),
+            (Bound::Included(NOW - 3 * HOUR / 2), Bound::Included(NOW + 500_000))
+        );
+        assert_eq!(
+            parse("-1w..-2d"),
+            (Bound::Included(NOW - 168 * HOUR), Bound::Excluded(NOW - 48 * HOUR))
+        );
+        assert_eq!(parse(" -10us .. "), (Bound::Included(NOW - 10), Bound::Unbounded));
+        assert_eq!(parse(".."), (Bound::Unbounded, Bound::Unbounded));
+    }
+
+    #[test]
+    fn absolute() {
+        assert_eq!(
+            parse("2024-01-01..2024-02-01"),
+            (
+                Bound::Included(1_704_067_200_000_000),
+                Bound::Excluded(1_706_745_600_000_000)
+            )
+        );
+        assert_eq!(
+            parse("2024-02-29T12:30:00.25+02:00..=2024-02-29 10:30"),
+            (
+                Bound::Included(1_709_202_600_250_000),
+                Bound::Included(1_709_202_600_000_000)
+            )
+        );
+        // fractions of a microsecond are rounded up for starts and down for
+        // ends, including before the epoch, so that ranges only include
+        // timestamps within them
+        assert_eq!(
+            parse("1969-12-31T23:59:59.9999999Z..0"),
+            (Bound::Included(0), Bound::Excluded(0))
+        );
+        assert_eq!(
+            parse("1969-12-31T23:59:59.9999980Z..1969-12-31T23:59:59.9999999Z"),
+            (Bound::Included(-2), Bound::Included(-1))
+        );
+        assert_eq!(
+            parse("-5..=1969-12-31T23:59:59.9999999Z"),
+            (Bound::Included(-5), Bound::Included(-1))
+        );
+        assert_eq!(
+            parse("-5..1700000000000000"),
+            (Bound::Included(-5), Bound::Excluded(NOW))
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        for expr in [
+            "",
+            "now",
+            "1..=",
+            "-1x..",
+            "-h..",
+            "--1h..",
+            "2024-13-01..",
+            "2023-02-29..",
+            "2024-01-01T24:00..",
+            "2024-01-01T12..",
+            "2024-01-01T12:00:00.Z..",
+            "2024-01-01T12:00+0200..",
+            "24-01-01..",
+            "-9999999999w..",
+        ] {
+            assert!(
+                matches!(TimeRange::parse_at(expr, NOW), Err(Error::BadRangeExpr(ref s)) if s == expr),
+                "{:?}",
+                expr
+            );
+        }
+    }
+
+    #[test]
+    fn from_str() {
+        let store = MemoryStore::default();
+        let entry = Entry::new_with_timestamp(crate::entry::now_micros(), "test_time_range", vec![1]);
+        store.push(Cow::Borrowed(&entry)).unwrap();
+        let range: TimeRange = "-1h..".parse().unwrap();
+        let entries: Vec<Entry> = store
+            .range(range, Option::<Atom>::None)
+            .unwrap()
+            .iter()
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(entries, vec![entry]);
+        let range: TimeRange = "..-1h".parse().unwrap();
+        assert!(store.range(range, Option::<Atom>::None).unwrap().is_empty().unwrap());
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn matches_time() {
+        use time::{Date, Month, PrimitiveDateTime, Time, UtcOffset};
+
+        for (datetime, date, time, offset) in [
+            ("0000-03-01T00:00:00Z", (0, Month::March, 1), (0, 0, 0, 0), (0, 0)),
+            (
+                "1900-02-28T23:59:59.123456-11:30",
+                (1900, Month::February, 28),
+                (23, 59, 59, 123_456),
+                (-11, -30),
+            ),
+            (
+                "2000-02-29T00:00:00+14:00",
+                (2000, Month::February, 29),
+                (0, 0, 0, 0),
+                (14, 0),
+            ),
+            (
+                "9999-12-31T23:59:59.999999Z",
+                (9999, Month::December, 31),
+                (23, 59, 59, 999_999),
+                (0, 0),
+            ),
+        ] {
+            let expected = PrimitiveDateTime::new(
+                Date::from_calendar_date(date.0, date.1, date.2).unwrap(),
+                Time::from_hms_micro(time.0, time.1, time.2, time.3).unwrap(),
+            )
+            .assume_offset(UtcOffset::from_hms(offset.0, offset.1, 0).unwrap());
+            assert_eq!(
+                super::parse_datetime(datetime),
+                Some((crate::utils::offset_datetime_micros(&expected), false))
+            );
+        }
+    }
+}
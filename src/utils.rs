@@ -1,6 +1,10 @@
-use super::Error;
+use super::{Entry, Error};
 use std::cmp::Ordering;
 use std::ops::Bound;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "time")]
+use time::OffsetDateTime;
 
 fn unwrap_bound(bound: Bound<&i64>) -> Option<i64> {
     match bound {
@@ -26,9 +30,190 @@ pub(crate) fn check_bounds(start_bound: Bound<&i64>, end_bound: Bound<&i64>) ->
     Ok(())
 }
 
+/// Converts a system time into a timestamp, in microseconds since the unix
+/// epoch, rounding down, and whether rounding dropped a fraction of a
+/// microsecond. Times before the epoch have negative timestamps.
+pub(crate) fn system_time_micros(time: &SystemTime) -> Result<(i64, bool), Error> {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => {
+            let micros = duration.as_micros().try_into().map_err(|_| Error::TimeTooLarge)?;
+            Ok((micros, duration.subsec_nanos() % 1000 != 0))
+        }
+        Err(err) => {
+            let duration = err.duration();
+            let micros: i64 = duration.as_micros().try_into().map_err(|_| Error::TimeTooLarge)?;
+            if duration.subsec_nanos() % 1000 != 0 {
+                Ok((-micros - 1, true))
+            } else {
+                Ok((-micros, false))
+            }
+        }
+    }
+}
+
+/// Converts the start of a range, given as a timestamp rounded down and
+/// whether rounding dropped a fraction of a microsecond, into a bound that
+/// only includes timestamps at or after the unrounded start.
+pub(crate) fn start_bound_micros(bound: Bound<(i64, bool)>) -> Bound<i64> {
+    match bound {
+        Bound::Included((micros, true)) => match micros.checked_add(1) {
+            Some(micros) => Bound::Included(micros),
+            None => Bound::Excluded(micros),
+        },
+        Bound::Included((micros, false)) => Bound::Included(micros),
+        Bound::Excluded((micros, _)) => Bound::Excluded(micros),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
+/// Like `start_bound_micros`, but for the end of a range, only including
+/// timestamps before (or at, if included) the unrounded end.
+pub(crate) fn end_bound_micros(bound: Bound<(i64, bool)>) -> Bound<i64> {
+    match bound {
+        Bound::Included((micros, _)) | Bound::Excluded((micros, true)) => Bound::Included(micros),
+        Bound::Excluded((micros, false)) => Bound::Excluded(micros),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
+pub(crate) fn system_time_bound(bound: Bound<&SystemTime>) -> Result<Bound<(i64, bool)>, Error> {
+    Ok(match bound {
+        Bound::Included(time) => Bound::Included(system_time_micros(time)?),
+        Bound::Excluded(time) => Bound::Excluded(system_time_micros(time)?),
+        Bound::Unbounded => Bound::Unbounded,
+    })
+}
+
+/// Converts a datetime into a timestamp, in microseconds since the unix
+/// epoch, rounding down. Every `OffsetDateTime` fits.
+#[cfg(feature = "time")]
+pub(crate) fn offset_datetime_micros(datetime: &OffsetDateTime) -> i64 {
+    datetime.unix_timestamp_nanos().div_euclid(1000) as i64
+}
+
+#[cfg(feature = "time")]
+pub(crate) fn offset_datetime_bound(bound: Bound<&OffsetDateTime>) -> Bound<(i64, bool)> {
+    let micros_rem = |datetime: &OffsetDateTime| {
+        let nanos = datetime.unix_timestamp_nanos();
+        (nanos.div_euclid(1000) as i64, nanos.rem_euclid(1000) != 0)
+    };
+    match bound {
+        Bound::Included(datetime) => Bound::Included(micros_rem(datetime)),
+        Bound::Excluded(datetime) => Bound::Excluded(micros_rem(datetime)),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
+/// Converts bounds into an inclusive `(start, end)` pair of timestamps, or
+/// `None` if no timestamp can fall within the bounds.
+pub(crate) fn inclusive_bounds(start_bound: Bound<&i64>, end_bound: Bound<&i64>) -> Option<(i64, i64)> {
+    let start = match start_bound {
+        Bound::Included(ts) => *ts,
+        Bound::Excluded(ts) => ts.checked_add(1)?,
+        Bound::Unbounded => i64::MIN,
+    };
+    let end = match end_bound {
+        Bound::Included(ts) => *ts,
+        Bound::Excluded(ts) => ts.checked_sub(1)?,
+        Bound::Unbounded => i64::MAX,
+    };
+    if start <= end {
+        Some((start, end))
+    } else {
+        None
+    }
+}
+
+/// Groups the entries of an iterator into pages. An error is yielded on its
+/// own, and entries read before it are kept for the next page.
+pub(crate) struct Pages<I> {
+    iter: I,
+    page_size: usize,
+    page: Vec<Entry>,
+}
+
+impl<I> Pages<I> {
+    pub(crate) fn new(iter: I, page_size: usize) -> Self {
+        Self {
+            iter,
+            page_size: page_size.max(1),
+            page: Vec::default(),
+        }
+    }
+}
+
+impl<I: Iterator<Item = Result<Entry, Error>>> Iterator for Pages<I> {
+    type Item = Result<Vec<Entry>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.page.len() < self.page_size {
+            match self.iter.next() {
+                Some(Ok(entry)) => self.page.push(entry),
+                Some(Err(err)) => return Some(Err(err)),
+                None => break,
+            }
+        }
+        if self.page.is_empty() {
+            None
+        } else {
+            Some(Ok(std::mem::take(&mut self.page)))
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use std::ops::Bound;
+    use std::borrow::Cow;
+    use std::ops::{Bound, RangeBounds};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::{Entry, Error, MemoryStore, Range, RangeableStore, Store};
+    use proptest::prelude::*;
+
+    #[test]
+    fn system_time_micros() {
+        assert_eq!(super::system_time_micros(&UNIX_EPOCH).unwrap(), (0, false));
+        let time = UNIX_EPOCH + Duration::from_micros(1_500);
+        assert_eq!(super::system_time_micros(&time).unwrap(), (1_500, false));
+        let time = UNIX_EPOCH - Duration::from_micros(1_500);
+        assert_eq!(super::system_time_micros(&time).unwrap(), (-1_500, false));
+        let time = UNIX_EPOCH + Duration::from_micros(i64::MAX as u64);
+        assert_eq!(super::system_time_micros(&time).unwrap(), (i64::MAX, false));
+        if let Some(time) = time.checked_add(Duration::from_micros(1)) {
+            assert!(matches!(super::system_time_micros(&time), Err(Error::TimeTooLarge)));
+        }
+        // fractions of a microsecond are rounded down, including before the
+        // epoch
+        let time = UNIX_EPOCH + Duration::from_nanos(1_500);
+        assert_eq!(super::system_time_micros(&time).unwrap(), (1, true));
+        let time = UNIX_EPOCH - Duration::from_nanos(1_500);
+        assert_eq!(super::system_time_micros(&time).unwrap(), (-2, true));
+    }
+
+    #[test]
+    fn rounded_bounds() {
+        // a start with a fraction of a microsecond excludes the microsecond
+        // it falls in, and an end includes it
+        assert_eq!(
+            super::start_bound_micros(Bound::Included((1, true))),
+            Bound::Included(2)
+        );
+        assert_eq!(
+            super::start_bound_micros(Bound::Included((1, false))),
+            Bound::Included(1)
+        );
+        assert_eq!(
+            super::start_bound_micros(Bound::Excluded((1, true))),
+            Bound::Excluded(1)
+        );
+        assert_eq!(
+            super::start_bound_micros(Bound::Included((i64::MAX, true))),
+            Bound::Excluded(i64::MAX)
+        );
+        assert_eq!(super::end_bound_micros(Bound::Included((1, true))), Bound::Included(1));
+        assert_eq!(super::end_bound_micros(Bound::Excluded((1, true))), Bound::Included(1));
+        assert_eq!(super::end_bound_micros(Bound::Excluded((1, false))), Bound::Excluded(1));
+    }
 
     #[test]
     fn check_bounds() {
@@ -38,4 +223,66 @@ mod tests {
         super::check_bounds(Bound::Unbounded, Bound::Included(&0)).unwrap();
         super::check_bounds(Bound::Unbounded, Bound::Excluded(&0)).unwrap();
     }
+
+    fn bound() -> impl Strategy<Value = Bound<i64>> {
+        // small values, so that bounds are often equal
+        let micros = prop_oneof![-4i64..=4, Just(i64::MIN), Just(i64::MAX)];
+        prop_oneof![
+            Just(Bound::Unbounded),
+            micros.clone().prop_map(Bound::Included),
+            micros.prop_map(Bound::Excluded),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn check_bounds_matches_memory_range(start in bound(), end in bound()) {
+            let store = MemoryStore::default();
+            let timestamps = [i64::MIN, -3, -1, 0, 0, 2, 3, i64::MAX];
+            for timestamp in timestamps {
+                let entry = Entry::new_with_timestamp(timestamp, "test_check_bounds", vec![]);
+                store.push(Cow::Owned(entry)).unwrap();
+            }
+
+            let checked = super::check_bounds(start.as_ref(), end.as_ref());
+            match (start, end) {
+                (Bound::Included(start), Bound::Included(end)) => prop_assert_eq!(checked.is_ok(), start <= end),
+                (Bound::Included(start) | Bound::Excluded(start), Bound::Included(end) | Bound::Excluded(end)) => {
+                    prop_assert_eq!(checked.is_ok(), start < end)
+                }
+                _ => prop_assert!(checked.is_ok()),
+            }
+
+            match store.range((start, end), None::<String>) {
+                Ok(range) => {
+                    prop_assert!(checked.is_ok());
+                    let expected = timestamps.iter().filter(|timestamp| (start, end).contains(*timestamp)).count();
+                    prop_assert_eq!(range.count().unwrap(), expected as u64);
+                }
+                Err(Error::BadRange) => prop_assert!(checked.is_err()),
+                Err(err) => panic!("unexpected error: {}", err),
+            }
+        }
+    }
+
+    #[test]
+    fn inclusive_bounds() {
+        assert_eq!(
+            super::inclusive_bounds(Bound::Unbounded, Bound::Unbounded),
+            Some((i64::MIN, i64::MAX))
+        );
+        assert_eq!(
+            super::inclusive_bounds(Bound::Excluded(&1), Bound::Excluded(&3)),
+            Some((2, 2))
+        );
+        assert_eq!(super::inclusive_bounds(Bound::Excluded(&1), Bound::Excluded(&2)), None);
+        assert_eq!(
+            super::inclusive_bounds(Bound::Excluded(&i64::MAX), Bound::Unbounded),
+            None
+        );
+        assert_eq!(
+            super::inclusive_bounds(Bound::Unbounded, Bound::Excluded(&i64::MIN)),
+            None
+        );
+    }
 }